@@ -1,3 +1,6 @@
+pub mod block_index;
 pub mod db;
+pub mod journal;
 pub mod lmdb_utils;
 pub mod progress;
+pub mod state_file;
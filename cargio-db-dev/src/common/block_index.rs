@@ -0,0 +1,335 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Error as IoError,
+    path::{Path, PathBuf},
+    result::Result,
+};
+
+use cargio_hashing::Digest;
+use cargio_types::EraId;
+use lmdb::{
+    Cursor, Database as LmdbDatabase, DatabaseFlags, Environment, EnvironmentFlags,
+    Error as LmdbError, Transaction, WriteFlags,
+};
+use lmdb_sys::MDB_LAST;
+use log::{info, warn};
+use master_node::types::BlockHash;
+use thiserror::Error;
+
+use super::{
+    db::{BlockHeaderDatabase, Database, DeserializationError},
+    lmdb_utils,
+    progress::ProgressTracker,
+};
+
+pub const INDEX_FILE_NAME: &str = "block_index.lmdb";
+const HEIGHT_DB_NAME: &str = "height";
+const SWITCH_BLOCK_DB_NAME: &str = "switch_block";
+const METADATA_DB_NAME: &str = "metadata";
+const HEADER_COUNT_KEY: &[u8] = b"block_header_count";
+const HIGHEST_BLOCK_KEY: &[u8] = b"highest_block";
+const INDEX_MAP_SIZE: usize = 1 << 34;
+const MAX_DBS: u32 = 3;
+
+#[derive(Debug, Error)]
+pub enum BlockIndexError {
+    #[error("Error operating the block index: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Found more than one block at height {0}")]
+    DuplicateBlock(u64),
+    #[error("Error parsing block header with key {0}: {1}")]
+    HeaderParsing(String, DeserializationError),
+    #[error("Invalid block hash key {0}")]
+    InvalidBlockHash(String),
+    #[error("Invalid block index height or era {0}, expected 8 bytes")]
+    InvalidHeight(String),
+    #[error("Error removing the previous block index at {0}: {1}")]
+    Remove(PathBuf, IoError),
+}
+
+fn index_env(path: &Path) -> Result<Environment, LmdbError> {
+    Environment::new()
+        .set_flags(EnvironmentFlags::NO_SUB_DIR | EnvironmentFlags::NO_TLS)
+        .set_max_dbs(MAX_DBS)
+        .set_map_size(INDEX_MAP_SIZE)
+        .open(path)
+}
+
+fn block_hash_from_value(raw_val: &[u8]) -> Result<BlockHash, BlockIndexError> {
+    Digest::try_from(raw_val)
+        .map(BlockHash::from)
+        .map_err(|_| BlockIndexError::InvalidBlockHash(hex::encode(raw_val)))
+}
+
+fn u64_from_bytes(raw_bytes: &[u8]) -> Result<u64, BlockIndexError> {
+    raw_bytes
+        .try_into()
+        .map(u64::from_be_bytes)
+        .map_err(|_| BlockIndexError::InvalidHeight(hex::encode(raw_bytes)))
+}
+
+/// The highest block is stored as its big endian height followed by its
+/// hash.
+fn highest_block_from_value(raw_val: &[u8]) -> Result<(u64, BlockHash), BlockIndexError> {
+    if raw_val.len() < 8 {
+        return Err(BlockIndexError::InvalidBlockHash(hex::encode(raw_val)));
+    }
+    let (raw_height, raw_block_hash) = raw_val.split_at(8);
+    Ok((
+        u64_from_bytes(raw_height)?,
+        block_hash_from_value(raw_block_hash)?,
+    ))
+}
+
+fn remove_index_files(index_path: &Path) -> Result<(), BlockIndexError> {
+    for path in [
+        index_path.to_path_buf(),
+        index_path.with_extension("lmdb-lock"),
+    ] {
+        if path.exists() {
+            fs::remove_file(&path).map_err(|io_err| BlockIndexError::Remove(path, io_err))?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the block index of the storage in `db_path`, if there is one.
+/// Commands changing the block headers call this before their changes, so
+/// the index can't be used with block headers it wasn't built from.
+pub fn remove_index<P: AsRef<Path>>(db_path: P) -> Result<(), BlockIndexError> {
+    let index_path = db_path.as_ref().join(INDEX_FILE_NAME);
+    if index_path.exists() {
+        info!(
+            "Removing block index at {}, run `build-index` to recreate it.",
+            index_path.display()
+        );
+    }
+    remove_index_files(&index_path)
+}
+
+/// A side LMDB file next to `storage.lmdb` mapping heights to block hashes
+/// and eras to the hashes of their switch blocks. Keys are big endian so the
+/// entries are ordered by height and era.
+pub struct BlockIndex {
+    env: Environment,
+    height_db: LmdbDatabase,
+    switch_block_db: LmdbDatabase,
+}
+
+impl BlockIndex {
+    /// Opens the block index in `db_path` if there is one and it was built
+    /// from the current contents of the block header database in `env`.
+    pub fn open<P: AsRef<Path>>(
+        db_path: P,
+        storage_env: &Environment,
+    ) -> Result<Option<Self>, BlockIndexError> {
+        let index_path = db_path.as_ref().join(INDEX_FILE_NAME);
+        if !index_path.exists() {
+            return Ok(None);
+        }
+        let env = index_env(&index_path)?;
+        let height_db = env.open_db(Some(HEIGHT_DB_NAME))?;
+        let switch_block_db = env.open_db(Some(SWITCH_BLOCK_DB_NAME))?;
+        let metadata_db = env.open_db(Some(METADATA_DB_NAME))?;
+
+        // The metadata is written last, so an index which was only partly
+        // built has none.
+        let (maybe_indexed_count, maybe_indexed_highest_block) = {
+            let txn = env.begin_ro_txn()?;
+            let maybe_count = match txn.get(metadata_db, &HEADER_COUNT_KEY) {
+                Ok(raw_count) => Some(u64_from_bytes(raw_count)?),
+                Err(LmdbError::NotFound) => None,
+                Err(lmdb_err) => return Err(lmdb_err.into()),
+            };
+            let maybe_highest_block = match txn.get(metadata_db, &HIGHEST_BLOCK_KEY) {
+                Ok(raw_val) => Some(highest_block_from_value(raw_val)?),
+                Err(LmdbError::NotFound) => None,
+                Err(lmdb_err) => return Err(lmdb_err.into()),
+            };
+            txn.commit()?;
+            (maybe_count, maybe_highest_block)
+        };
+        // Besides the number of headers, the highest indexed block must still
+        // be stored at the same height, which catches blocks replaced without
+        // changing the count.
+        let is_fresh = {
+            let txn = storage_env.begin_ro_txn()?;
+            let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+            let header_count = lmdb_utils::entry_count(&txn, header_db)?;
+            let is_fresh = maybe_indexed_count == Some(header_count as u64)
+                && match maybe_indexed_highest_block {
+                    Some((height, block_hash)) => match txn.get(header_db, &block_hash) {
+                        Ok(raw_header) => {
+                            let header = BlockHeaderDatabase::parse_element(raw_header).map_err(
+                                |parsing_err| {
+                                    BlockIndexError::HeaderParsing(
+                                        hex::encode(block_hash),
+                                        parsing_err,
+                                    )
+                                },
+                            )?;
+                            header.height() == height
+                        }
+                        Err(LmdbError::NotFound) => false,
+                        Err(lmdb_err) => return Err(lmdb_err.into()),
+                    },
+                    None => header_count == 0,
+                };
+            txn.commit()?;
+            is_fresh
+        };
+        if !is_fresh {
+            warn!(
+                "Block index at {} doesn't match the blocks in the storage, falling back to \
+                scanning block headers. Run `build-index` to update it.",
+                index_path.display()
+            );
+            return Ok(None);
+        }
+        info!("Using block index at {}.", index_path.display());
+        Ok(Some(Self {
+            env,
+            height_db,
+            switch_block_db,
+        }))
+    }
+
+    pub fn block_hash(&self, height: u64) -> Result<Option<BlockHash>, BlockIndexError> {
+        let txn = self.env.begin_ro_txn()?;
+        let maybe_block_hash = match txn.get(self.height_db, &height.to_be_bytes()) {
+            Ok(raw_val) => Some(block_hash_from_value(raw_val)?),
+            Err(LmdbError::NotFound) => None,
+            Err(lmdb_err) => return Err(lmdb_err.into()),
+        };
+        txn.commit()?;
+        Ok(maybe_block_hash)
+    }
+
+    /// Returns the height and hash of the highest block.
+    pub fn highest_block(&self) -> Result<Option<(u64, BlockHash)>, BlockIndexError> {
+        let txn = self.env.begin_ro_txn()?;
+        let maybe_highest_block = {
+            let cursor = txn.open_ro_cursor(self.height_db)?;
+            match cursor.get(None, None, MDB_LAST) {
+                Ok((Some(raw_key), raw_val)) => {
+                    Some((u64_from_bytes(raw_key)?, block_hash_from_value(raw_val)?))
+                }
+                Ok((None, _)) | Err(LmdbError::NotFound) => None,
+                Err(lmdb_err) => return Err(lmdb_err.into()),
+            }
+        };
+        txn.commit()?;
+        Ok(maybe_highest_block)
+    }
+
+    /// Returns the hashes of all blocks, by height.
+    pub fn blocks(&self) -> Result<BTreeMap<u64, BlockHash>, BlockIndexError> {
+        self.read_all(self.height_db)
+    }
+
+    /// Returns the hashes of the switch blocks, by the era they end.
+    pub fn switch_blocks(&self) -> Result<BTreeMap<EraId, BlockHash>, BlockIndexError> {
+        Ok(self
+            .read_all(self.switch_block_db)?
+            .into_iter()
+            .map(|(era_id, block_hash)| (EraId::new(era_id), block_hash))
+            .collect())
+    }
+
+    fn read_all(&self, db: LmdbDatabase) -> Result<BTreeMap<u64, BlockHash>, BlockIndexError> {
+        let txn = self.env.begin_ro_txn()?;
+        let mut entries = BTreeMap::new();
+        if let Ok(mut cursor) = txn.open_ro_cursor(db) {
+            for (raw_key, raw_val) in cursor.iter() {
+                entries.insert(u64_from_bytes(raw_key)?, block_hash_from_value(raw_val)?);
+            }
+        }
+        txn.commit()?;
+        Ok(entries)
+    }
+}
+
+/// Builds the block index of the storage in `db_path` from scratch,
+/// replacing any previous index. Returns the number of indexed blocks.
+pub fn build_index<P: AsRef<Path>>(
+    db_path: P,
+    storage_env: &Environment,
+) -> Result<usize, BlockIndexError> {
+    let index_path = db_path.as_ref().join(INDEX_FILE_NAME);
+    remove_index_files(&index_path)?;
+    let env = index_env(&index_path)?;
+    let height_db = env.create_db(Some(HEIGHT_DB_NAME), DatabaseFlags::empty())?;
+    let switch_block_db = env.create_db(Some(SWITCH_BLOCK_DB_NAME), DatabaseFlags::empty())?;
+    let metadata_db = env.create_db(Some(METADATA_DB_NAME), DatabaseFlags::empty())?;
+
+    let storage_txn = storage_env.begin_ro_txn()?;
+    let header_db = unsafe { storage_txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+    let header_count = lmdb_utils::entry_count(&storage_txn, header_db)?;
+    let mut maybe_progress_tracker = ProgressTracker::new(
+        header_count,
+        Box::new(|completion| info!("Block index {}% complete...", completion)),
+    )
+    .ok();
+
+    let mut index_txn = env.begin_rw_txn()?;
+    let mut maybe_highest_block: Option<(u64, &[u8])> = None;
+    if let Ok(mut cursor) = storage_txn.open_ro_cursor(header_db) {
+        for (raw_key, raw_val) in cursor.iter() {
+            let header = BlockHeaderDatabase::parse_element(raw_val).map_err(|parsing_err| {
+                BlockIndexError::HeaderParsing(hex::encode(raw_key), parsing_err)
+            })?;
+            // Validates the key before storing it.
+            let _ = block_hash_from_value(raw_key)?;
+            match index_txn.put(
+                height_db,
+                &header.height().to_be_bytes(),
+                &raw_key,
+                WriteFlags::NO_OVERWRITE,
+            ) {
+                Ok(()) => {}
+                Err(LmdbError::KeyExist) => {
+                    return Err(BlockIndexError::DuplicateBlock(header.height()))
+                }
+                Err(lmdb_err) => return Err(lmdb_err.into()),
+            }
+            if maybe_highest_block.map_or(true, |(height, _)| header.height() > height) {
+                maybe_highest_block = Some((header.height(), raw_key));
+            }
+            if header.is_switch_block() {
+                index_txn.put(
+                    switch_block_db,
+                    &header.era_id().value().to_be_bytes(),
+                    &raw_key,
+                    WriteFlags::empty(),
+                )?;
+            }
+            if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
+                progress_tracker.advance_by(1);
+            }
+        }
+    }
+    if let Some((height, raw_block_hash)) = maybe_highest_block {
+        let mut raw_val = height.to_be_bytes().to_vec();
+        raw_val.extend_from_slice(raw_block_hash);
+        index_txn.put(
+            metadata_db,
+            &HIGHEST_BLOCK_KEY,
+            &raw_val,
+            WriteFlags::empty(),
+        )?;
+    }
+    index_txn.put(
+        metadata_db,
+        &HEADER_COUNT_KEY,
+        &(header_count as u64).to_be_bytes(),
+        WriteFlags::empty(),
+    )?;
+    index_txn.commit()?;
+    storage_txn.commit()?;
+    info!("Indexed {header_count} blocks in {}.", index_path.display());
+    Ok(header_count)
+}
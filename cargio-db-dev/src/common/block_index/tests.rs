@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+
+use cargio_types::EraId;
+use lmdb::{Environment, Transaction, WriteFlags};
+use master_node::types::BlockHash;
+use serde::Serialize;
+
+use super::{build_index, remove_index, BlockIndex, BlockIndexError, INDEX_FILE_NAME};
+use crate::{
+    common::db::STORAGE_FILE_NAME,
+    test_utils::{mock_block_header, mock_switch_block_header, LmdbTestFixture},
+};
+
+fn put_header<T: Serialize>(
+    env: &Environment,
+    fixture: &LmdbTestFixture,
+    block_hash: &BlockHash,
+    header: &T,
+) {
+    let db = fixture.db(Some("block_header")).unwrap();
+    let mut txn = env.begin_rw_txn().unwrap();
+    txn.put(
+        *db,
+        block_hash,
+        &bincode::serialize(header).unwrap(),
+        WriteFlags::empty(),
+    )
+    .unwrap();
+    txn.commit().unwrap();
+}
+
+#[test]
+fn block_index_should_map_heights_and_eras() {
+    let fixture = LmdbTestFixture::new(vec!["block_header"], Some(STORAGE_FILE_NAME));
+    let env = &fixture.env;
+
+    let (first_block_hash, mut first_block) = mock_block_header(0);
+    first_block.height = 0;
+    put_header(env, &fixture, &first_block_hash, &first_block);
+    let (switch_block_hash, mut switch_block) = mock_switch_block_header(1);
+    switch_block.height = 1;
+    switch_block.era_id = EraId::new(0);
+    put_header(env, &fixture, &switch_block_hash, &switch_block);
+    let (last_block_hash, mut last_block) = mock_block_header(2);
+    last_block.height = 2;
+    last_block.era_id = EraId::new(1);
+    put_header(env, &fixture, &last_block_hash, &last_block);
+
+    assert!(BlockIndex::open(fixture.tmp_dir.as_ref(), env)
+        .unwrap()
+        .is_none());
+    assert_eq!(build_index(fixture.tmp_dir.as_ref(), env).unwrap(), 3);
+
+    let block_index = BlockIndex::open(fixture.tmp_dir.as_ref(), env)
+        .unwrap()
+        .unwrap();
+    assert_eq!(block_index.block_hash(1).unwrap(), Some(switch_block_hash));
+    assert_eq!(block_index.block_hash(3).unwrap(), None);
+    assert_eq!(
+        block_index.highest_block().unwrap(),
+        Some((2, last_block_hash))
+    );
+    assert_eq!(
+        block_index.blocks().unwrap(),
+        BTreeMap::from([
+            (0, first_block_hash),
+            (1, switch_block_hash),
+            (2, last_block_hash)
+        ])
+    );
+    assert_eq!(
+        block_index.switch_blocks().unwrap(),
+        BTreeMap::from([(EraId::new(0), switch_block_hash)])
+    );
+    drop(block_index);
+
+    // A block stored after the index was built makes the index stale.
+    let (new_block_hash, mut new_block) = mock_block_header(3);
+    new_block.height = 3;
+    new_block.era_id = EraId::new(1);
+    put_header(env, &fixture, &new_block_hash, &new_block);
+    assert!(BlockIndex::open(fixture.tmp_dir.as_ref(), env)
+        .unwrap()
+        .is_none());
+
+    assert_eq!(build_index(fixture.tmp_dir.as_ref(), env).unwrap(), 4);
+    let block_index = BlockIndex::open(fixture.tmp_dir.as_ref(), env)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        block_index.highest_block().unwrap(),
+        Some((3, new_block_hash))
+    );
+}
+
+#[test]
+fn block_index_should_reject_duplicate_heights() {
+    let fixture = LmdbTestFixture::new(vec!["block_header"], Some(STORAGE_FILE_NAME));
+    let env = &fixture.env;
+
+    let (first_block_hash, mut first_block) = mock_block_header(0);
+    first_block.height = 5;
+    put_header(env, &fixture, &first_block_hash, &first_block);
+    let (second_block_hash, mut second_block) = mock_block_header(1);
+    second_block.height = 5;
+    put_header(env, &fixture, &second_block_hash, &second_block);
+
+    assert!(matches!(
+        build_index(fixture.tmp_dir.as_ref(), env),
+        Err(BlockIndexError::DuplicateBlock(5))
+    ));
+    assert!(BlockIndex::open(fixture.tmp_dir.as_ref(), env)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn block_index_should_be_stale_after_highest_block_is_replaced() {
+    let fixture = LmdbTestFixture::new(vec!["block_header"], Some(STORAGE_FILE_NAME));
+    let env = &fixture.env;
+
+    let mut block_hashes = vec![];
+    for idx in 0..3 {
+        let (block_hash, mut header) = mock_block_header(idx);
+        header.height = idx as u64;
+        put_header(env, &fixture, &block_hash, &header);
+        block_hashes.push(block_hash);
+    }
+    assert_eq!(build_index(fixture.tmp_dir.as_ref(), env).unwrap(), 3);
+
+    // Replace the highest block, keeping the number of headers.
+    {
+        let db = fixture.db(Some("block_header")).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.del(*db, &block_hashes[2], None).unwrap();
+        txn.commit().unwrap();
+    }
+    let (replacement_hash, mut replacement) = mock_block_header(3);
+    replacement.height = 2;
+    put_header(env, &fixture, &replacement_hash, &replacement);
+
+    assert!(BlockIndex::open(fixture.tmp_dir.as_ref(), env)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn removed_block_index_should_not_be_opened() {
+    let fixture = LmdbTestFixture::new(vec!["block_header"], Some(STORAGE_FILE_NAME));
+    let env = &fixture.env;
+    let (block_hash, header) = mock_block_header(0);
+    put_header(env, &fixture, &block_hash, &header);
+    assert_eq!(build_index(fixture.tmp_dir.as_ref(), env).unwrap(), 1);
+
+    remove_index(fixture.tmp_dir.as_ref()).unwrap();
+    assert!(!fixture.tmp_dir.path().join(INDEX_FILE_NAME).exists());
+    assert!(BlockIndex::open(fixture.tmp_dir.as_ref(), env)
+        .unwrap()
+        .is_none());
+    // Removing a missing index is fine.
+    remove_index(fixture.tmp_dir.as_ref()).unwrap();
+}
+
+#[test]
+fn block_index_should_reject_truncated_heights() {
+    let fixture = LmdbTestFixture::new(vec!["block_header"], Some(STORAGE_FILE_NAME));
+    let env = &fixture.env;
+    let (block_hash, header) = mock_block_header(0);
+    put_header(env, &fixture, &block_hash, &header);
+    assert_eq!(build_index(fixture.tmp_dir.as_ref(), env).unwrap(), 1);
+
+    let block_index = BlockIndex::open(fixture.tmp_dir.as_ref(), env)
+        .unwrap()
+        .unwrap();
+    let mut txn = block_index.env.begin_rw_txn().unwrap();
+    txn.put(
+        block_index.height_db,
+        &[1u8, 2, 3],
+        &block_hash,
+        WriteFlags::empty(),
+    )
+    .unwrap();
+    txn.commit().unwrap();
+
+    assert!(matches!(
+        block_index.blocks(),
+        Err(BlockIndexError::InvalidHeight(_))
+    ));
+    assert!(matches!(
+        block_index.highest_block(),
+        Err(BlockIndexError::InvalidHeight(_))
+    ));
+}
@@ -2,11 +2,13 @@ mod block_body_db;
 mod block_body_merkle_db;
 mod block_header_db;
 mod block_metadata_db;
+mod checkpoint;
 mod deploy_hashes_db;
 mod deploy_metadata_db;
 mod deploys_db;
 mod finalized_approvals_db;
 mod proposers_db;
+mod report;
 mod state_store_db;
 #[cfg(test)]
 mod tests;
@@ -17,11 +19,13 @@ pub use block_body_db::BlockBodyDatabase;
 pub use block_body_merkle_db::BlockBodyMerkleDatabase;
 pub use block_header_db::BlockHeaderDatabase;
 pub use block_metadata_db::BlockMetadataDatabase;
+pub use checkpoint::{Checkpoint, CheckpointError, DatabaseCheckpoint};
 pub use deploy_hashes_db::DeployHashesDatabase;
 pub use deploy_metadata_db::DeployMetadataDatabase;
 pub use deploys_db::DeployDatabase;
 pub use finalized_approvals_db::FinalizedApprovalsDatabase;
 pub use proposers_db::ProposerDatabase;
+pub use report::{CheckReport, DatabaseReport, EntryFailure, EntryFailureKind};
 pub use state_store_db::StateStoreDatabase;
 pub use transfer_db::TransferDatabase;
 pub use transfer_hashes_db::TransferHashesDatabase;
@@ -33,8 +37,9 @@ use std::{
 };
 
 use bincode::Error as BincodeError;
-use lmdb::{Cursor, Environment, EnvironmentFlags, Error as LmdbError, RoCursor, Transaction};
+use lmdb::{Cursor, Environment, EnvironmentFlags, Error as LmdbError, Transaction};
 use log::info;
+use serde::Serialize;
 use thiserror::Error;
 
 use cargio_types::bytesrepr::Error as BytesreprError;
@@ -52,16 +57,47 @@ pub enum DeserializationError {
     BytesreprError(String),
 }
 
+impl DeserializationError {
+    /// The error message along with the underlying error.
+    pub fn detailed_message(&self) -> String {
+        match self {
+            Self::BincodeError(bincode_err) => format!("{self}: {bincode_err}"),
+            Self::BytesreprError(bytesrepr_err) => format!("{self}: {bytesrepr_err}"),
+        }
+    }
+}
+
 impl From<BytesreprError> for DeserializationError {
     fn from(error: BytesreprError) -> Self {
         Self::BytesreprError(error.to_string())
     }
 }
 
+/// A successfully deserialized entry which doesn't hold what its key says.
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    #[error("key {key} doesn't match the hash {value_hash} of the value")]
+    KeyMismatch { key: String, value_hash: String },
+    #[error("invalid value: {0}")]
+    InvalidValue(String),
+}
+
+fn check_key(key: &[u8], value_hash: &[u8]) -> Result<(), ValidationError> {
+    if key != value_hash {
+        return Err(ValidationError::KeyMismatch {
+            key: hex::encode(key),
+            value_hash: hex::encode(value_hash),
+        });
+    }
+    Ok(())
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     Accumulated(Vec<Self>),
+    Checkpoint(#[from] CheckpointError),
     Parsing(usize, DeserializationError),
+    Validation(usize, ValidationError),
     Database(#[from] LmdbError),
 }
 
@@ -69,7 +105,9 @@ impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> FormatterResult {
         match self {
             Self::Database(e) => write!(f, "Error operating the database: {e}"),
+            Self::Checkpoint(e) => write!(f, "Error writing checkpoint: {e}"),
             Self::Parsing(idx, inner) => write!(f, "Error parsing element {idx}: {inner}"),
+            Self::Validation(idx, inner) => write!(f, "Error validating element {idx}: {inner}"),
             Self::Accumulated(accumulated_errors) => {
                 writeln!(f, "Errors caught:")?;
                 for error in accumulated_errors {
@@ -81,6 +119,52 @@ impl Display for Error {
     }
 }
 
+pub fn storage_database_names() -> [&'static str; 12] {
+    [
+        BlockBodyDatabase::db_name(),
+        BlockBodyMerkleDatabase::db_name(),
+        BlockHeaderDatabase::db_name(),
+        BlockMetadataDatabase::db_name(),
+        DeployHashesDatabase::db_name(),
+        DeployMetadataDatabase::db_name(),
+        DeployDatabase::db_name(),
+        FinalizedApprovalsDatabase::db_name(),
+        ProposerDatabase::db_name(),
+        StateStoreDatabase::db_name(),
+        TransferDatabase::db_name(),
+        TransferHashesDatabase::db_name(),
+    ]
+}
+
+/// An operation generic over the type of a storage database, for when the
+/// database is only known by its name. See [`visit_database`].
+pub trait DatabaseVisitor {
+    type Output;
+
+    fn visit<D: Database>(self) -> Self::Output;
+}
+
+/// Calls `visitor` with the type of the storage database named `db_name`.
+/// Returns `None` if there is no such database.
+pub fn visit_database<V: DatabaseVisitor>(db_name: &str, visitor: V) -> Option<V::Output> {
+    let output = match db_name {
+        "block_body" => visitor.visit::<BlockBodyDatabase>(),
+        "block_body_merkle" => visitor.visit::<BlockBodyMerkleDatabase>(),
+        "block_header" => visitor.visit::<BlockHeaderDatabase>(),
+        "block_metadata" => visitor.visit::<BlockMetadataDatabase>(),
+        "deploy_hashes" => visitor.visit::<DeployHashesDatabase>(),
+        "deploy_metadata" => visitor.visit::<DeployMetadataDatabase>(),
+        "deploys" => visitor.visit::<DeployDatabase>(),
+        "finalized_approvals" => visitor.visit::<FinalizedApprovalsDatabase>(),
+        "proposers" => visitor.visit::<ProposerDatabase>(),
+        "state_store" => visitor.visit::<StateStoreDatabase>(),
+        "transfer" => visitor.visit::<TransferDatabase>(),
+        "transfer_hashes" => visitor.visit::<TransferHashesDatabase>(),
+        _ => return None,
+    };
+    Some(output)
+}
+
 pub fn db_env<P: AsRef<Path>>(path: P) -> Result<Environment, LmdbError> {
     let env = Environment::new()
         .set_flags(
@@ -94,30 +178,83 @@ pub fn db_env<P: AsRef<Path>>(path: P) -> Result<Environment, LmdbError> {
 }
 
 pub trait Database {
+    type Element: Serialize;
+
     fn db_name() -> &'static str;
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError>;
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError>;
+
+    /// Checks the decoded element against its key, e.g. by recomputing its
+    /// hash. Entries which only need to deserialize keep the default.
+    fn validate_element(_key: &[u8], _element: &Self::Element) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    /// Parses and validates a single entry, recording any failure in
+    /// `report`.
+    fn check_element(
+        idx: usize,
+        raw_key: &[u8],
+        raw_val: &[u8],
+        report: &mut DatabaseReport,
+    ) -> Result<(), Error> {
+        let element = Self::parse_element(raw_val).map_err(|parsing_err| {
+            report.record_failure(idx, raw_key, &parsing_err);
+            Error::Parsing(idx, parsing_err)
+        })?;
+        Self::validate_element(raw_key, &element).map_err(|validation_err| {
+            report.record_validation_failure(idx, raw_key, &validation_err);
+            Error::Validation(idx, validation_err)
+        })
+    }
 
-    fn parse_elements(mut cursor: RoCursor, failfast: bool, start_at: usize) -> Result<(), Error> {
+    fn parse_elements<'a, I: Iterator<Item = (&'a [u8], &'a [u8])>>(
+        entries: I,
+        failfast: bool,
+        start_at: usize,
+        report: &mut DatabaseReport,
+        mut maybe_checkpoint: Option<&mut Checkpoint>,
+    ) -> Result<(), Error> {
         if start_at > 0 {
             info!("Skipping {} entries.", start_at);
         }
         let mut error_buffer = vec![];
-        for (idx, (_raw_key, raw_val)) in cursor.iter().skip(start_at).enumerate() {
-            if let Err(e) =
-                Self::parse_element(raw_val).map_err(|parsing_err| Error::Parsing(idx, parsing_err))
-            {
+        // The key the checkpoint can move to. It stays before the first
+        // failing entry so a resumed check goes over the failures again.
+        let mut last_key = None;
+        for (idx, (raw_key, raw_val)) in entries.enumerate().skip(start_at) {
+            report.entries_scanned += 1;
+            if let Err(e) = Self::check_element(idx, raw_key, raw_val, report) {
                 if failfast {
+                    if let (Some(checkpoint), Some(last_key)) =
+                        (maybe_checkpoint.as_mut(), last_key)
+                    {
+                        checkpoint.update(Self::db_name(), last_key, false)?;
+                    }
                     return Err(e);
                 } else {
                     error_buffer.push(e);
                 }
             }
+            if error_buffer.is_empty() {
+                last_key = Some(raw_key);
+            }
             if idx % ENTRY_LOG_INTERVAL == 0 {
                 info!("Parsed {} entries...", idx);
+                if let (Some(checkpoint), Some(last_key)) = (maybe_checkpoint.as_mut(), last_key) {
+                    checkpoint.update(Self::db_name(), last_key, false)?;
+                }
             }
         }
         info!("Parsing complete.");
+        if let Some(checkpoint) = maybe_checkpoint {
+            let complete = error_buffer.is_empty();
+            match last_key {
+                Some(last_key) => checkpoint.update(Self::db_name(), last_key, complete)?,
+                None if complete => checkpoint.mark_complete(Self::db_name())?,
+                None => (),
+            }
+        }
         if !failfast && !error_buffer.is_empty() {
             return Err(Error::Accumulated(error_buffer));
         }
@@ -125,12 +262,47 @@ pub trait Database {
     }
 
     fn check_db(env: &Environment, failfast: bool, start_at: usize) -> Result<(), Error> {
+        let mut report = DatabaseReport::new(Self::db_name());
+        Self::check_db_with_report(env, failfast, start_at, &mut report, None)
+    }
+
+    /// Checks the entries of the database, recording them in `report`. If a
+    /// checkpoint is given, the check continues after the last key recorded
+    /// in it for this database and the checkpoint is updated as entries are
+    /// parsed.
+    fn check_db_with_report(
+        env: &Environment,
+        failfast: bool,
+        start_at: usize,
+        report: &mut DatabaseReport,
+        maybe_checkpoint: Option<&mut Checkpoint>,
+    ) -> Result<(), Error> {
         info!("Checking {} database.", Self::db_name());
         let txn = env.begin_ro_txn()?;
         let db = unsafe { txn.open_db(Some(Self::db_name()))? };
+        let maybe_resume_key = match maybe_checkpoint.as_ref() {
+            Some(checkpoint) => checkpoint.resume_key(Self::db_name())?,
+            None => None,
+        };
 
-        if let Ok(cursor) = txn.open_ro_cursor(db) {
-            Self::parse_elements(cursor, failfast, start_at)?;
+        if let Ok(mut cursor) = txn.open_ro_cursor(db) {
+            match maybe_resume_key {
+                Some(resume_key) => {
+                    info!("Resuming after key {}.", hex::encode(&resume_key));
+                    report.resumed_after = Some(hex::encode(&resume_key));
+                    let entries = cursor
+                        .iter_from(&resume_key)
+                        .skip_while(|(raw_key, _)| *raw_key == resume_key.as_slice());
+                    Self::parse_elements(entries, failfast, start_at, report, maybe_checkpoint)?;
+                }
+                None => Self::parse_elements(
+                    cursor.iter_start(),
+                    failfast,
+                    start_at,
+                    report,
+                    maybe_checkpoint,
+                )?,
+            }
         }
         Ok(())
     }
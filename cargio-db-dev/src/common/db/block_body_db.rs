@@ -5,7 +5,7 @@ use std::{
 
 use master_node::types::BlockBody;
 
-use super::{Database, DeserializationError};
+use super::{check_key, Database, DeserializationError, ValidationError};
 
 pub struct BlockBodyDatabase;
 
@@ -16,12 +16,17 @@ impl Display for BlockBodyDatabase {
 }
 
 impl Database for BlockBodyDatabase {
+    type Element = BlockBody;
+
     fn db_name() -> &'static str {
         "block_body"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        let _: BlockBody = bincode::deserialize(bytes)?;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    fn validate_element(key: &[u8], body: &Self::Element) -> Result<(), ValidationError> {
+        check_key(key, body.hash().as_ref())
     }
 }
@@ -6,7 +6,7 @@ use std::{
 use cargio_hashing::Digest;
 use cargio_types::bytesrepr::FromBytes;
 
-use super::{Database, DeserializationError};
+use super::{check_key, Database, DeserializationError, ValidationError};
 
 pub struct BlockBodyMerkleDatabase;
 
@@ -17,12 +17,20 @@ impl Display for BlockBodyMerkleDatabase {
 }
 
 impl Database for BlockBodyMerkleDatabase {
+    type Element = (Digest, Digest);
+
     fn db_name() -> &'static str {
         "block_body_merkle"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        let _: (Digest, Digest) = FromBytes::from_bytes(bytes)?.0;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        let (element, _) = FromBytes::from_bytes(bytes)?;
+        Ok(element)
+    }
+
+    /// Entries are the links of the merkle tree of block bodies, keyed by the
+    /// hash of the digest pair they hold.
+    fn validate_element(key: &[u8], (left, right): &Self::Element) -> Result<(), ValidationError> {
+        check_key(key, Digest::hash_pair(left, right).as_ref())
     }
 }
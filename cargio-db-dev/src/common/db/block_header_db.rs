@@ -5,7 +5,7 @@ use std::{
 
 use master_node::types::BlockHeader;
 
-use super::{Database, DeserializationError};
+use super::{check_key, Database, DeserializationError, ValidationError};
 
 pub struct BlockHeaderDatabase;
 
@@ -16,12 +16,17 @@ impl Display for BlockHeaderDatabase {
 }
 
 impl Database for BlockHeaderDatabase {
+    type Element = BlockHeader;
+
     fn db_name() -> &'static str {
         "block_header"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        let _: BlockHeader = bincode::deserialize(bytes)?;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    fn validate_element(key: &[u8], header: &Self::Element) -> Result<(), ValidationError> {
+        check_key(key, header.hash().as_ref())
     }
 }
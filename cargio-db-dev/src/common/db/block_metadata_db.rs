@@ -16,12 +16,13 @@ impl Display for BlockMetadataDatabase {
 }
 
 impl Database for BlockMetadataDatabase {
+    type Element = BlockSignatures;
+
     fn db_name() -> &'static str {
         "block_metadata"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        let _: BlockSignatures = bincode::deserialize(bytes)?;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        Ok(bincode::deserialize(bytes)?)
     }
 }
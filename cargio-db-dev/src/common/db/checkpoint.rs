@@ -0,0 +1,112 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{Error as IoError, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use hex::FromHexError;
+use serde::{Deserialize, Serialize};
+use serde_json::Error as JsonError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    #[error("Error accessing checkpoint file {0}: {1}")]
+    Io(PathBuf, IoError),
+    #[error("Error (de)serializing checkpoint file {0}: {1}")]
+    Json(PathBuf, JsonError),
+    #[error("Invalid key {0} in checkpoint for database {1}: {2}")]
+    InvalidKey(String, String, FromHexError),
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DatabaseCheckpoint {
+    pub last_key: Option<String>,
+    pub complete: bool,
+}
+
+/// Records the last checked key of every database so an interrupted check
+/// can continue where it left off.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    #[serde(skip)]
+    path: PathBuf,
+    databases: BTreeMap<String, DatabaseCheckpoint>,
+}
+
+impl Checkpoint {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            databases: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the checkpoint at `path`, starting with an empty one if the file
+    /// doesn't exist yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CheckpointError> {
+        let path = path.as_ref().to_path_buf();
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(io_err) if io_err.kind() == ErrorKind::NotFound => return Ok(Self::new(path)),
+            Err(io_err) => return Err(CheckpointError::Io(path, io_err)),
+        };
+        let mut checkpoint: Self = serde_json::from_reader(file)
+            .map_err(|json_err| CheckpointError::Json(path.clone(), json_err))?;
+        checkpoint.path = path;
+        Ok(checkpoint)
+    }
+
+    pub fn save(&self) -> Result<(), CheckpointError> {
+        let tmp_path = self.path.with_extension("tmp");
+        let file = File::create(&tmp_path)
+            .map_err(|io_err| CheckpointError::Io(tmp_path.clone(), io_err))?;
+        serde_json::to_writer_pretty(&file, self)
+            .map_err(|json_err| CheckpointError::Json(tmp_path.clone(), json_err))?;
+        file.sync_all()
+            .map_err(|io_err| CheckpointError::Io(tmp_path.clone(), io_err))?;
+        fs::rename(&tmp_path, &self.path)
+            .map_err(|io_err| CheckpointError::Io(self.path.clone(), io_err))
+    }
+
+    pub fn is_complete(&self, db_name: &str) -> bool {
+        self.databases
+            .get(db_name)
+            .map(|db_checkpoint| db_checkpoint.complete)
+            .unwrap_or(false)
+    }
+
+    pub fn resume_key(&self, db_name: &str) -> Result<Option<Vec<u8>>, CheckpointError> {
+        match self
+            .databases
+            .get(db_name)
+            .and_then(|db_checkpoint| db_checkpoint.last_key.as_ref())
+        {
+            Some(hex_key) => hex::decode(hex_key).map(Some).map_err(|hex_err| {
+                CheckpointError::InvalidKey(hex_key.clone(), db_name.to_string(), hex_err)
+            }),
+            None => Ok(None),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        db_name: &str,
+        last_key: &[u8],
+        complete: bool,
+    ) -> Result<(), CheckpointError> {
+        let db_checkpoint = self.databases.entry(db_name.to_string()).or_default();
+        db_checkpoint.last_key = Some(hex::encode(last_key));
+        db_checkpoint.complete = complete;
+        self.save()
+    }
+
+    pub fn mark_complete(&mut self, db_name: &str) -> Result<(), CheckpointError> {
+        self.databases
+            .entry(db_name.to_string())
+            .or_default()
+            .complete = true;
+        self.save()
+    }
+}
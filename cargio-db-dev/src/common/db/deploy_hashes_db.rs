@@ -16,12 +16,14 @@ impl Display for DeployHashesDatabase {
 }
 
 impl Database for DeployHashesDatabase {
+    type Element = Vec<DeployHash>;
+
     fn db_name() -> &'static str {
         "deploy_hashes"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        let _: Vec<DeployHash> = FromBytes::from_bytes(bytes)?.0;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        let (element, _) = FromBytes::from_bytes(bytes)?;
+        Ok(element)
     }
 }
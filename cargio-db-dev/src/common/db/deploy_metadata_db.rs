@@ -15,12 +15,13 @@ impl Display for DeployMetadataDatabase {
 }
 
 impl Database for DeployMetadataDatabase {
+    type Element = DeployMetadata;
+
     fn db_name() -> &'static str {
         "deploy_metadata"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        let _: DeployMetadata = bincode::deserialize(bytes)?;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        Ok(bincode::deserialize(bytes)?)
     }
 }
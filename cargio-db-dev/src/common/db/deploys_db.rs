@@ -5,7 +5,7 @@ use std::{
 
 use master_node::types::Deploy;
 
-use super::{Database, DeserializationError};
+use super::{check_key, Database, DeserializationError, ValidationError};
 
 pub struct DeployDatabase;
 
@@ -16,12 +16,22 @@ impl Display for DeployDatabase {
 }
 
 impl Database for DeployDatabase {
+    type Element = Deploy;
+
     fn db_name() -> &'static str {
         "deploys"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        let _: Deploy = bincode::deserialize(bytes)?;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    fn validate_element(key: &[u8], deploy: &Self::Element) -> Result<(), ValidationError> {
+        check_key(key, deploy.id().as_ref())?;
+        // Recomputes the body and deploy hashes and verifies the approvals.
+        deploy
+            .clone()
+            .is_valid()
+            .map_err(|deploy_err| ValidationError::InvalidValue(deploy_err.to_string()))
     }
 }
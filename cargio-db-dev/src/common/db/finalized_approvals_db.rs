@@ -16,12 +16,13 @@ impl Display for FinalizedApprovalsDatabase {
 }
 
 impl Database for FinalizedApprovalsDatabase {
+    type Element = FinalizedApprovals;
+
     fn db_name() -> &'static str {
         "finalized_approvals"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        let _: FinalizedApprovals = bincode::deserialize(bytes)?;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        Ok(bincode::deserialize(bytes)?)
     }
 }
@@ -16,12 +16,14 @@ impl Display for ProposerDatabase {
 }
 
 impl Database for ProposerDatabase {
+    type Element = PublicKey;
+
     fn db_name() -> &'static str {
         "proposers"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        let _: PublicKey = FromBytes::from_bytes(bytes)?.0;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        let (element, _) = FromBytes::from_bytes(bytes)?;
+        Ok(element)
     }
 }
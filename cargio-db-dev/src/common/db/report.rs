@@ -0,0 +1,122 @@
+use serde::Serialize;
+
+use super::{DeserializationError, ValidationError};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryFailureKind {
+    Bincode,
+    Bytesrepr,
+    Validation,
+}
+
+impl From<&DeserializationError> for EntryFailureKind {
+    fn from(error: &DeserializationError) -> Self {
+        match error {
+            DeserializationError::BincodeError(_) => Self::Bincode,
+            DeserializationError::BytesreprError(_) => Self::Bytesrepr,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct EntryFailure {
+    pub index: usize,
+    /// Set when an earlier key range of the database wasn't fully checked,
+    /// in which case `index` is relative to the first entry at or after
+    /// this key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range_start: Option<String>,
+    pub key: String,
+    pub error_kind: EntryFailureKind,
+    pub error: String,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct DatabaseReport {
+    pub db_name: String,
+    /// Set when the check resumed from a checkpoint, in which case the
+    /// entry indices are relative to the first entry after this key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resumed_after: Option<String>,
+    pub entries_scanned: usize,
+    pub failures: Vec<EntryFailure>,
+}
+
+impl DatabaseReport {
+    pub fn new(db_name: &str) -> Self {
+        Self {
+            db_name: db_name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn record_failure(&mut self, index: usize, raw_key: &[u8], error: &DeserializationError) {
+        self.failures.push(EntryFailure {
+            index,
+            range_start: None,
+            key: hex::encode(raw_key),
+            error_kind: error.into(),
+            error: error.detailed_message(),
+        });
+    }
+
+    pub fn record_validation_failure(
+        &mut self,
+        index: usize,
+        raw_key: &[u8],
+        error: &ValidationError,
+    ) {
+        self.failures.push(EntryFailure {
+            index,
+            range_start: None,
+            key: hex::encode(raw_key),
+            error_kind: EntryFailureKind::Validation,
+            error: error.to_string(),
+        });
+    }
+
+    /// Appends the report of a key range following the ranges already
+    /// covered by this report.
+    ///
+    /// If all the previous ranges were fully checked, `maybe_range_start`
+    /// should be `None` and the failing entry indices are adjusted to be
+    /// relative to the start of the database. Otherwise the number of
+    /// entries before the range is unknown, so the indices are kept relative
+    /// to the start key of the range, which is recorded in the failures.
+    pub fn append(&mut self, range_report: DatabaseReport, maybe_range_start: Option<&[u8]>) {
+        let offset = self.entries_scanned;
+        self.entries_scanned += range_report.entries_scanned;
+        self.failures
+            .extend(range_report.failures.into_iter().map(|mut failure| {
+                match maybe_range_start {
+                    Some(range_start) => failure.range_start = Some(hex::encode(range_start)),
+                    None => failure.index += offset,
+                }
+                failure
+            }));
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct CheckReport {
+    pub passed: bool,
+    pub entries_scanned: usize,
+    pub failure_count: usize,
+    pub databases: Vec<DatabaseReport>,
+}
+
+impl CheckReport {
+    pub fn new(databases: Vec<DatabaseReport>, passed: bool) -> Self {
+        Self {
+            passed: passed && databases.iter().all(DatabaseReport::passed),
+            entries_scanned: databases.iter().map(|db| db.entries_scanned).sum(),
+            failure_count: databases.iter().map(|db| db.failures.len()).sum(),
+            databases,
+        }
+    }
+}
@@ -16,12 +16,14 @@ impl Display for StateStoreDatabase {
 }
 
 impl Database for StateStoreDatabase {
+    type Element = u64;
+
     fn db_name() -> &'static str {
         "state_store"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        let _: u64 = FromBytes::from_bytes(bytes)?.0;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        let (element, _) = FromBytes::from_bytes(bytes)?;
+        Ok(element)
     }
 }
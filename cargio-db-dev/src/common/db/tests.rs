@@ -1,9 +1,14 @@
+use cargio_hashing::Digest;
+use cargio_types::bytesrepr::ToBytes;
 use lmdb::{Database as LmdbDatabase, Environment, Transaction, WriteFlags};
 use rand::{self, prelude::ThreadRng, Rng, RngCore};
 use serde::{Deserialize, Serialize};
 
-use super::{Database, DeserializationError};
-use crate::test_utils::LmdbTestFixture;
+use super::{
+    check_key, storage_database_names, visit_database, BlockBodyMerkleDatabase, Database,
+    DatabaseReport, DatabaseVisitor, DeserializationError, EntryFailureKind, ValidationError,
+};
+use crate::{common::lmdb_utils, test_utils::LmdbTestFixture};
 
 fn gen_bytes(rng: &mut ThreadRng) -> Vec<u8> {
     let mock = MockStruct::random(rng);
@@ -114,13 +119,33 @@ impl FaultyMockStruct {
 struct MockDb {}
 
 impl Database for MockDb {
+    type Element = MockStruct;
+
     fn db_name() -> &'static str {
         "test_db"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        bincode::deserialize::<MockStruct>(bytes)?;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+// Same as `MockDb`, but entries must be keyed by their `a` field.
+struct MockKeyedDb {}
+
+impl Database for MockKeyedDb {
+    type Element = MockStruct;
+
+    fn db_name() -> &'static str {
+        "test_keyed_db"
+    }
+
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    fn validate_element(key: &[u8], element: &Self::Element) -> Result<(), ValidationError> {
+        check_key(key, &element.a.to_le_bytes())
     }
 }
 
@@ -160,3 +185,139 @@ fn bad_db_should_fail_check() {
     assert!(MockDb::check_db(&fixture.env, true, 4).is_err());
     assert!(MockDb::check_db(&fixture.env, false, 4).is_err());
 }
+
+#[test]
+fn check_report_should_record_failures() {
+    let fixture = LmdbTestFixture::new(vec![MockDb::db_name()], None);
+    populate_faulty_db(&fixture.env, fixture.db(Some(MockDb::db_name())).unwrap());
+    let entry_count = {
+        let txn = fixture.env.begin_ro_txn().unwrap();
+        let count =
+            lmdb_utils::entry_count(&txn, *fixture.db(Some(MockDb::db_name())).unwrap()).unwrap();
+        txn.commit().unwrap();
+        count
+    };
+
+    let mut report = DatabaseReport::new(MockDb::db_name());
+    assert!(MockDb::check_db_with_report(&fixture.env, false, 0, &mut report, None).is_err());
+    assert_eq!(report.db_name, MockDb::db_name());
+    assert_eq!(report.entries_scanned, entry_count);
+    assert_eq!(report.failures.len(), (entry_count + 4) / 5);
+    for failure in report.failures.iter() {
+        assert_eq!(failure.index % 5, 0);
+        assert_eq!(
+            failure.key,
+            hex::encode((failure.index as u32).to_le_bytes())
+        );
+        assert_eq!(failure.error_kind, EntryFailureKind::Bincode);
+    }
+
+    let mut report = DatabaseReport::new(MockDb::db_name());
+    assert!(MockDb::check_db_with_report(&fixture.env, true, 1, &mut report, None).is_err());
+    assert_eq!(report.entries_scanned, 5);
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].index, 5);
+}
+
+#[test]
+fn mis_keyed_entries_should_fail_validation() {
+    const ENTRY_COUNT: u32 = 10;
+    const MIS_KEYED_ENTRY: u32 = 3;
+
+    let fixture = LmdbTestFixture::new(vec![MockKeyedDb::db_name()], None);
+    let mut rng = rand::thread_rng();
+    {
+        let db = *fixture.db(Some(MockKeyedDb::db_name())).unwrap();
+        let mut txn = fixture.env.begin_rw_txn().unwrap();
+        for i in 0..ENTRY_COUNT {
+            let mut mock = MockStruct::random(&mut rng);
+            mock.a = if i == MIS_KEYED_ENTRY { i + 1 } else { i };
+            let bytes = bincode::serialize(&mock).unwrap();
+            txn.put(db, &i.to_le_bytes(), &bytes, WriteFlags::empty())
+                .unwrap();
+        }
+        txn.commit().unwrap();
+    }
+
+    let mut report = DatabaseReport::new(MockKeyedDb::db_name());
+    assert!(MockKeyedDb::check_db_with_report(&fixture.env, false, 0, &mut report, None).is_err());
+    assert_eq!(report.entries_scanned, ENTRY_COUNT as usize);
+    // The entry deserializes fine, only the validation catches it.
+    assert_eq!(report.failures.len(), 1);
+    let failure = &report.failures[0];
+    assert_eq!(failure.index, MIS_KEYED_ENTRY as usize);
+    assert_eq!(failure.key, hex::encode(MIS_KEYED_ENTRY.to_le_bytes()));
+    assert_eq!(failure.error_kind, EntryFailureKind::Validation);
+}
+
+#[test]
+fn block_body_merkle_entries_should_be_keyed_by_digest_pair_hash() {
+    let left = Digest::hash([1u8; 8]);
+    let right = Digest::hash([2u8; 8]);
+    let element = (left, right);
+
+    assert!(BlockBodyMerkleDatabase::validate_element(
+        Digest::hash_pair(left, right).as_ref(),
+        &element
+    )
+    .is_ok());
+    assert!(BlockBodyMerkleDatabase::validate_element(
+        Digest::hash_pair(right, left).as_ref(),
+        &element
+    )
+    .is_err());
+
+    let bytes = element.to_bytes().unwrap();
+    assert_eq!(
+        BlockBodyMerkleDatabase::parse_element(&bytes).unwrap(),
+        element
+    );
+}
+
+#[test]
+fn appended_range_reports_should_keep_indices_relative_to_unknown_offsets() {
+    let range_report = |entries_scanned, failing_index: u32| {
+        let mut report = DatabaseReport::new(MockDb::db_name());
+        report.entries_scanned = entries_scanned;
+        report.record_failure(
+            failing_index as usize,
+            &failing_index.to_le_bytes(),
+            &DeserializationError::BincodeError(bincode::ErrorKind::SizeLimit.into()),
+        );
+        report
+    };
+
+    let mut report = DatabaseReport::new(MockDb::db_name());
+    // The first range is complete, so the second one starts right after it.
+    report.append(range_report(10, 2), None);
+    report.append(range_report(4, 3), None);
+    // The second range was aborted after 4 entries, so the indices of the
+    // third one can only be relative to its start key.
+    report.append(range_report(5, 1), Some(&[0x80]));
+
+    assert_eq!(report.entries_scanned, 19);
+    let positions: Vec<(usize, Option<&str>)> = report
+        .failures
+        .iter()
+        .map(|failure| (failure.index, failure.range_start.as_deref()))
+        .collect();
+    assert_eq!(positions, vec![(2, None), (13, None), (1, Some("80"))]);
+}
+
+#[test]
+fn every_storage_database_should_be_visitable_by_name() {
+    struct NameVisitor;
+
+    impl DatabaseVisitor for NameVisitor {
+        type Output = &'static str;
+
+        fn visit<D: Database>(self) -> Self::Output {
+            D::db_name()
+        }
+    }
+
+    for db_name in storage_database_names() {
+        assert_eq!(visit_database(db_name, NameVisitor), Some(db_name));
+    }
+    assert_eq!(visit_database("unknown", NameVisitor), None);
+}
@@ -16,12 +16,13 @@ impl Display for TransferDatabase {
 }
 
 impl Database for TransferDatabase {
+    type Element = Vec<Transfer>;
+
     fn db_name() -> &'static str {
         "transfer"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        let _: Vec<Transfer> = bincode::deserialize(bytes)?;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        Ok(bincode::deserialize(bytes)?)
     }
 }
@@ -16,12 +16,14 @@ impl Display for TransferHashesDatabase {
 }
 
 impl Database for TransferHashesDatabase {
+    type Element = Vec<DeployHash>;
+
     fn db_name() -> &'static str {
         "transfer_hashes"
     }
 
-    fn parse_element(bytes: &[u8]) -> Result<(), DeserializationError> {
-        let _: Vec<DeployHash> = FromBytes::from_bytes(bytes)?.0;
-        Ok(())
+    fn parse_element(bytes: &[u8]) -> Result<Self::Element, DeserializationError> {
+        let (element, _) = FromBytes::from_bytes(bytes)?;
+        Ok(element)
     }
 }
@@ -0,0 +1,384 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::HashMap,
+    env,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Error as IoError, Write},
+    path::{Path, PathBuf},
+    result::Result,
+};
+
+use bincode::Error as BincodeError;
+use lmdb::{
+    Database, Environment, Error as LmdbError, RoTransaction, RwTransaction, Transaction,
+    WriteFlags,
+};
+use lmdb_sys::{MDB_dbi, MDB_txn};
+use log::info;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::db;
+
+const JOURNAL_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum JournalError {
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Error accessing journal file {0}: {1}")]
+    Io(PathBuf, IoError),
+    #[error("Error (de)serializing journal file {0}: {1}")]
+    Serialization(PathBuf, BincodeError),
+    #[error("Database with handle {0} wasn't opened through the journaled transaction")]
+    UnknownDatabase(MDB_dbi),
+    #[error("Unsupported journal version {0}")]
+    UnsupportedVersion(u32),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalHeader {
+    version: u32,
+    db_file: PathBuf,
+}
+
+/// The state of an entry before it was changed.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// `None` for the unnamed database of the environment.
+    pub db_name: Option<String>,
+    pub key: Vec<u8>,
+    /// `None` if the entry didn't exist before the change.
+    pub prior_value: Option<Vec<u8>>,
+}
+
+/// An undo file recording the prior state of every entry changed in an
+/// LMDB file, in the order of the changes.
+pub struct Journal {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl Journal {
+    /// Creates a new journal at `path` for changes to the LMDB file at
+    /// `db_file`. Fails if there is a file at `path` already.
+    pub fn create<P1: AsRef<Path>, P2: AsRef<Path>>(
+        path: P1,
+        db_file: P2,
+    ) -> Result<Self, JournalError> {
+        let path = path.as_ref().to_path_buf();
+        // The LMDB file might not exist yet, so it can't be canonicalized.
+        let db_file = if db_file.as_ref().is_absolute() {
+            db_file.as_ref().to_path_buf()
+        } else {
+            env::current_dir()
+                .map_err(|io_err| JournalError::Io(db_file.as_ref().to_path_buf(), io_err))?
+                .join(db_file)
+        };
+        let file = OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&path)
+            .map_err(|io_err| JournalError::Io(path.clone(), io_err))?;
+        let mut journal = Self {
+            path,
+            writer: BufWriter::new(file),
+        };
+        let header = JournalHeader {
+            version: JOURNAL_VERSION,
+            db_file,
+        };
+        bincode::serialize_into(&mut journal.writer, &header).map_err(|bincode_err| {
+            JournalError::Serialization(journal.path.clone(), bincode_err)
+        })?;
+        journal.sync()?;
+        Ok(journal)
+    }
+
+    pub fn record(
+        &mut self,
+        db_name: Option<&str>,
+        key: &[u8],
+        prior_value: Option<&[u8]>,
+    ) -> Result<(), JournalError> {
+        let entry = JournalEntry {
+            db_name: db_name.map(str::to_string),
+            key: key.to_vec(),
+            prior_value: prior_value.map(<[u8]>::to_vec),
+        };
+        bincode::serialize_into(&mut self.writer, &entry)
+            .map_err(|bincode_err| JournalError::Serialization(self.path.clone(), bincode_err))
+    }
+
+    /// Writes the recorded entries to disk. Must be called before the
+    /// changes they precede are committed.
+    pub fn sync(&mut self) -> Result<(), JournalError> {
+        self.writer
+            .flush()
+            .and_then(|_| self.writer.get_ref().sync_all())
+            .map_err(|io_err| JournalError::Io(self.path.clone(), io_err))
+    }
+}
+
+fn open_journal(path: &Path) -> Result<(JournalHeader, BufReader<File>), JournalError> {
+    let file = File::open(path).map_err(|io_err| JournalError::Io(path.to_path_buf(), io_err))?;
+    let mut reader = BufReader::new(file);
+    let header: JournalHeader = bincode::deserialize_from(&mut reader)
+        .map_err(|bincode_err| JournalError::Serialization(path.to_path_buf(), bincode_err))?;
+    if header.version != JOURNAL_VERSION {
+        return Err(JournalError::UnsupportedVersion(header.version));
+    }
+    Ok((header, reader))
+}
+
+/// Returns the LMDB file the journal at `path` applies to, without reading
+/// its entries.
+pub fn journaled_db_file<P: AsRef<Path>>(path: P) -> Result<PathBuf, JournalError> {
+    open_journal(path.as_ref()).map(|(header, _)| header.db_file)
+}
+
+/// Reads the journal at `path`, returning the LMDB file it applies to and
+/// its entries in the order they were recorded.
+pub fn read_journal<P: AsRef<Path>>(path: P) -> Result<(PathBuf, Vec<JournalEntry>), JournalError> {
+    let path = path.as_ref().to_path_buf();
+    let (header, mut reader) = open_journal(&path)?;
+    let mut entries = vec![];
+    while !reader
+        .fill_buf()
+        .map_err(|io_err| JournalError::Io(path.clone(), io_err))?
+        .is_empty()
+    {
+        entries.push(
+            bincode::deserialize_from(&mut reader)
+                .map_err(|bincode_err| JournalError::Serialization(path.clone(), bincode_err))?,
+        );
+    }
+    Ok((header.db_file, entries))
+}
+
+/// Restores the prior state of all entries in the journal at `path`, in
+/// reverse order, in a single transaction. Returns the number of restored
+/// entries.
+pub fn undo<P: AsRef<Path>>(path: P) -> Result<usize, JournalError> {
+    let (db_file, entries) = read_journal(path)?;
+    let env = db::db_env(&db_file)?;
+    let mut txn = env.begin_rw_txn()?;
+    let mut dbs: HashMap<Option<String>, Database> = HashMap::new();
+    for entry in entries.iter().rev() {
+        let db = match dbs.get(&entry.db_name) {
+            Some(db) => *db,
+            None => {
+                let db = unsafe { txn.open_db(entry.db_name.as_deref())? };
+                dbs.insert(entry.db_name.clone(), db);
+                db
+            }
+        };
+        match entry.prior_value.as_ref() {
+            Some(prior_value) => txn.put(db, &entry.key, prior_value, WriteFlags::empty())?,
+            None => match txn.del(db, &entry.key, None) {
+                Ok(()) | Err(LmdbError::NotFound) => {}
+                Err(lmdb_err) => return Err(lmdb_err.into()),
+            },
+        }
+    }
+    txn.commit()?;
+    info!(
+        "Restored {} entries in {}.",
+        entries.len(),
+        db_file.display()
+    );
+    Ok(entries.len())
+}
+
+/// A change a dry run would have made. A `new_value` of `None` is a
+/// deletion.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PlannedChange {
+    pub db_name: Option<String>,
+    pub key: Vec<u8>,
+    pub new_value: Option<Vec<u8>>,
+}
+
+pub fn log_planned_changes(planned_changes: &[PlannedChange]) {
+    for change in planned_changes {
+        let db_name = change.db_name.as_deref().unwrap_or("default");
+        match change.new_value {
+            Some(_) => info!(
+                "Would write entry {} in {db_name}.",
+                hex::encode(&change.key)
+            ),
+            None => info!(
+                "Would delete entry {} from {db_name}.",
+                hex::encode(&change.key)
+            ),
+        }
+    }
+    info!("{} entries would be changed.", planned_changes.len());
+}
+
+/// The transaction under a `JournaledTransaction`, read-only for dry runs.
+pub enum InnerTransaction<'env> {
+    ReadOnly(RoTransaction<'env>),
+    ReadWrite(RwTransaction<'env>),
+}
+
+impl Transaction for InnerTransaction<'_> {
+    fn txn(&self) -> *mut MDB_txn {
+        match self {
+            InnerTransaction::ReadOnly(txn) => txn.txn(),
+            InnerTransaction::ReadWrite(txn) => txn.txn(),
+        }
+    }
+}
+
+/// A write transaction recording the prior state of every entry it changes
+/// in a journal before changing it.
+///
+/// In a dry run, the transaction is read-only and the changes are kept in
+/// memory instead, so reads through `get` see them and the caller gets the
+/// list of planned changes on commit.
+pub struct JournaledTransaction<'env, 'j> {
+    txn: InnerTransaction<'env>,
+    maybe_journal: Option<&'j mut Journal>,
+    db_names: HashMap<MDB_dbi, Option<String>>,
+    overlay: HashMap<(MDB_dbi, Vec<u8>), Option<Vec<u8>>>,
+    planned_changes: Vec<PlannedChange>,
+}
+
+impl<'env, 'j> JournaledTransaction<'env, 'j> {
+    pub fn begin(
+        env: &'env Environment,
+        maybe_journal: Option<&'j mut Journal>,
+        dry_run: bool,
+    ) -> Result<Self, JournalError> {
+        let txn = if dry_run {
+            InnerTransaction::ReadOnly(env.begin_ro_txn()?)
+        } else {
+            InnerTransaction::ReadWrite(env.begin_rw_txn()?)
+        };
+        Ok(Self {
+            txn,
+            maybe_journal,
+            db_names: HashMap::new(),
+            overlay: HashMap::new(),
+            planned_changes: vec![],
+        })
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        matches!(self.txn, InnerTransaction::ReadOnly(_))
+    }
+
+    /// Opens a database which can then be changed through this transaction.
+    ///
+    /// # Safety
+    ///
+    /// Same as `lmdb::Transaction::open_db`.
+    pub unsafe fn open_db(&mut self, name: Option<&str>) -> Result<Database, LmdbError> {
+        let db = self.txn.open_db(name)?;
+        self.db_names.insert(db.dbi(), name.map(str::to_string));
+        Ok(db)
+    }
+
+    /// The underlying transaction, for cursors and helpers generic over
+    /// `Transaction`. Reads through it don't see the changes planned by a
+    /// dry run.
+    pub fn reader(&self) -> &InnerTransaction<'env> {
+        &self.txn
+    }
+
+    pub fn get<K: AsRef<[u8]>>(&self, db: Database, key: &K) -> Result<&[u8], LmdbError> {
+        if !self.overlay.is_empty() {
+            if let Some(planned_value) = self.overlay.get(&(db.dbi(), key.as_ref().to_vec())) {
+                return planned_value.as_deref().ok_or(LmdbError::NotFound);
+            }
+        }
+        self.txn.get(db, key)
+    }
+
+    pub fn put<K: AsRef<[u8]>, D: AsRef<[u8]>>(
+        &mut self,
+        db: Database,
+        key: &K,
+        data: &D,
+        flags: WriteFlags,
+    ) -> Result<(), JournalError> {
+        let prior_value = self.prior_value(db, key.as_ref())?;
+        if flags.contains(WriteFlags::NO_OVERWRITE) && prior_value.is_some() {
+            return Err(LmdbError::KeyExist.into());
+        }
+        self.apply(db, key.as_ref(), prior_value, Some(data.as_ref()), flags)
+    }
+
+    pub fn del<K: AsRef<[u8]>>(&mut self, db: Database, key: &K) -> Result<(), JournalError> {
+        let prior_value = self.prior_value(db, key.as_ref())?;
+        if prior_value.is_none() {
+            return Err(LmdbError::NotFound.into());
+        }
+        self.apply(db, key.as_ref(), prior_value, None, WriteFlags::empty())
+    }
+
+    /// Commits the changes, after writing the journal to disk. Returns the
+    /// changes a dry run would have made, or nothing for an actual run.
+    pub fn commit(self) -> Result<Vec<PlannedChange>, JournalError> {
+        match self.txn {
+            InnerTransaction::ReadOnly(txn) => {
+                txn.abort();
+                Ok(self.planned_changes)
+            }
+            InnerTransaction::ReadWrite(txn) => {
+                if let Some(journal) = self.maybe_journal {
+                    journal.sync()?;
+                }
+                txn.commit()?;
+                Ok(vec![])
+            }
+        }
+    }
+
+    fn prior_value(&self, db: Database, key: &[u8]) -> Result<Option<Vec<u8>>, JournalError> {
+        match self.get(db, &key) {
+            Ok(value) => Ok(Some(value.to_vec())),
+            Err(LmdbError::NotFound) => Ok(None),
+            Err(lmdb_err) => Err(lmdb_err.into()),
+        }
+    }
+
+    fn apply(
+        &mut self,
+        db: Database,
+        key: &[u8],
+        prior_value: Option<Vec<u8>>,
+        new_value: Option<&[u8]>,
+        flags: WriteFlags,
+    ) -> Result<(), JournalError> {
+        let db_name = self
+            .db_names
+            .get(&db.dbi())
+            .cloned()
+            .ok_or_else(|| JournalError::UnknownDatabase(db.dbi()))?;
+        match &mut self.txn {
+            InnerTransaction::ReadOnly(_) => {
+                self.overlay
+                    .insert((db.dbi(), key.to_vec()), new_value.map(<[u8]>::to_vec));
+                self.planned_changes.push(PlannedChange {
+                    db_name,
+                    key: key.to_vec(),
+                    new_value: new_value.map(<[u8]>::to_vec),
+                });
+            }
+            InnerTransaction::ReadWrite(txn) => {
+                if let Some(journal) = self.maybe_journal.as_mut() {
+                    journal.record(db_name.as_deref(), key, prior_value.as_deref())?;
+                }
+                match new_value {
+                    Some(value) => txn.put(db, &key, &value, flags)?,
+                    None => txn.del(db, &key, None)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
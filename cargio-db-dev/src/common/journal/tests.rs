@@ -0,0 +1,118 @@
+use lmdb::{Error as LmdbError, Transaction, WriteFlags};
+
+use super::{read_journal, undo, Journal, JournalEntry, JournalError, JournaledTransaction};
+use crate::test_utils::LmdbTestFixture;
+
+const DB_NAME: &str = "entries";
+
+fn put_entry(fixture: &LmdbTestFixture, key: &[u8], value: &[u8]) {
+    let db = fixture.db(Some(DB_NAME)).unwrap();
+    let mut txn = fixture.env.begin_rw_txn().unwrap();
+    txn.put(*db, &key, &value, WriteFlags::empty()).unwrap();
+    txn.commit().unwrap();
+}
+
+fn get_entry(fixture: &LmdbTestFixture, key: &[u8]) -> Option<Vec<u8>> {
+    let db = fixture.db(Some(DB_NAME)).unwrap();
+    let txn = fixture.env.begin_ro_txn().unwrap();
+    let maybe_value = match txn.get(*db, &key) {
+        Ok(value) => Some(value.to_vec()),
+        Err(LmdbError::NotFound) => None,
+        Err(lmdb_err) => panic!("unexpected error: {lmdb_err}"),
+    };
+    txn.commit().unwrap();
+    maybe_value
+}
+
+#[test]
+fn journal_should_round_trip_entries() {
+    let fixture = LmdbTestFixture::new(vec![DB_NAME], None);
+    let journal_path = fixture.tmp_dir.as_ref().join("undo.journal");
+    {
+        let mut journal = Journal::create(&journal_path, &fixture.file_path).unwrap();
+        journal.record(Some(DB_NAME), &[1], Some(&[2, 3])).unwrap();
+        journal.record(None, &[4], None).unwrap();
+        journal.sync().unwrap();
+    }
+    let (db_file, entries) = read_journal(&journal_path).unwrap();
+    assert_eq!(db_file, fixture.file_path);
+    assert_eq!(
+        entries,
+        vec![
+            JournalEntry {
+                db_name: Some(DB_NAME.to_string()),
+                key: vec![1],
+                prior_value: Some(vec![2, 3]),
+            },
+            JournalEntry {
+                db_name: None,
+                key: vec![4],
+                prior_value: None,
+            },
+        ]
+    );
+    // Journals are never overwritten.
+    assert!(matches!(
+        Journal::create(&journal_path, &fixture.file_path),
+        Err(JournalError::Io(..))
+    ));
+}
+
+#[test]
+fn undo_should_restore_changed_entries() {
+    let fixture = LmdbTestFixture::new(vec![DB_NAME], None);
+    put_entry(&fixture, &[1], &[10]);
+    put_entry(&fixture, &[2], &[20]);
+
+    let journal_path = fixture.tmp_dir.as_ref().join("undo.journal");
+    let mut journal = Journal::create(&journal_path, &fixture.file_path).unwrap();
+    {
+        let mut txn = JournaledTransaction::begin(&fixture.env, Some(&mut journal), false).unwrap();
+        let db = unsafe { txn.open_db(Some(DB_NAME)).unwrap() };
+        txn.put(db, &[1u8], &[11u8], WriteFlags::empty()).unwrap();
+        // The second change of the same entry must be undone first.
+        txn.put(db, &[1u8], &[12u8], WriteFlags::empty()).unwrap();
+        txn.del(db, &[2u8]).unwrap();
+        txn.put(db, &[3u8], &[30u8], WriteFlags::empty()).unwrap();
+        assert!(matches!(
+            txn.del(db, &[4u8]),
+            Err(JournalError::Database(LmdbError::NotFound))
+        ));
+        assert!(txn.commit().unwrap().is_empty());
+    }
+    drop(journal);
+    assert_eq!(get_entry(&fixture, &[1]), Some(vec![12]));
+    assert_eq!(get_entry(&fixture, &[2]), None);
+    assert_eq!(get_entry(&fixture, &[3]), Some(vec![30]));
+
+    assert_eq!(undo(&journal_path).unwrap(), 4);
+    assert_eq!(get_entry(&fixture, &[1]), Some(vec![10]));
+    assert_eq!(get_entry(&fixture, &[2]), Some(vec![20]));
+    assert_eq!(get_entry(&fixture, &[3]), None);
+}
+
+#[test]
+fn dry_run_should_plan_changes_without_writing() {
+    let fixture = LmdbTestFixture::new(vec![DB_NAME], None);
+    put_entry(&fixture, &[1], &[10]);
+
+    let mut txn = JournaledTransaction::begin(&fixture.env, None, true).unwrap();
+    let db = unsafe { txn.open_db(Some(DB_NAME)).unwrap() };
+    txn.put(db, &[2u8], &[20u8], WriteFlags::empty()).unwrap();
+    // Reads see the planned changes.
+    assert_eq!(txn.get(db, &[2u8]).unwrap(), &[20]);
+    txn.del(db, &[1u8]).unwrap();
+    assert!(matches!(txn.get(db, &[1u8]), Err(LmdbError::NotFound)));
+    assert!(matches!(
+        txn.del(db, &[1u8]),
+        Err(JournalError::Database(LmdbError::NotFound))
+    ));
+    let planned_changes = txn.commit().unwrap();
+    assert_eq!(planned_changes.len(), 2);
+    assert_eq!(planned_changes[0].new_value, Some(vec![20]));
+    assert_eq!(planned_changes[1].key, vec![1]);
+    assert_eq!(planned_changes[1].new_value, None);
+
+    assert_eq!(get_entry(&fixture, &[1]), Some(vec![10]));
+    assert_eq!(get_entry(&fixture, &[2]), None);
+}
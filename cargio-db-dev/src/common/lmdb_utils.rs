@@ -1,7 +1,7 @@
-use std::result::Result;
+use std::{ptr, result::Result};
 
-use lmdb::{Database, Error, Transaction};
-use lmdb_sys::{mdb_stat, MDB_stat};
+use lmdb::{Database, Environment, Error, Transaction};
+use lmdb_sys::{mdb_env_info, mdb_env_stat, mdb_stat, MDB_envinfo, MDB_stat};
 
 pub fn entry_count<T: Transaction>(txn: &'_ T, database: Database) -> Result<usize, Error> {
     let mut stat = MDB_stat {
@@ -20,6 +20,36 @@ pub fn entry_count<T: Transaction>(txn: &'_ T, database: Database) -> Result<usi
     }
 }
 
+/// Returns the number of bytes of the environment's file which are in use,
+/// i.e. the size the file would have if it were shrunk to fit its data.
+pub fn used_size(env: &Environment) -> Result<u64, Error> {
+    let mut info = MDB_envinfo {
+        me_mapaddr: ptr::null_mut(),
+        me_mapsize: 0,
+        me_last_pgno: 0,
+        me_last_txnid: 0,
+        me_maxreaders: 0,
+        me_numreaders: 0,
+    };
+    let result = unsafe { mdb_env_info(env.env(), &mut info as *mut MDB_envinfo) };
+    if result != 0 {
+        return Err(Error::from_err_code(result));
+    }
+    let mut stat = MDB_stat {
+        ms_psize: 0,
+        ms_depth: 0,
+        ms_branch_pages: 0,
+        ms_leaf_pages: 0,
+        ms_overflow_pages: 0,
+        ms_entries: 0,
+    };
+    let result = unsafe { mdb_env_stat(env.env(), &mut stat as *mut MDB_stat) };
+    if result != 0 {
+        return Err(Error::from_err_code(result));
+    }
+    Ok((info.me_last_pgno as u64 + 1) * stat.ms_psize as u64)
+}
+
 #[cfg(test)]
 mod tests {
     use lmdb::{Transaction, WriteFlags};
@@ -10,13 +10,13 @@ pub struct ProgressTracker {
     total_to_process: usize,
     processed: usize,
     progress_factor: u64,
-    log_progress: Box<dyn Fn(u64)>,
+    log_progress: Box<dyn Fn(u64) + Send>,
 }
 
 impl ProgressTracker {
     pub fn new(
         total_to_process: usize,
-        log_progress: Box<dyn Fn(u64)>,
+        log_progress: Box<dyn Fn(u64) + Send>,
     ) -> Result<Self, &'static str> {
         if total_to_process == 0 {
             Err(NULL_TOTAL_TO_PROCESS_ERROR)
@@ -0,0 +1,244 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Error as IoError, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    result::Result,
+};
+
+use thiserror::Error;
+use zstd::{Decoder, Encoder};
+
+use cargio_hashing::Digest;
+use cargio_types::{
+    bytesrepr::{self, ToBytes},
+    Key, StoredValue,
+};
+
+const MAGIC: [u8; 8] = *b"CRGSTATE";
+const STATE_FILE_VERSION: u32 = 1;
+const COMPRESSION_LEVEL: i32 = 3;
+/// Offset of the entry count, the last field of the header.
+const ENTRY_COUNT_OFFSET: usize = MAGIC.len() + 4 + Digest::LENGTH;
+const HEADER_LEN: usize = ENTRY_COUNT_OFFSET + 8;
+
+#[derive(Debug, Error)]
+pub enum StateFileError {
+    #[error("Error (de)serializing entry {1} of state file {0}: {2}")]
+    Bytesrepr(PathBuf, u64, String),
+    #[error("State file {path} has {actual} entries, but its header says {expected}")]
+    EntryCount {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("File {0} isn't a state file")]
+    InvalidHeader(PathBuf),
+    #[error("Error accessing state file {0}: {1}")]
+    Io(PathBuf, IoError),
+    #[error("State file {0} has unsupported version {1}")]
+    UnsupportedVersion(PathBuf, u32),
+}
+
+/// The uncompressed header at the start of a state file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StateFileHeader {
+    pub state_root: Digest,
+    pub entry_count: u64,
+}
+
+impl StateFileHeader {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0u8; HEADER_LEN];
+        bytes[..MAGIC.len()].copy_from_slice(&MAGIC);
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&STATE_FILE_VERSION.to_le_bytes());
+        bytes[MAGIC.len() + 4..ENTRY_COUNT_OFFSET].copy_from_slice(self.state_root.as_ref());
+        bytes[ENTRY_COUNT_OFFSET..].copy_from_slice(&self.entry_count.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(path: &Path, bytes: &[u8; HEADER_LEN]) -> Result<Self, StateFileError> {
+        if bytes[..MAGIC.len()] != MAGIC {
+            return Err(StateFileError::InvalidHeader(path.to_path_buf()));
+        }
+        let mut version_bytes = [0u8; 4];
+        version_bytes.copy_from_slice(&bytes[MAGIC.len()..MAGIC.len() + 4]);
+        let version = u32::from_le_bytes(version_bytes);
+        if version != STATE_FILE_VERSION {
+            return Err(StateFileError::UnsupportedVersion(
+                path.to_path_buf(),
+                version,
+            ));
+        }
+        let state_root = Digest::try_from(&bytes[MAGIC.len() + 4..ENTRY_COUNT_OFFSET])
+            .map_err(|_| StateFileError::InvalidHeader(path.to_path_buf()))?;
+        let mut entry_count_bytes = [0u8; 8];
+        entry_count_bytes.copy_from_slice(&bytes[ENTRY_COUNT_OFFSET..]);
+        Ok(Self {
+            state_root,
+            entry_count: u64::from_le_bytes(entry_count_bytes),
+        })
+    }
+}
+
+/// Writes the global state under a state root to a state file.
+///
+/// A state file starts with an uncompressed `StateFileHeader`, followed by a
+/// zstd stream of `(Key, StoredValue)` entries, each serialized with
+/// bytesrepr and prefixed by its length as a little endian `u32`. The entry
+/// count in the header is only filled in by `finish`.
+pub struct StateFileWriter {
+    path: PathBuf,
+    state_root: Digest,
+    encoder: Encoder<'static, BufWriter<File>>,
+    entry_count: u64,
+}
+
+impl StateFileWriter {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        state_root: Digest,
+        overwrite: bool,
+    ) -> Result<Self, StateFileError> {
+        let path = path.as_ref().to_path_buf();
+        let io_err = |io_err| StateFileError::Io(path.clone(), io_err);
+        let mut file = OpenOptions::new()
+            .create_new(!overwrite)
+            .create(overwrite)
+            .truncate(overwrite)
+            .write(true)
+            .open(&path)
+            .map_err(io_err)?;
+        let header = StateFileHeader {
+            state_root,
+            entry_count: 0,
+        };
+        file.write_all(&header.to_bytes()).map_err(io_err)?;
+        let mut encoder = Encoder::new(BufWriter::new(file), COMPRESSION_LEVEL).map_err(io_err)?;
+        encoder.include_checksum(true).map_err(io_err)?;
+        Ok(Self {
+            path,
+            state_root,
+            encoder,
+            entry_count: 0,
+        })
+    }
+
+    pub fn write_entry(&mut self, key: &Key, value: &StoredValue) -> Result<(), StateFileError> {
+        let bytesrepr_err = |err: bytesrepr::Error| {
+            StateFileError::Bytesrepr(self.path.clone(), self.entry_count, err.to_string())
+        };
+        let mut entry_bytes = key.to_bytes().map_err(bytesrepr_err)?;
+        entry_bytes.extend(value.to_bytes().map_err(bytesrepr_err)?);
+        let entry_len = u32::try_from(entry_bytes.len()).map_err(|_| {
+            StateFileError::Bytesrepr(
+                self.path.clone(),
+                self.entry_count,
+                format!("entry of {} bytes is too large", entry_bytes.len()),
+            )
+        })?;
+        self.encoder
+            .write_all(&entry_len.to_le_bytes())
+            .and_then(|_| self.encoder.write_all(&entry_bytes))
+            .map_err(|io_err| StateFileError::Io(self.path.clone(), io_err))?;
+        self.entry_count += 1;
+        Ok(())
+    }
+
+    /// Ends the compressed stream and writes the entry count to the header.
+    /// Returns the number of written entries.
+    pub fn finish(self) -> Result<u64, StateFileError> {
+        let io_err = |io_err| StateFileError::Io(self.path.clone(), io_err);
+        let mut file = self
+            .encoder
+            .finish()
+            .and_then(|buf_writer| buf_writer.into_inner().map_err(|err| err.into_error()))
+            .map_err(io_err)?;
+        let header = StateFileHeader {
+            state_root: self.state_root,
+            entry_count: self.entry_count,
+        };
+        file.seek(SeekFrom::Start(ENTRY_COUNT_OFFSET as u64))
+            .and_then(|_| file.write_all(&header.to_bytes()[ENTRY_COUNT_OFFSET..]))
+            .and_then(|_| file.sync_all())
+            .map_err(io_err)?;
+        Ok(self.entry_count)
+    }
+}
+
+/// Reads the entries of a state file written by `StateFileWriter`.
+pub struct StateFileReader {
+    path: PathBuf,
+    header: StateFileHeader,
+    decoder: Decoder<'static, BufReader<File>>,
+    entries_read: u64,
+}
+
+impl StateFileReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StateFileError> {
+        let path = path.as_ref().to_path_buf();
+        let io_err = |io_err| StateFileError::Io(path.clone(), io_err);
+        let mut file = File::open(&path).map_err(io_err)?;
+        let mut header_bytes = [0u8; HEADER_LEN];
+        file.read_exact(&mut header_bytes).map_err(|io_err| {
+            if io_err.kind() == ErrorKind::UnexpectedEof {
+                StateFileError::InvalidHeader(path.clone())
+            } else {
+                StateFileError::Io(path.clone(), io_err)
+            }
+        })?;
+        let header = StateFileHeader::from_bytes(&path, &header_bytes)?;
+        let decoder = Decoder::new(file).map_err(io_err)?;
+        Ok(Self {
+            path,
+            header,
+            decoder,
+            entries_read: 0,
+        })
+    }
+
+    pub fn header(&self) -> StateFileHeader {
+        self.header
+    }
+
+    /// Returns the next entry, or `None` once all entries were read.
+    pub fn next_entry(&mut self) -> Result<Option<(Key, StoredValue)>, StateFileError> {
+        let io_err = |io_err| StateFileError::Io(self.path.clone(), io_err);
+        let mut len_bytes = [0u8; 4];
+        let mut filled = 0;
+        while filled < len_bytes.len() {
+            match self.decoder.read(&mut len_bytes[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(io_err(err)),
+            }
+        }
+        if filled == 0 {
+            if self.entries_read != self.header.entry_count {
+                return Err(StateFileError::EntryCount {
+                    path: self.path.clone(),
+                    expected: self.header.entry_count,
+                    actual: self.entries_read,
+                });
+            }
+            return Ok(None);
+        }
+        if filled < len_bytes.len() {
+            return Err(io_err(ErrorKind::UnexpectedEof.into()));
+        }
+        let mut entry_bytes = vec![0u8; u32::from_le_bytes(len_bytes) as usize];
+        self.decoder.read_exact(&mut entry_bytes).map_err(io_err)?;
+        let entry = bytesrepr::deserialize(entry_bytes).map_err(|bytesrepr_err| {
+            StateFileError::Bytesrepr(
+                self.path.clone(),
+                self.entries_read,
+                bytesrepr_err.to_string(),
+            )
+        })?;
+        self.entries_read += 1;
+        Ok(Some(entry))
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+};
+
+use tempfile::tempdir;
+
+use cargio_hashing::Digest;
+use cargio_types::{account::AccountHash, CLValue, Key, StoredValue};
+
+use super::{StateFileError, StateFileHeader, StateFileReader, StateFileWriter, HEADER_LEN};
+
+fn entries() -> Vec<(Key, StoredValue)> {
+    (0..3u8)
+        .map(|idx| {
+            (
+                Key::Account(AccountHash::new([idx; 32])),
+                StoredValue::CLValue(CLValue::from_t(idx as u64).unwrap()),
+            )
+        })
+        .collect()
+}
+
+fn read_all(reader: &mut StateFileReader) -> Result<Vec<(Key, StoredValue)>, StateFileError> {
+    let mut read_entries = vec![];
+    while let Some(entry) = reader.next_entry()? {
+        read_entries.push(entry);
+    }
+    Ok(read_entries)
+}
+
+#[test]
+fn state_file_should_round_trip_entries() {
+    let tmp_dir = tempdir().unwrap();
+    let path = tmp_dir.path().join("state.bin");
+    let state_root = Digest::hash([1u8; 8]);
+    let mut writer = StateFileWriter::create(&path, state_root, false).unwrap();
+    for (key, value) in entries() {
+        writer.write_entry(&key, &value).unwrap();
+    }
+    assert_eq!(writer.finish().unwrap(), 3);
+
+    let mut reader = StateFileReader::open(&path).unwrap();
+    assert_eq!(
+        reader.header(),
+        StateFileHeader {
+            state_root,
+            entry_count: 3
+        }
+    );
+    assert_eq!(read_all(&mut reader).unwrap(), entries());
+    assert!(reader.next_entry().unwrap().is_none());
+
+    // Existing files are only replaced with `overwrite`.
+    assert!(matches!(
+        StateFileWriter::create(&path, state_root, false),
+        Err(StateFileError::Io(..))
+    ));
+    StateFileWriter::create(&path, state_root, true)
+        .unwrap()
+        .finish()
+        .unwrap();
+    let mut reader = StateFileReader::open(&path).unwrap();
+    assert_eq!(reader.header().entry_count, 0);
+    assert!(read_all(&mut reader).unwrap().is_empty());
+}
+
+#[test]
+fn wrong_entry_count_should_fail() {
+    let tmp_dir = tempdir().unwrap();
+    let path = tmp_dir.path().join("state.bin");
+    let mut writer = StateFileWriter::create(&path, Digest::hash([1u8; 8]), false).unwrap();
+    for (key, value) in entries() {
+        writer.write_entry(&key, &value).unwrap();
+    }
+    writer.finish().unwrap();
+
+    // Restores the placeholder count of an unfinished export.
+    let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+    file.seek(SeekFrom::Start((HEADER_LEN - 8) as u64)).unwrap();
+    file.write_all(&0u64.to_le_bytes()).unwrap();
+    drop(file);
+
+    let mut reader = StateFileReader::open(&path).unwrap();
+    assert!(matches!(
+        read_all(&mut reader),
+        Err(StateFileError::EntryCount {
+            expected: 0,
+            actual: 3,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn invalid_header_should_fail() {
+    let tmp_dir = tempdir().unwrap();
+    let path = tmp_dir.path().join("state.bin");
+    fs::write(&path, b"not a state file").unwrap();
+    assert!(matches!(
+        StateFileReader::open(&path),
+        Err(StateFileError::InvalidHeader(_))
+    ));
+
+    fs::write(&path, [0u8; HEADER_LEN]).unwrap();
+    assert!(matches!(
+        StateFileReader::open(&path),
+        Err(StateFileError::InvalidHeader(_))
+    ));
+}
@@ -8,23 +8,42 @@ use std::{fs::OpenOptions, process};
 use clap::{crate_description, crate_name, crate_version, Arg, Command};
 use log::error;
 
+#[cfg(feature = "export-chain")]
+use subcommands::export_chain;
 use subcommands::{
-    archive, check, execution_results_summary, extract_slice, latest_block_summary,
-    purge_signatures, remove_block, trie_compact, unsparse, Error,
+    archive, build_index, check, diff_state, diff_storage, dump, execution_results_summary,
+    export_state, extract_slice, gc_deploys, get, import_state, latest_block_summary,
+    prune_execution_results, prune_history, purge_signatures, remove_block, trie_compact,
+    trie_stats, undo, unsparse, verify_trie, Error,
 };
 
 const LOGGING: &str = "logging";
 
 enum DisplayOrder {
     Archive,
+    BuildIndex,
     Check,
+    DiffState,
+    DiffStorage,
+    Dump,
     ExecutionResults,
+    #[cfg(feature = "export-chain")]
+    ExportChain,
+    ExportState,
     ExtractSlice,
+    GcDeploys,
+    Get,
+    ImportState,
     LatestBlock,
+    PruneExecutionResults,
+    PruneHistory,
     PurgeSignatures,
     RemoveBlock,
     TrieCompact,
+    TrieStats,
+    Undo,
     Unsparse,
+    VerifyTrie,
 }
 
 const VERSION_STRING: &str = concat!(
@@ -37,25 +56,40 @@ const VERSION_STRING: &str = concat!(
 );
 
 fn cli() -> Command<'static> {
-    Command::new("cargio-db")
+    let cli = Command::new("cargio-db")
         .version(VERSION_STRING)
         .about(crate_description!())
         .arg_required_else_help(true)
         .subcommand(archive::command(DisplayOrder::Archive as usize))
+        .subcommand(build_index::command(DisplayOrder::BuildIndex as usize))
         .subcommand(check::command(DisplayOrder::Check as usize))
+        .subcommand(diff_state::command(DisplayOrder::DiffState as usize))
+        .subcommand(diff_storage::command(DisplayOrder::DiffStorage as usize))
+        .subcommand(dump::command(DisplayOrder::Dump as usize))
         .subcommand(execution_results_summary::command(
             DisplayOrder::ExecutionResults as usize,
         ))
+        .subcommand(export_state::command(DisplayOrder::ExportState as usize))
         .subcommand(extract_slice::command(DisplayOrder::ExtractSlice as usize))
+        .subcommand(gc_deploys::command(DisplayOrder::GcDeploys as usize))
+        .subcommand(get::command(DisplayOrder::Get as usize))
+        .subcommand(import_state::command(DisplayOrder::ImportState as usize))
         .subcommand(latest_block_summary::command(
             DisplayOrder::LatestBlock as usize,
         ))
+        .subcommand(prune_execution_results::command(
+            DisplayOrder::PruneExecutionResults as usize,
+        ))
+        .subcommand(prune_history::command(DisplayOrder::PruneHistory as usize))
         .subcommand(purge_signatures::command(
             DisplayOrder::PurgeSignatures as usize,
         ))
         .subcommand(remove_block::command(DisplayOrder::RemoveBlock as usize))
         .subcommand(trie_compact::command(DisplayOrder::TrieCompact as usize))
+        .subcommand(trie_stats::command(DisplayOrder::TrieStats as usize))
+        .subcommand(undo::command(DisplayOrder::Undo as usize))
         .subcommand(unsparse::command(DisplayOrder::Unsparse as usize))
+        .subcommand(verify_trie::command(DisplayOrder::VerifyTrie as usize))
         .arg(
             Arg::new(LOGGING)
                 .short('l')
@@ -63,7 +97,10 @@ fn cli() -> Command<'static> {
                 .takes_value(true)
                 .value_name("LOGFILE_PATH")
                 .help("Path to file where program will dump log messages."),
-        )
+        );
+    #[cfg(feature = "export-chain")]
+    let cli = cli.subcommand(export_chain::command(DisplayOrder::ExportChain as usize));
+    cli
 }
 
 fn main() {
@@ -92,18 +129,35 @@ fn main() {
 
     let result: Result<(), Error> = match subcommand_name {
         archive::COMMAND_NAME => archive::run(matches).map_err(Error::from),
+        build_index::COMMAND_NAME => build_index::run(matches).map_err(Error::from),
         check::COMMAND_NAME => check::run(matches).map_err(Error::from),
+        diff_state::COMMAND_NAME => diff_state::run(matches).map_err(Error::from),
+        diff_storage::COMMAND_NAME => diff_storage::run(matches).map_err(Error::from),
+        dump::COMMAND_NAME => dump::run(matches).map_err(Error::from),
         execution_results_summary::COMMAND_NAME => {
             execution_results_summary::run(matches).map_err(Error::from)
         }
+        #[cfg(feature = "export-chain")]
+        export_chain::COMMAND_NAME => export_chain::run(matches).map_err(Error::from),
+        export_state::COMMAND_NAME => export_state::run(matches).map_err(Error::from),
         extract_slice::COMMAND_NAME => extract_slice::run(matches).map_err(Error::from),
+        gc_deploys::COMMAND_NAME => gc_deploys::run(matches).map_err(Error::from),
+        get::COMMAND_NAME => get::run(matches).map_err(Error::from),
+        import_state::COMMAND_NAME => import_state::run(matches).map_err(Error::from),
         latest_block_summary::COMMAND_NAME => {
             latest_block_summary::run(matches).map_err(Error::from)
         }
+        prune_execution_results::COMMAND_NAME => {
+            prune_execution_results::run(matches).map_err(Error::from)
+        }
+        prune_history::COMMAND_NAME => prune_history::run(matches).map_err(Error::from),
         purge_signatures::COMMAND_NAME => purge_signatures::run(matches).map_err(Error::from),
         remove_block::COMMAND_NAME => remove_block::run(matches).map_err(Error::from),
         trie_compact::COMMAND_NAME => trie_compact::run(matches).map_err(Error::from),
+        trie_stats::COMMAND_NAME => trie_stats::run(matches).map_err(Error::from),
+        undo::COMMAND_NAME => undo::run(matches).map_err(Error::from),
         unsparse::COMMAND_NAME => unsparse::run(matches).map_err(Error::from),
+        verify_trie::COMMAND_NAME => verify_trie::run(matches).map_err(Error::from),
         _ => unreachable!("{} should be handled above", subcommand_name),
     };
 
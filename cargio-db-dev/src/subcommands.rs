@@ -1,24 +1,54 @@
 pub mod archive;
+pub mod build_index;
 pub mod check;
+pub mod diff_state;
+pub mod diff_storage;
+pub mod dump;
 pub mod execution_results_summary;
+#[cfg(feature = "export-chain")]
+pub mod export_chain;
+pub mod export_state;
 pub mod extract_slice;
+pub mod gc_deploys;
+pub mod get;
+pub mod import_state;
 pub mod latest_block_summary;
+pub mod prune_execution_results;
+pub mod prune_history;
 pub mod purge_signatures;
 pub mod remove_block;
 pub mod trie_compact;
+pub mod trie_stats;
+pub mod undo;
 pub mod unsparse;
+pub mod verify_trie;
 
 use thiserror::Error as ThisError;
 
 use archive::{CreateError, UnpackError};
+use build_index::Error as BuildIndexError;
 use check::Error as CheckError;
+use diff_state::Error as DiffStateError;
+use diff_storage::Error as DiffStorageError;
+use dump::Error as DumpError;
 use execution_results_summary::Error as ExecutionResultsSummaryError;
+#[cfg(feature = "export-chain")]
+use export_chain::Error as ExportChainError;
+use export_state::Error as ExportStateError;
 use extract_slice::Error as ExtractSliceError;
+use gc_deploys::Error as GcDeploysError;
+use get::Error as GetError;
+use import_state::Error as ImportStateError;
 use latest_block_summary::Error as LatestBlockSummaryError;
+use prune_execution_results::Error as PruneExecutionResultsError;
+use prune_history::Error as PruneHistoryError;
 use purge_signatures::Error as PurgeSignaturesError;
 use remove_block::Error as RemoveBlockError;
 use trie_compact::Error as TrieCompactError;
+use trie_stats::Error as TrieStatsError;
+use undo::Error as UndoError;
 use unsparse::Error as UnsparseError;
+use verify_trie::Error as VerifyTrieError;
 
 #[derive(ThisError, Debug)]
 pub enum Error {
@@ -26,20 +56,49 @@ pub enum Error {
     ArchiveCreate(#[from] CreateError),
     #[error("Archive unpack failed: {0}")]
     ArchiveUnpack(#[from] UnpackError),
+    #[error("Build index command failed: {0}")]
+    BuildIndex(#[from] BuildIndexError),
     #[error("Check command failed: {0}")]
     Check(#[from] CheckError),
+    #[error("Diff state command failed: {0}")]
+    DiffState(#[from] DiffStateError),
+    #[error("Diff storage command failed: {0}")]
+    DiffStorage(#[from] DiffStorageError),
+    #[error("Dump command failed: {0}")]
+    Dump(#[from] DumpError),
     #[error("Execution results summary command failed: {0}")]
     ExecutionResultsSummary(#[from] ExecutionResultsSummaryError),
+    #[cfg(feature = "export-chain")]
+    #[error("Export chain command failed: {0}")]
+    ExportChain(#[from] ExportChainError),
+    #[error("Export state command failed: {0}")]
+    ExportState(#[from] ExportStateError),
     #[error("Extract slice command failed: {0}")]
     ExtractSlice(#[from] ExtractSliceError),
+    #[error("Garbage collect deploys command failed: {0}")]
+    GcDeploys(#[from] GcDeploysError),
+    #[error("Get command failed: {0}")]
+    Get(#[from] GetError),
+    #[error("Import state command failed: {0}")]
+    ImportState(#[from] ImportStateError),
     #[error("Latest block summary command failed: {0}")]
     LatestBlockSummary(#[from] LatestBlockSummaryError),
+    #[error("Prune execution results command failed: {0}")]
+    PruneExecutionResults(#[from] PruneExecutionResultsError),
+    #[error("Prune history command failed: {0}")]
+    PruneHistory(#[from] PruneHistoryError),
     #[error("Purge signatures failed: {0}")]
     PurgeSignatures(#[from] PurgeSignaturesError),
     #[error("Remove block failed: {0}")]
     RemoveBlock(#[from] RemoveBlockError),
     #[error("Trie compact failed: {0}")]
     TrieCompact(#[from] TrieCompactError),
+    #[error("Trie stats command failed: {0}")]
+    TrieStats(#[from] TrieStatsError),
+    #[error("Undo failed: {0}")]
+    Undo(#[from] UndoError),
     #[error("Unsparse failed: {0}")]
     Unsparse(#[from] UnsparseError),
+    #[error("Verify trie command failed: {0}")]
+    VerifyTrie(#[from] VerifyTrieError),
 }
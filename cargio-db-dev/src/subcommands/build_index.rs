@@ -0,0 +1,54 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Arg, ArgMatches, Command};
+use lmdb::Error as LmdbError;
+use thiserror::Error as ThisError;
+
+use crate::common::{
+    block_index::{self, BlockIndexError},
+    db::{self, STORAGE_FILE_NAME},
+};
+
+pub const COMMAND_NAME: &str = "build-index";
+const DB_PATH: &str = "db-path";
+
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error("Error building the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
+    #[error("Error initializing lmdb environment at {0}: {1}")]
+    Path(PathBuf, LmdbError),
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Writes a `block_index.lmdb` file next to `storage.lmdb` mapping block heights to \
+            block hashes and eras to their switch blocks. Commands which look up blocks by \
+            height use it instead of scanning all block headers, as long as it is up to date \
+            with the block header database.",
+        )
+        .arg(
+            Arg::new(DB_PATH)
+                .display_order(0)
+                .required(true)
+                .short('d')
+                .long(DB_PATH)
+                .takes_value(true)
+                .value_name("DB_PATH")
+                .help("Path of the directory with the `storage.lmdb` file."),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let path = Path::new(matches.value_of(DB_PATH).expect("should have db-path arg"));
+    build_index(path)
+}
+
+fn build_index(path: &Path) -> Result<(), Error> {
+    let env = db::db_env(path.join(STORAGE_FILE_NAME))
+        .map_err(|lmdb_err| Error::Path(path.to_path_buf(), lmdb_err))?;
+    block_index::build_index(path, &env)?;
+    Ok(())
+}
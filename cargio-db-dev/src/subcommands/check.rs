@@ -1,19 +1,41 @@
-use std::path::{Path, PathBuf};
+mod consistency;
+mod parallel;
+mod quarantine;
+#[cfg(test)]
+mod tests;
 
+use std::{
+    fs::{File, OpenOptions},
+    io::Error as IoError,
+    path::{Path, PathBuf},
+};
+
+use bincode::Error as BincodeError;
 use clap::{Arg, ArgMatches, Command};
-use lmdb::Error as LmdbError;
+use lmdb::{Environment, Error as LmdbError};
+use log::info;
+use serde_json::Error as JsonSerializationError;
 use thiserror::Error as ThisError;
 
-use crate::common::db::{
-    db_env, BlockBodyDatabase, BlockBodyMerkleDatabase, BlockHeaderDatabase, BlockMetadataDatabase,
-    Database, DeployDatabase, DeployHashesDatabase, DeployMetadataDatabase, Error as DbError,
-    FinalizedApprovalsDatabase, ProposerDatabase, StateStoreDatabase, TransferDatabase,
-    TransferHashesDatabase, STORAGE_FILE_NAME,
+use crate::common::{
+    block_index::{self, BlockIndexError},
+    db::{
+        self, db_env, storage_database_names, CheckReport, Checkpoint, Database, DatabaseReport,
+        DatabaseVisitor, Error as DbError, STORAGE_FILE_NAME,
+    },
 };
 
 pub const COMMAND_NAME: &str = "check";
+const CHECKPOINT: &str = "checkpoint";
+const CONSISTENCY: &str = "consistency";
 const DB_PATH: &str = "db-path";
+const JOBS: &str = "jobs";
 const NO_FAILFAST: &str = "no-failfast";
+const OUTPUT: &str = "output";
+const OVERWRITE: &str = "overwrite";
+const QUARANTINE: &str = "quarantine";
+const RESTORE: &str = "restore";
+const RESUME: &str = "resume";
 const SPECIFIC: &str = "specific";
 const START_AT: &str = "start-at";
 
@@ -22,12 +44,36 @@ enum DisplayOrder {
     DbPath,
     Specific,
     StartAt,
+    Consistency,
+    Output,
+    Overwrite,
+    Jobs,
+    Checkpoint,
+    Resume,
+    Quarantine,
+    Restore,
 }
 
 #[derive(ThisError, Debug)]
 pub enum Error {
+    #[error("Error removing the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
+    #[error("Error parsing block body with key {0}: {1}")]
+    BodyParsing(String, BincodeError),
     #[error("Error checking the database: {0}")]
     Database(#[from] DbError),
+    #[error("Error parsing block header with key {0}: {1}")]
+    HeaderParsing(String, BincodeError),
+    #[error("Found {0} inconsistent references between databases")]
+    Inconsistent(usize),
+    #[error("Found {0} entries which failed to parse")]
+    InvalidEntries(usize),
+    #[error("Error serializing report: {0}")]
+    JsonSerialize(#[from] JsonSerializationError),
+    #[error("Error while operating on LMDB: {0}")]
+    LmdbOperation(#[from] LmdbError),
+    #[error("Error writing report: {0}")]
+    Output(#[from] IoError),
     #[error("Error initializing lmdb environment at {0}: {1}")]
     Path(PathBuf, LmdbError),
     #[error("Unknown database {0}")]
@@ -38,7 +84,7 @@ pub fn command(display_order: usize) -> Command<'static> {
     Command::new(COMMAND_NAME)
         .about(
             "Checks validity of entries in a storage database through ensuring deserialization is \
-            successful.",
+            successful and, where possible, that the decoded entries match their keys.",
         )
         .display_order(display_order)
         .arg(
@@ -84,10 +130,125 @@ pub fn command(display_order: usize) -> Command<'static> {
                     to be set.",
                 ),
         )
+        .arg(
+            Arg::new(CONSISTENCY)
+                .display_order(DisplayOrder::Consistency as usize)
+                .short('c')
+                .long(CONSISTENCY)
+                .takes_value(false)
+                .conflicts_with(SPECIFIC)
+                .help(
+                    "Check the references between databases instead of the entries themselves. \
+                    Reports block headers, bodies, deploys, execution results, signatures and \
+                    transfers which are missing their counterpart or are not referenced at all.",
+                ),
+        )
+        .arg(
+            Arg::new(OUTPUT)
+                .display_order(DisplayOrder::Output as usize)
+                .short('o')
+                .long(OUTPUT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .conflicts_with(CONSISTENCY)
+                .help(
+                    "Path to where the program will output a JSON report with the number of \
+                    entries scanned and the failing entries of each checked database.",
+                ),
+        )
+        .arg(
+            Arg::new(OVERWRITE)
+                .display_order(DisplayOrder::Overwrite as usize)
+                .required(false)
+                .short('w')
+                .long(OVERWRITE)
+                .takes_value(false)
+                .requires(OUTPUT)
+                .help("Overwrite an already existing report file."),
+        )
+        .arg(
+            Arg::new(JOBS)
+                .display_order(DisplayOrder::Jobs as usize)
+                .short('j')
+                .long(JOBS)
+                .takes_value(true)
+                .value_name("JOB_COUNT")
+                .default_value("1")
+                .validator(|jobs| match jobs.parse::<usize>() {
+                    Ok(jobs) if jobs > 0 => Ok(()),
+                    _ => Err(String::from("must be an integer greater than 0")),
+                })
+                .conflicts_with(START_AT)
+                .help(
+                    "Number of threads checking databases at the same time, each in its own \
+                    read-only transaction. Large databases are split into key ranges checked \
+                    by separate threads. Can't be used with \"--start-at\" or \"--checkpoint\".",
+                ),
+        )
+        .arg(
+            Arg::new(CHECKPOINT)
+                .display_order(DisplayOrder::Checkpoint as usize)
+                .short('k')
+                .long(CHECKPOINT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .conflicts_with_all(&[CONSISTENCY, JOBS])
+                .help(
+                    "Path of a file where the last checked key of each database is recorded \
+                    while checking.",
+                ),
+        )
+        .arg(
+            Arg::new(RESUME)
+                .display_order(DisplayOrder::Resume as usize)
+                .short('r')
+                .long(RESUME)
+                .takes_value(false)
+                .requires(CHECKPOINT)
+                .conflicts_with(START_AT)
+                .help(
+                    "Resume an interrupted check from the keys recorded in the \
+                    \"--checkpoint\" file, skipping databases which were already fully \
+                    checked.",
+                ),
+        )
+        .arg(
+            Arg::new(QUARANTINE)
+                .display_order(DisplayOrder::Quarantine as usize)
+                .short('q')
+                .long(QUARANTINE)
+                .takes_value(true)
+                .value_name("DIR_PATH")
+                .conflicts_with(CONSISTENCY)
+                .help(
+                    "Move the entries which fail the check out of their database into a \
+                    quarantine LMDB file in this directory, keeping their database name and key.",
+                ),
+        )
+        .arg(
+            Arg::new(RESTORE)
+                .display_order(DisplayOrder::Restore as usize)
+                .long(RESTORE)
+                .takes_value(false)
+                .requires(QUARANTINE)
+                .conflicts_with_all(&[CONSISTENCY, SPECIFIC, OUTPUT, CHECKPOINT])
+                .help(
+                    "Instead of checking, put the entries of the \"--quarantine\" directory back \
+                    into their databases.",
+                ),
+        )
 }
 
 pub fn run(matches: &ArgMatches) -> Result<(), Error> {
     let path = matches.value_of(DB_PATH).unwrap();
+    if matches.is_present(CONSISTENCY) {
+        return consistency::check_consistency(path);
+    }
+    let maybe_quarantine_dir = matches.value_of(QUARANTINE).map(Path::new);
+    if matches.is_present(RESTORE) {
+        let quarantine_dir = maybe_quarantine_dir.expect("\"--restore\" requires \"--quarantine\"");
+        return quarantine::restore_entries(path, quarantine_dir).map(|_| ());
+    }
     let failfast = !matches.is_present(NO_FAILFAST);
     let specific = matches.value_of(SPECIFIC);
     let start_at: usize = matches
@@ -95,51 +256,170 @@ pub fn run(matches: &ArgMatches) -> Result<(), Error> {
         .expect("should have a default")
         .parse()
         .unwrap_or_else(|_| panic!("Value of \"--{START_AT}\" must be an integer."));
+    let output = matches.value_of(OUTPUT).map(Path::new);
+    let overwrite = matches.is_present(OVERWRITE);
+    let jobs: usize = matches
+        .value_of(JOBS)
+        .expect("should have a default")
+        .parse()
+        .expect("should have been validated");
+    let maybe_checkpoint = match matches.value_of(CHECKPOINT) {
+        Some(checkpoint_path) => {
+            if matches.is_present(RESUME) {
+                Some(Checkpoint::load(checkpoint_path).map_err(DbError::from)?)
+            } else {
+                Some(Checkpoint::new(checkpoint_path))
+            }
+        }
+        None => None,
+    };
+    let options = CheckOptions {
+        failfast,
+        specific,
+        start_at,
+        jobs,
+        quarantine_dir: maybe_quarantine_dir,
+    };
+
+    check_db(path, &options, output, overwrite, maybe_checkpoint)
+}
+
+struct CheckVisitor<'a> {
+    env: &'a Environment,
+    failfast: bool,
+    start_at: usize,
+    report: &'a mut DatabaseReport,
+    maybe_checkpoint: Option<&'a mut Checkpoint>,
+}
+
+impl DatabaseVisitor for CheckVisitor<'_> {
+    type Output = Result<(), DbError>;
+
+    fn visit<D: Database>(self) -> Self::Output {
+        D::check_db_with_report(
+            self.env,
+            self.failfast,
+            self.start_at,
+            self.report,
+            self.maybe_checkpoint,
+        )
+    }
+}
 
-    check_db(path, failfast, specific, start_at)
+fn check_database(
+    env: &Environment,
+    db_name: &str,
+    failfast: bool,
+    start_at: usize,
+    report: &mut DatabaseReport,
+    maybe_checkpoint: Option<&mut Checkpoint>,
+) -> Result<(), Error> {
+    let visitor = CheckVisitor {
+        env,
+        failfast,
+        start_at,
+        report,
+        maybe_checkpoint,
+    };
+    db::visit_database(db_name, visitor).ok_or_else(|| Error::UnknownDb(db_name.to_string()))??;
+    Ok(())
 }
 
-fn check_db<P: AsRef<Path>>(
-    path: P,
+struct CheckOptions<'a> {
     failfast: bool,
-    specific: Option<&str>,
+    specific: Option<&'a str>,
     start_at: usize,
+    jobs: usize,
+    quarantine_dir: Option<&'a Path>,
+}
+
+fn check_databases(
+    env: &Environment,
+    options: &CheckOptions,
+    reports: &mut Vec<DatabaseReport>,
+    mut maybe_checkpoint: Option<&mut Checkpoint>,
+) -> Result<(), Error> {
+    let db_names: Vec<&str> = match options.specific {
+        Some(db_name) => vec![db_name.trim()],
+        None => {
+            assert_eq!(options.start_at, 0);
+            storage_database_names().to_vec()
+        }
+    };
+    for db_name in db_names {
+        if let Some(checkpoint) = maybe_checkpoint.as_ref() {
+            if checkpoint.is_complete(db_name) {
+                info!("Skipping {db_name} database, already checked according to the checkpoint.");
+                continue;
+            }
+        }
+        reports.push(DatabaseReport::new(db_name));
+        let report = reports
+            .last_mut()
+            .expect("should have just pushed a report");
+        check_database(
+            env,
+            db_name,
+            options.failfast,
+            options.start_at,
+            report,
+            maybe_checkpoint.as_deref_mut(),
+        )?;
+    }
+    Ok(())
+}
+
+fn create_report_file<P: AsRef<Path>>(output: P, overwrite: bool) -> Result<File, Error> {
+    let file = OpenOptions::new()
+        .create_new(!overwrite)
+        .create(overwrite)
+        .truncate(overwrite)
+        .write(true)
+        .open(output)?;
+    Ok(file)
+}
+
+fn write_report(report: &CheckReport, file: File) -> Result<(), Error> {
+    serde_json::to_writer_pretty(file, report)?;
+    Ok(())
+}
+
+fn check_db<P1: AsRef<Path>, P2: AsRef<Path>>(
+    path: P1,
+    options: &CheckOptions,
+    output: Option<P2>,
+    overwrite: bool,
+    mut maybe_checkpoint: Option<Checkpoint>,
 ) -> Result<(), Error> {
+    // Created before checking so an existing file doesn't fail a long check
+    // at its end.
+    let maybe_report_file = output
+        .map(|output| create_report_file(output, overwrite))
+        .transpose()?;
     let storage_path = path.as_ref().join(STORAGE_FILE_NAME);
     let env = db_env(storage_path)
         .map_err(|lmdb_err| Error::Path(path.as_ref().to_path_buf(), lmdb_err))?;
-    if let Some(db_name) = specific {
-        match db_name.trim() {
-            "block_body" => BlockBodyDatabase::check_db(&env, failfast, start_at)?,
-            "block_body_merkle" => BlockBodyMerkleDatabase::check_db(&env, failfast, start_at)?,
-            "block_header" => BlockHeaderDatabase::check_db(&env, failfast, start_at)?,
-            "block_metadata" => BlockMetadataDatabase::check_db(&env, failfast, start_at)?,
-            "deploy_hashes" => DeployHashesDatabase::check_db(&env, failfast, start_at)?,
-            "deploy_metadata" => DeployMetadataDatabase::check_db(&env, failfast, start_at)?,
-            "deploys" => DeployDatabase::check_db(&env, failfast, start_at)?,
-            "finalized_approvals" => {
-                FinalizedApprovalsDatabase::check_db(&env, failfast, start_at)?
-            }
-            "proposers" => ProposerDatabase::check_db(&env, failfast, start_at)?,
-            "state_store" => StateStoreDatabase::check_db(&env, failfast, start_at)?,
-            "transfer" => TransferDatabase::check_db(&env, failfast, start_at)?,
-            "transfer_hashes" => TransferHashesDatabase::check_db(&env, failfast, start_at)?,
-            _ => return Err(Error::UnknownDb(db_name.to_string())),
-        }
+    let mut reports = vec![];
+    let result = if options.jobs > 1 {
+        parallel::check_databases(
+            &env,
+            options.failfast,
+            options.specific,
+            options.jobs,
+            &mut reports,
+        )
     } else {
-        assert_eq!(start_at, 0);
-        BlockBodyDatabase::check_db(&env, failfast, start_at)?;
-        BlockBodyMerkleDatabase::check_db(&env, failfast, start_at)?;
-        BlockHeaderDatabase::check_db(&env, failfast, start_at)?;
-        BlockMetadataDatabase::check_db(&env, failfast, start_at)?;
-        DeployHashesDatabase::check_db(&env, failfast, start_at)?;
-        DeployMetadataDatabase::check_db(&env, failfast, start_at)?;
-        DeployDatabase::check_db(&env, failfast, start_at)?;
-        FinalizedApprovalsDatabase::check_db(&env, failfast, start_at)?;
-        ProposerDatabase::check_db(&env, failfast, start_at)?;
-        StateStoreDatabase::check_db(&env, failfast, start_at)?;
-        TransferDatabase::check_db(&env, failfast, start_at)?;
-        TransferHashesDatabase::check_db(&env, failfast, start_at)?;
+        check_databases(&env, options, &mut reports, maybe_checkpoint.as_mut())
     };
-    Ok(())
+    if let Some(quarantine_dir) = options.quarantine_dir {
+        if !reports.iter().all(DatabaseReport::passed) {
+            block_index::remove_index(&path)?;
+        }
+        quarantine::quarantine_entries(&env, &reports, quarantine_dir)?;
+    }
+    if let Some(report_file) = maybe_report_file {
+        let report = CheckReport::new(reports, result.is_ok());
+        write_report(&report, report_file)?;
+    }
+    result
 }
@@ -0,0 +1,205 @@
+use std::{
+    collections::BTreeSet,
+    fmt::{Display, Formatter, Result as FormatterResult},
+    path::Path,
+    result::Result,
+};
+
+use lmdb::{Cursor, Environment, Transaction};
+use log::{info, warn};
+use serde::Serialize;
+
+use master_node::types::BlockHeader;
+
+use crate::{
+    common::db::{
+        self, BlockBodyDatabase, BlockHeaderDatabase, BlockMetadataDatabase, Database,
+        DeployDatabase, DeployMetadataDatabase, TransferDatabase, STORAGE_FILE_NAME,
+    },
+    subcommands::execution_results_summary::block_body::BlockBody,
+};
+
+use super::Error;
+
+/// Outcome of checking the references from one database into another.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub(crate) struct RelationReport {
+    pub(crate) relation: String,
+    /// Hex encoded keys which are referenced but missing from the target
+    /// database.
+    pub(crate) dangling: Vec<String>,
+    /// Hex encoded keys present in the target database which are not
+    /// referenced by anything.
+    pub(crate) orphaned: Vec<String>,
+}
+
+impl RelationReport {
+    fn new(
+        source: &str,
+        target: &str,
+        referenced: &BTreeSet<Vec<u8>>,
+        present: &BTreeSet<Vec<u8>>,
+        report_dangling: bool,
+    ) -> Self {
+        let dangling = if report_dangling {
+            referenced.difference(present).map(hex::encode).collect()
+        } else {
+            vec![]
+        };
+        let orphaned = present.difference(referenced).map(hex::encode).collect();
+        Self {
+            relation: format!("{source} -> {target}"),
+            dangling,
+            orphaned,
+        }
+    }
+
+    pub(crate) fn is_consistent(&self) -> bool {
+        self.dangling.is_empty() && self.orphaned.is_empty()
+    }
+}
+
+impl Display for RelationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatterResult {
+        write!(
+            f,
+            "{}: {} dangling, {} orphaned",
+            self.relation,
+            self.dangling.len(),
+            self.orphaned.len()
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub(crate) struct ConsistencyReport {
+    pub(crate) relations: Vec<RelationReport>,
+}
+
+impl ConsistencyReport {
+    pub(crate) fn inconsistency_count(&self) -> usize {
+        self.relations
+            .iter()
+            .map(|relation| relation.dangling.len() + relation.orphaned.len())
+            .sum()
+    }
+}
+
+fn read_keys<T: Transaction>(txn: &T, db_name: &str) -> Result<BTreeSet<Vec<u8>>, Error> {
+    let db = unsafe { txn.open_db(Some(db_name))? };
+    let mut keys = BTreeSet::new();
+    if let Ok(mut cursor) = txn.open_ro_cursor(db) {
+        for (raw_key, _raw_val) in cursor.iter() {
+            keys.insert(raw_key.to_vec());
+        }
+    }
+    Ok(keys)
+}
+
+pub(crate) fn check_references(env: &Environment) -> Result<ConsistencyReport, Error> {
+    let txn = env.begin_ro_txn()?;
+
+    info!("Reading {} database.", BlockHeaderDatabase::db_name());
+    let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+    let mut header_keys = BTreeSet::new();
+    let mut referenced_bodies = BTreeSet::new();
+    if let Ok(mut cursor) = txn.open_ro_cursor(header_db) {
+        for (raw_key, raw_val) in cursor.iter() {
+            let header: BlockHeader = bincode::deserialize(raw_val)
+                .map_err(|bincode_err| Error::HeaderParsing(hex::encode(raw_key), bincode_err))?;
+            header_keys.insert(raw_key.to_vec());
+            referenced_bodies.insert(header.body_hash().as_ref().to_vec());
+        }
+    }
+
+    info!("Reading {} database.", BlockBodyDatabase::db_name());
+    let body_db = unsafe { txn.open_db(Some(BlockBodyDatabase::db_name()))? };
+    let mut body_keys = BTreeSet::new();
+    let mut referenced_deploys = BTreeSet::new();
+    if let Ok(mut cursor) = txn.open_ro_cursor(body_db) {
+        for (raw_key, raw_val) in cursor.iter() {
+            let body: BlockBody = bincode::deserialize(raw_val)
+                .map_err(|bincode_err| Error::BodyParsing(hex::encode(raw_key), bincode_err))?;
+            body_keys.insert(raw_key.to_vec());
+            for deploy_hash in body.deploy_hashes.iter().chain(body.transfer_hashes.iter()) {
+                referenced_deploys.insert(deploy_hash.as_ref().to_vec());
+            }
+        }
+    }
+
+    info!("Reading remaining databases.");
+    let deploy_keys = read_keys(&txn, DeployDatabase::db_name())?;
+    let deploy_metadata_keys = read_keys(&txn, DeployMetadataDatabase::db_name())?;
+    let block_metadata_keys = read_keys(&txn, BlockMetadataDatabase::db_name())?;
+    let transfer_keys = read_keys(&txn, TransferDatabase::db_name())?;
+    txn.commit()?;
+
+    let relations = vec![
+        RelationReport::new(
+            BlockHeaderDatabase::db_name(),
+            BlockBodyDatabase::db_name(),
+            &referenced_bodies,
+            &body_keys,
+            true,
+        ),
+        RelationReport::new(
+            BlockBodyDatabase::db_name(),
+            DeployDatabase::db_name(),
+            &referenced_deploys,
+            &deploy_keys,
+            true,
+        ),
+        RelationReport::new(
+            BlockBodyDatabase::db_name(),
+            DeployMetadataDatabase::db_name(),
+            &referenced_deploys,
+            &deploy_metadata_keys,
+            true,
+        ),
+        RelationReport::new(
+            BlockHeaderDatabase::db_name(),
+            BlockMetadataDatabase::db_name(),
+            &header_keys,
+            &block_metadata_keys,
+            true,
+        ),
+        // Not every block has transfers, so only entries without a matching
+        // header are reported.
+        RelationReport::new(
+            BlockHeaderDatabase::db_name(),
+            TransferDatabase::db_name(),
+            &header_keys,
+            &transfer_keys,
+            false,
+        ),
+    ];
+
+    Ok(ConsistencyReport { relations })
+}
+
+pub fn check_consistency<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    let storage_path = path.as_ref().join(STORAGE_FILE_NAME);
+    let env = db::db_env(storage_path)
+        .map_err(|lmdb_err| Error::Path(path.as_ref().to_path_buf(), lmdb_err))?;
+    let report = check_references(&env)?;
+    for relation in report.relations.iter() {
+        if relation.is_consistent() {
+            info!("{relation}");
+            continue;
+        }
+        warn!("{relation}");
+        for key in relation.dangling.iter() {
+            warn!("Dangling reference in {}: {key}", relation.relation);
+        }
+        for key in relation.orphaned.iter() {
+            warn!("Orphaned entry in {}: {key}", relation.relation);
+        }
+    }
+    match report.inconsistency_count() {
+        0 => {
+            info!("All references between databases are consistent.");
+            Ok(())
+        }
+        count => Err(Error::Inconsistent(count)),
+    }
+}
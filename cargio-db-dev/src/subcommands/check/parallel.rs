@@ -0,0 +1,293 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use lmdb::{Cursor, Environment, Transaction};
+use log::{info, warn};
+
+use crate::common::{
+    db::{
+        self, storage_database_names, Database, DatabaseReport, DatabaseVisitor, Error as DbError,
+    },
+    lmdb_utils,
+    progress::ProgressTracker,
+};
+
+use super::Error;
+
+// Databases with fewer entries than this are checked by a single worker.
+#[cfg(not(test))]
+pub(crate) const SPLIT_THRESHOLD: usize = 1_000_000;
+#[cfg(test)]
+pub(crate) const SPLIT_THRESHOLD: usize = 10;
+const PROGRESS_BATCH_SIZE: usize = 10_000;
+const KEY_SPACE_PARTS: usize = u8::MAX as usize + 1;
+
+/// A range of keys `[start, end)` in a database. Missing bounds mean the
+/// range is open on that side.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct KeyRange {
+    pub(crate) start: Option<Vec<u8>>,
+    pub(crate) end: Option<Vec<u8>>,
+}
+
+/// Splits the key space in `parts` ranges based on the first byte of the
+/// keys. Most databases are keyed by hashes, so the entries are spread
+/// evenly across the ranges.
+pub(crate) fn split_key_space(parts: usize) -> Vec<KeyRange> {
+    let parts = parts.clamp(1, KEY_SPACE_PARTS);
+    let boundaries: Vec<Vec<u8>> = (1..parts)
+        .map(|part| vec![(part * KEY_SPACE_PARTS / parts) as u8])
+        .collect();
+    let mut ranges = Vec::with_capacity(parts);
+    let mut start = None;
+    for boundary in boundaries {
+        ranges.push(KeyRange {
+            start: start.take(),
+            end: Some(boundary.clone()),
+        });
+        start = Some(boundary);
+    }
+    ranges.push(KeyRange { start, end: None });
+    ranges
+}
+
+type RangeChecker = fn(
+    &Environment,
+    &KeyRange,
+    bool,
+    &AtomicBool,
+    Option<&Mutex<ProgressTracker>>,
+    &mut DatabaseReport,
+) -> Result<bool, DbError>;
+
+struct RangeCheckerVisitor;
+
+impl DatabaseVisitor for RangeCheckerVisitor {
+    type Output = RangeChecker;
+
+    fn visit<D: Database>(self) -> Self::Output {
+        check_range::<D>
+    }
+}
+
+fn advance_progress(maybe_progress_tracker: Option<&Mutex<ProgressTracker>>, step: usize) {
+    if let Some(progress_tracker) = maybe_progress_tracker {
+        progress_tracker
+            .lock()
+            .expect("progress tracker lock should not be poisoned")
+            .advance_by(step);
+    }
+}
+
+/// Checks the entries in `range`. Returns whether the whole range was
+/// checked, which isn't the case if the check was aborted because of a
+/// failure in another range.
+fn check_range<D: Database>(
+    env: &Environment,
+    range: &KeyRange,
+    failfast: bool,
+    abort: &AtomicBool,
+    maybe_progress_tracker: Option<&Mutex<ProgressTracker>>,
+    report: &mut DatabaseReport,
+) -> Result<bool, DbError> {
+    let txn = env.begin_ro_txn()?;
+    let db = unsafe { txn.open_db(Some(D::db_name()))? };
+    let mut error_buffer = vec![];
+    let mut unreported_progress = 0usize;
+    let mut complete = true;
+
+    if let Ok(mut cursor) = txn.open_ro_cursor(db) {
+        let iter = match range.start.as_ref() {
+            Some(start) => cursor.iter_from(start),
+            None => cursor.iter_start(),
+        };
+        for (idx, (raw_key, raw_val)) in iter.enumerate() {
+            if let Some(end) = range.end.as_ref() {
+                if raw_key >= end.as_slice() {
+                    break;
+                }
+            }
+            if abort.load(Ordering::Relaxed) {
+                complete = false;
+                break;
+            }
+            report.entries_scanned += 1;
+            if let Err(e) = D::check_element(idx, raw_key, raw_val, report) {
+                if failfast {
+                    abort.store(true, Ordering::Relaxed);
+                    return Err(e);
+                } else {
+                    error_buffer.push(e);
+                }
+            }
+            unreported_progress += 1;
+            if unreported_progress == PROGRESS_BATCH_SIZE {
+                advance_progress(maybe_progress_tracker, unreported_progress);
+                unreported_progress = 0;
+            }
+        }
+    }
+    advance_progress(maybe_progress_tracker, unreported_progress);
+
+    if !error_buffer.is_empty() {
+        return Err(DbError::Accumulated(error_buffer));
+    }
+    Ok(complete)
+}
+
+struct Task {
+    db_idx: usize,
+    range_idx: usize,
+    range: KeyRange,
+}
+
+pub(crate) fn check_databases(
+    env: &Environment,
+    failfast: bool,
+    specific: Option<&str>,
+    jobs: usize,
+    reports: &mut Vec<DatabaseReport>,
+) -> Result<(), Error> {
+    let db_names: Vec<&'static str> = match specific {
+        Some(db_name) => {
+            let db_name = db_name.trim();
+            vec![storage_database_names()
+                .into_iter()
+                .find(|known_name| *known_name == db_name)
+                .ok_or_else(|| Error::UnknownDb(db_name.to_string()))?]
+        }
+        None => storage_database_names().to_vec(),
+    };
+
+    let mut tasks = VecDeque::new();
+    let mut progress_trackers = vec![];
+    {
+        let txn = env.begin_ro_txn()?;
+        for (db_idx, db_name) in db_names.iter().copied().enumerate() {
+            let entry_count = unsafe { txn.open_db(Some(db_name)) }
+                .ok()
+                .and_then(|db| lmdb_utils::entry_count(&txn, db).ok())
+                .unwrap_or_default();
+            let parts = if entry_count >= SPLIT_THRESHOLD {
+                jobs
+            } else {
+                1
+            };
+            info!("Checking {db_name} database in {parts} key range(s).");
+            for (range_idx, range) in split_key_space(parts).into_iter().enumerate() {
+                tasks.push_back(Task {
+                    db_idx,
+                    range_idx,
+                    range,
+                });
+            }
+            progress_trackers.push(
+                ProgressTracker::new(
+                    entry_count,
+                    Box::new(move |completion| {
+                        info!("{db_name} database parsing {completion}% complete...")
+                    }),
+                )
+                .ok()
+                .map(Mutex::new),
+            );
+        }
+        txn.commit()?;
+    }
+
+    let tasks = Mutex::new(tasks);
+    let results = Mutex::new(vec![]);
+    let abort = AtomicBool::new(false);
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let maybe_task = tasks
+                    .lock()
+                    .expect("task queue lock should not be poisoned")
+                    .pop_front();
+                let task = match maybe_task {
+                    Some(task) => task,
+                    None => break,
+                };
+                let db_name = db_names[task.db_idx];
+                let checker = db::visit_database(db_name, RangeCheckerVisitor)
+                    .expect("should be a known database");
+                let mut range_report = DatabaseReport::new(db_name);
+                let result = checker(
+                    env,
+                    &task.range,
+                    failfast,
+                    &abort,
+                    progress_trackers[task.db_idx].as_ref(),
+                    &mut range_report,
+                );
+                results
+                    .lock()
+                    .expect("results lock should not be poisoned")
+                    .push((
+                        task.db_idx,
+                        task.range_idx,
+                        task.range,
+                        range_report,
+                        result,
+                    ));
+            });
+        }
+    });
+
+    let mut results = results
+        .into_inner()
+        .expect("results lock should not be poisoned");
+    results.sort_by_key(|(db_idx, range_idx, _, _, _)| (*db_idx, *range_idx));
+
+    let mut db_reports: Vec<DatabaseReport> = db_names
+        .iter()
+        .map(|db_name| DatabaseReport::new(db_name))
+        .collect();
+    // Whether all the ranges appended so far to each database report were
+    // fully checked, so the indices of the next range can be made absolute.
+    let mut offsets_known = vec![true; db_names.len()];
+    let mut maybe_lmdb_error = None;
+    for (db_idx, _range_idx, range, range_report, result) in results {
+        let maybe_range_start = if offsets_known[db_idx] {
+            None
+        } else {
+            range.start.as_deref()
+        };
+        db_reports[db_idx].append(range_report, maybe_range_start);
+        // With failfast the range stops at its first failure, without it
+        // the failures are accumulated until the end of the range.
+        offsets_known[db_idx] &= matches!(result, Ok(true) | Err(DbError::Accumulated(_)));
+        if let Err(DbError::Database(lmdb_err)) = result {
+            maybe_lmdb_error.get_or_insert(lmdb_err);
+        }
+    }
+    let failure_count: usize = db_reports
+        .iter()
+        .map(|db_report| db_report.failures.len())
+        .sum();
+    for db_report in db_reports.iter() {
+        for failure in db_report.failures.iter() {
+            warn!(
+                "Error parsing element {} with key {} in {} database: {}",
+                failure.index, failure.key, db_report.db_name, failure.error
+            );
+        }
+    }
+    reports.extend(db_reports);
+
+    if let Some(lmdb_err) = maybe_lmdb_error {
+        return Err(Error::LmdbOperation(lmdb_err));
+    }
+    if failure_count > 0 {
+        return Err(Error::InvalidEntries(failure_count));
+    }
+    info!("Parsing complete.");
+    Ok(())
+}
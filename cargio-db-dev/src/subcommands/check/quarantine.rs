@@ -0,0 +1,141 @@
+use std::{fs, path::Path};
+
+use lmdb::{
+    Cursor, DatabaseFlags, Environment, EnvironmentFlags, Error as LmdbError, Transaction,
+    WriteFlags,
+};
+use log::{info, warn};
+
+use crate::common::{
+    block_index,
+    db::{self, storage_database_names, DatabaseReport, STORAGE_FILE_NAME},
+};
+
+use super::Error;
+
+pub(crate) const QUARANTINE_FILE_NAME: &str = "quarantine.lmdb";
+const QUARANTINE_MAP_SIZE: usize = 1 << 30;
+const MAX_DBS: u32 = 100;
+
+/// Opens the quarantine environment in `dir`, creating it if needed. Its
+/// databases are named after the storage databases the entries came from.
+fn quarantine_env(dir: &Path) -> Result<Environment, Error> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(QUARANTINE_FILE_NAME);
+    Environment::new()
+        .set_flags(EnvironmentFlags::NO_SUB_DIR | EnvironmentFlags::NO_TLS)
+        .set_max_dbs(MAX_DBS)
+        .set_map_size(QUARANTINE_MAP_SIZE)
+        .open(&path)
+        .map_err(|lmdb_err| Error::Path(path, lmdb_err))
+}
+
+/// Moves the failing entries listed in `reports` from the storage into the
+/// quarantine environment in `dir`. Returns the number of moved entries.
+pub(crate) fn quarantine_entries(
+    env: &Environment,
+    reports: &[DatabaseReport],
+    dir: &Path,
+) -> Result<usize, Error> {
+    if reports.iter().all(DatabaseReport::passed) {
+        return Ok(0);
+    }
+    let quarantine_env = quarantine_env(dir)?;
+    let mut storage_txn = env.begin_rw_txn()?;
+    let mut quarantine_txn = quarantine_env.begin_rw_txn()?;
+    let mut quarantined = 0;
+    for report in reports.iter().filter(|report| !report.passed()) {
+        let storage_db = unsafe { storage_txn.open_db(Some(report.db_name.as_str()))? };
+        let quarantine_db = unsafe {
+            quarantine_txn.create_db(Some(report.db_name.as_str()), DatabaseFlags::empty())?
+        };
+        for failure in report.failures.iter() {
+            let key = hex::decode(&failure.key).expect("report keys should be hex encoded");
+            let value = match storage_txn.get(storage_db, &key) {
+                Ok(value) => value.to_vec(),
+                Err(LmdbError::NotFound) => {
+                    warn!(
+                        "Entry with key {} is no longer in the {} database.",
+                        failure.key, report.db_name
+                    );
+                    continue;
+                }
+                Err(lmdb_err) => return Err(lmdb_err.into()),
+            };
+            quarantine_txn.put(quarantine_db, &key, &value, WriteFlags::empty())?;
+            storage_txn.del(storage_db, &key, None)?;
+            info!(
+                "Quarantined entry with key {} from the {} database.",
+                failure.key, report.db_name
+            );
+            quarantined += 1;
+        }
+    }
+    // Commit the quarantine first so an interruption leaves the entries in
+    // both environments rather than in neither.
+    quarantine_txn.commit()?;
+    storage_txn.commit()?;
+    info!(
+        "Moved {quarantined} entries to {}.",
+        dir.join(QUARANTINE_FILE_NAME).display()
+    );
+    Ok(quarantined)
+}
+
+/// Moves the entries of the quarantine environment in `dir` back to the
+/// storage in `path`. Entries whose key was written again in the storage in
+/// the meantime are left in quarantine. Returns the number of restored
+/// entries.
+pub(crate) fn restore_entries<P1: AsRef<Path>, P2: AsRef<Path>>(
+    path: P1,
+    dir: P2,
+) -> Result<usize, Error> {
+    let storage_path = path.as_ref().join(STORAGE_FILE_NAME);
+    let env = db::db_env(storage_path)
+        .map_err(|lmdb_err| Error::Path(path.as_ref().to_path_buf(), lmdb_err))?;
+    let quarantine_env = quarantine_env(dir.as_ref())?;
+    let mut storage_txn = env.begin_rw_txn()?;
+    let mut quarantine_txn = quarantine_env.begin_rw_txn()?;
+    let mut restored = 0;
+    for db_name in storage_database_names() {
+        let quarantine_db = match unsafe { quarantine_txn.open_db(Some(db_name)) } {
+            Ok(db) => db,
+            Err(LmdbError::NotFound) => continue,
+            Err(lmdb_err) => return Err(lmdb_err.into()),
+        };
+        let storage_db = unsafe { storage_txn.open_db(Some(db_name))? };
+        let mut restored_keys = vec![];
+        if let Ok(mut cursor) = quarantine_txn.open_ro_cursor(quarantine_db) {
+            for (raw_key, raw_val) in cursor.iter() {
+                match storage_txn.put(storage_db, &raw_key, &raw_val, WriteFlags::NO_OVERWRITE) {
+                    Ok(()) => restored_keys.push(raw_key.to_vec()),
+                    Err(LmdbError::KeyExist) => warn!(
+                        "Entry with key {} already exists in the {db_name} database, leaving it \
+                        in quarantine.",
+                        hex::encode(raw_key)
+                    ),
+                    Err(lmdb_err) => return Err(lmdb_err.into()),
+                }
+            }
+        }
+        for key in restored_keys.iter() {
+            quarantine_txn.del(quarantine_db, key, None)?;
+        }
+        if !restored_keys.is_empty() {
+            info!(
+                "Restored {} entries to the {db_name} database.",
+                restored_keys.len()
+            );
+        }
+        restored += restored_keys.len();
+    }
+    if restored > 0 {
+        block_index::remove_index(&path)?;
+    }
+    // Commit the storage first so an interruption leaves the entries in both
+    // environments rather than in neither.
+    storage_txn.commit()?;
+    quarantine_txn.commit()?;
+    info!("Restored {restored} entries in total.");
+    Ok(restored)
+}
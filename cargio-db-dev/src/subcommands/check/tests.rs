@@ -0,0 +1,583 @@
+use std::fs;
+
+use cargio_types::bytesrepr::ToBytes;
+use lmdb::{Transaction, WriteFlags};
+use master_node::types::{BlockHash, DeployHash};
+use serde_json::Value;
+
+use crate::{
+    common::db::{
+        BlockBodyDatabase, BlockHeaderDatabase, BlockMetadataDatabase, Checkpoint, Database,
+        DeployDatabase, DeployMetadataDatabase, StateStoreDatabase, TransferDatabase,
+        STORAGE_FILE_NAME,
+    },
+    subcommands::{
+        check::{
+            check_databases, check_db, command, consistency,
+            parallel::{self, split_key_space, KeyRange, SPLIT_THRESHOLD},
+            quarantine::{self, QUARANTINE_FILE_NAME},
+            CheckOptions, Error,
+        },
+        execution_results_summary::block_body::BlockBody,
+    },
+    test_utils::{
+        mock_block_header, mock_deploy_hash, mock_deploy_metadata, LmdbTestFixture, MockBlockHeader,
+    },
+};
+
+const BLOCK_COUNT: usize = 3;
+const DEPLOY_COUNT: usize = 3;
+
+fn populate_consistent_db(
+    fixture: &LmdbTestFixture,
+) -> (Vec<(BlockHash, MockBlockHeader)>, Vec<DeployHash>) {
+    let deploy_hashes: Vec<DeployHash> = (0..DEPLOY_COUNT as u8).map(mock_deploy_hash).collect();
+    let block_headers: Vec<(BlockHash, MockBlockHeader)> =
+        (0..BLOCK_COUNT as u8).map(mock_block_header).collect();
+
+    let mut txn = fixture.env.begin_rw_txn().unwrap();
+    for (idx, (block_hash, header)) in block_headers.iter().enumerate() {
+        txn.put(
+            *fixture.db(Some(BlockHeaderDatabase::db_name())).unwrap(),
+            block_hash,
+            &bincode::serialize(header).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+        txn.put(
+            *fixture.db(Some(BlockBodyDatabase::db_name())).unwrap(),
+            &header.body_hash,
+            &bincode::serialize(&BlockBody::new(vec![deploy_hashes[idx]])).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+        txn.put(
+            *fixture.db(Some(BlockMetadataDatabase::db_name())).unwrap(),
+            block_hash,
+            &bincode::serialize(block_hash).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+        txn.put(
+            *fixture.db(Some(DeployDatabase::db_name())).unwrap(),
+            &deploy_hashes[idx],
+            &bincode::serialize(&deploy_hashes[idx]).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+        txn.put(
+            *fixture.db(Some(DeployMetadataDatabase::db_name())).unwrap(),
+            &deploy_hashes[idx],
+            &bincode::serialize(&mock_deploy_metadata(&[*block_hash])).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+    }
+    txn.put(
+        *fixture.db(Some(TransferDatabase::db_name())).unwrap(),
+        &block_headers[0].0,
+        &bincode::serialize(&block_headers[0].0).unwrap(),
+        WriteFlags::empty(),
+    )
+    .unwrap();
+    txn.commit().unwrap();
+
+    (block_headers, deploy_hashes)
+}
+
+fn state_store_options(failfast: bool, start_at: usize, jobs: usize) -> CheckOptions<'static> {
+    CheckOptions {
+        failfast,
+        specific: Some(StateStoreDatabase::db_name()),
+        start_at,
+        jobs,
+        quarantine_dir: None,
+    }
+}
+
+fn consistency_fixture() -> LmdbTestFixture {
+    LmdbTestFixture::new(
+        vec![
+            BlockHeaderDatabase::db_name(),
+            BlockBodyDatabase::db_name(),
+            BlockMetadataDatabase::db_name(),
+            DeployDatabase::db_name(),
+            DeployMetadataDatabase::db_name(),
+            TransferDatabase::db_name(),
+        ],
+        Some(STORAGE_FILE_NAME),
+    )
+}
+
+#[test]
+fn consistent_db_should_pass_consistency_check() {
+    let fixture = consistency_fixture();
+    let _ = populate_consistent_db(&fixture);
+
+    let report = consistency::check_references(&fixture.env).unwrap();
+    assert_eq!(report.relations.len(), 5);
+    assert_eq!(report.inconsistency_count(), 0);
+    assert!(consistency::check_consistency(fixture.tmp_dir.path()).is_ok());
+}
+
+#[test]
+fn half_deleted_block_should_fail_consistency_check() {
+    let fixture = consistency_fixture();
+    let (block_headers, deploy_hashes) = populate_consistent_db(&fixture);
+
+    // Simulate a crash in the middle of removing the first block: the header
+    // and signatures are gone, but the body, transfers and deploys are left.
+    {
+        let mut txn = fixture.env.begin_rw_txn().unwrap();
+        txn.del(
+            *fixture.db(Some(BlockHeaderDatabase::db_name())).unwrap(),
+            &block_headers[0].0,
+            None,
+        )
+        .unwrap();
+        txn.del(
+            *fixture.db(Some(BlockMetadataDatabase::db_name())).unwrap(),
+            &block_headers[0].0,
+            None,
+        )
+        .unwrap();
+        txn.del(
+            *fixture.db(Some(DeployMetadataDatabase::db_name())).unwrap(),
+            &deploy_hashes[1],
+            None,
+        )
+        .unwrap();
+        txn.commit().unwrap();
+    }
+
+    let report = consistency::check_references(&fixture.env).unwrap();
+    let relation = |name: &str| {
+        report
+            .relations
+            .iter()
+            .find(|relation| relation.relation == name)
+            .unwrap()
+    };
+
+    let body_relation = relation("block_header -> block_body");
+    assert!(body_relation.dangling.is_empty());
+    assert_eq!(
+        body_relation.orphaned,
+        vec![hex::encode(block_headers[0].1.body_hash)]
+    );
+
+    let metadata_relation = relation("block_body -> deploy_metadata");
+    assert_eq!(
+        metadata_relation.dangling,
+        vec![hex::encode(deploy_hashes[1])]
+    );
+    assert!(metadata_relation.orphaned.is_empty());
+
+    assert!(relation("block_body -> deploys").is_consistent());
+    assert!(relation("block_header -> block_metadata").is_consistent());
+
+    let transfer_relation = relation("block_header -> transfer");
+    assert_eq!(
+        transfer_relation.orphaned,
+        vec![hex::encode(block_headers[0].0)]
+    );
+
+    assert_eq!(report.inconsistency_count(), 3);
+    assert!(consistency::check_consistency(fixture.tmp_dir.path()).is_err());
+}
+
+#[test]
+fn check_should_write_json_report() {
+    let fixture =
+        LmdbTestFixture::new(vec![StateStoreDatabase::db_name()], Some(STORAGE_FILE_NAME));
+    let out_dir = tempfile::tempdir().unwrap();
+    let report_path = out_dir.path().join("report.json");
+
+    {
+        let mut txn = fixture.env.begin_rw_txn().unwrap();
+        let db = *fixture.db(Some(StateStoreDatabase::db_name())).unwrap();
+        txn.put(db, &[0u8], &0u64.to_bytes().unwrap(), WriteFlags::empty())
+            .unwrap();
+        txn.put(db, &[1u8], &[1u8, 2u8], WriteFlags::empty())
+            .unwrap();
+        txn.put(db, &[2u8], &2u64.to_bytes().unwrap(), WriteFlags::empty())
+            .unwrap();
+        txn.commit().unwrap();
+    }
+
+    assert!(check_db(
+        fixture.tmp_dir.path(),
+        &state_store_options(false, 0, 1),
+        Some(&report_path),
+        false,
+        None,
+    )
+    .is_err());
+
+    let report: Value = serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+    assert_eq!(report["passed"], Value::Bool(false));
+    assert_eq!(report["entries_scanned"], 3);
+    assert_eq!(report["failure_count"], 1);
+    let database = &report["databases"][0];
+    assert_eq!(database["db_name"], StateStoreDatabase::db_name());
+    assert_eq!(database["entries_scanned"], 3);
+    assert_eq!(database["failures"][0]["index"], 1);
+    assert_eq!(database["failures"][0]["key"], "01");
+    assert_eq!(database["failures"][0]["error_kind"], "bytesrepr");
+
+    // The report file already exists, so it can only be written again with
+    // `overwrite`.
+    assert!(check_db(
+        fixture.tmp_dir.path(),
+        &state_store_options(true, 2, 1),
+        Some(&report_path),
+        false,
+        None,
+    )
+    .is_err());
+    assert!(check_db(
+        fixture.tmp_dir.path(),
+        &state_store_options(true, 2, 1),
+        Some(&report_path),
+        true,
+        None,
+    )
+    .is_ok());
+    let report: Value = serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+    assert_eq!(report["passed"], Value::Bool(true));
+    assert_eq!(report["entries_scanned"], 1);
+}
+
+#[test]
+fn existing_report_should_fail_before_checking() {
+    let db_dir = tempfile::tempdir().unwrap();
+    let out_dir = tempfile::tempdir().unwrap();
+    let report_path = out_dir.path().join("report.json");
+    fs::write(&report_path, "previous report").unwrap();
+
+    // The storage doesn't exist, so the report file must have been rejected
+    // before the environment was opened.
+    assert!(matches!(
+        check_db(
+            db_dir.path(),
+            &state_store_options(true, 0, 1),
+            Some(&report_path),
+            false,
+            None,
+        ),
+        Err(Error::Output(_))
+    ));
+    assert_eq!(fs::read_to_string(&report_path).unwrap(), "previous report");
+}
+
+#[test]
+fn key_space_split() {
+    assert_eq!(split_key_space(0), vec![KeyRange::default()]);
+    assert_eq!(split_key_space(1), vec![KeyRange::default()]);
+    assert_eq!(
+        split_key_space(2),
+        vec![
+            KeyRange {
+                start: None,
+                end: Some(vec![128]),
+            },
+            KeyRange {
+                start: Some(vec![128]),
+                end: None,
+            },
+        ]
+    );
+    let ranges = split_key_space(3);
+    assert_eq!(ranges.len(), 3);
+    assert_eq!(ranges[0].end, Some(vec![85]));
+    assert_eq!(ranges[1].start, Some(vec![85]));
+    assert_eq!(ranges[1].end, Some(vec![170]));
+    assert_eq!(ranges[2].start, Some(vec![170]));
+    assert_eq!(split_key_space(1000).len(), 256);
+}
+
+#[test]
+fn parallel_check_should_match_sequential_check() {
+    const ENTRY_COUNT: u8 = 4 * SPLIT_THRESHOLD as u8;
+
+    let fixture =
+        LmdbTestFixture::new(vec![StateStoreDatabase::db_name()], Some(STORAGE_FILE_NAME));
+    {
+        let mut txn = fixture.env.begin_rw_txn().unwrap();
+        let db = *fixture.db(Some(StateStoreDatabase::db_name())).unwrap();
+        for i in 0..ENTRY_COUNT {
+            let key = [i.wrapping_mul(61), i];
+            if i % 7 == 0 {
+                txn.put(db, &key, &[i], WriteFlags::empty()).unwrap();
+            } else {
+                txn.put(
+                    db,
+                    &key,
+                    &(i as u64).to_bytes().unwrap(),
+                    WriteFlags::empty(),
+                )
+                .unwrap();
+            }
+        }
+        txn.commit().unwrap();
+    }
+
+    let mut sequential_reports = vec![];
+    assert!(check_databases(
+        &fixture.env,
+        &state_store_options(false, 0, 1),
+        &mut sequential_reports,
+        None,
+    )
+    .is_err());
+
+    for jobs in [2, 3, 8] {
+        let mut parallel_reports = vec![];
+        assert!(parallel::check_databases(
+            &fixture.env,
+            false,
+            Some(StateStoreDatabase::db_name()),
+            jobs,
+            &mut parallel_reports,
+        )
+        .is_err());
+        assert_eq!(sequential_reports, parallel_reports);
+    }
+    assert_eq!(sequential_reports[0].entries_scanned, ENTRY_COUNT as usize);
+    assert_eq!(
+        sequential_reports[0].failures.len(),
+        (ENTRY_COUNT as usize + 6) / 7
+    );
+}
+
+#[test]
+fn interrupted_check_should_resume_from_checkpoint() {
+    const ENTRY_COUNT: u8 = 20;
+    const FAULTY_ENTRY: u8 = 12;
+
+    let fixture =
+        LmdbTestFixture::new(vec![StateStoreDatabase::db_name()], Some(STORAGE_FILE_NAME));
+    let checkpoint_dir = tempfile::tempdir().unwrap();
+    let checkpoint_path = checkpoint_dir.path().join("checkpoint.json");
+    let db = *fixture.db(Some(StateStoreDatabase::db_name())).unwrap();
+    {
+        let mut txn = fixture.env.begin_rw_txn().unwrap();
+        for i in 0..ENTRY_COUNT {
+            if i == FAULTY_ENTRY {
+                txn.put(db, &[i], &[i], WriteFlags::empty()).unwrap();
+            } else {
+                txn.put(
+                    db,
+                    &[i],
+                    &(i as u64).to_bytes().unwrap(),
+                    WriteFlags::empty(),
+                )
+                .unwrap();
+            }
+        }
+        txn.commit().unwrap();
+    }
+
+    // The first run stops at the faulty entry and records the key before it.
+    let mut reports = vec![];
+    let mut checkpoint = Checkpoint::new(&checkpoint_path);
+    assert!(check_databases(
+        &fixture.env,
+        &state_store_options(true, 0, 1),
+        &mut reports,
+        Some(&mut checkpoint),
+    )
+    .is_err());
+    assert_eq!(reports[0].entries_scanned, FAULTY_ENTRY as usize + 1);
+    let checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+    assert!(!checkpoint.is_complete(StateStoreDatabase::db_name()));
+    assert_eq!(
+        checkpoint
+            .resume_key(StateStoreDatabase::db_name())
+            .unwrap(),
+        Some(vec![FAULTY_ENTRY - 1])
+    );
+
+    // Fix the entry and resume, only the remaining entries are checked.
+    {
+        let mut txn = fixture.env.begin_rw_txn().unwrap();
+        txn.put(
+            db,
+            &[FAULTY_ENTRY],
+            &(FAULTY_ENTRY as u64).to_bytes().unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+        txn.commit().unwrap();
+    }
+    let mut reports = vec![];
+    let mut checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+    assert!(check_databases(
+        &fixture.env,
+        &state_store_options(true, 0, 1),
+        &mut reports,
+        Some(&mut checkpoint),
+    )
+    .is_ok());
+    assert_eq!(
+        reports[0].entries_scanned,
+        (ENTRY_COUNT - FAULTY_ENTRY) as usize
+    );
+    assert_eq!(
+        reports[0].resumed_after,
+        Some(hex::encode([FAULTY_ENTRY - 1]))
+    );
+    let checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+    assert!(checkpoint.is_complete(StateStoreDatabase::db_name()));
+    assert_eq!(
+        checkpoint
+            .resume_key(StateStoreDatabase::db_name())
+            .unwrap(),
+        Some(vec![ENTRY_COUNT - 1])
+    );
+
+    // Completed databases are skipped altogether.
+    let mut reports = vec![];
+    let mut checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+    assert!(check_databases(
+        &fixture.env,
+        &state_store_options(true, 0, 1),
+        &mut reports,
+        Some(&mut checkpoint),
+    )
+    .is_ok());
+    assert!(reports.is_empty());
+}
+
+#[test]
+fn check_with_failures_should_not_complete_checkpoint() {
+    const ENTRY_COUNT: u8 = 10;
+    const FAULTY_ENTRY: u8 = 4;
+
+    let fixture =
+        LmdbTestFixture::new(vec![StateStoreDatabase::db_name()], Some(STORAGE_FILE_NAME));
+    let checkpoint_dir = tempfile::tempdir().unwrap();
+    let checkpoint_path = checkpoint_dir.path().join("checkpoint.json");
+    {
+        let db = *fixture.db(Some(StateStoreDatabase::db_name())).unwrap();
+        let mut txn = fixture.env.begin_rw_txn().unwrap();
+        for i in 0..ENTRY_COUNT {
+            let value = if i == FAULTY_ENTRY {
+                vec![i]
+            } else {
+                (i as u64).to_bytes().unwrap()
+            };
+            txn.put(db, &[i], &value, WriteFlags::empty()).unwrap();
+        }
+        txn.commit().unwrap();
+    }
+
+    // Without failfast every entry is checked, but the checkpoint stays
+    // before the faulty entry.
+    let mut reports = vec![];
+    let mut checkpoint = Checkpoint::new(&checkpoint_path);
+    assert!(check_databases(
+        &fixture.env,
+        &state_store_options(false, 0, 1),
+        &mut reports,
+        Some(&mut checkpoint),
+    )
+    .is_err());
+    assert_eq!(reports[0].entries_scanned, ENTRY_COUNT as usize);
+    let checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+    assert!(!checkpoint.is_complete(StateStoreDatabase::db_name()));
+    assert_eq!(
+        checkpoint
+            .resume_key(StateStoreDatabase::db_name())
+            .unwrap(),
+        Some(vec![FAULTY_ENTRY - 1])
+    );
+
+    // Resuming checks the faulty entry again.
+    let mut reports = vec![];
+    let mut checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+    assert!(check_databases(
+        &fixture.env,
+        &state_store_options(false, 0, 1),
+        &mut reports,
+        Some(&mut checkpoint),
+    )
+    .is_err());
+    assert_eq!(
+        reports[0].entries_scanned,
+        (ENTRY_COUNT - FAULTY_ENTRY) as usize
+    );
+    assert_eq!(reports[0].failures.len(), 1);
+}
+
+#[test]
+fn quarantined_entries_should_be_restored() {
+    let fixture =
+        LmdbTestFixture::new(vec![StateStoreDatabase::db_name()], Some(STORAGE_FILE_NAME));
+    let quarantine_dir = tempfile::tempdir().unwrap();
+    let db = *fixture.db(Some(StateStoreDatabase::db_name())).unwrap();
+    let faulty_value = [1u8, 2u8];
+    {
+        let mut txn = fixture.env.begin_rw_txn().unwrap();
+        txn.put(db, &[0u8], &0u64.to_bytes().unwrap(), WriteFlags::empty())
+            .unwrap();
+        txn.put(db, &[1u8], &faulty_value, WriteFlags::empty())
+            .unwrap();
+        txn.put(db, &[2u8], &2u64.to_bytes().unwrap(), WriteFlags::empty())
+            .unwrap();
+        txn.commit().unwrap();
+    }
+
+    let options = CheckOptions {
+        quarantine_dir: Some(quarantine_dir.path()),
+        ..state_store_options(false, 0, 1)
+    };
+    assert!(check_db(fixture.tmp_dir.path(), &options, None::<&str>, false, None).is_err());
+    assert!(quarantine_dir.path().join(QUARANTINE_FILE_NAME).exists());
+    {
+        let txn = fixture.env.begin_ro_txn().unwrap();
+        assert_eq!(txn.get(db, &[1u8]), Err(lmdb::Error::NotFound));
+        assert!(txn.get(db, &[0u8]).is_ok());
+        assert!(txn.get(db, &[2u8]).is_ok());
+        txn.commit().unwrap();
+    }
+    // With the faulty entry out of the way, the database passes the check.
+    assert!(check_db(fixture.tmp_dir.path(), &options, None::<&str>, false, None).is_ok());
+
+    assert_eq!(
+        quarantine::restore_entries(fixture.tmp_dir.path(), quarantine_dir.path()).unwrap(),
+        1
+    );
+    {
+        let txn = fixture.env.begin_ro_txn().unwrap();
+        assert_eq!(txn.get(db, &[1u8]).unwrap(), faulty_value);
+        txn.commit().unwrap();
+    }
+    // Nothing is left in quarantine.
+    assert_eq!(
+        quarantine::restore_entries(fixture.tmp_dir.path(), quarantine_dir.path()).unwrap(),
+        0
+    );
+}
+
+#[test]
+fn invalid_job_arguments_should_be_rejected() {
+    let parse = |args: &[&str]| {
+        command(0).try_get_matches_from([&["check", "--db-path", "db"][..], args].concat())
+    };
+    assert!(parse(&["--jobs", "2"]).is_ok());
+    assert!(parse(&["--jobs", "0"]).is_err());
+    assert!(parse(&["--jobs", "two"]).is_err());
+    assert!(parse(&["--jobs", "2", "--checkpoint", "checkpoint"]).is_err());
+    assert!(parse(&["--checkpoint", "checkpoint", "--resume"]).is_ok());
+    assert!(parse(&[
+        "--specific",
+        "deploys",
+        "--start-at",
+        "3",
+        "--checkpoint",
+        "checkpoint",
+        "--resume"
+    ])
+    .is_err());
+}
@@ -0,0 +1,151 @@
+mod diff;
+#[cfg(test)]
+mod tests;
+
+use std::{io::Error as IoError, path::Path};
+
+use clap::{Arg, ArgMatches, Command};
+use lmdb::Error as LmdbError;
+use serde_json::Error as SerializationError;
+use thiserror::Error as ThisError;
+
+use cargio_hashing::Digest;
+
+pub const COMMAND_NAME: &str = "diff-state";
+const NEW_ROOT: &str = "new-root";
+const NEW_TRIE_PATH: &str = "new-trie-path";
+const OLD_ROOT: &str = "old-root";
+const OUTPUT: &str = "output";
+const OVERWRITE: &str = "overwrite";
+const TRIE_PATH: &str = "trie-path";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Trie with key {0} not present in the trie store")]
+    MissingTrie(Digest),
+    #[error("Error writing output: {0}")]
+    Output(#[from] IoError),
+    #[error("Error serializing output: {0}")]
+    Serialize(#[from] SerializationError),
+    #[error("Error parsing trie with key {0}: {1}")]
+    TrieParsing(Digest, String),
+}
+
+enum DisplayOrder {
+    TriePath,
+    NewTriePath,
+    OldRoot,
+    NewRoot,
+    Output,
+    Overwrite,
+}
+
+fn parse_state_root(matches: &ArgMatches, arg: &str) -> Digest {
+    let state_root_hash_str = matches
+        .value_of(arg)
+        .unwrap_or_else(|| panic!("should have {arg} arg"));
+    Digest::from_hex(state_root_hash_str).expect("should parse state root hash to hex format")
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Walks the tries under two state roots together and outputs the keys which \
+            were added, removed or changed between them as lines of JSON, along with \
+            their old and new values.",
+        )
+        .arg(
+            Arg::new(TRIE_PATH)
+                .display_order(DisplayOrder::TriePath as usize)
+                .required(true)
+                .short('t')
+                .long(TRIE_PATH)
+                .takes_value(true)
+                .value_name("TRIE_STORE_DIR_PATH")
+                .help(
+                    "Path of the directory with the `data.lmdb` file holding the old state \
+                    root, and the new one unless `--new-trie-path` is given.",
+                ),
+        )
+        .arg(
+            Arg::new(NEW_TRIE_PATH)
+                .display_order(DisplayOrder::NewTriePath as usize)
+                .short('u')
+                .long(NEW_TRIE_PATH)
+                .takes_value(true)
+                .value_name("TRIE_STORE_DIR_PATH")
+                .help(
+                    "Path of the directory with the `data.lmdb` file holding the new state root.",
+                ),
+        )
+        .arg(
+            Arg::new(OLD_ROOT)
+                .display_order(DisplayOrder::OldRoot as usize)
+                .required(true)
+                .short('a')
+                .long(OLD_ROOT)
+                .takes_value(true)
+                .value_name("STATE_ROOT_HASH")
+                .help("Hash of the old state root."),
+        )
+        .arg(
+            Arg::new(NEW_ROOT)
+                .display_order(DisplayOrder::NewRoot as usize)
+                .required(true)
+                .short('b')
+                .long(NEW_ROOT)
+                .takes_value(true)
+                .value_name("STATE_ROOT_HASH")
+                .help("Hash of the new state root."),
+        )
+        .arg(
+            Arg::new(OUTPUT)
+                .display_order(DisplayOrder::Output as usize)
+                .short('o')
+                .long(OUTPUT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help(
+                    "Path to where the program will output the changes. \
+                    If unspecified, defaults to standard output.",
+                ),
+        )
+        .arg(
+            Arg::new(OVERWRITE)
+                .display_order(DisplayOrder::Overwrite as usize)
+                .required(false)
+                .short('w')
+                .long(OVERWRITE)
+                .takes_value(false)
+                .requires(OUTPUT)
+                .help(
+                    "Overwrite an already existing output file in destination \
+                    directory.",
+                ),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let trie_path = Path::new(
+        matches
+            .value_of(TRIE_PATH)
+            .expect("should have trie-path arg"),
+    );
+    let maybe_new_trie_path = matches.value_of(NEW_TRIE_PATH).map(Path::new);
+    let old_root = parse_state_root(matches, OLD_ROOT);
+    let new_root = parse_state_root(matches, NEW_ROOT);
+    let output = matches.value_of(OUTPUT).map(Path::new);
+    let overwrite = matches.is_present(OVERWRITE);
+    diff::diff_state_roots(
+        trie_path,
+        maybe_new_trie_path,
+        old_root,
+        new_root,
+        output,
+        overwrite,
+    )?;
+    Ok(())
+}
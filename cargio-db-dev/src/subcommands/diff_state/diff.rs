@@ -0,0 +1,353 @@
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+};
+
+use lmdb::{Database as LmdbDatabase, Error as LmdbError, RoTransaction, Transaction};
+use log::info;
+use serde::Serialize;
+
+use cargio_execution_engine::storage::trie::{Pointer, Trie};
+use cargio_hashing::Digest;
+use cargio_types::{bytesrepr, Key, StoredValue};
+
+use crate::common::db::{self, TRIE_STORE_FILE_NAME};
+
+use super::Error;
+
+/// A global state key which differs between the old and the new state root.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub(crate) enum StateChange {
+    Added {
+        key: Key,
+        new_value: StoredValue,
+    },
+    Removed {
+        key: Key,
+        old_value: StoredValue,
+    },
+    Changed {
+        key: Key,
+        old_value: StoredValue,
+        new_value: StoredValue,
+    },
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct DiffSummary {
+    pub(crate) added: u64,
+    pub(crate) removed: u64,
+    pub(crate) changed: u64,
+}
+
+/// Reads tries from the unnamed database of a trie store.
+pub(crate) struct TrieReader<'a> {
+    txn: &'a RoTransaction<'a>,
+    db: LmdbDatabase,
+}
+
+impl<'a> TrieReader<'a> {
+    pub(crate) fn new(txn: &'a RoTransaction<'a>) -> Result<Self, Error> {
+        let db = unsafe { txn.open_db(None)? };
+        Ok(Self { txn, db })
+    }
+
+    fn read(&self, trie_key: Digest) -> Result<Trie<Key, StoredValue>, Error> {
+        let raw_trie = match self.txn.get(self.db, &trie_key) {
+            Ok(raw_trie) => raw_trie,
+            Err(LmdbError::NotFound) => return Err(Error::MissingTrie(trie_key)),
+            Err(lmdb_err) => return Err(lmdb_err.into()),
+        };
+        bytesrepr::deserialize(raw_trie.to_vec())
+            .map_err(|bytesrepr_err| Error::TrieParsing(trie_key, bytesrepr_err.to_string()))
+    }
+}
+
+/// A subtrie found at the same path on both sides of the diff.
+#[derive(Clone, Debug, PartialEq)]
+enum Subtrie {
+    Stored(Digest),
+    /// What remains of an extension once the first bytes of its affix
+    /// were matched.
+    Affix {
+        affix: Vec<u8>,
+        pointer: Digest,
+    },
+}
+
+/// A subtrie read from the store: either a single leaf, or its children
+/// by the next byte of their path.
+enum Expanded {
+    Leaf(Key, StoredValue),
+    Branches(BTreeMap<u8, Subtrie>),
+}
+
+fn pointer_digest(pointer: Pointer) -> Digest {
+    match pointer {
+        Pointer::LeafPointer(pointer) | Pointer::NodePointer(pointer) => pointer,
+    }
+}
+
+fn expand_affix(affix: &[u8], pointer: Digest) -> Expanded {
+    let child = if affix.len() == 1 {
+        Subtrie::Stored(pointer)
+    } else {
+        Subtrie::Affix {
+            affix: affix[1..].to_vec(),
+            pointer,
+        }
+    };
+    Expanded::Branches(BTreeMap::from([(affix[0], child)]))
+}
+
+fn expand(reader: &TrieReader, subtrie: &Subtrie) -> Result<Expanded, Error> {
+    let (affix, pointer) = match subtrie {
+        Subtrie::Stored(trie_key) => match reader.read(*trie_key)? {
+            Trie::Leaf { key, value } => return Ok(Expanded::Leaf(key, value)),
+            Trie::Node { pointer_block } => {
+                return Ok(Expanded::Branches(
+                    pointer_block
+                        .as_indexed_pointers()
+                        .map(|(index, pointer)| (index, Subtrie::Stored(pointer_digest(pointer))))
+                        .collect(),
+                ))
+            }
+            Trie::Extension { affix, pointer } => (affix.to_vec(), pointer_digest(pointer)),
+        },
+        Subtrie::Affix { affix, pointer } => (affix.clone(), *pointer),
+    };
+    if affix.is_empty() {
+        return expand(reader, &Subtrie::Stored(pointer));
+    }
+    Ok(expand_affix(&affix, pointer))
+}
+
+/// Calls `f` on every leaf of `subtrie`.
+fn for_each_leaf<F>(reader: &TrieReader, subtrie: Subtrie, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(Key, StoredValue) -> Result<(), Error>,
+{
+    let mut pending = vec![subtrie];
+    while let Some(subtrie) = pending.pop() {
+        match expand(reader, &subtrie)? {
+            Expanded::Leaf(key, value) => f(key, value)?,
+            Expanded::Branches(branches) => pending.extend(branches.into_values().rev()),
+        }
+    }
+    Ok(())
+}
+
+/// Writes the changes as JSON lines and counts them.
+struct ChangeWriter<'a, W: Write + ?Sized> {
+    out_writer: &'a mut W,
+    summary: DiffSummary,
+}
+
+impl<'a, W: Write + ?Sized> ChangeWriter<'a, W> {
+    fn write(&mut self, change: StateChange) -> Result<(), Error> {
+        match change {
+            StateChange::Added { .. } => self.summary.added += 1,
+            StateChange::Removed { .. } => self.summary.removed += 1,
+            StateChange::Changed { .. } => self.summary.changed += 1,
+        }
+        serde_json::to_writer(&mut *self.out_writer, &change)?;
+        writeln!(self.out_writer)?;
+        Ok(())
+    }
+
+    fn write_leaf_diff(
+        &mut self,
+        key: Key,
+        old_value: StoredValue,
+        new_value: StoredValue,
+    ) -> Result<(), Error> {
+        if old_value == new_value {
+            return Ok(());
+        }
+        self.write(StateChange::Changed {
+            key,
+            old_value,
+            new_value,
+        })
+    }
+}
+
+/// Compares a single leaf on one side with a subtrie on the other. Called
+/// with `leaf_is_old` when the leaf is in the old trie.
+fn diff_leaf_with_subtrie<W: Write + ?Sized>(
+    writer: &mut ChangeWriter<W>,
+    reader: &TrieReader,
+    (leaf_key, leaf_value): (Key, StoredValue),
+    subtrie: Subtrie,
+    leaf_is_old: bool,
+) -> Result<(), Error> {
+    let mut maybe_leaf_value = Some(leaf_value);
+    for_each_leaf(reader, subtrie, |key, value| {
+        if key == leaf_key {
+            let leaf_value = maybe_leaf_value
+                .take()
+                .expect("keys should be unique in a trie");
+            if leaf_is_old {
+                writer.write_leaf_diff(key, leaf_value, value)
+            } else {
+                writer.write_leaf_diff(key, value, leaf_value)
+            }
+        } else if leaf_is_old {
+            writer.write(StateChange::Added {
+                key,
+                new_value: value,
+            })
+        } else {
+            writer.write(StateChange::Removed {
+                key,
+                old_value: value,
+            })
+        }
+    })?;
+    match maybe_leaf_value {
+        Some(old_value) if leaf_is_old => writer.write(StateChange::Removed {
+            key: leaf_key,
+            old_value,
+        }),
+        Some(new_value) => writer.write(StateChange::Added {
+            key: leaf_key,
+            new_value,
+        }),
+        None => Ok(()),
+    }
+}
+
+type SubtriePair = (Option<Subtrie>, Option<Subtrie>);
+
+/// Compares two different subtries found at the same path, queueing the pairs
+/// of their children which have to be compared next.
+fn diff_subtries<W: Write + ?Sized>(
+    writer: &mut ChangeWriter<W>,
+    old_reader: &TrieReader,
+    new_reader: &TrieReader,
+    (old, new): (Subtrie, Subtrie),
+    pending: &mut Vec<SubtriePair>,
+) -> Result<(), Error> {
+    match (expand(old_reader, &old)?, expand(new_reader, &new)?) {
+        (Expanded::Leaf(old_key, old_value), Expanded::Leaf(new_key, new_value)) => {
+            if old_key == new_key {
+                return writer.write_leaf_diff(old_key, old_value, new_value);
+            }
+            writer.write(StateChange::Removed {
+                key: old_key,
+                old_value,
+            })?;
+            writer.write(StateChange::Added {
+                key: new_key,
+                new_value,
+            })
+        }
+        (Expanded::Leaf(old_key, old_value), Expanded::Branches(_)) => {
+            diff_leaf_with_subtrie(writer, new_reader, (old_key, old_value), new, true)
+        }
+        (Expanded::Branches(_), Expanded::Leaf(new_key, new_value)) => {
+            diff_leaf_with_subtrie(writer, old_reader, (new_key, new_value), old, false)
+        }
+        (Expanded::Branches(mut old_branches), Expanded::Branches(new_branches)) => {
+            let mut pairs: BTreeMap<u8, SubtriePair> = new_branches
+                .into_iter()
+                .map(|(index, new)| (index, (old_branches.remove(&index), Some(new))))
+                .collect();
+            pairs.extend(
+                old_branches
+                    .into_iter()
+                    .map(|(index, old)| (index, (Some(old), None))),
+            );
+            // Pushed in reverse so the keys come out in path order.
+            pending.extend(pairs.into_values().rev());
+            Ok(())
+        }
+    }
+}
+
+/// Walks the tries under `old_root` and `new_root` together, writing every
+/// added, removed or changed key to `out_writer` as a line of JSON.
+/// Subtries with the same digest on both sides aren't walked.
+pub(crate) fn diff_state<W: Write + ?Sized>(
+    old_reader: &TrieReader,
+    new_reader: &TrieReader,
+    old_root: Digest,
+    new_root: Digest,
+    out_writer: &mut W,
+) -> Result<DiffSummary, Error> {
+    let mut writer = ChangeWriter {
+        out_writer,
+        summary: DiffSummary::default(),
+    };
+    let mut pending = vec![(
+        Some(Subtrie::Stored(old_root)),
+        Some(Subtrie::Stored(new_root)),
+    )];
+    while let Some(pair) = pending.pop() {
+        match pair {
+            (Some(old), Some(new)) if old == new => {}
+            (Some(old), Some(new)) => diff_subtries(
+                &mut writer,
+                old_reader,
+                new_reader,
+                (old, new),
+                &mut pending,
+            )?,
+            (Some(old), None) => for_each_leaf(old_reader, old, |key, old_value| {
+                writer.write(StateChange::Removed { key, old_value })
+            })?,
+            (None, Some(new)) => for_each_leaf(new_reader, new, |key, new_value| {
+                writer.write(StateChange::Added { key, new_value })
+            })?,
+            (None, None) => {}
+        }
+    }
+    Ok(writer.summary)
+}
+
+/// Diffs `old_root` in the trie store in `trie_path` against `new_root` in
+/// the one in `new_trie_path`, or in the same store if not given.
+pub(crate) fn diff_state_roots<P1: AsRef<Path>, P2: AsRef<Path>, P3: AsRef<Path>>(
+    trie_path: P1,
+    maybe_new_trie_path: Option<P2>,
+    old_root: Digest,
+    new_root: Digest,
+    output: Option<P3>,
+    overwrite: bool,
+) -> Result<DiffSummary, Error> {
+    let mut out_writer: Box<dyn Write> = if let Some(out_path) = output {
+        let file = OpenOptions::new()
+            .create_new(!overwrite)
+            .create(overwrite)
+            .truncate(overwrite)
+            .write(true)
+            .open(out_path)?;
+        Box::new(io::BufWriter::new(file))
+    } else {
+        Box::new(io::stdout())
+    };
+
+    let old_env = db::db_env(trie_path.as_ref().join(TRIE_STORE_FILE_NAME))?;
+    let maybe_new_env = maybe_new_trie_path
+        .map(|new_trie_path| db::db_env(new_trie_path.as_ref().join(TRIE_STORE_FILE_NAME)))
+        .transpose()?;
+    let old_txn = old_env.begin_ro_txn()?;
+    let maybe_new_txn = maybe_new_env
+        .as_ref()
+        .map(|new_env| new_env.begin_ro_txn())
+        .transpose()?;
+    let old_reader = TrieReader::new(&old_txn)?;
+    let maybe_new_reader = maybe_new_txn.as_ref().map(TrieReader::new).transpose()?;
+    let new_reader = maybe_new_reader.as_ref().unwrap_or(&old_reader);
+
+    let summary = diff_state(&old_reader, new_reader, old_root, new_root, &mut out_writer)?;
+    out_writer.flush()?;
+    info!(
+        "Found {} added, {} removed and {} changed keys.",
+        summary.added, summary.removed, summary.changed
+    );
+    Ok(summary)
+}
@@ -0,0 +1,272 @@
+use cargio_execution_engine::storage::trie::{Pointer, PointerBlock, Trie};
+use cargio_hashing::Digest;
+use cargio_types::{account::AccountHash, CLValue, Key, StoredValue};
+
+use crate::{
+    common::db::TRIE_STORE_FILE_NAME,
+    test_utils::{put_trie, LmdbTestFixture},
+};
+
+use super::{
+    diff::{self, DiffSummary, StateChange, TrieReader},
+    Error,
+};
+
+fn key(idx: u8) -> Key {
+    Key::Account(AccountHash::new([idx; 32]))
+}
+
+fn value(idx: u64) -> StoredValue {
+    StoredValue::CLValue(CLValue::from_t(idx).unwrap())
+}
+
+fn trie_fixture() -> LmdbTestFixture {
+    LmdbTestFixture::new(vec![], Some(TRIE_STORE_FILE_NAME))
+}
+
+fn put_leaf(fixture: &LmdbTestFixture, key_idx: u8, value_idx: u64) -> Digest {
+    put_trie(
+        fixture,
+        &Trie::Leaf {
+            key: key(key_idx),
+            value: value(value_idx),
+        },
+    )
+}
+
+fn put_node(fixture: &LmdbTestFixture, pointers: &[(usize, Pointer)]) -> Digest {
+    let mut pointer_block = PointerBlock::new();
+    for (index, pointer) in pointers {
+        pointer_block[*index] = Some(*pointer);
+    }
+    put_trie(
+        fixture,
+        &Trie::Node {
+            pointer_block: Box::new(pointer_block),
+        },
+    )
+}
+
+fn diff_in(
+    old_fixture: &LmdbTestFixture,
+    new_fixture: &LmdbTestFixture,
+    old_root: Digest,
+    new_root: Digest,
+) -> Result<(Vec<String>, DiffSummary), Error> {
+    let old_txn = old_fixture.env.begin_ro_txn().unwrap();
+    let new_txn = new_fixture.env.begin_ro_txn().unwrap();
+    let old_reader = TrieReader::new(&old_txn).unwrap();
+    let new_reader = TrieReader::new(&new_txn).unwrap();
+    let mut output = vec![];
+    let summary = diff::diff_state(&old_reader, &new_reader, old_root, new_root, &mut output)?;
+    let lines = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect();
+    Ok((lines, summary))
+}
+
+fn diff(
+    fixture: &LmdbTestFixture,
+    old_root: Digest,
+    new_root: Digest,
+) -> Result<(Vec<String>, DiffSummary), Error> {
+    diff_in(fixture, fixture, old_root, new_root)
+}
+
+fn json_lines(changes: Vec<StateChange>) -> Vec<String> {
+    changes
+        .iter()
+        .map(|change| serde_json::to_string(change).unwrap())
+        .collect()
+}
+
+#[test]
+fn same_root_should_have_no_changes() {
+    let fixture = trie_fixture();
+    let leaf = put_leaf(&fixture, 1, 1);
+    let root = put_node(&fixture, &[(1, Pointer::LeafPointer(leaf))]);
+
+    let (lines, summary) = diff(&fixture, root, root).unwrap();
+    assert!(lines.is_empty());
+    assert_eq!(summary, DiffSummary::default());
+}
+
+#[test]
+fn added_removed_and_changed_keys_should_be_found() {
+    let fixture = trie_fixture();
+    let leaf_1 = put_leaf(&fixture, 1, 1);
+    let leaf_2 = put_leaf(&fixture, 2, 2);
+    let leaf_3 = put_leaf(&fixture, 3, 3);
+    let changed_leaf_2 = put_leaf(&fixture, 2, 20);
+    let leaf_4 = put_leaf(&fixture, 4, 4);
+    let old_root = put_node(
+        &fixture,
+        &[
+            (1, Pointer::LeafPointer(leaf_1)),
+            (2, Pointer::LeafPointer(leaf_2)),
+            (3, Pointer::LeafPointer(leaf_3)),
+        ],
+    );
+    let new_root = put_node(
+        &fixture,
+        &[
+            (1, Pointer::LeafPointer(leaf_1)),
+            (2, Pointer::LeafPointer(changed_leaf_2)),
+            (4, Pointer::LeafPointer(leaf_4)),
+        ],
+    );
+
+    let (lines, summary) = diff(&fixture, old_root, new_root).unwrap();
+    assert_eq!(
+        lines,
+        json_lines(vec![
+            StateChange::Changed {
+                key: key(2),
+                old_value: value(2),
+                new_value: value(20),
+            },
+            StateChange::Removed {
+                key: key(3),
+                old_value: value(3),
+            },
+            StateChange::Added {
+                key: key(4),
+                new_value: value(4),
+            },
+        ])
+    );
+    assert_eq!(
+        summary,
+        DiffSummary {
+            added: 1,
+            removed: 1,
+            changed: 1
+        }
+    );
+}
+
+#[test]
+fn leaf_replaced_by_node_should_only_report_new_keys() {
+    let fixture = trie_fixture();
+    let leaf_1 = put_leaf(&fixture, 1, 1);
+    let leaf_2 = put_leaf(&fixture, 2, 2);
+    let old_root = put_node(&fixture, &[(1, Pointer::LeafPointer(leaf_1))]);
+    let inner_node = put_node(
+        &fixture,
+        &[
+            (0, Pointer::LeafPointer(leaf_1)),
+            (5, Pointer::LeafPointer(leaf_2)),
+        ],
+    );
+    let new_root = put_node(&fixture, &[(1, Pointer::NodePointer(inner_node))]);
+
+    let (lines, _) = diff(&fixture, old_root, new_root).unwrap();
+    assert_eq!(
+        lines,
+        json_lines(vec![StateChange::Added {
+            key: key(2),
+            new_value: value(2),
+        }])
+    );
+
+    let (lines, _) = diff(&fixture, new_root, old_root).unwrap();
+    assert_eq!(
+        lines,
+        json_lines(vec![StateChange::Removed {
+            key: key(2),
+            old_value: value(2),
+        }])
+    );
+}
+
+#[test]
+fn extension_should_be_matched_against_nodes() {
+    let fixture = trie_fixture();
+    let leaf_1 = put_leaf(&fixture, 1, 1);
+    let leaf_2 = put_leaf(&fixture, 2, 2);
+    let old_node = put_node(&fixture, &[(0, Pointer::LeafPointer(leaf_1))]);
+    let old_root = put_trie(
+        &fixture,
+        &Trie::Extension {
+            affix: vec![1u8, 2].into(),
+            pointer: Pointer::NodePointer(old_node),
+        },
+    );
+    let new_node = put_node(
+        &fixture,
+        &[
+            (0, Pointer::LeafPointer(leaf_1)),
+            (1, Pointer::LeafPointer(leaf_2)),
+        ],
+    );
+    let new_middle = put_node(&fixture, &[(2, Pointer::NodePointer(new_node))]);
+    let new_root = put_node(&fixture, &[(1, Pointer::NodePointer(new_middle))]);
+
+    let (lines, _) = diff(&fixture, old_root, new_root).unwrap();
+    assert_eq!(
+        lines,
+        json_lines(vec![StateChange::Added {
+            key: key(2),
+            new_value: value(2),
+        }])
+    );
+}
+
+#[test]
+fn equal_subtries_should_be_skipped() {
+    let fixture = trie_fixture();
+    // Not in the store, so reading it would fail.
+    let shared_subtrie = Digest::hash([1u8; 8]);
+    let leaf_1 = put_leaf(&fixture, 1, 1);
+    let changed_leaf_1 = put_leaf(&fixture, 1, 10);
+    let old_root = put_node(
+        &fixture,
+        &[
+            (0, Pointer::NodePointer(shared_subtrie)),
+            (1, Pointer::LeafPointer(leaf_1)),
+        ],
+    );
+    let new_root = put_node(
+        &fixture,
+        &[
+            (0, Pointer::NodePointer(shared_subtrie)),
+            (1, Pointer::LeafPointer(changed_leaf_1)),
+        ],
+    );
+
+    let (_, summary) = diff(&fixture, old_root, new_root).unwrap();
+    assert_eq!(
+        summary,
+        DiffSummary {
+            changed: 1,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn roots_in_different_stores_should_be_diffed() {
+    let old_fixture = trie_fixture();
+    let new_fixture = trie_fixture();
+    let old_leaf = put_leaf(&old_fixture, 1, 1);
+    let old_root = put_node(&old_fixture, &[(1, Pointer::LeafPointer(old_leaf))]);
+    let new_leaf = put_leaf(&new_fixture, 1, 2);
+    let new_root = put_node(&new_fixture, &[(1, Pointer::LeafPointer(new_leaf))]);
+
+    let (lines, _) = diff_in(&old_fixture, &new_fixture, old_root, new_root).unwrap();
+    assert_eq!(
+        lines,
+        json_lines(vec![StateChange::Changed {
+            key: key(1),
+            old_value: value(1),
+            new_value: value(2),
+        }])
+    );
+
+    assert!(matches!(
+        diff_in(&new_fixture, &new_fixture, old_root, new_root),
+        Err(Error::MissingTrie(trie_key)) if trie_key == old_root
+    ));
+}
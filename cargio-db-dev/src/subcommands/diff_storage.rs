@@ -0,0 +1,207 @@
+mod diff;
+#[cfg(test)]
+mod tests;
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Error as IoError, Write},
+    path::Path,
+};
+
+use bincode::Error as BincodeError;
+use clap::{Arg, ArgMatches, Command};
+use lmdb::Error as LmdbError;
+use log::info;
+use master_node::types::{BlockHash, DeployHash};
+use serde_json::Error as SerializationError;
+use thiserror::Error as ThisError;
+
+use crate::common::{block_index::BlockIndexError, db::DeserializationError};
+
+pub const COMMAND_NAME: &str = "diff-storage";
+const FROM_HEIGHT: &str = "from-height";
+const LEFT_DB_PATH: &str = "left-db-path";
+const OUTPUT: &str = "output";
+const OVERWRITE: &str = "overwrite";
+const RIGHT_DB_PATH: &str = "right-db-path";
+const SUMMARY: &str = "summary";
+const TO_HEIGHT: &str = "to-height";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error reading the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
+    #[error("Error parsing block body for block with hash {0}: {1}")]
+    BodyParsing(BlockHash, BincodeError),
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Error parsing block header with hash {0}: {1}")]
+    HeaderParsing(BlockHash, BincodeError),
+    #[error("Invalid block hash key {0}")]
+    InvalidBlockHash(String),
+    #[error("Error parsing merkle block body for block with hash {0}: {1}")]
+    MerkleBodyParsing(BlockHash, DeserializationError),
+    #[error("Error parsing deploy metadata for deploy with hash {0}: {1}")]
+    MetadataParsing(DeployHash, BincodeError),
+    #[error("Block header for block hash {0} not present in the database")]
+    MissingHeader(BlockHash),
+    #[error("Error writing output: {0}")]
+    Output(#[from] IoError),
+    #[error("Error serializing output: {0}")]
+    Serialize(#[from] SerializationError),
+}
+
+enum DisplayOrder {
+    LeftDbPath,
+    RightDbPath,
+    FromHeight,
+    ToHeight,
+    Output,
+    Summary,
+    Overwrite,
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Compares two storages block by block, reporting blocks, header fields, deploy \
+            lists and execution results which differ at the same height, as well as \
+            databases present in only one of them, missing block bodies and heights with \
+            several blocks. Every difference is output as a line of JSON, followed by a JSON \
+            summary.",
+        )
+        .arg(
+            Arg::new(LEFT_DB_PATH)
+                .display_order(DisplayOrder::LeftDbPath as usize)
+                .required(true)
+                .short('l')
+                .long(LEFT_DB_PATH)
+                .takes_value(true)
+                .value_name("DB_PATH")
+                .help("Path of the directory with the first `storage.lmdb` file."),
+        )
+        .arg(
+            Arg::new(RIGHT_DB_PATH)
+                .display_order(DisplayOrder::RightDbPath as usize)
+                .required(true)
+                .short('r')
+                .long(RIGHT_DB_PATH)
+                .takes_value(true)
+                .value_name("DB_PATH")
+                .help("Path of the directory with the second `storage.lmdb` file."),
+        )
+        .arg(
+            Arg::new(FROM_HEIGHT)
+                .display_order(DisplayOrder::FromHeight as usize)
+                .short('f')
+                .long(FROM_HEIGHT)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .help("Height of the lowest block to compare. Defaults to 0."),
+        )
+        .arg(
+            Arg::new(TO_HEIGHT)
+                .display_order(DisplayOrder::ToHeight as usize)
+                .short('t')
+                .long(TO_HEIGHT)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .help("Height of the highest block to compare. Defaults to the highest block."),
+        )
+        .arg(
+            Arg::new(OUTPUT)
+                .display_order(DisplayOrder::Output as usize)
+                .short('o')
+                .long(OUTPUT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help(
+                    "Path to where the program will output the differences as lines of JSON. \
+                    If unspecified, defaults to standard output.",
+                ),
+        )
+        .arg(
+            Arg::new(SUMMARY)
+                .display_order(DisplayOrder::Summary as usize)
+                .short('s')
+                .long(SUMMARY)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help(
+                    "Path to where the program will output the JSON summary. \
+                    If unspecified, the summary is logged.",
+                ),
+        )
+        .arg(
+            Arg::new(OVERWRITE)
+                .display_order(DisplayOrder::Overwrite as usize)
+                .required(false)
+                .short('w')
+                .long(OVERWRITE)
+                .takes_value(false)
+                .help("Overwrite already existing output files."),
+        )
+}
+
+fn parse_height(matches: &ArgMatches, arg: &str) -> Option<u64> {
+    matches.value_of(arg).map(|height| {
+        height
+            .parse()
+            .unwrap_or_else(|_| panic!("Value of \"--{arg}\" must be an integer."))
+    })
+}
+
+fn create_output(path: &Path, overwrite: bool) -> Result<File, IoError> {
+    OpenOptions::new()
+        .create_new(!overwrite)
+        .create(overwrite)
+        .truncate(overwrite)
+        .write(true)
+        .open(path)
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let left_path = Path::new(
+        matches
+            .value_of(LEFT_DB_PATH)
+            .expect("should have left-db-path arg"),
+    );
+    let right_path = Path::new(
+        matches
+            .value_of(RIGHT_DB_PATH)
+            .expect("should have right-db-path arg"),
+    );
+    let maybe_from_height = parse_height(matches, FROM_HEIGHT);
+    let maybe_to_height = parse_height(matches, TO_HEIGHT);
+    let overwrite = matches.is_present(OVERWRITE);
+    // Created before diffing so an existing file doesn't fail a long run at
+    // its end.
+    let maybe_summary_file = matches
+        .value_of(SUMMARY)
+        .map(|summary_path| create_output(Path::new(summary_path), overwrite))
+        .transpose()?;
+    let mut out_writer: Box<dyn Write> = if let Some(out_path) = matches.value_of(OUTPUT) {
+        let file = create_output(Path::new(out_path), overwrite)?;
+        Box::new(BufWriter::new(file))
+    } else {
+        Box::new(io::stdout())
+    };
+
+    let summary = diff::diff_storage(
+        left_path,
+        right_path,
+        maybe_from_height,
+        maybe_to_height,
+        &mut out_writer,
+    )?;
+    out_writer.flush()?;
+    match maybe_summary_file {
+        Some(summary_file) => serde_json::to_writer_pretty(summary_file, &summary)?,
+        None => info!("Summary: {}", serde_json::to_string_pretty(&summary)?),
+    }
+    if summary.is_empty() {
+        info!("No differences found.");
+    }
+    Ok(())
+}
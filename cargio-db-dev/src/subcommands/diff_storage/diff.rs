@@ -0,0 +1,606 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
+    io::Write,
+    path::Path,
+};
+
+use lmdb::{
+    Cursor, Database as LmdbDatabase, Environment, Error as LmdbError, RoTransaction, Transaction,
+};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+
+use cargio_hashing::Digest;
+use cargio_types::ExecutionResult;
+use master_node::types::{BlockHash, BlockHeader, DeployHash, DeployMetadata};
+
+use crate::{
+    common::{
+        block_index::BlockIndex,
+        db::{
+            self, BlockBodyDatabase, BlockBodyMerkleDatabase, BlockHeaderDatabase, Database,
+            DeployHashesDatabase, DeployMetadataDatabase, TransferHashesDatabase,
+            STORAGE_FILE_NAME,
+        },
+        lmdb_utils,
+        progress::ProgressTracker,
+    },
+    subcommands::execution_results_summary::block_body::BlockBody,
+};
+
+use super::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Side {
+    Left,
+    Right,
+}
+
+/// A difference between the two storages, written as a line of JSON.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum StorageDifference {
+    /// The database is only present on one side.
+    MissingDatabase {
+        database: &'static str,
+        missing_from: Side,
+    },
+    /// There is a block at `height` on one side only.
+    MissingBlock {
+        height: u64,
+        block_hash: BlockHash,
+        missing_from: Side,
+    },
+    /// There is more than one block at `height` on `side`, of which only the
+    /// first in `block_hashes` is compared.
+    DuplicateBlock {
+        height: u64,
+        block_hashes: Vec<BlockHash>,
+        side: Side,
+    },
+    /// The body of the block at `height` on one side isn't stored, so its
+    /// deploys aren't compared.
+    MissingBody {
+        height: u64,
+        block_hash: BlockHash,
+        missing_from: Side,
+    },
+    BlockHash {
+        height: u64,
+        left: BlockHash,
+        right: BlockHash,
+    },
+    HeaderField {
+        height: u64,
+        field: &'static str,
+        left: Value,
+        right: Value,
+    },
+    /// Deploys and transfers which are only in the block on one side.
+    DeployList {
+        height: u64,
+        only_left: Vec<DeployHash>,
+        only_right: Vec<DeployHash>,
+    },
+    ExecutionResult {
+        height: u64,
+        deploy_hash: DeployHash,
+        left: Option<ExecutionResult>,
+        right: Option<ExecutionResult>,
+    },
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct DiffSummary {
+    pub(crate) heights_compared: u64,
+    pub(crate) first_differing_height: Option<u64>,
+    pub(crate) missing_databases: u64,
+    pub(crate) missing_blocks: u64,
+    pub(crate) duplicate_blocks: u64,
+    pub(crate) missing_bodies: u64,
+    pub(crate) block_hashes: u64,
+    pub(crate) header_fields: u64,
+    pub(crate) deploy_lists: u64,
+    pub(crate) execution_results: u64,
+}
+
+impl DiffSummary {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.missing_databases == 0
+            && self.missing_blocks == 0
+            && self.duplicate_blocks == 0
+            && self.missing_bodies == 0
+            && self.block_hashes == 0
+            && self.header_fields == 0
+            && self.deploy_lists == 0
+            && self.execution_results == 0
+    }
+}
+
+/// Writes the differences as JSON lines and counts them.
+struct DifferenceWriter<'a, W: Write + ?Sized> {
+    out_writer: &'a mut W,
+    summary: DiffSummary,
+}
+
+impl<'a, W: Write + ?Sized> DifferenceWriter<'a, W> {
+    fn write(&mut self, difference: StorageDifference) -> Result<(), Error> {
+        let maybe_height = match &difference {
+            StorageDifference::MissingDatabase { .. } => {
+                self.summary.missing_databases += 1;
+                None
+            }
+            StorageDifference::MissingBlock { height, .. } => {
+                self.summary.missing_blocks += 1;
+                Some(*height)
+            }
+            StorageDifference::DuplicateBlock { height, .. } => {
+                self.summary.duplicate_blocks += 1;
+                Some(*height)
+            }
+            StorageDifference::MissingBody { height, .. } => {
+                self.summary.missing_bodies += 1;
+                Some(*height)
+            }
+            StorageDifference::BlockHash { height, .. } => {
+                self.summary.block_hashes += 1;
+                Some(*height)
+            }
+            StorageDifference::HeaderField { height, .. } => {
+                self.summary.header_fields += 1;
+                Some(*height)
+            }
+            StorageDifference::DeployList { height, .. } => {
+                self.summary.deploy_lists += 1;
+                Some(*height)
+            }
+            StorageDifference::ExecutionResult { height, .. } => {
+                self.summary.execution_results += 1;
+                Some(*height)
+            }
+        };
+        if let Some(height) = maybe_height {
+            self.summary.first_differing_height = Some(
+                self.summary
+                    .first_differing_height
+                    .map_or(height, |first_height| first_height.min(height)),
+            );
+        }
+        serde_json::to_writer(&mut *self.out_writer, &difference)?;
+        writeln!(self.out_writer)?;
+        Ok(())
+    }
+}
+
+fn open_optional_db<T: Transaction>(
+    txn: &T,
+    db_name: &str,
+) -> Result<Option<LmdbDatabase>, LmdbError> {
+    match unsafe { txn.open_db(Some(db_name)) } {
+        Ok(db) => Ok(Some(db)),
+        Err(LmdbError::NotFound) => Ok(None),
+        Err(lmdb_err) => Err(lmdb_err),
+    }
+}
+
+/// The databases of `storage_database_names` which are in the storage.
+fn present_databases(env: &Environment) -> Result<BTreeSet<&'static str>, Error> {
+    let txn = env.begin_ro_txn()?;
+    let mut present = BTreeSet::new();
+    for db_name in db::storage_database_names() {
+        match unsafe { txn.open_db(Some(db_name)) } {
+            Ok(_) => {
+                present.insert(db_name);
+            }
+            Err(LmdbError::NotFound) => {}
+            Err(lmdb_err) => return Err(lmdb_err.into()),
+        }
+    }
+    txn.commit()?;
+    Ok(present)
+}
+
+/// Hashes of the heights with more than one block, in the order they are
+/// stored.
+type DuplicateBlocks = BTreeMap<u64, Vec<BlockHash>>;
+
+/// Returns the hash of the first block at every height in the storage,
+/// using the block index if it is up to date, along with the heights which
+/// have more than one block.
+fn block_hashes_by_height<P: AsRef<Path>>(
+    db_path: P,
+    env: &Environment,
+) -> Result<(BTreeMap<u64, BlockHash>, DuplicateBlocks), Error> {
+    if let Some(block_index) = BlockIndex::open(&db_path, env)? {
+        return Ok((block_index.blocks()?, BTreeMap::new()));
+    }
+    let txn = env.begin_ro_txn()?;
+    let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+    let mut maybe_progress_tracker = ProgressTracker::new(
+        lmdb_utils::entry_count(&txn, header_db)?,
+        Box::new(|completion| info!("Header database parsing {}% complete...", completion)),
+    )
+    .ok();
+    let mut blocks = BTreeMap::new();
+    let mut duplicates = DuplicateBlocks::new();
+    if let Ok(mut cursor) = txn.open_ro_cursor(header_db) {
+        for (raw_key, raw_val) in cursor.iter() {
+            let block_hash: BlockHash = Digest::try_from(raw_key)
+                .map_err(|_| Error::InvalidBlockHash(hex::encode(raw_key)))?
+                .into();
+            let header: BlockHeader = bincode::deserialize(raw_val)
+                .map_err(|bincode_err| Error::HeaderParsing(block_hash, bincode_err))?;
+            match blocks.entry(header.height()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(block_hash);
+                }
+                Entry::Occupied(entry) => duplicates
+                    .entry(header.height())
+                    .or_insert_with(|| vec![*entry.get()])
+                    .push(block_hash),
+            }
+            if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
+                progress_tracker.advance_by(1);
+            }
+        }
+    }
+    txn.commit()?;
+    Ok((blocks, duplicates))
+}
+
+/// A block as stored on one side.
+struct StoredBlock {
+    block_hash: BlockHash,
+    header: BlockHeader,
+    /// The deploys and transfers of the body, legacy or merkle, unless it is
+    /// missing.
+    maybe_deploy_hashes: Option<BTreeSet<DeployHash>>,
+}
+
+/// Read access to the block databases of one side.
+struct BlockReader<'a> {
+    txn: RoTransaction<'a>,
+    header_db: LmdbDatabase,
+    body_db: LmdbDatabase,
+    maybe_body_merkle_db: Option<LmdbDatabase>,
+    maybe_deploy_hashes_db: Option<LmdbDatabase>,
+    maybe_transfer_hashes_db: Option<LmdbDatabase>,
+    deploy_metadata_db: LmdbDatabase,
+}
+
+impl<'a> BlockReader<'a> {
+    fn new(env: &'a Environment) -> Result<Self, Error> {
+        let txn = env.begin_ro_txn()?;
+        let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+        let body_db = unsafe { txn.open_db(Some(BlockBodyDatabase::db_name()))? };
+        let maybe_body_merkle_db = open_optional_db(&txn, BlockBodyMerkleDatabase::db_name())?;
+        let maybe_deploy_hashes_db = open_optional_db(&txn, DeployHashesDatabase::db_name())?;
+        let maybe_transfer_hashes_db = open_optional_db(&txn, TransferHashesDatabase::db_name())?;
+        let deploy_metadata_db = unsafe { txn.open_db(Some(DeployMetadataDatabase::db_name()))? };
+        Ok(Self {
+            txn,
+            header_db,
+            body_db,
+            maybe_body_merkle_db,
+            maybe_deploy_hashes_db,
+            maybe_transfer_hashes_db,
+            deploy_metadata_db,
+        })
+    }
+
+    /// Adds the hashes stored in `maybe_db` under `leaf`, if any, to
+    /// `deploy_hashes`.
+    fn read_merkle_leaf<D: Database<Element = Vec<cargio_types::DeployHash>>>(
+        &self,
+        maybe_db: Option<LmdbDatabase>,
+        block_hash: BlockHash,
+        leaf: &Digest,
+        deploy_hashes: &mut BTreeSet<DeployHash>,
+    ) -> Result<(), Error> {
+        let raw_hashes = match maybe_db.map(|db| self.txn.get(db, leaf)) {
+            Some(Ok(raw_hashes)) => raw_hashes,
+            None | Some(Err(LmdbError::NotFound)) => return Ok(()),
+            Some(Err(lmdb_err)) => return Err(lmdb_err.into()),
+        };
+        let leaf_hashes = D::parse_element(raw_hashes)
+            .map_err(|parsing_err| Error::MerkleBodyParsing(block_hash, parsing_err))?;
+        deploy_hashes.extend(
+            leaf_hashes
+                .iter()
+                .map(|deploy_hash| DeployHash::new(Digest::from(deploy_hash.value()))),
+        );
+        Ok(())
+    }
+
+    /// Reads the deploys and transfers of a merkle body, which are stored in
+    /// the leaves of the `block_body_merkle` chain starting at `body_hash`
+    /// instead of `block_body`. Returns `None` if there is no such chain.
+    fn read_merkle_body(
+        &self,
+        block_hash: BlockHash,
+        body_hash: &Digest,
+    ) -> Result<Option<BTreeSet<DeployHash>>, Error> {
+        let body_merkle_db = match self.maybe_body_merkle_db {
+            Some(body_merkle_db) => body_merkle_db,
+            None => return Ok(None),
+        };
+        let mut visited = BTreeSet::new();
+        let mut deploy_hashes = BTreeSet::new();
+        let mut key = *body_hash;
+        loop {
+            let raw_link = match self.txn.get(body_merkle_db, &key) {
+                Ok(raw_link) => raw_link,
+                Err(LmdbError::NotFound) if visited.is_empty() => return Ok(None),
+                Err(LmdbError::NotFound) => break,
+                Err(lmdb_err) => return Err(lmdb_err.into()),
+            };
+            let (leaf, right) = BlockBodyMerkleDatabase::parse_element(raw_link)
+                .map_err(|parsing_err| Error::MerkleBodyParsing(block_hash, parsing_err))?;
+            self.read_merkle_leaf::<DeployHashesDatabase>(
+                self.maybe_deploy_hashes_db,
+                block_hash,
+                &leaf,
+                &mut deploy_hashes,
+            )?;
+            self.read_merkle_leaf::<TransferHashesDatabase>(
+                self.maybe_transfer_hashes_db,
+                block_hash,
+                &leaf,
+                &mut deploy_hashes,
+            )?;
+            visited.insert(key);
+            // Guards against looping forever over a corrupted database.
+            if visited.contains(&right) {
+                break;
+            }
+            key = right;
+        }
+        Ok(Some(deploy_hashes))
+    }
+
+    fn read_block(&self, block_hash: BlockHash) -> Result<StoredBlock, Error> {
+        let header: BlockHeader = match self.txn.get(self.header_db, &block_hash) {
+            Ok(raw_header) => bincode::deserialize(raw_header)
+                .map_err(|bincode_err| Error::HeaderParsing(block_hash, bincode_err))?,
+            Err(LmdbError::NotFound) => return Err(Error::MissingHeader(block_hash)),
+            Err(lmdb_err) => return Err(lmdb_err.into()),
+        };
+        let maybe_deploy_hashes = match self.txn.get(self.body_db, header.body_hash()) {
+            Ok(raw_body) => {
+                let body: BlockBody = bincode::deserialize(raw_body)
+                    .map_err(|bincode_err| Error::BodyParsing(block_hash, bincode_err))?;
+                Some(
+                    body.deploy_hashes
+                        .into_iter()
+                        .chain(body.transfer_hashes)
+                        .collect(),
+                )
+            }
+            Err(LmdbError::NotFound) => self.read_merkle_body(block_hash, header.body_hash())?,
+            Err(lmdb_err) => return Err(lmdb_err.into()),
+        };
+        Ok(StoredBlock {
+            block_hash,
+            header,
+            maybe_deploy_hashes,
+        })
+    }
+
+    fn execution_result(
+        &self,
+        block_hash: BlockHash,
+        deploy_hash: DeployHash,
+    ) -> Result<Option<ExecutionResult>, Error> {
+        match self.txn.get(self.deploy_metadata_db, &deploy_hash) {
+            Ok(raw_metadata) => {
+                let mut metadata: DeployMetadata = bincode::deserialize(raw_metadata)
+                    .map_err(|bincode_err| Error::MetadataParsing(deploy_hash, bincode_err))?;
+                Ok(metadata.execution_results.remove(&block_hash))
+            }
+            Err(LmdbError::NotFound) => Ok(None),
+            Err(lmdb_err) => Err(lmdb_err.into()),
+        }
+    }
+}
+
+fn header_fields(header: &BlockHeader) -> Result<[(&'static str, Value); 3], Error> {
+    Ok([
+        (
+            "state_root_hash",
+            serde_json::to_value(header.state_root_hash())?,
+        ),
+        ("era_id", serde_json::to_value(header.era_id())?),
+        (
+            "protocol_version",
+            serde_json::to_value(header.protocol_version())?,
+        ),
+    ])
+}
+
+fn diff_blocks<W: Write + ?Sized>(
+    writer: &mut DifferenceWriter<W>,
+    (left_reader, right_reader): (&BlockReader, &BlockReader),
+    height: u64,
+    left: StoredBlock,
+    right: StoredBlock,
+) -> Result<(), Error> {
+    if left.block_hash != right.block_hash {
+        writer.write(StorageDifference::BlockHash {
+            height,
+            left: left.block_hash,
+            right: right.block_hash,
+        })?;
+    }
+    for ((field, left_value), (_, right_value)) in header_fields(&left.header)?
+        .into_iter()
+        .zip(header_fields(&right.header)?)
+    {
+        if left_value != right_value {
+            writer.write(StorageDifference::HeaderField {
+                height,
+                field,
+                left: left_value,
+                right: right_value,
+            })?;
+        }
+    }
+
+    let (left_deploy_hashes, right_deploy_hashes) =
+        match (left.maybe_deploy_hashes, right.maybe_deploy_hashes) {
+            (Some(left_deploy_hashes), Some(right_deploy_hashes)) => {
+                (left_deploy_hashes, right_deploy_hashes)
+            }
+            (maybe_left_deploy_hashes, maybe_right_deploy_hashes) => {
+                for (block_hash, maybe_deploy_hashes, missing_from) in [
+                    (left.block_hash, maybe_left_deploy_hashes, Side::Left),
+                    (right.block_hash, maybe_right_deploy_hashes, Side::Right),
+                ] {
+                    if maybe_deploy_hashes.is_none() {
+                        writer.write(StorageDifference::MissingBody {
+                            height,
+                            block_hash,
+                            missing_from,
+                        })?;
+                    }
+                }
+                return Ok(());
+            }
+        };
+    let only_left: Vec<DeployHash> = left_deploy_hashes
+        .difference(&right_deploy_hashes)
+        .copied()
+        .collect();
+    let only_right: Vec<DeployHash> = right_deploy_hashes
+        .difference(&left_deploy_hashes)
+        .copied()
+        .collect();
+    if !only_left.is_empty() || !only_right.is_empty() {
+        writer.write(StorageDifference::DeployList {
+            height,
+            only_left,
+            only_right,
+        })?;
+    }
+    for deploy_hash in left_deploy_hashes.intersection(&right_deploy_hashes) {
+        let left_result = left_reader.execution_result(left.block_hash, *deploy_hash)?;
+        let right_result = right_reader.execution_result(right.block_hash, *deploy_hash)?;
+        if left_result != right_result {
+            writer.write(StorageDifference::ExecutionResult {
+                height,
+                deploy_hash: *deploy_hash,
+                left: left_result,
+                right: right_result,
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Compares the storages in `left_path` and `right_path` block by block,
+/// between `from_height` and `to_height` if given, writing every difference
+/// to `out_writer` as a line of JSON.
+pub(crate) fn diff_storage<P1: AsRef<Path>, P2: AsRef<Path>, W: Write + ?Sized>(
+    left_path: P1,
+    right_path: P2,
+    maybe_from_height: Option<u64>,
+    maybe_to_height: Option<u64>,
+    out_writer: &mut W,
+) -> Result<DiffSummary, Error> {
+    let left_env = db::db_env(left_path.as_ref().join(STORAGE_FILE_NAME))?;
+    let right_env = db::db_env(right_path.as_ref().join(STORAGE_FILE_NAME))?;
+    let mut writer = DifferenceWriter {
+        out_writer,
+        summary: DiffSummary::default(),
+    };
+
+    let left_databases = present_databases(&left_env)?;
+    let right_databases = present_databases(&right_env)?;
+    for &database in left_databases.symmetric_difference(&right_databases) {
+        let missing_from = if left_databases.contains(&database) {
+            Side::Right
+        } else {
+            Side::Left
+        };
+        writer.write(StorageDifference::MissingDatabase {
+            database,
+            missing_from,
+        })?;
+    }
+    let block_databases = [
+        BlockHeaderDatabase::db_name(),
+        BlockBodyDatabase::db_name(),
+        DeployMetadataDatabase::db_name(),
+    ];
+    if let Some(database) = block_databases
+        .into_iter()
+        .find(|database| !left_databases.contains(database) || !right_databases.contains(database))
+    {
+        warn!("Can't compare blocks without the {database} database on both sides.");
+        return Ok(writer.summary);
+    }
+
+    let height_range = maybe_from_height.unwrap_or(0)..=maybe_to_height.unwrap_or(u64::MAX);
+    let (mut left_blocks, mut left_duplicates) = block_hashes_by_height(&left_path, &left_env)?;
+    let (mut right_blocks, mut right_duplicates) = block_hashes_by_height(&right_path, &right_env)?;
+    let heights: BTreeSet<u64> = left_blocks
+        .keys()
+        .chain(right_blocks.keys())
+        .copied()
+        .filter(|height| height_range.contains(height))
+        .collect();
+    let mut maybe_progress_tracker = ProgressTracker::new(
+        heights.len(),
+        Box::new(|completion| info!("Storage comparison {}% complete...", completion)),
+    )
+    .ok();
+
+    let left_reader = BlockReader::new(&left_env)?;
+    let right_reader = BlockReader::new(&right_env)?;
+    for height in heights {
+        for (duplicates, side) in [
+            (&mut left_duplicates, Side::Left),
+            (&mut right_duplicates, Side::Right),
+        ] {
+            if let Some(block_hashes) = duplicates.remove(&height) {
+                writer.write(StorageDifference::DuplicateBlock {
+                    height,
+                    block_hashes,
+                    side,
+                })?;
+            }
+        }
+        match (left_blocks.remove(&height), right_blocks.remove(&height)) {
+            (Some(left_hash), Some(right_hash)) => {
+                let left = left_reader.read_block(left_hash)?;
+                let right = right_reader.read_block(right_hash)?;
+                diff_blocks(
+                    &mut writer,
+                    (&left_reader, &right_reader),
+                    height,
+                    left,
+                    right,
+                )?;
+            }
+            (Some(block_hash), None) => writer.write(StorageDifference::MissingBlock {
+                height,
+                block_hash,
+                missing_from: Side::Right,
+            })?,
+            (None, Some(block_hash)) => writer.write(StorageDifference::MissingBlock {
+                height,
+                block_hash,
+                missing_from: Side::Left,
+            })?,
+            (None, None) => unreachable!("height should be on at least one side"),
+        }
+        writer.summary.heights_compared += 1;
+        if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
+            progress_tracker.advance_by(1);
+        }
+    }
+    left_reader.txn.commit()?;
+    right_reader.txn.commit()?;
+    Ok(writer.summary)
+}
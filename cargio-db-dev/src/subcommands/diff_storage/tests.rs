@@ -0,0 +1,336 @@
+use lmdb::{Transaction, WriteFlags};
+
+use cargio_hashing::Digest;
+use cargio_types::bytesrepr::ToBytes;
+use master_node::types::{BlockHash, DeployHash};
+use serde_json::Value;
+
+use crate::{
+    common::db::{
+        BlockBodyDatabase, BlockBodyMerkleDatabase, BlockHeaderDatabase, Database,
+        DeployHashesDatabase, DeployMetadataDatabase, TransferDatabase, TransferHashesDatabase,
+        STORAGE_FILE_NAME,
+    },
+    subcommands::execution_results_summary::block_body::BlockBody,
+    test_utils::{
+        mock_block_header, mock_deploy_hash, mock_deploy_metadata, success_execution_result,
+        LmdbTestFixture, MockBlockHeader,
+    },
+};
+
+use super::diff::{self, DiffSummary, Side, StorageDifference};
+
+const BLOCK_COUNT: u8 = 4;
+
+fn storage_fixture(extra_db_names: &[&'static str]) -> LmdbTestFixture {
+    let mut db_names = vec![
+        BlockHeaderDatabase::db_name(),
+        BlockBodyDatabase::db_name(),
+        DeployMetadataDatabase::db_name(),
+    ];
+    db_names.extend_from_slice(extra_db_names);
+    LmdbTestFixture::new(db_names, Some(STORAGE_FILE_NAME))
+}
+
+/// Stores a block with a single deploy at the height `idx`, letting `modify`
+/// change the header, the deploys and whether the deploys have execution
+/// results before it is stored.
+fn put_block<F>(fixture: &LmdbTestFixture, idx: u8, modify: F) -> BlockHash
+where
+    F: FnOnce(&mut MockBlockHeader, &mut Vec<DeployHash>, &mut bool),
+{
+    let (block_hash, mut header) = mock_block_header(idx);
+    header.height = idx as u64;
+    let mut deploy_hashes = vec![mock_deploy_hash(idx)];
+    let mut with_results = true;
+    modify(&mut header, &mut deploy_hashes, &mut with_results);
+
+    let mut txn = fixture.env.begin_rw_txn().unwrap();
+    txn.put(
+        *fixture.db(Some(BlockHeaderDatabase::db_name())).unwrap(),
+        &block_hash,
+        &bincode::serialize(&header).unwrap(),
+        WriteFlags::empty(),
+    )
+    .unwrap();
+    txn.put(
+        *fixture.db(Some(BlockBodyDatabase::db_name())).unwrap(),
+        &header.body_hash,
+        &bincode::serialize(&BlockBody::new(deploy_hashes.clone())).unwrap(),
+        WriteFlags::empty(),
+    )
+    .unwrap();
+    let block_hashes = if with_results {
+        vec![block_hash]
+    } else {
+        vec![]
+    };
+    for deploy_hash in deploy_hashes {
+        txn.put(
+            *fixture.db(Some(DeployMetadataDatabase::db_name())).unwrap(),
+            &deploy_hash,
+            &bincode::serialize(&mock_deploy_metadata(&block_hashes)).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+    }
+    txn.commit().unwrap();
+    block_hash
+}
+
+/// Replaces the body of the block at the height `idx` with a merkle body
+/// listing the deploy of the block and `transfer_hashes`.
+fn put_merkle_body(fixture: &LmdbTestFixture, idx: u8, transfer_hashes: &[DeployHash]) {
+    let (_, header) = mock_block_header(idx);
+    let merkle_leaf = |deploy_hashes: &[DeployHash]| {
+        deploy_hashes
+            .iter()
+            .map(|deploy_hash| cargio_types::DeployHash::new(deploy_hash.inner().value()))
+            .collect::<Vec<_>>()
+            .to_bytes()
+            .unwrap()
+    };
+    let deploy_leaf = Digest::hash([idx, 1]);
+    let transfer_leaf = Digest::hash([idx, 2]);
+    let transfer_link = Digest::hash_pair(transfer_leaf, Digest::hash([idx, 3]));
+
+    let mut txn = fixture.env.begin_rw_txn().unwrap();
+    txn.del(
+        *fixture.db(Some(BlockBodyDatabase::db_name())).unwrap(),
+        &header.body_hash,
+        None,
+    )
+    .unwrap();
+    for (key, link) in [
+        (header.body_hash, (deploy_leaf, transfer_link)),
+        (transfer_link, (transfer_leaf, Digest::hash([idx, 3]))),
+    ] {
+        txn.put(
+            *fixture
+                .db(Some(BlockBodyMerkleDatabase::db_name()))
+                .unwrap(),
+            &key,
+            &link.to_bytes().unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+    }
+    txn.put(
+        *fixture.db(Some(DeployHashesDatabase::db_name())).unwrap(),
+        &deploy_leaf,
+        &merkle_leaf(&[mock_deploy_hash(idx)]),
+        WriteFlags::empty(),
+    )
+    .unwrap();
+    txn.put(
+        *fixture.db(Some(TransferHashesDatabase::db_name())).unwrap(),
+        &transfer_leaf,
+        &merkle_leaf(transfer_hashes),
+        WriteFlags::empty(),
+    )
+    .unwrap();
+    txn.commit().unwrap();
+}
+
+fn diff_fixtures(
+    left: &LmdbTestFixture,
+    right: &LmdbTestFixture,
+    maybe_from_height: Option<u64>,
+    maybe_to_height: Option<u64>,
+) -> (Vec<Value>, DiffSummary) {
+    let mut output = vec![];
+    let summary = diff::diff_storage(
+        left.tmp_dir.path(),
+        right.tmp_dir.path(),
+        maybe_from_height,
+        maybe_to_height,
+        &mut output,
+    )
+    .unwrap();
+    let lines = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    (lines, summary)
+}
+
+fn to_value(difference: StorageDifference) -> Value {
+    serde_json::to_value(difference).unwrap()
+}
+
+#[test]
+fn same_storage_should_have_no_differences() {
+    let left = storage_fixture(&[]);
+    let right = storage_fixture(&[]);
+    for idx in 0..BLOCK_COUNT {
+        put_block(&left, idx, |_, _, _| {});
+        put_block(&right, idx, |_, _, _| {});
+    }
+
+    let (lines, summary) = diff_fixtures(&left, &right, None, None);
+    assert!(lines.is_empty());
+    assert!(summary.is_empty());
+    assert_eq!(summary.heights_compared, BLOCK_COUNT as u64);
+    assert_eq!(summary.first_differing_height, None);
+}
+
+#[test]
+fn differences_should_be_reported_by_height() {
+    let left = storage_fixture(&[TransferDatabase::db_name()]);
+    let right = storage_fixture(&[]);
+    let mut left_hashes = vec![];
+    for idx in 0..BLOCK_COUNT {
+        left_hashes.push(put_block(&left, idx, |_, _, _| {}));
+    }
+    put_block(&right, 0, |_, _, with_results| *with_results = false);
+    put_block(&right, 1, |header, _, _| {
+        header.state_root_hash = [9u8; 32].into()
+    });
+    put_block(&right, 2, |_, deploy_hashes, _| {
+        deploy_hashes.push(mock_deploy_hash(9))
+    });
+
+    let (lines, summary) = diff_fixtures(&left, &right, None, None);
+    let (_, header) = mock_block_header(1);
+    assert_eq!(
+        lines,
+        vec![
+            to_value(StorageDifference::MissingDatabase {
+                database: TransferDatabase::db_name(),
+                missing_from: Side::Right,
+            }),
+            to_value(StorageDifference::ExecutionResult {
+                height: 0,
+                deploy_hash: mock_deploy_hash(0),
+                left: Some(success_execution_result()),
+                right: None,
+            }),
+            to_value(StorageDifference::HeaderField {
+                height: 1,
+                field: "state_root_hash",
+                left: serde_json::to_value(header.state_root_hash).unwrap(),
+                right: serde_json::to_value(Digest::from([9u8; 32])).unwrap(),
+            }),
+            to_value(StorageDifference::DeployList {
+                height: 2,
+                only_left: vec![],
+                only_right: vec![mock_deploy_hash(9)],
+            }),
+            to_value(StorageDifference::MissingBlock {
+                height: 3,
+                block_hash: left_hashes[3],
+                missing_from: Side::Right,
+            }),
+        ]
+    );
+    assert_eq!(
+        summary,
+        DiffSummary {
+            heights_compared: 4,
+            first_differing_height: Some(0),
+            missing_databases: 1,
+            missing_blocks: 1,
+            duplicate_blocks: 0,
+            missing_bodies: 0,
+            block_hashes: 0,
+            header_fields: 1,
+            deploy_lists: 1,
+            execution_results: 1,
+        }
+    );
+}
+
+#[test]
+fn missing_bodies_and_duplicate_heights_should_be_reported() {
+    let left = storage_fixture(&[]);
+    let right = storage_fixture(&[]);
+    let mut left_hashes = vec![];
+    for idx in 0..BLOCK_COUNT {
+        left_hashes.push(put_block(&left, idx, |_, _, _| {}));
+        put_block(&right, idx, |_, _, _| {});
+    }
+    let duplicate_hash = put_block(&right, 9, |header, _, _| header.height = 2);
+    let (_, header) = mock_block_header(1);
+    let mut txn = right.env.begin_rw_txn().unwrap();
+    txn.del(
+        *right.db(Some(BlockBodyDatabase::db_name())).unwrap(),
+        &header.body_hash,
+        None,
+    )
+    .unwrap();
+    txn.commit().unwrap();
+
+    let (lines, summary) = diff_fixtures(&left, &right, None, None);
+    assert_eq!(
+        lines[0],
+        to_value(StorageDifference::MissingBody {
+            height: 1,
+            block_hash: left_hashes[1],
+            missing_from: Side::Right,
+        })
+    );
+    let duplicate_block = |block_hashes| {
+        to_value(StorageDifference::DuplicateBlock {
+            height: 2,
+            block_hashes,
+            side: Side::Right,
+        })
+    };
+    assert!(
+        lines[1] == duplicate_block(vec![left_hashes[2], duplicate_hash])
+            || lines[1] == duplicate_block(vec![duplicate_hash, left_hashes[2]])
+    );
+    assert_eq!(summary.heights_compared, BLOCK_COUNT as u64);
+    assert_eq!(summary.missing_bodies, 1);
+    assert_eq!(summary.duplicate_blocks, 1);
+    assert_eq!(summary.first_differing_height, Some(1));
+}
+
+#[test]
+fn height_range_should_limit_the_comparison() {
+    let left = storage_fixture(&[]);
+    let right = storage_fixture(&[]);
+    for idx in 0..BLOCK_COUNT {
+        put_block(&left, idx, |_, _, _| {});
+    }
+    put_block(&right, 1, |_, _, _| {});
+    put_block(&right, 2, |_, _, _| {});
+
+    let (lines, summary) = diff_fixtures(&left, &right, Some(1), Some(2));
+    assert!(lines.is_empty());
+    assert_eq!(summary.heights_compared, 2);
+
+    let (_, summary) = diff_fixtures(&left, &right, Some(2), None);
+    assert_eq!(summary.missing_blocks, 1);
+    assert_eq!(summary.first_differing_height, Some(3));
+}
+
+#[test]
+fn merkle_bodies_should_be_compared() {
+    let merkle_db_names = [
+        BlockBodyMerkleDatabase::db_name(),
+        DeployHashesDatabase::db_name(),
+        TransferHashesDatabase::db_name(),
+    ];
+    let left = storage_fixture(&merkle_db_names);
+    let right = storage_fixture(&merkle_db_names);
+    for idx in 0..BLOCK_COUNT {
+        put_block(&left, idx, |_, _, _| {});
+        put_block(&right, idx, |_, _, _| {});
+    }
+    put_merkle_body(&right, 1, &[]);
+    put_merkle_body(&right, 2, &[mock_deploy_hash(9)]);
+
+    let (lines, summary) = diff_fixtures(&left, &right, None, None);
+    assert_eq!(
+        lines,
+        vec![to_value(StorageDifference::DeployList {
+            height: 2,
+            only_left: vec![],
+            only_right: vec![mock_deploy_hash(9)],
+        })]
+    );
+    assert_eq!(summary.missing_bodies, 0);
+    assert_eq!(summary.deploy_lists, 1);
+}
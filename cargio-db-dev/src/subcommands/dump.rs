@@ -0,0 +1,152 @@
+mod read_db;
+#[cfg(test)]
+mod tests;
+
+use std::{io::Error as IoError, path::Path};
+
+use clap::{Arg, ArgMatches, Command};
+use hex::FromHexError;
+use lmdb::Error as LmdbError;
+use serde_json::Error as SerializationError;
+use thiserror::Error as ThisError;
+
+pub const COMMAND_NAME: &str = "dump";
+const DATABASE: &str = "database";
+const DB_PATH: &str = "db-path";
+const FROM_KEY: &str = "from-key";
+const LIMIT: &str = "limit";
+const OUTPUT: &str = "output";
+const OVERWRITE: &str = "overwrite";
+const TO_KEY: &str = "to-key";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Invalid hex key {0}: {1}")]
+    InvalidKey(String, FromHexError),
+    #[error("Error writing output: {0}")]
+    Output(#[from] IoError),
+    #[error("Error serializing output: {0}")]
+    Serialize(#[from] SerializationError),
+    #[error("Unknown database {0}")]
+    UnknownDb(String),
+}
+
+enum DisplayOrder {
+    DbPath,
+    Database,
+    FromKey,
+    ToKey,
+    Limit,
+    Output,
+    Overwrite,
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Decodes the entries of a storage database and outputs them as JSON lines, each \
+            holding the hex encoded key and the decoded value of an entry.",
+        )
+        .arg(
+            Arg::new(DB_PATH)
+                .display_order(DisplayOrder::DbPath as usize)
+                .required(true)
+                .short('d')
+                .long(DB_PATH)
+                .takes_value(true)
+                .value_name("DB_PATH")
+                .help("Path of the directory with the `storage.lmdb` file."),
+        )
+        .arg(
+            Arg::new(DATABASE)
+                .display_order(DisplayOrder::Database as usize)
+                .required(true)
+                .short('s')
+                .long(DATABASE)
+                .takes_value(true)
+                .value_name("DB_NAME")
+                .help("Name of the database to dump."),
+        )
+        .arg(
+            Arg::new(FROM_KEY)
+                .display_order(DisplayOrder::FromKey as usize)
+                .short('f')
+                .long(FROM_KEY)
+                .takes_value(true)
+                .value_name("HEX_KEY")
+                .help("Hex encoded key of the first entry to dump."),
+        )
+        .arg(
+            Arg::new(TO_KEY)
+                .display_order(DisplayOrder::ToKey as usize)
+                .short('t')
+                .long(TO_KEY)
+                .takes_value(true)
+                .value_name("HEX_KEY")
+                .help("Hex encoded key at which the dump stops, excluding it."),
+        )
+        .arg(
+            Arg::new(LIMIT)
+                .display_order(DisplayOrder::Limit as usize)
+                .short('n')
+                .long(LIMIT)
+                .takes_value(true)
+                .value_name("ENTRY_COUNT")
+                .help("Maximum number of entries to dump."),
+        )
+        .arg(
+            Arg::new(OUTPUT)
+                .display_order(DisplayOrder::Output as usize)
+                .short('o')
+                .long(OUTPUT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help(
+                    "Path to where the program will output the entries. \
+                    If unspecified, defaults to standard output.",
+                ),
+        )
+        .arg(
+            Arg::new(OVERWRITE)
+                .display_order(DisplayOrder::Overwrite as usize)
+                .required(false)
+                .short('w')
+                .long(OVERWRITE)
+                .takes_value(false)
+                .requires(OUTPUT)
+                .help(
+                    "Overwrite an already existing output file in destination \
+                    directory.",
+                ),
+        )
+}
+
+fn parse_key(maybe_hex_key: Option<&str>) -> Result<Option<Vec<u8>>, Error> {
+    maybe_hex_key
+        .map(|hex_key| {
+            hex::decode(hex_key).map_err(|hex_err| Error::InvalidKey(hex_key.to_string(), hex_err))
+        })
+        .transpose()
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let path = Path::new(matches.value_of(DB_PATH).expect("should have db-path arg"));
+    let db_name = matches
+        .value_of(DATABASE)
+        .expect("should have database arg");
+    let options = read_db::DumpOptions {
+        from_key: parse_key(matches.value_of(FROM_KEY))?,
+        to_key: parse_key(matches.value_of(TO_KEY))?,
+        limit: matches.value_of(LIMIT).map(|limit| {
+            limit
+                .parse()
+                .unwrap_or_else(|_| panic!("Value of \"--{LIMIT}\" must be an integer."))
+        }),
+    };
+    let output = matches.value_of(OUTPUT).map(Path::new);
+    let overwrite = matches.is_present(OVERWRITE);
+    read_db::dump_db(path, db_name, &options, output, overwrite)
+}
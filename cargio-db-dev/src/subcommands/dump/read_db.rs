@@ -0,0 +1,130 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    path::Path,
+    result::Result,
+};
+
+use lmdb::{Cursor, Environment, Transaction};
+use log::info;
+use serde::Serialize;
+
+use crate::common::db::{self, Database, DatabaseVisitor, STORAGE_FILE_NAME};
+
+use super::Error;
+
+#[derive(Clone, Debug, Default)]
+pub struct DumpOptions {
+    /// First key to dump, inclusive.
+    pub from_key: Option<Vec<u8>>,
+    /// Key at which the dump stops, exclusive.
+    pub to_key: Option<Vec<u8>>,
+    pub limit: Option<usize>,
+}
+
+/// A single line of the dump. Entries which fail to decode are still
+/// written, with the decoding error instead of the value.
+#[derive(Serialize)]
+struct DumpEntry<'a, T: Serialize> {
+    key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<&'a T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+type Dumper = fn(&Environment, &DumpOptions, &mut dyn Write) -> Result<usize, Error>;
+
+struct DumperVisitor;
+
+impl DatabaseVisitor for DumperVisitor {
+    type Output = Dumper;
+
+    fn visit<D: Database>(self) -> Self::Output {
+        dump_entries::<D>
+    }
+}
+
+pub(crate) fn dump_entries<D: Database>(
+    env: &Environment,
+    options: &DumpOptions,
+    out_writer: &mut dyn Write,
+) -> Result<usize, Error> {
+    let txn = env.begin_ro_txn()?;
+    let db = unsafe { txn.open_db(Some(D::db_name()))? };
+    let mut dumped = 0;
+
+    if let Ok(mut cursor) = txn.open_ro_cursor(db) {
+        let iter = match options.from_key.as_ref() {
+            Some(from_key) => cursor.iter_from(from_key),
+            None => cursor.iter_start(),
+        };
+        for (raw_key, raw_val) in iter {
+            if let Some(to_key) = options.to_key.as_ref() {
+                if raw_key >= to_key.as_slice() {
+                    break;
+                }
+            }
+            if options.limit == Some(dumped) {
+                break;
+            }
+            let key = hex::encode(raw_key);
+            match D::parse_element(raw_val) {
+                Ok(element) => serde_json::to_writer(
+                    &mut *out_writer,
+                    &DumpEntry {
+                        key,
+                        value: Some(&element),
+                        error: None,
+                    },
+                )?,
+                Err(parsing_err) => serde_json::to_writer(
+                    &mut *out_writer,
+                    &DumpEntry::<D::Element> {
+                        key,
+                        value: None,
+                        error: Some(parsing_err.detailed_message()),
+                    },
+                )?,
+            }
+            writeln!(out_writer)?;
+            dumped += 1;
+        }
+    }
+    out_writer.flush()?;
+    Ok(dumped)
+}
+
+pub fn dump_db<P1: AsRef<Path>, P2: AsRef<Path>>(
+    db_path: P1,
+    db_name: &str,
+    options: &DumpOptions,
+    output: Option<P2>,
+    overwrite: bool,
+) -> Result<(), Error> {
+    let db_name = db_name.trim();
+    let dumper = db::visit_database(db_name, DumperVisitor)
+        .ok_or_else(|| Error::UnknownDb(db_name.to_string()))?;
+    let storage_path = db_path.as_ref().join(STORAGE_FILE_NAME);
+    let env = db::db_env(storage_path)?;
+    let mut log_summary = false;
+    let mut out_writer: Box<dyn Write> = if let Some(out_path) = output {
+        let file = OpenOptions::new()
+            .create_new(!overwrite)
+            .create(overwrite)
+            .truncate(overwrite)
+            .write(true)
+            .open(out_path)?;
+        log_summary = true;
+        Box::new(BufWriter::new(file))
+    } else {
+        Box::new(BufWriter::new(io::stdout()))
+    };
+
+    let dumped = dumper(&env, options, out_writer.as_mut())?;
+    // Logging would get mixed with the entries on standard output.
+    if log_summary {
+        info!("Dumped {dumped} entries from the {db_name} database.");
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use std::fs;
+
+use cargio_types::bytesrepr::ToBytes;
+use lmdb::{Transaction, WriteFlags};
+use serde_json::Value;
+
+use crate::{
+    common::db::{Database, StateStoreDatabase, STORAGE_FILE_NAME},
+    subcommands::dump::{
+        read_db::{dump_db, dump_entries, DumpOptions},
+        Error,
+    },
+    test_utils::LmdbTestFixture,
+};
+
+const ENTRY_COUNT: u8 = 10;
+const FAULTY_ENTRY: u8 = 5;
+
+fn populate_state_store(fixture: &LmdbTestFixture) {
+    let db = *fixture.db(Some(StateStoreDatabase::db_name())).unwrap();
+    let mut txn = fixture.env.begin_rw_txn().unwrap();
+    for i in 0..ENTRY_COUNT {
+        if i == FAULTY_ENTRY {
+            txn.put(db, &[i], &[i], WriteFlags::empty()).unwrap();
+        } else {
+            txn.put(
+                db,
+                &[i],
+                &(i as u64).to_bytes().unwrap(),
+                WriteFlags::empty(),
+            )
+            .unwrap();
+        }
+    }
+    txn.commit().unwrap();
+}
+
+fn dump_lines(fixture: &LmdbTestFixture, options: &DumpOptions) -> Vec<Value> {
+    let mut out = vec![];
+    let dumped = dump_entries::<StateStoreDatabase>(&fixture.env, options, &mut out).unwrap();
+    let lines: Vec<Value> = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), dumped);
+    lines
+}
+
+#[test]
+fn dump_should_decode_every_entry() {
+    let fixture =
+        LmdbTestFixture::new(vec![StateStoreDatabase::db_name()], Some(STORAGE_FILE_NAME));
+    populate_state_store(&fixture);
+
+    let lines = dump_lines(&fixture, &DumpOptions::default());
+    assert_eq!(lines.len(), ENTRY_COUNT as usize);
+    for (i, line) in lines.iter().enumerate() {
+        assert_eq!(line["key"], hex::encode([i as u8]));
+        if i == FAULTY_ENTRY as usize {
+            assert!(line.get("value").is_none());
+            assert!(line["error"].is_string());
+        } else {
+            assert_eq!(line["value"], i);
+            assert!(line.get("error").is_none());
+        }
+    }
+}
+
+#[test]
+fn dump_should_apply_key_range_and_limit() {
+    let fixture =
+        LmdbTestFixture::new(vec![StateStoreDatabase::db_name()], Some(STORAGE_FILE_NAME));
+    populate_state_store(&fixture);
+
+    let options = DumpOptions {
+        from_key: Some(vec![2]),
+        to_key: Some(vec![7]),
+        limit: None,
+    };
+    let keys: Vec<Value> = dump_lines(&fixture, &options)
+        .into_iter()
+        .map(|line| line["key"].clone())
+        .collect();
+    assert_eq!(keys, ["02", "03", "04", "05", "06"]);
+
+    let options = DumpOptions {
+        limit: Some(3),
+        ..options
+    };
+    let keys: Vec<Value> = dump_lines(&fixture, &options)
+        .into_iter()
+        .map(|line| line["key"].clone())
+        .collect();
+    assert_eq!(keys, ["02", "03", "04"]);
+
+    let options = DumpOptions {
+        from_key: Some(vec![ENTRY_COUNT]),
+        ..Default::default()
+    };
+    assert!(dump_lines(&fixture, &options).is_empty());
+}
+
+#[test]
+fn dump_db_should_write_output_file() {
+    let fixture =
+        LmdbTestFixture::new(vec![StateStoreDatabase::db_name()], Some(STORAGE_FILE_NAME));
+    populate_state_store(&fixture);
+    let out_dir = tempfile::tempdir().unwrap();
+    let out_path = out_dir.path().join("dump.jsonl");
+
+    let options = DumpOptions {
+        limit: Some(2),
+        ..Default::default()
+    };
+    dump_db(
+        fixture.tmp_dir.path(),
+        StateStoreDatabase::db_name(),
+        &options,
+        Some(&out_path),
+        false,
+    )
+    .unwrap();
+    assert_eq!(fs::read_to_string(&out_path).unwrap().lines().count(), 2);
+
+    // The output file already exists.
+    assert!(dump_db(
+        fixture.tmp_dir.path(),
+        StateStoreDatabase::db_name(),
+        &options,
+        Some(&out_path),
+        false,
+    )
+    .is_err());
+    assert!(matches!(
+        dump_db(
+            fixture.tmp_dir.path(),
+            "unknown_db",
+            &options,
+            Some(&out_path),
+            true,
+        ),
+        Err(Error::UnknownDb(_))
+    ));
+}
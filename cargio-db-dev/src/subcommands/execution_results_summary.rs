@@ -1,5 +1,5 @@
 pub(crate) mod block_body;
-mod read_db;
+pub(crate) mod read_db;
 mod summary;
 #[cfg(test)]
 mod tests;
@@ -13,6 +13,8 @@ use lmdb::Error as LmdbError;
 use serde_json::Error as JsonSerializationError;
 use thiserror::Error as ThisError;
 
+use crate::common::block_index::BlockIndexError;
+
 pub const COMMAND_NAME: &str = "execution-results-summary";
 const DB_PATH: &str = "db-path";
 const OVERWRITE: &str = "overwrite";
@@ -20,6 +22,8 @@ const OUTPUT: &str = "output";
 
 #[derive(Debug, ThisError)]
 pub enum Error {
+    #[error("Error reading the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
     #[error("Error operating the database: {0}")]
     Database(#[from] LmdbError),
     #[error("Error deserializing raw key of block header DB element: {0}")]
@@ -26,6 +26,11 @@ impl BlockBody {
         }
     }
 
+    #[cfg(feature = "export-chain")]
+    pub(crate) fn proposer(&self) -> &PublicKey {
+        &self.proposer
+    }
+
     pub(crate) fn deploy_hashes(&self) -> &Vec<DeployHash> {
         &self.deploy_hashes
     }
@@ -1,17 +1,19 @@
 use std::{
     fs::OpenOptions,
     io::{self, Write},
+    ops::RangeInclusive,
     path::Path,
     result::Result,
 };
 
-use lmdb::{Cursor, Environment, Transaction};
+use lmdb::{Cursor, Database as LmdbDatabase, Environment, RoTransaction, Transaction};
 use log::{info, warn};
 use serde_json::{self, Error as JsonSerializationError};
 
 use master_node::types::{BlockHash, BlockHeader, DeployMetadata};
 
 use crate::common::{
+    block_index::BlockIndex,
     db::{
         self, BlockBodyDatabase, BlockHeaderDatabase, Database, DeployMetadataDatabase,
         STORAGE_FILE_NAME,
@@ -26,8 +28,98 @@ use super::{
     Error,
 };
 
+/// Adds the execution results of the block with the given header to `stats`.
+fn feed_block_stats(
+    txn: &RoTransaction,
+    block_body_db: LmdbDatabase,
+    deploy_metadata_db: LmdbDatabase,
+    block_hash: BlockHash,
+    header: &BlockHeader,
+    stats: &mut ExecutionResultsStats,
+) -> Result<(), Error> {
+    let block_body_raw = txn.get(block_body_db, header.body_hash())?;
+    let block_body: BlockBody = bincode::deserialize(block_body_raw).map_err(|bincode_err| {
+        Error::Parsing(
+            block_hash,
+            BlockBodyDatabase::db_name().to_string(),
+            bincode_err,
+        )
+    })?;
+
+    let mut execution_results = vec![];
+
+    for deploy_hash in block_body.deploy_hashes() {
+        let metadata_raw = txn.get(deploy_metadata_db, &deploy_hash)?;
+        let mut metadata: DeployMetadata =
+            bincode::deserialize(metadata_raw).map_err(|bincode_err| {
+                Error::Parsing(
+                    block_hash,
+                    DeployMetadataDatabase::db_name().to_string(),
+                    bincode_err,
+                )
+            })?;
+        if let Some(execution_result) = metadata.execution_results.remove(&block_hash) {
+            execution_results.push(execution_result);
+        }
+    }
+
+    stats.feed(execution_results)?;
+    Ok(())
+}
+
+fn parse_header(block_hash: BlockHash, raw_header: &[u8]) -> Result<BlockHeader, Error> {
+    bincode::deserialize(raw_header).map_err(|bincode_err| {
+        Error::Parsing(
+            block_hash,
+            BlockHeaderDatabase::db_name().to_string(),
+            bincode_err,
+        )
+    })
+}
+
+/// Calls `visit` with the hash and header of every block with a height in
+/// `heights`. Blocks are visited in height order when the block index is
+/// available, otherwise in the order of the block header database.
+pub(crate) fn visit_blocks<E, F>(
+    txn: &RoTransaction,
+    block_header_db: LmdbDatabase,
+    maybe_block_index: Option<&BlockIndex>,
+    heights: RangeInclusive<u64>,
+    mut visit: F,
+) -> Result<(), E>
+where
+    E: From<Error>,
+    F: FnMut(BlockHash, BlockHeader) -> Result<(), E>,
+{
+    if heights.is_empty() {
+        return Ok(());
+    }
+    if let Some(block_index) = maybe_block_index {
+        for (height, block_hash) in block_index.blocks().map_err(Error::from)?.range(heights) {
+            let raw_header = txn.get(block_header_db, block_hash).map_err(Error::from)?;
+            let header = parse_header(*block_hash, raw_header)?;
+            debug_assert_eq!(header.height(), *height);
+            visit(*block_hash, header)?;
+        }
+    } else if let Ok(mut cursor) = txn.open_ro_cursor(block_header_db) {
+        for (idx, (block_hash_raw, raw_val)) in cursor.iter().enumerate() {
+            let block_hash = BlockHash::new(
+                block_hash_raw
+                    .try_into()
+                    .map_err(|_| Error::InvalidKey(idx))?,
+            );
+            let header = parse_header(block_hash, raw_val)?;
+            if heights.contains(&header.height()) {
+                visit(block_hash, header)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 fn get_execution_results_stats(
     env: &Environment,
+    maybe_block_index: Option<&BlockIndex>,
     log_progress: bool,
 ) -> Result<ExecutionResultsStats, Error> {
     let txn = env.begin_ro_txn()?;
@@ -37,76 +129,45 @@ fn get_execution_results_stats(
 
     let maybe_entry_count = lmdb_utils::entry_count(&txn, block_header_db).ok();
     let mut maybe_progress_tracker = None;
-
-    let mut stats = ExecutionResultsStats::default();
-    if let Ok(mut cursor) = txn.open_ro_cursor(block_header_db) {
-        if log_progress {
-            match maybe_entry_count {
-                Some(entry_count) => {
-                    match ProgressTracker::new(
-                        entry_count,
-                        Box::new(|completion| {
-                            info!("Database parsing {}% complete...", completion)
-                        }),
-                    ) {
-                        Ok(progress_tracker) => maybe_progress_tracker = Some(progress_tracker),
-                        Err(progress_tracker_error) => warn!(
-                            "Couldn't initialize progress tracker: {}",
-                            progress_tracker_error
-                        ),
-                    }
+    if log_progress {
+        match maybe_entry_count {
+            Some(entry_count) => {
+                match ProgressTracker::new(
+                    entry_count,
+                    Box::new(|completion| info!("Database parsing {}% complete...", completion)),
+                ) {
+                    Ok(progress_tracker) => maybe_progress_tracker = Some(progress_tracker),
+                    Err(progress_tracker_error) => warn!(
+                        "Couldn't initialize progress tracker: {}",
+                        progress_tracker_error
+                    ),
                 }
-                None => warn!("Unable to count db entries, progress will not be logged."),
             }
+            None => warn!("Unable to count db entries, progress will not be logged."),
         }
+    }
 
-        for (idx, (block_hash_raw, raw_val)) in cursor.iter().enumerate() {
-            let block_hash = BlockHash::new(
-                block_hash_raw
-                    .try_into()
-                    .map_err(|_| Error::InvalidKey(idx))?,
-            );
-            let header: BlockHeader = bincode::deserialize(raw_val).map_err(|bincode_err| {
-                Error::Parsing(
-                    block_hash,
-                    BlockHeaderDatabase::db_name().to_string(),
-                    bincode_err,
-                )
-            })?;
-            let block_body_raw = txn.get(block_body_db, header.body_hash())?;
-            let block_body: BlockBody =
-                bincode::deserialize(block_body_raw).map_err(|bincode_err| {
-                    Error::Parsing(
-                        block_hash,
-                        BlockBodyDatabase::db_name().to_string(),
-                        bincode_err,
-                    )
-                })?;
-
-            let mut execution_results = vec![];
-
-            for deploy_hash in block_body.deploy_hashes() {
-                let metadata_raw = txn.get(deploy_metadata_db, &deploy_hash)?;
-                let mut metadata: DeployMetadata =
-                    bincode::deserialize(metadata_raw).map_err(|bincode_err| {
-                        Error::Parsing(
-                            block_hash,
-                            DeployMetadataDatabase::db_name().to_string(),
-                            bincode_err,
-                        )
-                    })?;
-                if let Some(execution_result) = metadata.execution_results.remove(&block_hash) {
-                    execution_results.push(execution_result);
-                }
-            }
-
-            stats.feed(execution_results)?;
-
+    let mut stats = ExecutionResultsStats::default();
+    visit_blocks(
+        &txn,
+        block_header_db,
+        maybe_block_index,
+        0..=u64::MAX,
+        |block_hash, header| {
+            feed_block_stats(
+                &txn,
+                block_body_db,
+                deploy_metadata_db,
+                block_hash,
+                &header,
+                &mut stats,
+            )?;
             if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
                 progress_tracker.advance_by(1);
             }
-        }
-    }
+            Ok::<_, Error>(())
+        },
+    )?;
     Ok(stats)
 }
 
@@ -136,7 +197,9 @@ pub fn execution_results_summary<P1: AsRef<Path>, P2: AsRef<Path>>(
         Box::new(io::stdout())
     };
 
-    let execution_results_stats = get_execution_results_stats(&env, log_progress)?;
+    let maybe_block_index = BlockIndex::open(&db_path, &env)?;
+    let execution_results_stats =
+        get_execution_results_stats(&env, maybe_block_index.as_ref(), log_progress)?;
     let execution_results_summary: ExecutionResultsSummary = execution_results_stats.into();
     dump_execution_results_summary(&execution_results_summary, out_writer)?;
 
@@ -0,0 +1,178 @@
+mod export;
+mod rows;
+mod table;
+#[cfg(test)]
+mod tests;
+
+use std::{io::Error as IoError, path::Path};
+
+use arrow::error::ArrowError;
+use bincode::Error as BincodeError;
+use clap::{Arg, ArgGroup, ArgMatches, Command};
+use lmdb::Error as LmdbError;
+use master_node::types::{BlockHash, DeployHash};
+use parquet::errors::ParquetError;
+use thiserror::Error as ThisError;
+
+use crate::{common::block_index::BlockIndexError, subcommands::execution_results_summary};
+
+use table::Formats;
+
+pub const COMMAND_NAME: &str = "export-chain";
+const CSV: &str = "csv";
+const DB_PATH: &str = "db-path";
+const FROM_HEIGHT: &str = "from-height";
+const OUTPUT_DIR: &str = "output-dir";
+const OVERWRITE: &str = "overwrite";
+const PARQUET: &str = "parquet";
+const TO_HEIGHT: &str = "to-height";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error building table batch: {0}")]
+    Arrow(#[from] ArrowError),
+    #[error("Error reading the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
+    #[error("Error parsing block body for block with hash {0}: {1}")]
+    BodyParsing(BlockHash, BincodeError),
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Error parsing deploy with hash {0}: {1}")]
+    DeployParsing(DeployHash, BincodeError),
+    #[error("Error parsing deploy metadata for deploy with hash {0}: {1}")]
+    MetadataParsing(DeployHash, BincodeError),
+    #[error("Block body for block hash {0} not present in the database")]
+    MissingBody(BlockHash),
+    #[error("Error writing output: {0}")]
+    Output(#[from] IoError),
+    #[error("Error writing Parquet file: {0}")]
+    Parquet(#[from] ParquetError),
+    #[error("Error parsing transfers for block with hash {0}: {1}")]
+    TransferParsing(BlockHash, BincodeError),
+    #[error("Error reading block headers: {0}")]
+    Traversal(#[from] execution_results_summary::Error),
+}
+
+enum DisplayOrder {
+    DbPath,
+    OutputDir,
+    FromHeight,
+    ToHeight,
+    Csv,
+    Parquet,
+    Overwrite,
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Exports the blocks in a height range to `blocks`, `deploys`, \
+            `execution_results` and `transfers` tables in CSV and/or Parquet files.",
+        )
+        .arg(
+            Arg::new(DB_PATH)
+                .display_order(DisplayOrder::DbPath as usize)
+                .required(true)
+                .short('d')
+                .long(DB_PATH)
+                .takes_value(true)
+                .value_name("DB_PATH")
+                .help("Path of the directory with the `storage.lmdb` file."),
+        )
+        .arg(
+            Arg::new(OUTPUT_DIR)
+                .display_order(DisplayOrder::OutputDir as usize)
+                .required(true)
+                .short('o')
+                .long(OUTPUT_DIR)
+                .takes_value(true)
+                .value_name("DIR_PATH")
+                .help(
+                    "Path of the directory where the table files are written. It is created \
+                    if missing.",
+                ),
+        )
+        .arg(
+            Arg::new(FROM_HEIGHT)
+                .display_order(DisplayOrder::FromHeight as usize)
+                .short('f')
+                .long(FROM_HEIGHT)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .help("Height of the lowest block to export. Defaults to 0."),
+        )
+        .arg(
+            Arg::new(TO_HEIGHT)
+                .display_order(DisplayOrder::ToHeight as usize)
+                .short('t')
+                .long(TO_HEIGHT)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .help("Height of the highest block to export. Defaults to the highest block."),
+        )
+        .arg(
+            Arg::new(CSV)
+                .display_order(DisplayOrder::Csv as usize)
+                .short('c')
+                .long(CSV)
+                .takes_value(false)
+                .help("Write every table to a `.csv` file."),
+        )
+        .arg(
+            Arg::new(PARQUET)
+                .display_order(DisplayOrder::Parquet as usize)
+                .short('p')
+                .long(PARQUET)
+                .takes_value(false)
+                .help("Write every table to a `.parquet` file."),
+        )
+        .group(
+            ArgGroup::new("formats")
+                .required(true)
+                .multiple(true)
+                .args(&[CSV, PARQUET]),
+        )
+        .arg(
+            Arg::new(OVERWRITE)
+                .display_order(DisplayOrder::Overwrite as usize)
+                .required(false)
+                .short('w')
+                .long(OVERWRITE)
+                .takes_value(false)
+                .help("Overwrite already existing table files."),
+        )
+}
+
+fn parse_height(matches: &ArgMatches, arg: &str) -> Option<u64> {
+    matches.value_of(arg).map(|height| {
+        height
+            .parse()
+            .unwrap_or_else(|_| panic!("Value of \"--{arg}\" must be an integer."))
+    })
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let path = Path::new(matches.value_of(DB_PATH).expect("should have db-path arg"));
+    let output_dir = Path::new(
+        matches
+            .value_of(OUTPUT_DIR)
+            .expect("should have output-dir arg"),
+    );
+    let from_height = parse_height(matches, FROM_HEIGHT).unwrap_or(0);
+    let to_height = parse_height(matches, TO_HEIGHT).unwrap_or(u64::MAX);
+    let formats = Formats {
+        csv: matches.is_present(CSV),
+        parquet: matches.is_present(PARQUET),
+    };
+    let overwrite = matches.is_present(OVERWRITE);
+    let summary = export::export_chain(
+        path,
+        from_height..=to_height,
+        output_dir,
+        formats,
+        overwrite,
+    )?;
+    export::log_summary(&summary);
+    Ok(())
+}
@@ -0,0 +1,281 @@
+use std::{fs, ops::RangeInclusive, path::Path};
+
+use lmdb::{Database as LmdbDatabase, Error as LmdbError, RoTransaction, Transaction};
+use log::info;
+
+use cargio_types::{ExecutionResult, Transfer};
+use master_node::types::{BlockHash, BlockHeader, Deploy, DeployMetadata};
+
+use crate::{
+    common::{
+        block_index::BlockIndex,
+        db::{
+            self, BlockBodyDatabase, BlockHeaderDatabase, Database, DeployDatabase,
+            DeployMetadataDatabase, TransferDatabase, STORAGE_FILE_NAME,
+        },
+    },
+    subcommands::execution_results_summary::{block_body::BlockBody, read_db},
+};
+
+use super::{
+    rows::{BlockRow, DeployRow, ExecutionResultRow, TransferRow},
+    table::{Formats, Table},
+    Error,
+};
+
+const LOG_INTERVAL: u64 = 10_000;
+
+/// Number of rows written to every table.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ExportSummary {
+    pub(crate) blocks: u64,
+    pub(crate) deploys: u64,
+    pub(crate) execution_results: u64,
+    pub(crate) transfers: u64,
+}
+
+struct Tables {
+    blocks: Table<BlockRow>,
+    deploys: Table<DeployRow>,
+    execution_results: Table<ExecutionResultRow>,
+    transfers: Table<TransferRow>,
+}
+
+impl Tables {
+    fn create(output_dir: &Path, formats: Formats, overwrite: bool) -> Result<Self, Error> {
+        Ok(Self {
+            blocks: Table::create(output_dir, formats, overwrite)?,
+            deploys: Table::create(output_dir, formats, overwrite)?,
+            execution_results: Table::create(output_dir, formats, overwrite)?,
+            transfers: Table::create(output_dir, formats, overwrite)?,
+        })
+    }
+
+    fn finish(self) -> Result<ExportSummary, Error> {
+        Ok(ExportSummary {
+            blocks: self.blocks.finish()?,
+            deploys: self.deploys.finish()?,
+            execution_results: self.execution_results.finish()?,
+            transfers: self.transfers.finish()?,
+        })
+    }
+}
+
+/// The databases read for every block. All but the block bodies may be
+/// missing, e.g. after pruning, in which case their tables get no rows.
+struct StorageDatabases {
+    block_body: LmdbDatabase,
+    maybe_deploy: Option<LmdbDatabase>,
+    maybe_deploy_metadata: Option<LmdbDatabase>,
+    maybe_transfer: Option<LmdbDatabase>,
+}
+
+fn open_optional_db(txn: &RoTransaction, db_name: &str) -> Result<Option<LmdbDatabase>, Error> {
+    match unsafe { txn.open_db(Some(db_name)) } {
+        Ok(db) => Ok(Some(db)),
+        Err(LmdbError::NotFound) => Ok(None),
+        Err(lmdb_err) => Err(lmdb_err.into()),
+    }
+}
+
+fn get_optional<'txn>(
+    txn: &'txn RoTransaction,
+    maybe_db: Option<LmdbDatabase>,
+    key: &[u8],
+) -> Result<Option<&'txn [u8]>, Error> {
+    let db = match maybe_db {
+        Some(db) => db,
+        None => return Ok(None),
+    };
+    match txn.get(db, &key) {
+        Ok(raw_val) => Ok(Some(raw_val)),
+        Err(LmdbError::NotFound) => Ok(None),
+        Err(lmdb_err) => Err(lmdb_err.into()),
+    }
+}
+
+fn execution_result_row(
+    deploy_hash: String,
+    block_hash: String,
+    block_height: u64,
+    execution_result: ExecutionResult,
+) -> ExecutionResultRow {
+    let (success, cost, error_message, transfers) = match execution_result {
+        ExecutionResult::Success {
+            cost, transfers, ..
+        } => (true, cost, None, transfers),
+        ExecutionResult::Failure {
+            cost,
+            error_message,
+            transfers,
+            ..
+        } => (false, cost, Some(error_message), transfers),
+    };
+    ExecutionResultRow {
+        deploy_hash,
+        block_hash,
+        block_height,
+        success,
+        cost: cost.to_string(),
+        error_message,
+        transfer_count: transfers.len() as u32,
+    }
+}
+
+fn transfer_row(block_hash: String, block_height: u64, transfer: Transfer) -> TransferRow {
+    TransferRow {
+        block_hash,
+        block_height,
+        deploy_hash: hex::encode(transfer.deploy_hash.value()),
+        from: transfer.from.to_formatted_string(),
+        to: transfer.to.map(|account| account.to_formatted_string()),
+        source: transfer.source.to_formatted_string(),
+        target: transfer.target.to_formatted_string(),
+        amount: transfer.amount.to_string(),
+        gas: transfer.gas.to_string(),
+        id: transfer.id,
+    }
+}
+
+/// Adds the rows of the block with the given header to every table.
+fn export_block(
+    txn: &RoTransaction,
+    dbs: &StorageDatabases,
+    block_hash: BlockHash,
+    header: &BlockHeader,
+    tables: &mut Tables,
+) -> Result<(), Error> {
+    let body: BlockBody = match txn.get(dbs.block_body, header.body_hash()) {
+        Ok(raw_body) => bincode::deserialize(raw_body)
+            .map_err(|bincode_err| Error::BodyParsing(block_hash, bincode_err))?,
+        Err(LmdbError::NotFound) => return Err(Error::MissingBody(block_hash)),
+        Err(lmdb_err) => return Err(lmdb_err.into()),
+    };
+    let block_hash_hex = hex::encode(block_hash);
+    let height = header.height();
+    tables.blocks.push(BlockRow {
+        block_hash: block_hash_hex.clone(),
+        height,
+        era_id: header.era_id().value(),
+        timestamp: header.timestamp().millis(),
+        protocol_version: header.protocol_version().to_string(),
+        parent_hash: hex::encode(header.parent_hash()),
+        state_root_hash: hex::encode(header.state_root_hash()),
+        is_switch_block: header.is_switch_block(),
+        proposer: body.proposer().to_hex(),
+        deploy_count: body.deploy_hashes.len() as u32,
+        transfer_count: body.transfer_hashes.len() as u32,
+    })?;
+
+    let deploy_hashes = body
+        .deploy_hashes
+        .iter()
+        .map(|deploy_hash| (deploy_hash, false))
+        .chain(
+            body.transfer_hashes
+                .iter()
+                .map(|deploy_hash| (deploy_hash, true)),
+        );
+    for (deploy_hash, is_transfer) in deploy_hashes {
+        let deploy_hash_hex = hex::encode(deploy_hash);
+        let maybe_deploy: Option<Deploy> =
+            get_optional(txn, dbs.maybe_deploy, deploy_hash.as_ref())?
+                .map(|raw_deploy| {
+                    bincode::deserialize(raw_deploy)
+                        .map_err(|bincode_err| Error::DeployParsing(*deploy_hash, bincode_err))
+                })
+                .transpose()?;
+        let maybe_header = maybe_deploy.as_ref().map(Deploy::header);
+        tables.deploys.push(DeployRow {
+            deploy_hash: deploy_hash_hex.clone(),
+            block_hash: block_hash_hex.clone(),
+            block_height: height,
+            is_transfer,
+            account: maybe_header.map(|header| header.account().to_hex()),
+            timestamp: maybe_header.map(|header| header.timestamp().millis()),
+            ttl: maybe_header.map(|header| header.ttl().millis()),
+            gas_price: maybe_header.map(|header| header.gas_price()),
+            chain_name: maybe_header.map(|header| header.chain_name().to_string()),
+            approval_count: maybe_deploy
+                .as_ref()
+                .map(|deploy| deploy.approvals().len() as u32),
+        })?;
+
+        let maybe_metadata: Option<DeployMetadata> =
+            get_optional(txn, dbs.maybe_deploy_metadata, deploy_hash.as_ref())?
+                .map(|raw_metadata| {
+                    bincode::deserialize(raw_metadata)
+                        .map_err(|bincode_err| Error::MetadataParsing(*deploy_hash, bincode_err))
+                })
+                .transpose()?;
+        if let Some(execution_result) =
+            maybe_metadata.and_then(|mut metadata| metadata.execution_results.remove(&block_hash))
+        {
+            tables.execution_results.push(execution_result_row(
+                deploy_hash_hex,
+                block_hash_hex.clone(),
+                height,
+                execution_result,
+            ))?;
+        }
+    }
+
+    if let Some(raw_transfers) = get_optional(txn, dbs.maybe_transfer, block_hash.as_ref())? {
+        let transfers: Vec<Transfer> = bincode::deserialize(raw_transfers)
+            .map_err(|bincode_err| Error::TransferParsing(block_hash, bincode_err))?;
+        for transfer in transfers {
+            tables
+                .transfers
+                .push(transfer_row(block_hash_hex.clone(), height, transfer))?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the blocks with a height in `heights` to the tables in
+/// `output_dir`.
+pub(crate) fn export_chain<P1: AsRef<Path>, P2: AsRef<Path>>(
+    db_path: P1,
+    heights: RangeInclusive<u64>,
+    output_dir: P2,
+    formats: Formats,
+    overwrite: bool,
+) -> Result<ExportSummary, Error> {
+    let env = db::db_env(db_path.as_ref().join(STORAGE_FILE_NAME))?;
+    let maybe_block_index = BlockIndex::open(&db_path, &env)?;
+    fs::create_dir_all(&output_dir)?;
+    let mut tables = Tables::create(output_dir.as_ref(), formats, overwrite)?;
+
+    let txn = env.begin_ro_txn()?;
+    let block_header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+    let dbs = StorageDatabases {
+        block_body: unsafe { txn.open_db(Some(BlockBodyDatabase::db_name()))? },
+        maybe_deploy: open_optional_db(&txn, DeployDatabase::db_name())?,
+        maybe_deploy_metadata: open_optional_db(&txn, DeployMetadataDatabase::db_name())?,
+        maybe_transfer: open_optional_db(&txn, TransferDatabase::db_name())?,
+    };
+    let mut exported_blocks = 0u64;
+    read_db::visit_blocks(
+        &txn,
+        block_header_db,
+        maybe_block_index.as_ref(),
+        heights,
+        |block_hash, header| {
+            export_block(&txn, &dbs, block_hash, &header, &mut tables)?;
+            exported_blocks += 1;
+            if exported_blocks % LOG_INTERVAL == 0 {
+                info!("Exported {} blocks...", exported_blocks);
+            }
+            Ok::<_, Error>(())
+        },
+    )?;
+    txn.commit()?;
+    tables.finish()
+}
+
+pub(crate) fn log_summary(summary: &ExportSummary) {
+    info!(
+        "Exported {} blocks, {} deploys, {} execution results and {} transfers.",
+        summary.blocks, summary.deploys, summary.execution_results, summary.transfers
+    );
+}
@@ -0,0 +1,241 @@
+use std::sync::Arc;
+
+use arrow::{
+    array::{ArrayRef, BooleanArray, StringArray, UInt32Array, UInt64Array},
+    datatypes::{DataType, Field, Schema},
+};
+
+/// A row of an exported table.
+pub(crate) trait Row: Sized {
+    /// Name of the table, used as the name of its files.
+    const TABLE_NAME: &'static str;
+
+    fn schema() -> Schema;
+
+    /// Returns the columns of `rows`, in the order of `schema`.
+    fn columns(rows: &[Self]) -> Vec<ArrayRef>;
+}
+
+fn utf8<'a>(values: impl Iterator<Item = Option<&'a str>>) -> ArrayRef {
+    Arc::new(values.collect::<StringArray>())
+}
+
+#[derive(Debug)]
+pub(crate) struct BlockRow {
+    pub(crate) block_hash: String,
+    pub(crate) height: u64,
+    pub(crate) era_id: u64,
+    pub(crate) timestamp: u64,
+    pub(crate) protocol_version: String,
+    pub(crate) parent_hash: String,
+    pub(crate) state_root_hash: String,
+    pub(crate) is_switch_block: bool,
+    pub(crate) proposer: String,
+    pub(crate) deploy_count: u32,
+    pub(crate) transfer_count: u32,
+}
+
+impl Row for BlockRow {
+    const TABLE_NAME: &'static str = "blocks";
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("block_hash", DataType::Utf8, false),
+            Field::new("height", DataType::UInt64, false),
+            Field::new("era_id", DataType::UInt64, false),
+            Field::new("timestamp", DataType::UInt64, false),
+            Field::new("protocol_version", DataType::Utf8, false),
+            Field::new("parent_hash", DataType::Utf8, false),
+            Field::new("state_root_hash", DataType::Utf8, false),
+            Field::new("is_switch_block", DataType::Boolean, false),
+            Field::new("proposer", DataType::Utf8, false),
+            Field::new("deploy_count", DataType::UInt32, false),
+            Field::new("transfer_count", DataType::UInt32, false),
+        ])
+    }
+
+    fn columns(rows: &[Self]) -> Vec<ArrayRef> {
+        vec![
+            utf8(rows.iter().map(|row| Some(row.block_hash.as_str()))),
+            Arc::new(UInt64Array::from_iter_values(
+                rows.iter().map(|row| row.height),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                rows.iter().map(|row| row.era_id),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                rows.iter().map(|row| row.timestamp),
+            )),
+            utf8(rows.iter().map(|row| Some(row.protocol_version.as_str()))),
+            utf8(rows.iter().map(|row| Some(row.parent_hash.as_str()))),
+            utf8(rows.iter().map(|row| Some(row.state_root_hash.as_str()))),
+            Arc::new(BooleanArray::from(
+                rows.iter()
+                    .map(|row| row.is_switch_block)
+                    .collect::<Vec<_>>(),
+            )),
+            utf8(rows.iter().map(|row| Some(row.proposer.as_str()))),
+            Arc::new(UInt32Array::from_iter_values(
+                rows.iter().map(|row| row.deploy_count),
+            )),
+            Arc::new(UInt32Array::from_iter_values(
+                rows.iter().map(|row| row.transfer_count),
+            )),
+        ]
+    }
+}
+
+/// A deploy of a block. The columns read from the deploy itself are empty if
+/// it isn't in the `deploys` database.
+#[derive(Debug)]
+pub(crate) struct DeployRow {
+    pub(crate) deploy_hash: String,
+    pub(crate) block_hash: String,
+    pub(crate) block_height: u64,
+    pub(crate) is_transfer: bool,
+    pub(crate) account: Option<String>,
+    pub(crate) timestamp: Option<u64>,
+    pub(crate) ttl: Option<u64>,
+    pub(crate) gas_price: Option<u64>,
+    pub(crate) chain_name: Option<String>,
+    pub(crate) approval_count: Option<u32>,
+}
+
+impl Row for DeployRow {
+    const TABLE_NAME: &'static str = "deploys";
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("deploy_hash", DataType::Utf8, false),
+            Field::new("block_hash", DataType::Utf8, false),
+            Field::new("block_height", DataType::UInt64, false),
+            Field::new("is_transfer", DataType::Boolean, false),
+            Field::new("account", DataType::Utf8, true),
+            Field::new("timestamp", DataType::UInt64, true),
+            Field::new("ttl", DataType::UInt64, true),
+            Field::new("gas_price", DataType::UInt64, true),
+            Field::new("chain_name", DataType::Utf8, true),
+            Field::new("approval_count", DataType::UInt32, true),
+        ])
+    }
+
+    fn columns(rows: &[Self]) -> Vec<ArrayRef> {
+        vec![
+            utf8(rows.iter().map(|row| Some(row.deploy_hash.as_str()))),
+            utf8(rows.iter().map(|row| Some(row.block_hash.as_str()))),
+            Arc::new(UInt64Array::from_iter_values(
+                rows.iter().map(|row| row.block_height),
+            )),
+            Arc::new(BooleanArray::from(
+                rows.iter().map(|row| row.is_transfer).collect::<Vec<_>>(),
+            )),
+            utf8(rows.iter().map(|row| row.account.as_deref())),
+            Arc::new(UInt64Array::from_iter(rows.iter().map(|row| row.timestamp))),
+            Arc::new(UInt64Array::from_iter(rows.iter().map(|row| row.ttl))),
+            Arc::new(UInt64Array::from_iter(rows.iter().map(|row| row.gas_price))),
+            utf8(rows.iter().map(|row| row.chain_name.as_deref())),
+            Arc::new(UInt32Array::from_iter(
+                rows.iter().map(|row| row.approval_count),
+            )),
+        ]
+    }
+}
+
+/// The execution result of a deploy in a block. Costs are decimal strings,
+/// since they are 512 bit integers.
+#[derive(Debug)]
+pub(crate) struct ExecutionResultRow {
+    pub(crate) deploy_hash: String,
+    pub(crate) block_hash: String,
+    pub(crate) block_height: u64,
+    pub(crate) success: bool,
+    pub(crate) cost: String,
+    pub(crate) error_message: Option<String>,
+    pub(crate) transfer_count: u32,
+}
+
+impl Row for ExecutionResultRow {
+    const TABLE_NAME: &'static str = "execution_results";
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("deploy_hash", DataType::Utf8, false),
+            Field::new("block_hash", DataType::Utf8, false),
+            Field::new("block_height", DataType::UInt64, false),
+            Field::new("success", DataType::Boolean, false),
+            Field::new("cost", DataType::Utf8, false),
+            Field::new("error_message", DataType::Utf8, true),
+            Field::new("transfer_count", DataType::UInt32, false),
+        ])
+    }
+
+    fn columns(rows: &[Self]) -> Vec<ArrayRef> {
+        vec![
+            utf8(rows.iter().map(|row| Some(row.deploy_hash.as_str()))),
+            utf8(rows.iter().map(|row| Some(row.block_hash.as_str()))),
+            Arc::new(UInt64Array::from_iter_values(
+                rows.iter().map(|row| row.block_height),
+            )),
+            Arc::new(BooleanArray::from(
+                rows.iter().map(|row| row.success).collect::<Vec<_>>(),
+            )),
+            utf8(rows.iter().map(|row| Some(row.cost.as_str()))),
+            utf8(rows.iter().map(|row| row.error_message.as_deref())),
+            Arc::new(UInt32Array::from_iter_values(
+                rows.iter().map(|row| row.transfer_count),
+            )),
+        ]
+    }
+}
+
+/// A transfer of a block. Amounts are decimal strings, since they are 512 bit
+/// integers.
+#[derive(Debug)]
+pub(crate) struct TransferRow {
+    pub(crate) block_hash: String,
+    pub(crate) block_height: u64,
+    pub(crate) deploy_hash: String,
+    pub(crate) from: String,
+    pub(crate) to: Option<String>,
+    pub(crate) source: String,
+    pub(crate) target: String,
+    pub(crate) amount: String,
+    pub(crate) gas: String,
+    pub(crate) id: Option<u64>,
+}
+
+impl Row for TransferRow {
+    const TABLE_NAME: &'static str = "transfers";
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("block_hash", DataType::Utf8, false),
+            Field::new("block_height", DataType::UInt64, false),
+            Field::new("deploy_hash", DataType::Utf8, false),
+            Field::new("from", DataType::Utf8, false),
+            Field::new("to", DataType::Utf8, true),
+            Field::new("source", DataType::Utf8, false),
+            Field::new("target", DataType::Utf8, false),
+            Field::new("amount", DataType::Utf8, false),
+            Field::new("gas", DataType::Utf8, false),
+            Field::new("id", DataType::UInt64, true),
+        ])
+    }
+
+    fn columns(rows: &[Self]) -> Vec<ArrayRef> {
+        vec![
+            utf8(rows.iter().map(|row| Some(row.block_hash.as_str()))),
+            Arc::new(UInt64Array::from_iter_values(
+                rows.iter().map(|row| row.block_height),
+            )),
+            utf8(rows.iter().map(|row| Some(row.deploy_hash.as_str()))),
+            utf8(rows.iter().map(|row| Some(row.from.as_str()))),
+            utf8(rows.iter().map(|row| row.to.as_deref())),
+            utf8(rows.iter().map(|row| Some(row.source.as_str()))),
+            utf8(rows.iter().map(|row| Some(row.target.as_str()))),
+            utf8(rows.iter().map(|row| Some(row.amount.as_str()))),
+            utf8(rows.iter().map(|row| Some(row.gas.as_str()))),
+            Arc::new(UInt64Array::from_iter(rows.iter().map(|row| row.id))),
+        ]
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Error as IoError,
+    path::Path,
+    sync::Arc,
+};
+
+use arrow::{csv::Writer as CsvWriter, datatypes::SchemaRef, record_batch::RecordBatch};
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression, ZstdLevel},
+    file::properties::WriterProperties,
+};
+
+use super::{rows::Row, Error};
+
+/// Number of rows written to the table files at once.
+const BATCH_ROWS: usize = 65_536;
+
+/// The file formats tables are written in.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Formats {
+    pub(crate) csv: bool,
+    pub(crate) parquet: bool,
+}
+
+fn create_output(path: &Path, overwrite: bool) -> Result<File, IoError> {
+    OpenOptions::new()
+        .create_new(!overwrite)
+        .create(overwrite)
+        .truncate(overwrite)
+        .write(true)
+        .open(path)
+}
+
+/// Buffers the rows of a table and writes them in batches to a
+/// `<table name>.csv` and/or `<table name>.parquet` file.
+pub(crate) struct Table<R: Row> {
+    schema: SchemaRef,
+    rows: Vec<R>,
+    row_count: u64,
+    maybe_csv_writer: Option<CsvWriter<File>>,
+    maybe_parquet_writer: Option<ArrowWriter<File>>,
+}
+
+impl<R: Row> Table<R> {
+    pub(crate) fn create(
+        output_dir: &Path,
+        formats: Formats,
+        overwrite: bool,
+    ) -> Result<Self, Error> {
+        let schema = Arc::new(R::schema());
+        let maybe_csv_writer = if formats.csv {
+            let path = output_dir.join(format!("{}.csv", R::TABLE_NAME));
+            Some(CsvWriter::new(create_output(&path, overwrite)?))
+        } else {
+            None
+        };
+        let maybe_parquet_writer = if formats.parquet {
+            let path = output_dir.join(format!("{}.parquet", R::TABLE_NAME));
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::ZSTD(ZstdLevel::default()))
+                .build();
+            Some(ArrowWriter::try_new(
+                create_output(&path, overwrite)?,
+                schema.clone(),
+                Some(properties),
+            )?)
+        } else {
+            None
+        };
+        Ok(Self {
+            schema,
+            rows: Vec::with_capacity(BATCH_ROWS),
+            row_count: 0,
+            maybe_csv_writer,
+            maybe_parquet_writer,
+        })
+    }
+
+    pub(crate) fn push(&mut self, row: R) -> Result<(), Error> {
+        self.rows.push(row);
+        if self.rows.len() >= BATCH_ROWS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let batch = RecordBatch::try_new(self.schema.clone(), R::columns(&self.rows))?;
+        if let Some(csv_writer) = self.maybe_csv_writer.as_mut() {
+            csv_writer.write(&batch)?;
+        }
+        if let Some(parquet_writer) = self.maybe_parquet_writer.as_mut() {
+            parquet_writer.write(&batch)?;
+        }
+        self.row_count += self.rows.len() as u64;
+        self.rows.clear();
+        Ok(())
+    }
+
+    /// Writes the remaining rows and completes the files. Returns the number
+    /// of rows in the table.
+    pub(crate) fn finish(mut self) -> Result<u64, Error> {
+        self.flush()?;
+        // The CSV writer flushes after every batch.
+        if let Some(parquet_writer) = self.maybe_parquet_writer.take() {
+            parquet_writer.close()?;
+        }
+        Ok(self.row_count)
+    }
+}
@@ -0,0 +1,201 @@
+use std::fs::{self, File};
+
+use cargio_types::Transfer;
+use lmdb::{Transaction, WriteFlags};
+use master_node::types::BlockHash;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+
+use crate::{
+    common::db::{
+        BlockBodyDatabase, BlockHeaderDatabase, Database, DeployDatabase, DeployMetadataDatabase,
+        TransferDatabase, STORAGE_FILE_NAME,
+    },
+    subcommands::execution_results_summary::block_body::BlockBody,
+    test_utils::{mock_block_header, mock_deploy_hash, mock_deploy_metadata, LmdbTestFixture},
+};
+
+use super::{
+    export::{self, ExportSummary},
+    table::Formats,
+    Error,
+};
+
+const BLOCK_COUNT: u8 = 3;
+const CSV_ONLY: Formats = Formats {
+    csv: true,
+    parquet: false,
+};
+
+// Block `i` is at height `i` and has deploy `i` with its execution results.
+// Block 1 also has a transfer. Deploys themselves aren't stored.
+fn storage_fixture() -> (LmdbTestFixture, Vec<BlockHash>) {
+    let fixture = LmdbTestFixture::new(
+        vec![
+            BlockHeaderDatabase::db_name(),
+            BlockBodyDatabase::db_name(),
+            DeployDatabase::db_name(),
+            DeployMetadataDatabase::db_name(),
+            TransferDatabase::db_name(),
+        ],
+        Some(STORAGE_FILE_NAME),
+    );
+    let mut block_hashes = vec![];
+    let mut txn = fixture.env.begin_rw_txn().unwrap();
+    for idx in 0..BLOCK_COUNT {
+        let (block_hash, mut header) = mock_block_header(idx);
+        header.height = idx as u64;
+        let deploy_hash = mock_deploy_hash(idx);
+        txn.put(
+            *fixture.db(Some(BlockHeaderDatabase::db_name())).unwrap(),
+            &block_hash,
+            &bincode::serialize(&header).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+        txn.put(
+            *fixture.db(Some(BlockBodyDatabase::db_name())).unwrap(),
+            &header.body_hash,
+            &bincode::serialize(&BlockBody::new(vec![deploy_hash])).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+        txn.put(
+            *fixture.db(Some(DeployMetadataDatabase::db_name())).unwrap(),
+            &deploy_hash,
+            &bincode::serialize(&mock_deploy_metadata(&[block_hash])).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+        block_hashes.push(block_hash);
+    }
+    let transfer = Transfer {
+        amount: 7.into(),
+        ..Default::default()
+    };
+    txn.put(
+        *fixture.db(Some(TransferDatabase::db_name())).unwrap(),
+        &block_hashes[1],
+        &bincode::serialize(&vec![transfer]).unwrap(),
+        WriteFlags::empty(),
+    )
+    .unwrap();
+    txn.commit().unwrap();
+    (fixture, block_hashes)
+}
+
+fn csv_lines(path: &std::path::Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn export_should_write_csv_tables_for_height_range() {
+    let (fixture, block_hashes) = storage_fixture();
+    let out_dir = tempfile::tempdir().unwrap();
+
+    let summary = export::export_chain(
+        fixture.tmp_dir.path(),
+        1..=2,
+        out_dir.path(),
+        CSV_ONLY,
+        false,
+    )
+    .unwrap();
+    assert_eq!(
+        summary,
+        ExportSummary {
+            blocks: 2,
+            deploys: 2,
+            execution_results: 2,
+            transfers: 1,
+        }
+    );
+
+    let blocks = csv_lines(&out_dir.path().join("blocks.csv"));
+    assert_eq!(blocks.len(), 3);
+    assert!(blocks[0].starts_with("block_hash,height,"));
+    assert!(!blocks
+        .iter()
+        .any(|line| line.contains(&hex::encode(block_hashes[0]))));
+    assert!(blocks[1..]
+        .iter()
+        .all(|line| line.contains(&hex::encode(block_hashes[1]))
+            || line.contains(&hex::encode(block_hashes[2]))));
+
+    let deploys = csv_lines(&out_dir.path().join("deploys.csv"));
+    assert_eq!(deploys.len(), 3);
+    assert!(deploys[1].starts_with(&hex::encode(mock_deploy_hash(1))));
+
+    let execution_results = csv_lines(&out_dir.path().join("execution_results.csv"));
+    assert_eq!(execution_results.len(), 3);
+    assert!(execution_results[1].contains(",true,100,"));
+
+    let transfers = csv_lines(&out_dir.path().join("transfers.csv"));
+    assert_eq!(transfers.len(), 2);
+    assert!(transfers[1].starts_with(&format!("{},1,", hex::encode(block_hashes[1]))));
+
+    assert!(!out_dir.path().join("blocks.parquet").exists());
+}
+
+#[test]
+fn export_should_write_parquet_tables() {
+    let (fixture, _block_hashes) = storage_fixture();
+    let out_dir = tempfile::tempdir().unwrap();
+    let formats = Formats {
+        csv: false,
+        parquet: true,
+    };
+
+    export::export_chain(
+        fixture.tmp_dir.path(),
+        0..=u64::MAX,
+        out_dir.path(),
+        formats,
+        false,
+    )
+    .unwrap();
+
+    for (table_name, expected_rows) in [
+        ("blocks", BLOCK_COUNT as i64),
+        ("deploys", BLOCK_COUNT as i64),
+        ("execution_results", BLOCK_COUNT as i64),
+        ("transfers", 1),
+    ] {
+        let file = File::open(out_dir.path().join(format!("{table_name}.parquet"))).unwrap();
+        let reader = SerializedFileReader::new(file).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), expected_rows);
+    }
+    assert!(!out_dir.path().join("blocks.csv").exists());
+}
+
+#[test]
+fn export_should_not_overwrite_without_flag() {
+    let (fixture, _block_hashes) = storage_fixture();
+    let out_dir = tempfile::tempdir().unwrap();
+    fs::write(out_dir.path().join("deploys.csv"), "").unwrap();
+
+    assert!(matches!(
+        export::export_chain(
+            fixture.tmp_dir.path(),
+            0..=1,
+            out_dir.path(),
+            CSV_ONLY,
+            false
+        ),
+        Err(Error::Output(_))
+    ));
+
+    let summary = export::export_chain(
+        fixture.tmp_dir.path(),
+        0..=1,
+        out_dir.path(),
+        CSV_ONLY,
+        true,
+    )
+    .unwrap();
+    assert_eq!(summary.blocks, 2);
+    assert_eq!(csv_lines(&out_dir.path().join("deploys.csv")).len(), 3);
+}
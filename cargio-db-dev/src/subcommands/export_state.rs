@@ -0,0 +1,105 @@
+mod export;
+#[cfg(test)]
+mod tests;
+
+use std::path::Path;
+
+use clap::{Arg, ArgMatches, Command};
+use lmdb::Error as LmdbError;
+use thiserror::Error as ThisError;
+
+use cargio_hashing::Digest;
+
+use crate::common::state_file::StateFileError;
+
+pub const COMMAND_NAME: &str = "export-state";
+const OUTPUT: &str = "output";
+const OVERWRITE: &str = "overwrite";
+const STATE_ROOT: &str = "state-root";
+const TRIE_PATH: &str = "trie-path";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Trie with key {0} not present in the trie store")]
+    MissingTrie(Digest),
+    #[error("Error writing the state file: {0}")]
+    StateFile(#[from] StateFileError),
+    #[error("Error parsing trie with key {0}: {1}")]
+    TrieParsing(Digest, String),
+}
+
+enum DisplayOrder {
+    TriePath,
+    StateRoot,
+    Output,
+    Overwrite,
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Writes every key and value under a state root to a zstd compressed state \
+            file, which can be read without LMDB or the execution engine and turned back \
+            into a trie store with `import-state`.",
+        )
+        .arg(
+            Arg::new(TRIE_PATH)
+                .display_order(DisplayOrder::TriePath as usize)
+                .required(true)
+                .short('t')
+                .long(TRIE_PATH)
+                .takes_value(true)
+                .value_name("TRIE_STORE_DIR_PATH")
+                .help("Path of the directory with the `data.lmdb` file."),
+        )
+        .arg(
+            Arg::new(STATE_ROOT)
+                .display_order(DisplayOrder::StateRoot as usize)
+                .required(true)
+                .short('r')
+                .long(STATE_ROOT)
+                .takes_value(true)
+                .value_name("STATE_ROOT_HASH")
+                .help("Hash of the state root to export."),
+        )
+        .arg(
+            Arg::new(OUTPUT)
+                .display_order(DisplayOrder::Output as usize)
+                .required(true)
+                .short('o')
+                .long(OUTPUT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help("Path of the state file to create."),
+        )
+        .arg(
+            Arg::new(OVERWRITE)
+                .display_order(DisplayOrder::Overwrite as usize)
+                .required(false)
+                .short('w')
+                .long(OVERWRITE)
+                .takes_value(false)
+                .help("Overwrite an already existing state file."),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let trie_path = Path::new(
+        matches
+            .value_of(TRIE_PATH)
+            .expect("should have trie-path arg"),
+    );
+    let state_root = Digest::from_hex(
+        matches
+            .value_of(STATE_ROOT)
+            .expect("should have state-root arg"),
+    )
+    .expect("should parse state root hash to hex format");
+    let output = Path::new(matches.value_of(OUTPUT).expect("should have output arg"));
+    let overwrite = matches.is_present(OVERWRITE);
+    export::export_state(trie_path, state_root, output, overwrite)?;
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use std::{fs, path::Path};
+
+use lmdb::{Database as LmdbDatabase, Error as LmdbError, RoTransaction, Transaction};
+use log::{info, warn};
+
+use cargio_execution_engine::storage::trie::Trie;
+use cargio_hashing::Digest;
+use cargio_types::{bytesrepr, Key, StoredValue};
+
+use crate::{
+    common::{
+        db::{self, TRIE_STORE_FILE_NAME},
+        state_file::StateFileWriter,
+    },
+    subcommands::trie_compact,
+};
+
+use super::Error;
+
+const HEARTBEAT_INTERVAL: u64 = 1_000_000;
+
+/// Writes the leaves of the trie under `state_root` to `writer`.
+fn write_entries(
+    txn: &RoTransaction,
+    trie_db: LmdbDatabase,
+    state_root: Digest,
+    writer: &mut StateFileWriter,
+) -> Result<(), Error> {
+    let mut entry_count = 0u64;
+    let mut pending = vec![state_root];
+    while let Some(trie_key) = pending.pop() {
+        let raw_trie = match txn.get(trie_db, &trie_key) {
+            Ok(raw_trie) => raw_trie,
+            Err(LmdbError::NotFound) => return Err(Error::MissingTrie(trie_key)),
+            Err(lmdb_err) => return Err(lmdb_err.into()),
+        };
+        let trie: Trie<Key, StoredValue> = bytesrepr::deserialize(raw_trie.to_vec())
+            .map_err(|bytesrepr_err| Error::TrieParsing(trie_key, bytesrepr_err.to_string()))?;
+        if let Trie::Leaf { key, value } = &trie {
+            writer.write_entry(key, value)?;
+            entry_count += 1;
+            if entry_count % HEARTBEAT_INTERVAL == 0 {
+                info!("Exported {entry_count} entries...");
+            }
+        } else {
+            pending.extend(trie_compact::trie_pointers(&trie));
+        }
+    }
+    Ok(())
+}
+
+/// Writes every key and value under `state_root` in the trie store in
+/// `trie_path` to a new state file at `output`. Returns the number of
+/// exported entries.
+pub(crate) fn export_state<P1: AsRef<Path>, P2: AsRef<Path>>(
+    trie_path: P1,
+    state_root: Digest,
+    output: P2,
+    overwrite: bool,
+) -> Result<u64, Error> {
+    let env = db::db_env(trie_path.as_ref().join(TRIE_STORE_FILE_NAME))?;
+    let txn = env.begin_ro_txn()?;
+    let trie_db = unsafe { txn.open_db(None)? };
+    let mut writer = StateFileWriter::create(&output, state_root, overwrite)?;
+    if let Err(error) = write_entries(&txn, trie_db, state_root, &mut writer) {
+        // An incomplete state file could pass for a smaller state.
+        drop(writer);
+        if let Err(io_err) = fs::remove_file(&output) {
+            warn!(
+                "Couldn't remove incomplete state file {}: {io_err}",
+                output.as_ref().display()
+            );
+        }
+        return Err(error);
+    }
+    txn.commit()?;
+    let entry_count = writer.finish()?;
+    info!("Exported {entry_count} entries under state root {state_root}.");
+    Ok(entry_count)
+}
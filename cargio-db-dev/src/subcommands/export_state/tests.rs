@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use tempfile::tempdir;
+
+use cargio_execution_engine::shared::newtypes::CorrelationId;
+use cargio_hashing::Digest;
+use cargio_types::{account::AccountHash, CLValue, Key, StoredValue};
+
+use crate::{common::state_file::StateFileReader, subcommands::trie_compact};
+
+use super::{export, Error};
+
+const MAX_DB_SIZE: usize = 100 * 1024 * 1024;
+
+fn entries() -> HashMap<Key, StoredValue> {
+    (0..5u8)
+        .map(|idx| {
+            (
+                Key::Account(AccountHash::new([idx; 32])),
+                StoredValue::CLValue(CLValue::from_t(idx as u64).unwrap()),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn export_should_write_all_entries() {
+    let tmp_dir = tempdir().unwrap();
+    let trie_dir = tmp_dir.path().join("trie");
+    let state_root = {
+        let (engine_state, _env) =
+            trie_compact::create_execution_engine(&trie_dir, MAX_DB_SIZE, true).unwrap();
+        let global_state = engine_state.get_state();
+        let state_root = global_state
+            .put_stored_values(CorrelationId::new(), global_state.empty_root(), entries())
+            .unwrap();
+        engine_state.flush_environment().unwrap();
+        state_root
+    };
+
+    let state_file = tmp_dir.path().join("state.bin");
+    assert_eq!(
+        export::export_state(&trie_dir, state_root, &state_file, false).unwrap(),
+        entries().len() as u64
+    );
+
+    let mut reader = StateFileReader::open(&state_file).unwrap();
+    assert_eq!(reader.header().state_root, state_root);
+    assert_eq!(reader.header().entry_count, entries().len() as u64);
+    let mut exported = HashMap::new();
+    while let Some((key, value)) = reader.next_entry().unwrap() {
+        exported.insert(key, value);
+    }
+    assert_eq!(exported, entries());
+}
+
+#[test]
+fn missing_state_root_should_not_leave_a_state_file() {
+    let tmp_dir = tempdir().unwrap();
+    let trie_dir = tmp_dir.path().join("trie");
+    {
+        let (engine_state, _env) =
+            trie_compact::create_execution_engine(&trie_dir, MAX_DB_SIZE, true).unwrap();
+        engine_state.flush_environment().unwrap();
+    }
+    let missing_root = Digest::hash([1u8; 8]);
+
+    let state_file = tmp_dir.path().join("state.bin");
+    assert!(matches!(
+        export::export_state(&trie_dir, missing_root, &state_file, false),
+        Err(Error::MissingTrie(trie_key)) if trie_key == missing_root
+    ));
+    assert!(!state_file.exists());
+}
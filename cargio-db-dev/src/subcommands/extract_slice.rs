@@ -14,30 +14,45 @@ use clap::{Arg, ArgMatches, Command};
 use lmdb::Error as LmdbError;
 use thiserror::Error as ThisError;
 
+use crate::{common::block_index::BlockIndexError, subcommands::execution_results_summary};
+
 use self::extract::SliceIdentifier;
 
 pub const COMMAND_NAME: &str = "extract-slice";
+const ALL_STATE_ROOTS: &str = "all-state-roots";
 const BLOCK_HASH: &str = "block-hash";
+const FROM_HEIGHT: &str = "from-height";
 const STATE_ROOT_HASH: &str = "state-root-hash";
 const OUTPUT: &str = "output";
 const SOURCE_DB_PATH: &str = "source-db-path";
+const TO_HEIGHT: &str = "to-height";
 
 #[derive(Debug, ThisError)]
 pub enum Error {
     #[error("Error (de)serializing items with bincode: {0}")]
     Bincode(#[from] BincodeError),
+    #[error("Error reading the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
     #[error("Error creating the destination execution engine: {0}")]
     CreateExecutionEngine(anyhow::Error),
     #[error("Error operating the database: {0}")]
     Database(#[from] LmdbError),
+    #[error("Found more than one block at height {0}")]
+    DuplicateBlock(u64),
+    #[error("No blocks found with heights from {0} to {1}")]
+    EmptyRange(u64, u64),
     #[error("Error loading the source execution engine: {0}")]
     LoadExecutionEngine(anyhow::Error),
+    #[error("No blocks found at {count} heights of the range, the lowest being {first}")]
+    MissingHeights { first: u64, count: u64 },
     #[error("Error writing output: {0}")]
     Output(#[from] IoError),
     #[error("Error parsing element for block hash {0} in {1} DB: {2}")]
     Parsing(BlockHash, String, BincodeError),
     #[error("Error transferring state root: {0}")]
     StateRootTransfer(anyhow::Error),
+    #[error("Error reading block headers: {0}")]
+    Traversal(#[from] execution_results_summary::Error),
 }
 
 enum DisplayOrder {
@@ -45,6 +60,9 @@ enum DisplayOrder {
     Output,
     BlockHash,
     StateRootHash,
+    FromHeight,
+    ToHeight,
+    AllStateRoots,
 }
 
 pub fn command(display_order: usize) -> Command<'static> {
@@ -56,7 +74,9 @@ pub fn command(display_order: usize) -> Command<'static> {
                 them to a new directory in two LMDB files. If a state root \
                 hash is provided instead of a block hash, only the global \
                 state under that root hash will be stored in the new \
-                directory",
+                directory. If a height range is provided, all data of the \
+                blocks in the range and the global state of the last of them \
+                will be stored in the new directory.",
         )
         .arg(
             Arg::new(SOURCE_DB_PATH)
@@ -91,7 +111,7 @@ pub fn command(display_order: usize) -> Command<'static> {
                 .long(BLOCK_HASH)
                 .takes_value(true)
                 .value_name("BLOCK_HASH")
-                .conflicts_with(STATE_ROOT_HASH)
+                .conflicts_with_all(&[STATE_ROOT_HASH, FROM_HEIGHT])
                 .help("Hash of the block which defines the slice."),
         )
         .arg(
@@ -101,8 +121,52 @@ pub fn command(display_order: usize) -> Command<'static> {
                 .long(STATE_ROOT_HASH)
                 .takes_value(true)
                 .value_name("STATE_ROOT_HASH")
+                .conflicts_with(FROM_HEIGHT)
                 .help("State root hash to be copied over to the new database."),
         )
+        .arg(
+            Arg::new(FROM_HEIGHT)
+                .display_order(DisplayOrder::FromHeight as usize)
+                .short('f')
+                .long(FROM_HEIGHT)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .requires(TO_HEIGHT)
+                .help("Height of the lowest block of the slice."),
+        )
+        .arg(
+            Arg::new(TO_HEIGHT)
+                .display_order(DisplayOrder::ToHeight as usize)
+                .short('t')
+                .long(TO_HEIGHT)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .requires(FROM_HEIGHT)
+                .help(
+                    "Height of the highest block of the slice. Every height in the range \
+                    must have a block.",
+                ),
+        )
+        .arg(
+            Arg::new(ALL_STATE_ROOTS)
+                .display_order(DisplayOrder::AllStateRoots as usize)
+                .short('a')
+                .long(ALL_STATE_ROOTS)
+                .takes_value(false)
+                .requires(FROM_HEIGHT)
+                .help(
+                    "Copy the global state of every block in the height range instead of \
+                    only that of the last block.",
+                ),
+        )
+}
+
+fn parse_height(matches: &ArgMatches, arg: &str) -> Option<u64> {
+    matches.value_of(arg).map(|height| {
+        height
+            .parse()
+            .unwrap_or_else(|_| panic!("Value of \"--{arg}\" must be an integer."))
+    })
 }
 
 pub fn run(matches: &ArgMatches) -> Result<(), Error> {
@@ -112,6 +176,13 @@ pub fn run(matches: &ArgMatches) -> Result<(), Error> {
             .expect("should have db-path arg"),
     );
     let output = Path::new(matches.value_of(OUTPUT).expect("should have output arg"));
+    let maybe_height_range = parse_height(matches, FROM_HEIGHT)
+        .zip(parse_height(matches, TO_HEIGHT))
+        .map(|(from_height, to_height)| SliceIdentifier::HeightRange {
+            from_height,
+            to_height,
+            all_state_roots: matches.is_present(ALL_STATE_ROOTS),
+        });
     let slice_identifier = matches
         .value_of(BLOCK_HASH)
         .map(|block_hash_str| {
@@ -120,6 +191,7 @@ pub fn run(matches: &ArgMatches) -> Result<(), Error> {
                 .into();
             SliceIdentifier::BlockHash(block_hash)
         })
+        .or(maybe_height_range)
         .unwrap_or_else(|| {
             matches
                 .value_of(STATE_ROOT_HASH)
@@ -128,7 +200,7 @@ pub fn run(matches: &ArgMatches) -> Result<(), Error> {
                         .expect("should parse state root hash to hex format");
                     SliceIdentifier::StateRootHash(state_root_hash)
                 })
-                .expect("should have either BLOCK_HASH or STATE_ROOT_HASH arg")
+                .expect("should have either BLOCK_HASH, STATE_ROOT_HASH or FROM_HEIGHT arg")
         });
 
     extract::extract_slice(path, output, slice_identifier)
@@ -8,6 +8,14 @@ use super::{global_state, storage, Error};
 pub enum SliceIdentifier {
     BlockHash(BlockHash),
     StateRootHash(Digest),
+    /// The blocks with heights from `from_height` to `to_height`, with the
+    /// global state of the last block, or of every block if
+    /// `all_state_roots` is set.
+    HeightRange {
+        from_height: u64,
+        to_height: u64,
+        all_state_roots: bool,
+    },
 }
 
 pub fn extract_slice<P1: AsRef<Path>, P2: AsRef<Path>>(
@@ -16,12 +24,27 @@ pub fn extract_slice<P1: AsRef<Path>, P2: AsRef<Path>>(
     slice_identifier: SliceIdentifier,
 ) -> Result<(), Error> {
     storage::create_output_db(&output)?;
-    let state_root_hash = match slice_identifier {
+    let state_root_hashes = match slice_identifier {
         SliceIdentifier::BlockHash(block_hash) => {
-            storage::transfer_block_info(&db_path, &output, block_hash)?
+            vec![storage::transfer_block_info(&db_path, &output, block_hash)?]
+        }
+        SliceIdentifier::StateRootHash(state_root_hash) => vec![state_root_hash],
+        SliceIdentifier::HeightRange {
+            from_height,
+            to_height,
+            all_state_roots,
+        } => {
+            let mut state_root_hashes =
+                storage::transfer_block_range(&db_path, &output, from_height..=to_height)?;
+            if all_state_roots {
+                // Consecutive blocks without deploys share their state root.
+                state_root_hashes.dedup();
+            } else {
+                state_root_hashes = state_root_hashes.split_off(state_root_hashes.len() - 1);
+            }
+            state_root_hashes
         }
-        SliceIdentifier::StateRootHash(state_root_hash) => state_root_hash,
     };
-    global_state::transfer_global_state(&db_path, &output, state_root_hash)?;
+    global_state::transfer_global_state(&db_path, &output, &state_root_hashes)?;
     Ok(())
 }
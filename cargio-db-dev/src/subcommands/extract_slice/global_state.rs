@@ -9,10 +9,12 @@ use crate::subcommands::trie_compact::{
 
 use super::Error;
 
+/// Copies the global state under every root in `state_root_hashes`. Subtries
+/// shared between the roots are copied once.
 pub(crate) fn transfer_global_state<P1: AsRef<Path>, P2: AsRef<Path>>(
     source: P1,
     destination: P2,
-    state_root_hash: Digest,
+    state_root_hashes: &[Digest],
 ) -> Result<(), Error> {
     let max_db_size = DEFAULT_MAX_DB_SIZE
         .parse()
@@ -22,10 +24,12 @@ pub(crate) fn transfer_global_state<P1: AsRef<Path>, P2: AsRef<Path>>(
         .map_err(Error::LoadExecutionEngine)?;
     let (destination_state, _env) = create_execution_engine(destination, max_db_size, true)
         .map_err(Error::CreateExecutionEngine)?;
-    info!("Starting transfer process for state root hash {state_root_hash}");
-    copy_state_root(state_root_hash, &source_state, &destination_state)
-        .map_err(Error::StateRootTransfer)?;
-    destination_state.flush_environment()?;
+    for state_root_hash in state_root_hashes {
+        info!("Starting transfer process for state root hash {state_root_hash}");
+        copy_state_root(*state_root_hash, &source_state, &destination_state, None)
+            .map_err(Error::StateRootTransfer)?;
+        destination_state.flush_environment()?;
+    }
 
     Ok(())
 }
@@ -1,17 +1,27 @@
-use std::{fs, io::ErrorKind, path::Path, result::Result};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    io::ErrorKind,
+    ops::RangeInclusive,
+    path::Path,
+    result::Result,
+};
 
 use cargio_hashing::Digest;
-use lmdb::{DatabaseFlags, Error as LmdbError, Transaction};
+use lmdb::{DatabaseFlags, Error as LmdbError, RoTransaction, RwTransaction, Transaction};
 
 use master_node::types::{BlockHash, BlockHeader, DeployMetadata};
 use log::info;
 
 use crate::{
-    common::db::{
-        self, BlockBodyDatabase, BlockHeaderDatabase, Database, DeployDatabase,
-        DeployMetadataDatabase, TransferDatabase, STORAGE_FILE_NAME,
+    common::{
+        block_index::BlockIndex,
+        db::{
+            self, BlockBodyDatabase, BlockHeaderDatabase, BlockMetadataDatabase, Database,
+            DeployDatabase, DeployMetadataDatabase, TransferDatabase, STORAGE_FILE_NAME,
+        },
     },
-    subcommands::execution_results_summary::block_body::BlockBody,
+    subcommands::execution_results_summary::{block_body::BlockBody, read_db},
 };
 
 use super::{db_helpers, Error};
@@ -27,6 +37,10 @@ pub(crate) fn create_output_db<P: AsRef<Path>>(output_path: P) -> Result<(), Err
 
     storage_env.create_db(Some(BlockHeaderDatabase::db_name()), DatabaseFlags::empty())?;
     storage_env.create_db(Some(BlockBodyDatabase::db_name()), DatabaseFlags::empty())?;
+    storage_env.create_db(
+        Some(BlockMetadataDatabase::db_name()),
+        DatabaseFlags::empty(),
+    )?;
     storage_env.create_db(Some(DeployDatabase::db_name()), DatabaseFlags::empty())?;
     storage_env.create_db(Some(TransferDatabase::db_name()), DatabaseFlags::empty())?;
     storage_env.create_db(
@@ -37,28 +51,18 @@ pub(crate) fn create_output_db<P: AsRef<Path>>(output_path: P) -> Result<(), Err
     Ok(())
 }
 
-pub(crate) fn transfer_block_info<P1: AsRef<Path>, P2: AsRef<Path>>(
-    source: P1,
-    destination: P2,
+/// Copies the header, body, signatures, transfers and deploys of the block
+/// with the given hash, along with the execution results of those deploys
+/// in the blocks of `slice_block_hashes`. Returns the block header.
+fn transfer_block(
+    source_txn: &mut RoTransaction,
+    destination_txn: &mut RwTransaction,
     block_hash: BlockHash,
-) -> Result<Digest, Error> {
-    let source_path = source.as_ref().join(STORAGE_FILE_NAME);
-    let source_env = db::db_env(&source_path)?;
-    let destination_path = destination.as_ref().join(STORAGE_FILE_NAME);
-    let destination_env = db::db_env(&destination_path)?;
-
-    let mut source_txn = source_env.begin_ro_txn()?;
-    let mut destination_txn = destination_env.begin_rw_txn()?;
-
-    info!(
-        "Initiating block information transfer from {} to {} for block {block_hash}",
-        source_path.to_string_lossy(),
-        destination_path.to_string_lossy()
-    );
-
+    slice_block_hashes: &HashSet<BlockHash>,
+) -> Result<BlockHeader, Error> {
     let block_header_bytes = db_helpers::transfer_to_new_db(
-        &mut source_txn,
-        &mut destination_txn,
+        source_txn,
+        destination_txn,
         BlockHeaderDatabase::db_name(),
         &block_hash,
     )?;
@@ -66,8 +70,8 @@ pub(crate) fn transfer_block_info<P1: AsRef<Path>, P2: AsRef<Path>>(
     let block_header: BlockHeader = bincode::deserialize(&block_header_bytes)?;
 
     let block_body_bytes = db_helpers::transfer_to_new_db(
-        &mut source_txn,
-        &mut destination_txn,
+        source_txn,
+        destination_txn,
         BlockBodyDatabase::db_name(),
         block_header.body_hash(),
     )?;
@@ -75,8 +79,19 @@ pub(crate) fn transfer_block_info<P1: AsRef<Path>, P2: AsRef<Path>>(
     let block_body: BlockBody = bincode::deserialize(&block_body_bytes)?;
 
     match db_helpers::transfer_to_new_db(
-        &mut source_txn,
-        &mut destination_txn,
+        source_txn,
+        destination_txn,
+        BlockMetadataDatabase::db_name(),
+        &block_hash,
+    ) {
+        Ok(_) => info!("Found signatures in the source DB and successfully transferred them"),
+        Err(LmdbError::NotFound) => info!("No signatures found in the source DB"),
+        Err(lmdb_error) => return Err(Error::Database(lmdb_error)),
+    }
+
+    match db_helpers::transfer_to_new_db(
+        source_txn,
+        destination_txn,
         TransferDatabase::db_name(),
         &block_hash,
     ) {
@@ -87,10 +102,14 @@ pub(crate) fn transfer_block_info<P1: AsRef<Path>, P2: AsRef<Path>>(
 
     let deploy_metadata_db =
         unsafe { source_txn.open_db(Some(DeployMetadataDatabase::db_name()))? };
-    for deploy_hash in block_body.deploy_hashes() {
+    for deploy_hash in block_body
+        .deploy_hashes()
+        .iter()
+        .chain(block_body.transfer_hashes.iter())
+    {
         db_helpers::transfer_to_new_db(
-            &mut source_txn,
-            &mut destination_txn,
+            source_txn,
+            destination_txn,
             DeployDatabase::db_name(),
             deploy_hash,
         )?;
@@ -105,14 +124,14 @@ pub(crate) fn transfer_block_info<P1: AsRef<Path>, P2: AsRef<Path>>(
                     bincode_err,
                 )
             })?;
-        if let Some(execution_result) = metadata.execution_results.remove(&block_hash) {
-            let mut new_metadata = DeployMetadata::default();
-            new_metadata
-                .execution_results
-                .insert(block_hash, execution_result.clone());
-            let serialized_new_metadata = bincode::serialize(&new_metadata)?;
+        // Results of blocks outside the slice would refer to missing blocks.
+        metadata
+            .execution_results
+            .retain(|result_block_hash, _| slice_block_hashes.contains(result_block_hash));
+        if !metadata.execution_results.is_empty() {
+            let serialized_new_metadata = bincode::serialize(&metadata)?;
             db_helpers::write_to_db(
-                &mut destination_txn,
+                destination_txn,
                 DeployMetadataDatabase::db_name(),
                 deploy_hash,
                 &serialized_new_metadata,
@@ -120,8 +139,111 @@ pub(crate) fn transfer_block_info<P1: AsRef<Path>, P2: AsRef<Path>>(
             info!("Successfully transferred execution results for {deploy_hash}");
         }
     }
+    Ok(block_header)
+}
+
+pub(crate) fn transfer_block_info<P1: AsRef<Path>, P2: AsRef<Path>>(
+    source: P1,
+    destination: P2,
+    block_hash: BlockHash,
+) -> Result<Digest, Error> {
+    let source_path = source.as_ref().join(STORAGE_FILE_NAME);
+    let source_env = db::db_env(&source_path)?;
+    let destination_path = destination.as_ref().join(STORAGE_FILE_NAME);
+    let destination_env = db::db_env(&destination_path)?;
+
+    let mut source_txn = source_env.begin_ro_txn()?;
+    let mut destination_txn = destination_env.begin_rw_txn()?;
+
+    info!(
+        "Initiating block information transfer from {} to {} for block {block_hash}",
+        source_path.to_string_lossy(),
+        destination_path.to_string_lossy()
+    );
+
+    let block_header = transfer_block(
+        &mut source_txn,
+        &mut destination_txn,
+        block_hash,
+        &HashSet::from([block_hash]),
+    )?;
     source_txn.commit()?;
     destination_txn.commit()?;
     info!("Storage transfer complete");
     Ok(*block_header.state_root_hash())
 }
+
+/// Copies the blocks with a height in `heights` as `transfer_block_info`
+/// does, with the execution results of their deploys in any of them. Fails
+/// if any height in `heights` has no block. Returns the state root hashes of
+/// the copied blocks in height order.
+pub(crate) fn transfer_block_range<P1: AsRef<Path>, P2: AsRef<Path>>(
+    source: P1,
+    destination: P2,
+    heights: RangeInclusive<u64>,
+) -> Result<Vec<Digest>, Error> {
+    let source_path = source.as_ref().join(STORAGE_FILE_NAME);
+    let source_env = db::db_env(&source_path)?;
+    let destination_path = destination.as_ref().join(STORAGE_FILE_NAME);
+    let destination_env = db::db_env(&destination_path)?;
+    let maybe_block_index = BlockIndex::open(&source, &source_env)?;
+
+    let mut source_txn = source_env.begin_ro_txn()?;
+    let block_header_db = unsafe { source_txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+    let mut block_hashes = BTreeMap::new();
+    read_db::visit_blocks(
+        &source_txn,
+        block_header_db,
+        maybe_block_index.as_ref(),
+        heights.clone(),
+        |block_hash, header| {
+            if block_hashes.insert(header.height(), block_hash).is_some() {
+                return Err(Error::DuplicateBlock(header.height()));
+            }
+            Ok(())
+        },
+    )?;
+    if block_hashes.is_empty() {
+        return Err(Error::EmptyRange(*heights.start(), *heights.end()));
+    }
+    // A slice with gaps can't be used as a chain, so every height has to
+    // have a block.
+    let missing_heights = (heights.end() - heights.start()) - (block_hashes.len() as u64 - 1);
+    if missing_heights > 0 {
+        let first_missing_height = (*heights.start()..)
+            .zip(block_hashes.keys())
+            .find(|(expected_height, height)| expected_height != *height)
+            .map(|(expected_height, _)| expected_height)
+            .unwrap_or_else(|| *heights.start() + block_hashes.len() as u64);
+        return Err(Error::MissingHeights {
+            first: first_missing_height,
+            count: missing_heights,
+        });
+    }
+
+    info!(
+        "Initiating transfer of {} blocks from {} to {}",
+        block_hashes.len(),
+        source_path.to_string_lossy(),
+        destination_path.to_string_lossy()
+    );
+    let slice_block_hashes: HashSet<BlockHash> = block_hashes.values().copied().collect();
+    let mut state_root_hashes = vec![];
+    for (height, block_hash) in block_hashes {
+        info!("Transferring block {block_hash} at height {height}");
+        // A transaction per block keeps the size of a write transaction
+        // bounded for large ranges.
+        let mut destination_txn = destination_env.begin_rw_txn()?;
+        let block_header = transfer_block(
+            &mut source_txn,
+            &mut destination_txn,
+            block_hash,
+            &slice_block_hashes,
+        )?;
+        destination_txn.commit()?;
+        state_root_hashes.push(*block_header.state_root_hash());
+    }
+    source_txn.commit()?;
+    info!("Storage transfer complete");
+    Ok(state_root_hashes)
+}
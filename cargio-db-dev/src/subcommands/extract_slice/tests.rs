@@ -13,12 +13,12 @@ use lmdb::{DatabaseFlags, Error as LmdbError, Transaction, WriteFlags};
 
 use crate::{
     common::db::{
-        BlockBodyDatabase, BlockHeaderDatabase, Database, DeployDatabase, DeployMetadataDatabase,
-        TransferDatabase, STORAGE_FILE_NAME,
+        self, BlockBodyDatabase, BlockHeaderDatabase, BlockMetadataDatabase, Database,
+        DeployDatabase, DeployMetadataDatabase, TransferDatabase, STORAGE_FILE_NAME,
     },
     subcommands::{
         execution_results_summary::block_body::BlockBody,
-        extract_slice::{db_helpers, global_state, storage},
+        extract_slice::{db_helpers, global_state, storage, Error},
         trie_compact::{
             create_execution_engine, load_execution_engine, tests::create_data, DEFAULT_MAX_DB_SIZE,
         },
@@ -430,6 +430,145 @@ fn transfer_blocks() {
     }
 }
 
+#[test]
+fn transfer_block_range() {
+    const BLOCK_COUNT: u8 = 4;
+
+    let source_fixture = LmdbTestFixture::new(
+        vec![
+            BlockHeaderDatabase::db_name(),
+            BlockBodyDatabase::db_name(),
+            BlockMetadataDatabase::db_name(),
+            DeployMetadataDatabase::db_name(),
+            DeployDatabase::db_name(),
+            TransferDatabase::db_name(),
+        ],
+        Some(STORAGE_FILE_NAME),
+    );
+
+    // Block `i` is at height `i` and has deploy `i`, which has execution
+    // results in all blocks.
+    let block_headers: Vec<(BlockHash, MockBlockHeader)> = (0..BLOCK_COUNT)
+        .map(|idx| {
+            let (block_hash, mut header) = mock_block_header(idx);
+            header.height = idx as u64;
+            header.state_root_hash = [idx.min(2); Digest::LENGTH].into();
+            (block_hash, header)
+        })
+        .collect();
+    let all_block_hashes: Vec<BlockHash> = block_headers
+        .iter()
+        .map(|(block_hash, _)| *block_hash)
+        .collect();
+    {
+        let mut txn = source_fixture.env.begin_rw_txn().unwrap();
+        for (idx, (block_hash, header)) in block_headers.iter().enumerate() {
+            let deploy_hash = mock_deploy_hash(idx as u8);
+            txn.put(
+                *source_fixture
+                    .db(Some(BlockHeaderDatabase::db_name()))
+                    .unwrap(),
+                block_hash,
+                &bincode::serialize(header).unwrap(),
+                WriteFlags::empty(),
+            )
+            .unwrap();
+            txn.put(
+                *source_fixture
+                    .db(Some(BlockBodyDatabase::db_name()))
+                    .unwrap(),
+                &header.body_hash,
+                &bincode::serialize(&BlockBody::new(vec![deploy_hash])).unwrap(),
+                WriteFlags::empty(),
+            )
+            .unwrap();
+            txn.put(
+                *source_fixture
+                    .db(Some(BlockMetadataDatabase::db_name()))
+                    .unwrap(),
+                block_hash,
+                &bincode::serialize(block_hash).unwrap(),
+                WriteFlags::empty(),
+            )
+            .unwrap();
+            txn.put(
+                *source_fixture
+                    .db(Some(DeployMetadataDatabase::db_name()))
+                    .unwrap(),
+                &deploy_hash,
+                &bincode::serialize(&mock_deploy_metadata(&all_block_hashes)).unwrap(),
+                WriteFlags::empty(),
+            )
+            .unwrap();
+            txn.put(
+                *source_fixture.db(Some(DeployDatabase::db_name())).unwrap(),
+                &deploy_hash,
+                &bincode::serialize(&deploy_hash).unwrap(),
+                WriteFlags::empty(),
+            )
+            .unwrap();
+        }
+        txn.commit().unwrap();
+    }
+
+    let out_dir = tempfile::tempdir().unwrap();
+    let destination = out_dir.path().join("slice");
+    storage::create_output_db(&destination).unwrap();
+    let state_root_hashes =
+        storage::transfer_block_range(source_fixture.tmp_dir.path(), &destination, 1..=2).unwrap();
+    assert_eq!(
+        state_root_hashes,
+        vec![
+            block_headers[1].1.state_root_hash,
+            block_headers[2].1.state_root_hash
+        ]
+    );
+
+    let destination_env = db::db_env(destination.join(STORAGE_FILE_NAME)).unwrap();
+    let txn = destination_env.begin_ro_txn().unwrap();
+    let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name())).unwrap() };
+    let signatures_db = unsafe { txn.open_db(Some(BlockMetadataDatabase::db_name())).unwrap() };
+    let metadata_db = unsafe {
+        txn.open_db(Some(DeployMetadataDatabase::db_name()))
+            .unwrap()
+    };
+    for (idx, block_hash) in all_block_hashes.iter().enumerate() {
+        let in_slice = (1..=2).contains(&idx);
+        assert_eq!(txn.get(header_db, block_hash).is_ok(), in_slice);
+        assert_eq!(txn.get(signatures_db, block_hash).is_ok(), in_slice);
+        if in_slice {
+            // Only the execution results in blocks of the slice are kept.
+            let metadata: DeployMetadata = txn
+                .get(metadata_db, &mock_deploy_hash(idx as u8))
+                .map(bincode::deserialize)
+                .unwrap()
+                .unwrap();
+            let mut result_block_hashes: Vec<BlockHash> =
+                metadata.execution_results.into_keys().collect();
+            result_block_hashes.sort();
+            assert_eq!(result_block_hashes, all_block_hashes[1..=2].to_vec());
+        }
+    }
+    txn.commit().unwrap();
+
+    assert!(matches!(
+        storage::transfer_block_range(
+            source_fixture.tmp_dir.path(),
+            out_dir.path().join("slice"),
+            10..=20
+        ),
+        Err(Error::EmptyRange(10, 20))
+    ));
+    assert!(matches!(
+        storage::transfer_block_range(
+            source_fixture.tmp_dir.path(),
+            out_dir.path().join("slice"),
+            2..=5
+        ),
+        Err(Error::MissingHeights { first: 4, count: 2 })
+    ));
+}
+
 #[test]
 fn transfer_global_state_information() {
     let source_tmp_dir = tempfile::tempdir().unwrap();
@@ -457,7 +596,7 @@ fn transfer_global_state_information() {
     global_state::transfer_global_state(
         source_tmp_dir.path(),
         destination_tmp_dir.path(),
-        data[4].0,
+        &[data[4].0],
     )
     .unwrap();
 
@@ -0,0 +1,136 @@
+mod gc;
+#[cfg(test)]
+mod tests;
+
+use std::{io::Error as IoError, path::Path};
+
+use bincode::Error as BincodeError;
+use clap::{Arg, ArgMatches, Command};
+use lmdb::Error as LmdbError;
+use serde_json::Error as SerializationError;
+use thiserror::Error as ThisError;
+
+use crate::{
+    common::{
+        db::{DeserializationError, STORAGE_FILE_NAME},
+        journal::{Journal, JournalError},
+    },
+    subcommands::get::Error as GetError,
+};
+
+pub const COMMAND_NAME: &str = "gc-deploys";
+const DB_PATH: &str = "db-path";
+const DRY_RUN: &str = "dry-run";
+const EXPORT: &str = "export";
+const JOURNAL: &str = "journal";
+const OVERWRITE: &str = "overwrite";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error parsing block body with key {0}: {1}")]
+    BodyParsing(String, BincodeError),
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Error reading orphaned deploy {0} for export: {1}")]
+    Export(String, GetError),
+    #[error("Error parsing {0} entry with key {1}: {2}")]
+    HashesParsing(&'static str, String, DeserializationError),
+    #[error("Invalid deploy hash key {0}")]
+    InvalidDeployHash(String),
+    #[error("Error writing the journal: {0}")]
+    Journal(#[from] JournalError),
+    #[error("Error writing output: {0}")]
+    Output(#[from] IoError),
+    #[error("Error serializing output: {0}")]
+    Serialize(#[from] SerializationError),
+}
+
+enum DisplayOrder {
+    DbPath,
+    DryRun,
+    Export,
+    Overwrite,
+    Journal,
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Deletes the deploys which no block body refers to from the `deploys`, \
+            `deploy_metadata` and `finalized_approvals` databases. Deploys are referenced by \
+            the deploy and transfer hashes of the bodies in `block_body` and of the merkle \
+            bodies in `deploy_hashes` and `transfer_hashes`.",
+        )
+        .arg(
+            Arg::new(DB_PATH)
+                .display_order(DisplayOrder::DbPath as usize)
+                .required(true)
+                .short('d')
+                .long(DB_PATH)
+                .takes_value(true)
+                .value_name("DB_PATH")
+                .help("Path of the directory with the `storage.lmdb` file."),
+        )
+        .arg(
+            Arg::new(DRY_RUN)
+                .display_order(DisplayOrder::DryRun as usize)
+                .short('n')
+                .long(DRY_RUN)
+                .takes_value(false)
+                .help(
+                    "Logs the orphaned deploys and the entries which would be deleted without \
+                    changing the database.",
+                ),
+        )
+        .arg(
+            Arg::new(EXPORT)
+                .display_order(DisplayOrder::Export as usize)
+                .short('e')
+                .long(EXPORT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help(
+                    "Path to where the program will output the orphaned deploys, with their \
+                    metadata and finalized approvals, as one JSON document per line before \
+                    deleting them. Combine with `--dry-run` to only export them.",
+                ),
+        )
+        .arg(
+            Arg::new(OVERWRITE)
+                .display_order(DisplayOrder::Overwrite as usize)
+                .required(false)
+                .short('w')
+                .long(OVERWRITE)
+                .takes_value(false)
+                .requires(EXPORT)
+                .help("Overwrite an already existing export file."),
+        )
+        .arg(
+            Arg::new(JOURNAL)
+                .display_order(DisplayOrder::Journal as usize)
+                .short('j')
+                .long(JOURNAL)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .conflicts_with(DRY_RUN)
+                .help(
+                    "Path of a new journal file recording the prior state of every changed \
+                    entry, which the `undo` command restores.",
+                ),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let path = Path::new(matches.value_of(DB_PATH).expect("should have db-path arg"));
+    let dry_run = matches.is_present(DRY_RUN);
+    let export = matches.value_of(EXPORT).map(Path::new);
+    let overwrite = matches.is_present(OVERWRITE);
+    let mut maybe_journal = matches
+        .value_of(JOURNAL)
+        .map(|journal_path| Journal::create(journal_path, path.join(STORAGE_FILE_NAME)))
+        .transpose()?;
+    let summary = gc::gc_deploys(path, dry_run, export, overwrite, maybe_journal.as_mut())?;
+    gc::log_summary(&summary);
+    Ok(())
+}
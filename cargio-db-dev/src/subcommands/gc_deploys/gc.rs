@@ -0,0 +1,269 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::OpenOptions,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use cargio_hashing::Digest;
+use lmdb::{
+    Cursor, Database as LmdbDatabase, Environment, Error as LmdbError, RoTransaction, Transaction,
+};
+use log::info;
+use master_node::types::DeployHash;
+
+use crate::{
+    common::{
+        db::{
+            self, BlockBodyDatabase, Database, DeployDatabase, DeployHashesDatabase,
+            DeployMetadataDatabase, FinalizedApprovalsDatabase, TransferHashesDatabase,
+            STORAGE_FILE_NAME,
+        },
+        journal::{Journal, JournalError, JournaledTransaction},
+        lmdb_utils,
+        progress::ProgressTracker,
+    },
+    subcommands::{execution_results_summary::block_body::BlockBody, get::lookup},
+};
+
+use super::Error;
+
+/// Outcome of a deploy garbage collection.
+#[derive(Debug, Default)]
+pub(crate) struct GcSummary {
+    pub(crate) dry_run: bool,
+    /// Number of distinct deploy and transfer hashes in block bodies, legacy
+    /// and merkle.
+    pub(crate) referenced: usize,
+    pub(crate) orphans: BTreeSet<DeployHash>,
+    pub(crate) exported: usize,
+    /// Number of deleted entries per database.
+    pub(crate) deleted: BTreeMap<&'static str, usize>,
+}
+
+fn open_optional_db<T: Transaction>(
+    txn: &T,
+    db_name: &str,
+) -> Result<Option<LmdbDatabase>, LmdbError> {
+    match unsafe { txn.open_db(Some(db_name)) } {
+        Ok(db) => Ok(Some(db)),
+        Err(LmdbError::NotFound) => Ok(None),
+        Err(lmdb_err) => Err(lmdb_err),
+    }
+}
+
+/// Returns the hashes of all deploys and transfers included in a block body.
+fn referenced_deploys(
+    txn: &RoTransaction,
+    body_db: LmdbDatabase,
+) -> Result<BTreeSet<DeployHash>, Error> {
+    let mut maybe_progress_tracker = ProgressTracker::new(
+        lmdb_utils::entry_count(txn, body_db)?,
+        Box::new(|completion| info!("Block body database parsing {}% complete...", completion)),
+    )
+    .ok();
+    let mut referenced = BTreeSet::new();
+    if let Ok(mut cursor) = txn.open_ro_cursor(body_db) {
+        for (raw_key, raw_body) in cursor.iter() {
+            let body: BlockBody = bincode::deserialize(raw_body)
+                .map_err(|bincode_err| Error::BodyParsing(hex::encode(raw_key), bincode_err))?;
+            referenced.extend(
+                body.deploy_hashes
+                    .iter()
+                    .chain(body.transfer_hashes.iter())
+                    .copied(),
+            );
+            if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
+                progress_tracker.advance_by(1);
+            }
+        }
+    }
+    Ok(referenced)
+}
+
+/// Adds the deploy or transfer hashes of the merkle block bodies, which are
+/// stored in `D` instead of `block_body`, to `referenced`.
+fn add_merkle_referenced_deploys<D>(
+    txn: &RoTransaction,
+    referenced: &mut BTreeSet<DeployHash>,
+) -> Result<(), Error>
+where
+    D: Database<Element = Vec<cargio_types::DeployHash>>,
+{
+    let db = match open_optional_db(txn, D::db_name())? {
+        Some(db) => db,
+        None => return Ok(()),
+    };
+    if let Ok(mut cursor) = txn.open_ro_cursor(db) {
+        for (raw_key, raw_hashes) in cursor.iter() {
+            let deploy_hashes = D::parse_element(raw_hashes).map_err(|parsing_err| {
+                Error::HashesParsing(D::db_name(), hex::encode(raw_key), parsing_err)
+            })?;
+            referenced.extend(
+                deploy_hashes
+                    .iter()
+                    .map(|deploy_hash| DeployHash::new(Digest::from(deploy_hash.value()))),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Adds the keys of `db` which aren't referenced deploy hashes to `orphans`.
+fn collect_orphans(
+    txn: &RoTransaction,
+    db: LmdbDatabase,
+    referenced: &BTreeSet<DeployHash>,
+    orphans: &mut BTreeSet<DeployHash>,
+) -> Result<(), Error> {
+    if let Ok(mut cursor) = txn.open_ro_cursor(db) {
+        for (raw_key, _raw_val) in cursor.iter() {
+            let deploy_hash = Digest::try_from(raw_key)
+                .map(DeployHash::new)
+                .map_err(|_| Error::InvalidDeployHash(hex::encode(raw_key)))?;
+            if !referenced.contains(&deploy_hash) {
+                orphans.insert(deploy_hash);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the orphaned deploys to `out_path` as JSON lines. Returns the
+/// number of exported deploys.
+fn export_orphans(
+    txn: &RoTransaction,
+    orphans: &BTreeSet<DeployHash>,
+    out_path: &Path,
+    overwrite: bool,
+) -> Result<usize, Error> {
+    let file = OpenOptions::new()
+        .create_new(!overwrite)
+        .create(overwrite)
+        .truncate(overwrite)
+        .write(true)
+        .open(out_path)?;
+    let mut writer = BufWriter::new(file);
+    let mut exported = 0;
+    for deploy_hash in orphans {
+        if let Some(record) = lookup::get_deploy(txn, *deploy_hash)
+            .map_err(|get_err| Error::Export(deploy_hash.to_string(), get_err))?
+        {
+            serde_json::to_writer(&mut writer, &record)?;
+            writer.write_all(b"\n")?;
+            exported += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(exported)
+}
+
+fn delete_orphans(
+    env: &Environment,
+    orphans: &BTreeSet<DeployHash>,
+    dry_run: bool,
+    maybe_journal: Option<&mut Journal>,
+) -> Result<BTreeMap<&'static str, usize>, Error> {
+    let mut txn = JournaledTransaction::begin(env, maybe_journal, dry_run)?;
+    let mut dbs = vec![];
+    for db_name in [
+        DeployDatabase::db_name(),
+        DeployMetadataDatabase::db_name(),
+        FinalizedApprovalsDatabase::db_name(),
+    ] {
+        match unsafe { txn.open_db(Some(db_name)) } {
+            Ok(db) => dbs.push((db_name, db)),
+            Err(LmdbError::NotFound) => {}
+            Err(lmdb_err) => return Err(lmdb_err.into()),
+        }
+    }
+
+    let mut maybe_progress_tracker = ProgressTracker::new(
+        orphans.len(),
+        Box::new(|completion| info!("Deploy garbage collection {}% complete...", completion)),
+    )
+    .ok();
+    let mut deleted: BTreeMap<&'static str, usize> = BTreeMap::new();
+    for deploy_hash in orphans {
+        for &(db_name, db) in dbs.iter() {
+            match txn.del(db, deploy_hash) {
+                Ok(()) => *deleted.entry(db_name).or_default() += 1,
+                Err(JournalError::Database(LmdbError::NotFound)) => {}
+                Err(journal_err) => return Err(journal_err.into()),
+            }
+        }
+        if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
+            progress_tracker.advance_by(1);
+        }
+    }
+    txn.commit()?;
+    Ok(deleted)
+}
+
+/// Finds the deploys no block body, legacy or merkle, refers to, exports them to `export` if
+/// given and deletes them, along with their metadata and finalized
+/// approvals, in a single transaction. In a dry run nothing is deleted.
+pub(crate) fn gc_deploys<P1: AsRef<Path>, P2: AsRef<Path>>(
+    db_path: P1,
+    dry_run: bool,
+    export: Option<P2>,
+    overwrite: bool,
+    maybe_journal: Option<&mut Journal>,
+) -> Result<GcSummary, Error> {
+    let storage_path = db_path.as_ref().join(STORAGE_FILE_NAME);
+    let env = db::db_env(storage_path)?;
+
+    let mut summary = GcSummary {
+        dry_run,
+        ..Default::default()
+    };
+    {
+        let txn = env.begin_ro_txn()?;
+        let body_db = unsafe { txn.open_db(Some(BlockBodyDatabase::db_name()))? };
+        let mut referenced = referenced_deploys(&txn, body_db)?;
+        add_merkle_referenced_deploys::<DeployHashesDatabase>(&txn, &mut referenced)?;
+        add_merkle_referenced_deploys::<TransferHashesDatabase>(&txn, &mut referenced)?;
+        for db_name in [
+            DeployDatabase::db_name(),
+            DeployMetadataDatabase::db_name(),
+            FinalizedApprovalsDatabase::db_name(),
+        ] {
+            if let Some(db) = open_optional_db(&txn, db_name)? {
+                collect_orphans(&txn, db, &referenced, &mut summary.orphans)?;
+            }
+        }
+        summary.referenced = referenced.len();
+        if let Some(out_path) = export {
+            summary.exported =
+                export_orphans(&txn, &summary.orphans, out_path.as_ref(), overwrite)?;
+        }
+        txn.commit()?;
+    }
+
+    if dry_run {
+        for deploy_hash in summary.orphans.iter() {
+            info!("Would delete orphaned deploy {deploy_hash}.");
+        }
+    }
+    summary.deleted = delete_orphans(&env, &summary.orphans, dry_run, maybe_journal)?;
+    Ok(summary)
+}
+
+pub(crate) fn log_summary(summary: &GcSummary) {
+    info!(
+        "Found {} orphaned deploys, {} deploys are referenced by block bodies.",
+        summary.orphans.len(),
+        summary.referenced
+    );
+    if summary.exported > 0 {
+        info!("Exported {} orphaned deploys.", summary.exported);
+    }
+    let verb = if summary.dry_run {
+        "would be deleted"
+    } else {
+        "deleted"
+    };
+    for (db_name, count) in summary.deleted.iter() {
+        info!("{db_name}: {count} entries {verb}.");
+    }
+}
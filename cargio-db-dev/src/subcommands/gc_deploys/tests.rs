@@ -0,0 +1,298 @@
+use std::fs;
+
+use cargio_types::bytesrepr::ToBytes;
+use master_node::types::DeployHash;
+
+use crate::{
+    common::{
+        db::{
+            BlockBodyDatabase, Database, DeployDatabase, DeployHashesDatabase,
+            DeployMetadataDatabase, FinalizedApprovalsDatabase, TransferHashesDatabase,
+            STORAGE_FILE_NAME,
+        },
+        journal::{self, Journal},
+    },
+    subcommands::execution_results_summary::block_body::BlockBody,
+    test_utils::{has_entry, mock_deploy_hash, mock_deploy_metadata, put_entry, LmdbTestFixture},
+};
+
+use super::{gc::gc_deploys, Error};
+
+fn gc_fixture() -> LmdbTestFixture {
+    LmdbTestFixture::new(
+        vec![
+            BlockBodyDatabase::db_name(),
+            DeployDatabase::db_name(),
+            DeployHashesDatabase::db_name(),
+            DeployMetadataDatabase::db_name(),
+            FinalizedApprovalsDatabase::db_name(),
+            TransferHashesDatabase::db_name(),
+        ],
+        Some(STORAGE_FILE_NAME),
+    )
+}
+
+/// Stores a block body with deploy 1 and transfer 2. Deploy 3 has entries in
+/// all deploy databases, deploy 4 only has metadata and deploy 5 only has
+/// finalized approvals.
+fn populate(fixture: &LmdbTestFixture) -> Vec<DeployHash> {
+    let deploy_hashes: Vec<DeployHash> = (0..=5).map(mock_deploy_hash).collect();
+    let mut body = BlockBody::new(vec![deploy_hashes[1]]);
+    body.transfer_hashes = vec![deploy_hashes[2]];
+    put_entry(
+        fixture,
+        BlockBodyDatabase::db_name(),
+        &[0; 32],
+        &bincode::serialize(&body).unwrap(),
+    );
+    for idx in [1, 2, 3] {
+        put_entry(
+            fixture,
+            DeployDatabase::db_name(),
+            deploy_hashes[idx].as_ref(),
+            &[idx as u8],
+        );
+    }
+    for idx in [1, 2, 3, 4] {
+        put_entry(
+            fixture,
+            DeployMetadataDatabase::db_name(),
+            deploy_hashes[idx].as_ref(),
+            &bincode::serialize(&mock_deploy_metadata(&[])).unwrap(),
+        );
+    }
+    for idx in [1, 3, 5] {
+        put_entry(
+            fixture,
+            FinalizedApprovalsDatabase::db_name(),
+            deploy_hashes[idx].as_ref(),
+            &[idx as u8],
+        );
+    }
+    deploy_hashes
+}
+
+fn assert_present(fixture: &LmdbTestFixture, deploy_hashes: &[DeployHash], orphans_present: bool) {
+    assert!(has_entry(
+        fixture,
+        DeployDatabase::db_name(),
+        deploy_hashes[1].as_ref()
+    ));
+    assert!(has_entry(
+        fixture,
+        DeployMetadataDatabase::db_name(),
+        deploy_hashes[2].as_ref()
+    ));
+    assert!(has_entry(
+        fixture,
+        FinalizedApprovalsDatabase::db_name(),
+        deploy_hashes[1].as_ref()
+    ));
+    for db_name in [
+        DeployDatabase::db_name(),
+        DeployMetadataDatabase::db_name(),
+        FinalizedApprovalsDatabase::db_name(),
+    ] {
+        assert_eq!(
+            has_entry(fixture, db_name, deploy_hashes[3].as_ref()),
+            orphans_present
+        );
+    }
+    assert_eq!(
+        has_entry(
+            fixture,
+            DeployMetadataDatabase::db_name(),
+            deploy_hashes[4].as_ref()
+        ),
+        orphans_present
+    );
+    assert_eq!(
+        has_entry(
+            fixture,
+            FinalizedApprovalsDatabase::db_name(),
+            deploy_hashes[5].as_ref()
+        ),
+        orphans_present
+    );
+}
+
+#[test]
+fn gc_deploys_should_delete_orphans() {
+    let fixture = gc_fixture();
+    let deploy_hashes = populate(&fixture);
+
+    let summary = gc_deploys(fixture.tmp_dir.path(), false, None::<&str>, false, None).unwrap();
+    assert_eq!(summary.referenced, 2);
+    assert_eq!(
+        summary.orphans.into_iter().collect::<Vec<_>>(),
+        vec![deploy_hashes[3], deploy_hashes[4], deploy_hashes[5]]
+    );
+    assert_eq!(
+        summary.deleted.into_iter().collect::<Vec<_>>(),
+        vec![
+            (DeployDatabase::db_name(), 1),
+            (DeployMetadataDatabase::db_name(), 2),
+            (FinalizedApprovalsDatabase::db_name(), 2),
+        ]
+    );
+    assert_present(&fixture, &deploy_hashes, false);
+}
+
+#[test]
+fn gc_deploys_dry_run_should_not_change_db() {
+    let fixture = gc_fixture();
+    let deploy_hashes = populate(&fixture);
+
+    let summary = gc_deploys(fixture.tmp_dir.path(), true, None::<&str>, false, None).unwrap();
+    assert_eq!(summary.orphans.len(), 3);
+    assert_eq!(summary.deleted.values().sum::<usize>(), 5);
+    assert_present(&fixture, &deploy_hashes, true);
+}
+
+#[test]
+fn gc_deploys_should_export_orphans() {
+    let fixture = gc_fixture();
+    let deploy_hashes: Vec<DeployHash> = (0..2).map(mock_deploy_hash).collect();
+    put_entry(
+        &fixture,
+        BlockBodyDatabase::db_name(),
+        &[0; 32],
+        &bincode::serialize(&BlockBody::new(vec![deploy_hashes[0]])).unwrap(),
+    );
+    for deploy_hash in deploy_hashes.iter() {
+        put_entry(
+            &fixture,
+            DeployMetadataDatabase::db_name(),
+            deploy_hash.as_ref(),
+            &bincode::serialize(&mock_deploy_metadata(&[])).unwrap(),
+        );
+    }
+
+    let export_path = fixture.tmp_dir.path().join("orphans.json");
+    let summary = gc_deploys(
+        fixture.tmp_dir.path(),
+        true,
+        Some(&export_path),
+        false,
+        None,
+    )
+    .unwrap();
+    assert_eq!(summary.exported, 1);
+    let exported = fs::read_to_string(&export_path).unwrap();
+    let lines: Vec<&str> = exported.lines().collect();
+    assert_eq!(lines.len(), 1);
+    let record: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(
+        record["deploy_hash"],
+        serde_json::to_value(deploy_hashes[1]).unwrap()
+    );
+    // Exporting only in a dry run.
+    assert!(has_entry(
+        &fixture,
+        DeployMetadataDatabase::db_name(),
+        deploy_hashes[1].as_ref()
+    ));
+    // Existing exports aren't overwritten by default.
+    assert!(gc_deploys(
+        fixture.tmp_dir.path(),
+        true,
+        Some(&export_path),
+        false,
+        None
+    )
+    .is_err());
+}
+
+#[test]
+fn gc_deploys_should_be_undone_from_journal() {
+    let fixture = gc_fixture();
+    let deploy_hashes = populate(&fixture);
+
+    let journal_path = fixture.tmp_dir.path().join("gc.journal");
+    let mut journal = Journal::create(&journal_path, &fixture.file_path).unwrap();
+    gc_deploys(
+        fixture.tmp_dir.path(),
+        false,
+        None::<&str>,
+        false,
+        Some(&mut journal),
+    )
+    .unwrap();
+    drop(journal);
+    assert_present(&fixture, &deploy_hashes, false);
+
+    assert_eq!(journal::undo(&journal_path).unwrap(), 5);
+    assert_present(&fixture, &deploy_hashes, true);
+}
+
+/// Returns the merkle leaf listing `deploy_hashes`.
+fn merkle_leaf(deploy_hashes: &[DeployHash]) -> Vec<u8> {
+    deploy_hashes
+        .iter()
+        .map(|deploy_hash| cargio_types::DeployHash::new(deploy_hash.inner().value()))
+        .collect::<Vec<_>>()
+        .to_bytes()
+        .unwrap()
+}
+
+#[test]
+fn gc_deploys_should_keep_deploys_of_merkle_bodies() {
+    let fixture = gc_fixture();
+    let deploy_hashes = populate(&fixture);
+    // A merkle body has its deploys and transfers only in the leaves.
+    let merkle_deploy = mock_deploy_hash(6);
+    let merkle_transfer = mock_deploy_hash(7);
+    put_entry(
+        &fixture,
+        DeployHashesDatabase::db_name(),
+        &[1; 32],
+        &merkle_leaf(&[merkle_deploy]),
+    );
+    put_entry(
+        &fixture,
+        TransferHashesDatabase::db_name(),
+        &[2; 32],
+        &merkle_leaf(&[merkle_transfer]),
+    );
+    for deploy_hash in [merkle_deploy, merkle_transfer] {
+        put_entry(
+            &fixture,
+            DeployDatabase::db_name(),
+            deploy_hash.as_ref(),
+            &[6],
+        );
+    }
+
+    let summary = gc_deploys(fixture.tmp_dir.path(), false, None::<&str>, false, None).unwrap();
+    assert_eq!(summary.referenced, 4);
+    assert_eq!(
+        summary.orphans.into_iter().collect::<Vec<_>>(),
+        vec![deploy_hashes[3], deploy_hashes[4], deploy_hashes[5]]
+    );
+    assert_present(&fixture, &deploy_hashes, false);
+    for deploy_hash in [merkle_deploy, merkle_transfer] {
+        assert!(has_entry(
+            &fixture,
+            DeployDatabase::db_name(),
+            deploy_hash.as_ref()
+        ));
+    }
+}
+
+#[test]
+fn gc_deploys_should_refuse_unparsable_merkle_leaves() {
+    let fixture = gc_fixture();
+    let deploy_hashes = populate(&fixture);
+    put_entry(
+        &fixture,
+        TransferHashesDatabase::db_name(),
+        &[2; 32],
+        &[1, 2, 3],
+    );
+
+    assert!(matches!(
+        gc_deploys(fixture.tmp_dir.path(), false, None::<&str>, false, None),
+        Err(Error::HashesParsing(db_name, _, _)) if db_name == TransferHashesDatabase::db_name()
+    ));
+    assert_present(&fixture, &deploy_hashes, true);
+}
@@ -0,0 +1,181 @@
+pub(crate) mod lookup;
+#[cfg(test)]
+mod tests;
+
+use std::{io::Error as IoError, path::Path};
+
+use bincode::Error as BincodeError;
+use cargio_hashing::Digest;
+use clap::{Arg, ArgGroup, ArgMatches, Command};
+use lmdb::Error as LmdbError;
+use master_node::types::{BlockHash, DeployHash};
+use serde_json::Error as SerializationError;
+use thiserror::Error as ThisError;
+
+use crate::common::{block_index::BlockIndexError, db::DeserializationError};
+
+use self::lookup::RecordIdentifier;
+
+pub const COMMAND_NAME: &str = "get";
+const BLOCK_HASH: &str = "block-hash";
+const BLOCK_HEIGHT: &str = "block-height";
+const DB_PATH: &str = "db-path";
+const DEPLOY_HASH: &str = "deploy-hash";
+const IDENTIFIER: &str = "identifier";
+const OUTPUT: &str = "output";
+const OVERWRITE: &str = "overwrite";
+const STATE_ROOT_HASH: &str = "state-root-hash";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error reading the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
+    #[error("Error parsing block body with key {0}: {1}")]
+    BodyParsing(String, BincodeError),
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Invalid block hash key {0}")]
+    InvalidBlockHash(String),
+    #[error("No {0} found in the storage")]
+    NotFound(String),
+    #[error("Error writing output: {0}")]
+    Output(#[from] IoError),
+    #[error("Error parsing element with key {1} in {0} DB: {2}")]
+    Parsing(String, String, DeserializationError),
+    #[error("Error serializing output: {0}")]
+    Serialize(#[from] SerializationError),
+}
+
+enum DisplayOrder {
+    DbPath,
+    BlockHash,
+    BlockHeight,
+    DeployHash,
+    StateRootHash,
+    Output,
+    Overwrite,
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Looks up everything the storage holds for a block, deploy or state root hash and \
+            outputs it as a single JSON document. Blocks are output with their body, deploys, \
+            execution results, transfers and signatures.",
+        )
+        .arg(
+            Arg::new(DB_PATH)
+                .display_order(DisplayOrder::DbPath as usize)
+                .required(true)
+                .short('d')
+                .long(DB_PATH)
+                .takes_value(true)
+                .value_name("DB_PATH")
+                .help("Path of the directory with the `storage.lmdb` file."),
+        )
+        .arg(
+            Arg::new(BLOCK_HASH)
+                .display_order(DisplayOrder::BlockHash as usize)
+                .short('b')
+                .long(BLOCK_HASH)
+                .takes_value(true)
+                .value_name("BLOCK_HASH")
+                .help("Hash of the block to look up."),
+        )
+        .arg(
+            Arg::new(BLOCK_HEIGHT)
+                .display_order(DisplayOrder::BlockHeight as usize)
+                .short('n')
+                .long(BLOCK_HEIGHT)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .help(
+                    "Height of the block to look up. The block index is used if it is up to \
+                    date, otherwise the block header database is scanned for the block at this \
+                    height.",
+                ),
+        )
+        .arg(
+            Arg::new(DEPLOY_HASH)
+                .display_order(DisplayOrder::DeployHash as usize)
+                .short('p')
+                .long(DEPLOY_HASH)
+                .takes_value(true)
+                .value_name("DEPLOY_HASH")
+                .help(
+                    "Hash of the deploy to look up, output with its execution results and \
+                    finalized approvals.",
+                ),
+        )
+        .arg(
+            Arg::new(STATE_ROOT_HASH)
+                .display_order(DisplayOrder::StateRootHash as usize)
+                .short('s')
+                .long(STATE_ROOT_HASH)
+                .takes_value(true)
+                .value_name("STATE_ROOT_HASH")
+                .help("State root hash to look up the blocks of."),
+        )
+        .arg(
+            Arg::new(OUTPUT)
+                .display_order(DisplayOrder::Output as usize)
+                .short('o')
+                .long(OUTPUT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help(
+                    "Path to where the program will output the record. \
+                    If unspecified, defaults to standard output.",
+                ),
+        )
+        .arg(
+            Arg::new(OVERWRITE)
+                .display_order(DisplayOrder::Overwrite as usize)
+                .required(false)
+                .short('w')
+                .long(OVERWRITE)
+                .takes_value(false)
+                .requires(OUTPUT)
+                .help(
+                    "Overwrite an already existing output file in destination \
+                    directory.",
+                ),
+        )
+        .group(ArgGroup::new(IDENTIFIER).required(true).args(&[
+            BLOCK_HASH,
+            BLOCK_HEIGHT,
+            DEPLOY_HASH,
+            STATE_ROOT_HASH,
+        ]))
+}
+
+fn parse_digest(matches: &ArgMatches, arg: &str) -> Option<Digest> {
+    matches.value_of(arg).map(|hex_digest| {
+        Digest::from_hex(hex_digest)
+            .unwrap_or_else(|_| panic!("Value of \"--{arg}\" must be a hex encoded hash."))
+    })
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let path = Path::new(matches.value_of(DB_PATH).expect("should have db-path arg"));
+    let identifier = if let Some(block_hash) = parse_digest(matches, BLOCK_HASH) {
+        RecordIdentifier::BlockHash(BlockHash::from(block_hash))
+    } else if let Some(height) = matches.value_of(BLOCK_HEIGHT) {
+        RecordIdentifier::BlockHeight(
+            height
+                .parse()
+                .unwrap_or_else(|_| panic!("Value of \"--{BLOCK_HEIGHT}\" must be an integer.")),
+        )
+    } else if let Some(deploy_hash) = parse_digest(matches, DEPLOY_HASH) {
+        RecordIdentifier::DeployHash(DeployHash::new(deploy_hash))
+    } else {
+        RecordIdentifier::StateRootHash(
+            parse_digest(matches, STATE_ROOT_HASH)
+                .unwrap_or_else(|| panic!("Should have one of the {IDENTIFIER} args")),
+        )
+    };
+    let output = matches.value_of(OUTPUT).map(Path::new);
+    let overwrite = matches.is_present(OVERWRITE);
+    lookup::get(path, identifier, output, overwrite)
+}
@@ -0,0 +1,274 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    result::Result,
+};
+
+use cargio_hashing::Digest;
+use cargio_types::{ExecutionResult, Transfer};
+use lmdb::{Cursor, Error as LmdbError, RoTransaction, Transaction};
+use master_node::types::{
+    BlockHash, BlockHeader, BlockSignatures, Deploy, DeployHash, DeployMetadata, FinalizedApprovals,
+};
+use serde::Serialize;
+
+use crate::{
+    common::{
+        block_index::BlockIndex,
+        db::{
+            self, BlockBodyDatabase, BlockHeaderDatabase, BlockMetadataDatabase, Database,
+            DeployDatabase, DeployMetadataDatabase, FinalizedApprovalsDatabase, TransferDatabase,
+            STORAGE_FILE_NAME,
+        },
+    },
+    subcommands::execution_results_summary::block_body::BlockBody,
+};
+
+use super::Error;
+
+pub enum RecordIdentifier {
+    BlockHash(BlockHash),
+    BlockHeight(u64),
+    DeployHash(DeployHash),
+    StateRootHash(Digest),
+}
+
+/// A deploy of a block, along with its execution result in that block.
+#[derive(Debug, Serialize)]
+pub(crate) struct BlockDeploy {
+    pub(crate) deploy_hash: DeployHash,
+    pub(crate) deploy: Option<Deploy>,
+    pub(crate) execution_result: Option<ExecutionResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct BlockRecord {
+    pub(crate) block_hash: BlockHash,
+    pub(crate) header: BlockHeader,
+    pub(crate) body: Option<BlockBody>,
+    pub(crate) deploys: Vec<BlockDeploy>,
+    pub(crate) transfers: Option<Vec<Transfer>>,
+    pub(crate) signatures: Option<BlockSignatures>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct DeployRecord {
+    pub(crate) deploy_hash: DeployHash,
+    pub(crate) deploy: Option<Deploy>,
+    pub(crate) metadata: Option<DeployMetadata>,
+    pub(crate) finalized_approvals: Option<FinalizedApprovals>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct StateRootRecord {
+    pub(crate) state_root_hash: Digest,
+    pub(crate) blocks: Vec<BlockRecord>,
+}
+
+/// Returns the raw value under `key`, or `None` if either the database or
+/// the key are missing.
+fn get_raw<'txn>(
+    txn: &'txn RoTransaction,
+    db_name: &str,
+    key: &[u8],
+) -> Result<Option<&'txn [u8]>, Error> {
+    let db = match unsafe { txn.open_db(Some(db_name)) } {
+        Ok(db) => db,
+        Err(LmdbError::NotFound) => return Ok(None),
+        Err(lmdb_err) => return Err(lmdb_err.into()),
+    };
+    match txn.get(db, &key) {
+        Ok(raw_val) => Ok(Some(raw_val)),
+        Err(LmdbError::NotFound) => Ok(None),
+        Err(lmdb_err) => Err(lmdb_err.into()),
+    }
+}
+
+fn get_element<D: Database>(txn: &RoTransaction, key: &[u8]) -> Result<Option<D::Element>, Error> {
+    get_raw(txn, D::db_name(), key)?
+        .map(|raw_val| {
+            D::parse_element(raw_val).map_err(|parsing_err| {
+                Error::Parsing(D::db_name().to_string(), hex::encode(key), parsing_err)
+            })
+        })
+        .transpose()
+}
+
+pub(crate) fn get_block(
+    txn: &RoTransaction,
+    block_hash: BlockHash,
+) -> Result<Option<BlockRecord>, Error> {
+    let header = match get_element::<BlockHeaderDatabase>(txn, block_hash.as_ref())? {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let body: Option<BlockBody> = get_raw(
+        txn,
+        BlockBodyDatabase::db_name(),
+        header.body_hash().as_ref(),
+    )?
+    .map(|raw_val| {
+        bincode::deserialize(raw_val)
+            .map_err(|bincode_err| Error::BodyParsing(hex::encode(header.body_hash()), bincode_err))
+    })
+    .transpose()?;
+
+    let mut deploys = vec![];
+    if let Some(body) = body.as_ref() {
+        for deploy_hash in body.deploy_hashes.iter().chain(body.transfer_hashes.iter()) {
+            let deploy = get_element::<DeployDatabase>(txn, deploy_hash.as_ref())?;
+            let execution_result =
+                get_element::<DeployMetadataDatabase>(txn, deploy_hash.as_ref())?
+                    .and_then(|mut metadata| metadata.execution_results.remove(&block_hash));
+            deploys.push(BlockDeploy {
+                deploy_hash: *deploy_hash,
+                deploy,
+                execution_result,
+            });
+        }
+    }
+    let transfers = get_element::<TransferDatabase>(txn, block_hash.as_ref())?;
+    let signatures = get_element::<BlockMetadataDatabase>(txn, block_hash.as_ref())?;
+
+    Ok(Some(BlockRecord {
+        block_hash,
+        header,
+        body,
+        deploys,
+        transfers,
+        signatures,
+    }))
+}
+
+/// Scans the block header database for the hashes of the blocks matching
+/// `predicate`.
+fn find_block_hashes<F: Fn(&BlockHeader) -> bool>(
+    txn: &RoTransaction,
+    predicate: F,
+) -> Result<Vec<BlockHash>, Error> {
+    let db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+    let mut block_hashes = vec![];
+    if let Ok(mut cursor) = txn.open_ro_cursor(db) {
+        for (raw_key, raw_val) in cursor.iter() {
+            let header = BlockHeaderDatabase::parse_element(raw_val).map_err(|parsing_err| {
+                Error::Parsing(
+                    BlockHeaderDatabase::db_name().to_string(),
+                    hex::encode(raw_key),
+                    parsing_err,
+                )
+            })?;
+            if predicate(&header) {
+                let block_hash_digest = Digest::try_from(raw_key)
+                    .map_err(|_| Error::InvalidBlockHash(hex::encode(raw_key)))?;
+                block_hashes.push(BlockHash::from(block_hash_digest));
+            }
+        }
+    }
+    Ok(block_hashes)
+}
+
+pub(crate) fn get_deploy(
+    txn: &RoTransaction,
+    deploy_hash: DeployHash,
+) -> Result<Option<DeployRecord>, Error> {
+    let deploy = get_element::<DeployDatabase>(txn, deploy_hash.as_ref())?;
+    let metadata = get_element::<DeployMetadataDatabase>(txn, deploy_hash.as_ref())?;
+    let finalized_approvals = get_element::<FinalizedApprovalsDatabase>(txn, deploy_hash.as_ref())?;
+    if deploy.is_none() && metadata.is_none() && finalized_approvals.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(DeployRecord {
+        deploy_hash,
+        deploy,
+        metadata,
+        finalized_approvals,
+    }))
+}
+
+/// Writes the record to `output` if given, or to standard output otherwise.
+/// The output file is only created once there is a record to write.
+fn write_record<T: Serialize, P: AsRef<Path>>(
+    record: &T,
+    output: Option<P>,
+    overwrite: bool,
+) -> Result<(), Error> {
+    let out_writer: Box<dyn Write> = if let Some(out_path) = output {
+        let file = OpenOptions::new()
+            .create_new(!overwrite)
+            .create(overwrite)
+            .truncate(overwrite)
+            .write(true)
+            .open(out_path)?;
+        Box::new(file)
+    } else {
+        Box::new(io::stdout())
+    };
+    serde_json::to_writer_pretty(out_writer, record)?;
+    Ok(())
+}
+
+pub fn get<P1: AsRef<Path>, P2: AsRef<Path>>(
+    db_path: P1,
+    identifier: RecordIdentifier,
+    output: Option<P2>,
+    overwrite: bool,
+) -> Result<(), Error> {
+    let storage_path = db_path.as_ref().join(STORAGE_FILE_NAME);
+    let env = db::db_env(storage_path)?;
+    let maybe_block_index = match identifier {
+        RecordIdentifier::BlockHeight(_) => BlockIndex::open(&db_path, &env)?,
+        _ => None,
+    };
+
+    let txn = env.begin_ro_txn()?;
+    match identifier {
+        RecordIdentifier::BlockHash(block_hash) => {
+            let record = get_block(&txn, block_hash)?
+                .ok_or_else(|| Error::NotFound(format!("block with hash {block_hash}")))?;
+            write_record(&record, output, overwrite)?;
+        }
+        RecordIdentifier::BlockHeight(height) => {
+            let maybe_block_hash = match maybe_block_index {
+                Some(block_index) => block_index.block_hash(height)?,
+                None => find_block_hashes(&txn, |header| header.height() == height)?.pop(),
+            };
+            let block_hash = maybe_block_hash
+                .ok_or_else(|| Error::NotFound(format!("block at height {height}")))?;
+            let record = get_block(&txn, block_hash)?
+                .ok_or_else(|| Error::NotFound(format!("block at height {height}")))?;
+            write_record(&record, output, overwrite)?;
+        }
+        RecordIdentifier::DeployHash(deploy_hash) => {
+            let record = get_deploy(&txn, deploy_hash)?
+                .ok_or_else(|| Error::NotFound(format!("deploy with hash {deploy_hash}")))?;
+            write_record(&record, output, overwrite)?;
+        }
+        RecordIdentifier::StateRootHash(state_root_hash) => {
+            let mut blocks = vec![];
+            for block_hash in
+                find_block_hashes(&txn, |header| *header.state_root_hash() == state_root_hash)?
+            {
+                if let Some(record) = get_block(&txn, block_hash)? {
+                    blocks.push(record);
+                }
+            }
+            if blocks.is_empty() {
+                return Err(Error::NotFound(format!(
+                    "block with state root hash {state_root_hash}"
+                )));
+            }
+            blocks.sort_by_key(|record| record.header.height());
+            write_record(
+                &StateRootRecord {
+                    state_root_hash,
+                    blocks,
+                },
+                output,
+                overwrite,
+            )?;
+        }
+    }
+    txn.commit()?;
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use std::fs;
+
+use cargio_hashing::Digest;
+use lmdb::{Transaction, WriteFlags};
+use master_node::types::{BlockHash, DeployHash};
+use serde_json::Value;
+
+use crate::{
+    common::{
+        block_index,
+        db::{
+            BlockBodyDatabase, BlockHeaderDatabase, BlockMetadataDatabase, Database,
+            DeployDatabase, DeployMetadataDatabase, FinalizedApprovalsDatabase, TransferDatabase,
+            STORAGE_FILE_NAME,
+        },
+    },
+    subcommands::{
+        execution_results_summary::block_body::BlockBody,
+        get::{
+            lookup::{self, RecordIdentifier},
+            Error,
+        },
+    },
+    test_utils::{
+        mock_block_header, mock_deploy_hash, mock_deploy_metadata, LmdbTestFixture, MockBlockHeader,
+    },
+};
+
+const BLOCK_COUNT: u8 = 3;
+
+fn storage_fixture() -> LmdbTestFixture {
+    LmdbTestFixture::new(
+        vec![
+            BlockHeaderDatabase::db_name(),
+            BlockBodyDatabase::db_name(),
+            BlockMetadataDatabase::db_name(),
+            DeployDatabase::db_name(),
+            DeployMetadataDatabase::db_name(),
+            FinalizedApprovalsDatabase::db_name(),
+            TransferDatabase::db_name(),
+        ],
+        Some(STORAGE_FILE_NAME),
+    )
+}
+
+// Block `i` is at height `i` and has deploy `i` with its execution results.
+// The last two blocks share the same state root hash.
+fn populate_blocks(fixture: &LmdbTestFixture) -> Vec<(BlockHash, MockBlockHeader, DeployHash)> {
+    let mut blocks = vec![];
+    let mut txn = fixture.env.begin_rw_txn().unwrap();
+    for idx in 0..BLOCK_COUNT {
+        let (block_hash, mut header) = mock_block_header(idx);
+        header.height = idx as u64;
+        header.state_root_hash = [idx.min(1); Digest::LENGTH].into();
+        let deploy_hash = mock_deploy_hash(idx);
+        txn.put(
+            *fixture.db(Some(BlockHeaderDatabase::db_name())).unwrap(),
+            &block_hash,
+            &bincode::serialize(&header).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+        txn.put(
+            *fixture.db(Some(BlockBodyDatabase::db_name())).unwrap(),
+            &header.body_hash,
+            &bincode::serialize(&BlockBody::new(vec![deploy_hash])).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+        txn.put(
+            *fixture.db(Some(DeployMetadataDatabase::db_name())).unwrap(),
+            &deploy_hash,
+            &bincode::serialize(&mock_deploy_metadata(&[block_hash])).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+        blocks.push((block_hash, header, deploy_hash));
+    }
+    txn.commit().unwrap();
+    blocks
+}
+
+fn get_json(fixture: &LmdbTestFixture, identifier: RecordIdentifier) -> Result<Value, Error> {
+    let out_dir = tempfile::tempdir().unwrap();
+    let out_path = out_dir.path().join("record.json");
+    lookup::get(fixture.tmp_dir.path(), identifier, Some(&out_path), false)?;
+    Ok(serde_json::from_str(&fs::read_to_string(&out_path).unwrap()).unwrap())
+}
+
+#[test]
+fn get_block_should_follow_relations() {
+    let fixture = storage_fixture();
+    let blocks = populate_blocks(&fixture);
+    let (block_hash, header, deploy_hash) = &blocks[1];
+
+    let txn = fixture.env.begin_ro_txn().unwrap();
+    let record = lookup::get_block(&txn, *block_hash).unwrap().unwrap();
+    assert_eq!(record.block_hash, *block_hash);
+    assert_eq!(record.header.height(), header.height);
+    assert_eq!(
+        record.body.as_ref().unwrap().deploy_hashes(),
+        &vec![*deploy_hash]
+    );
+    assert_eq!(record.deploys.len(), 1);
+    assert_eq!(record.deploys[0].deploy_hash, *deploy_hash);
+    // Missing records are reported as such instead of failing the lookup.
+    assert!(record.deploys[0].deploy.is_none());
+    assert!(record.deploys[0].execution_result.is_some());
+    assert!(record.transfers.is_none());
+    assert!(record.signatures.is_none());
+
+    let unknown_block_hash: BlockHash = Digest::from([0xffu8; Digest::LENGTH]).into();
+    assert!(lookup::get_block(&txn, unknown_block_hash)
+        .unwrap()
+        .is_none());
+    txn.commit().unwrap();
+}
+
+#[test]
+fn get_should_find_blocks_by_height_and_state_root() {
+    let fixture = storage_fixture();
+    let blocks = populate_blocks(&fixture);
+
+    let record = get_json(&fixture, RecordIdentifier::BlockHeight(2)).unwrap();
+    assert_eq!(record["block_hash"], hex::encode(blocks[2].0));
+    assert_eq!(
+        record["deploys"][0]["deploy_hash"],
+        hex::encode(blocks[2].2)
+    );
+
+    let record = get_json(
+        &fixture,
+        RecordIdentifier::StateRootHash(blocks[1].1.state_root_hash),
+    )
+    .unwrap();
+    let block_hashes: Vec<Value> = record["blocks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|block| block["block_hash"].clone())
+        .collect();
+    assert_eq!(
+        block_hashes,
+        [hex::encode(blocks[1].0), hex::encode(blocks[2].0)]
+    );
+
+    assert!(matches!(
+        get_json(&fixture, RecordIdentifier::BlockHeight(BLOCK_COUNT as u64)),
+        Err(Error::NotFound(_))
+    ));
+}
+
+#[test]
+fn get_should_find_blocks_by_height_with_block_index() {
+    let fixture = storage_fixture();
+    let blocks = populate_blocks(&fixture);
+    block_index::build_index(fixture.tmp_dir.path(), &fixture.env).unwrap();
+
+    for (height, (block_hash, _header, _deploy_hash)) in blocks.iter().enumerate() {
+        let record = get_json(&fixture, RecordIdentifier::BlockHeight(height as u64)).unwrap();
+        assert_eq!(record["block_hash"], hex::encode(block_hash));
+    }
+    assert!(matches!(
+        get_json(&fixture, RecordIdentifier::BlockHeight(BLOCK_COUNT as u64)),
+        Err(Error::NotFound(_))
+    ));
+}
+
+#[test]
+fn missing_record_should_not_create_output_file() {
+    let fixture = storage_fixture();
+    let _ = populate_blocks(&fixture);
+    let out_dir = tempfile::tempdir().unwrap();
+    let out_path = out_dir.path().join("record.json");
+
+    assert!(matches!(
+        lookup::get(
+            fixture.tmp_dir.path(),
+            RecordIdentifier::BlockHeight(BLOCK_COUNT as u64),
+            Some(&out_path),
+            false,
+        ),
+        Err(Error::NotFound(_))
+    ));
+    assert!(!out_path.exists());
+}
+
+#[test]
+fn get_should_find_deploy_metadata() {
+    let fixture = storage_fixture();
+    let blocks = populate_blocks(&fixture);
+
+    let record = get_json(&fixture, RecordIdentifier::DeployHash(blocks[0].2)).unwrap();
+    assert_eq!(record["deploy_hash"], hex::encode(blocks[0].2));
+    assert!(record["deploy"].is_null());
+    assert!(record["metadata"]["execution_results"].is_object());
+    assert!(record["finalized_approvals"].is_null());
+
+    assert!(matches!(
+        get_json(
+            &fixture,
+            RecordIdentifier::DeployHash(mock_deploy_hash(BLOCK_COUNT))
+        ),
+        Err(Error::NotFound(_))
+    ));
+}
@@ -0,0 +1,107 @@
+mod import;
+#[cfg(test)]
+mod tests;
+
+use std::{
+    io::Error as IoError,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Error as AnyError;
+use clap::{Arg, ArgMatches, Command};
+use lmdb::Error as LmdbError;
+use thiserror::Error as ThisError;
+
+use cargio_hashing::Digest;
+use cargio_types::Key;
+
+use crate::{common::state_file::StateFileError, subcommands::trie_compact};
+
+pub const COMMAND_NAME: &str = "import-state";
+const DESTINATION_TRIE_STORE_PATH: &str = "dest-trie";
+const INPUT: &str = "input";
+const MAX_DB_SIZE: &str = "max-db-size";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error adding entries to the trie: {0}")]
+    Commit(AnyError),
+    #[error("Error creating the trie store: {0}")]
+    CreateTrieStore(AnyError),
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Error moving the trie store to {0}: {1}")]
+    Destination(PathBuf, IoError),
+    #[error("Destination trie store {0} already exists")]
+    DestinationExists(PathBuf),
+    #[error("Key {0} appears more than once in the state file")]
+    DuplicateKey(Key),
+    #[error("Error reading the state file: {0}")]
+    StateFile(#[from] StateFileError),
+    #[error("Imported entries have state root {actual}, but the state file says {expected}")]
+    StateRootMismatch { expected: Digest, actual: Digest },
+}
+
+enum DisplayOrder {
+    Input,
+    DestinationPath,
+    MaxDbSize,
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Builds a new trie store holding the state root of a state file written by \
+            `export-state`.",
+        )
+        .arg(
+            Arg::new(INPUT)
+                .display_order(DisplayOrder::Input as usize)
+                .required(true)
+                .short('i')
+                .long(INPUT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help("Path of the state file to import."),
+        )
+        .arg(
+            Arg::new(DESTINATION_TRIE_STORE_PATH)
+                .display_order(DisplayOrder::DestinationPath as usize)
+                .required(true)
+                .short('d')
+                .long(DESTINATION_TRIE_STORE_PATH)
+                .takes_value(true)
+                .value_name("DESTINATION_TRIE_STORE_DIR_PATH")
+                .help(
+                    "Path of the directory where the output `data.lmdb` file will be created. \
+                    It is only created once all entries were imported.",
+                ),
+        )
+        .arg(
+            Arg::new(MAX_DB_SIZE)
+                .display_order(DisplayOrder::MaxDbSize as usize)
+                .short('m')
+                .long(MAX_DB_SIZE)
+                .takes_value(true)
+                .value_name("MAX_DB_SIZE")
+                .default_value(trie_compact::DEFAULT_MAX_DB_SIZE)
+                .help("Maximum size the DB files are allowed to be, in bytes."),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let input = Path::new(matches.value_of(INPUT).expect("should have input arg"));
+    let dest_path = Path::new(
+        matches
+            .value_of(DESTINATION_TRIE_STORE_PATH)
+            .expect("should have dest-trie arg"),
+    );
+    let max_db_size: usize = matches
+        .value_of(MAX_DB_SIZE)
+        .expect("should have max-db-size arg")
+        .parse()
+        .unwrap_or_else(|_| panic!("Value of \"--{MAX_DB_SIZE}\" must be an integer."));
+    import::import_state(input, dest_path, max_db_size)?;
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs,
+    path::Path,
+};
+
+use log::{info, warn};
+
+use cargio_execution_engine::{
+    shared::newtypes::CorrelationId,
+    storage::global_state::{StateProvider, StateReader},
+};
+use cargio_hashing::Digest;
+
+use crate::{
+    common::{db::TRIE_STORE_FILE_NAME, progress::ProgressTracker, state_file::StateFileReader},
+    subcommands::trie_compact,
+};
+
+use super::Error;
+
+/// Number of entries added to the trie per commit.
+const IMPORT_BATCH_SIZE: usize = 100_000;
+/// Name of the directory in the destination where the trie store is built
+/// before it is moved into place.
+const TMP_DIR_NAME: &str = ".import-state";
+
+/// Builds the trie of the entries in the state file at `input` in a new
+/// trie store in `dest_path`, checking that it has the state root recorded
+/// in the file. Returns the state root.
+///
+/// The trie store is built in a temporary directory and only moved to
+/// `dest_path` once the import succeeded, so a failed import can be retried.
+pub(crate) fn import_state<P1: AsRef<Path>, P2: AsRef<Path>>(
+    input: P1,
+    dest_path: P2,
+    max_db_size: usize,
+) -> Result<Digest, Error> {
+    let dest_file = dest_path.as_ref().join(TRIE_STORE_FILE_NAME);
+    if dest_file.exists() {
+        return Err(Error::DestinationExists(dest_file));
+    }
+    let mut reader = StateFileReader::open(input)?;
+    let tmp_dir = dest_path.as_ref().join(TMP_DIR_NAME);
+    // A leftover of an interrupted import.
+    if tmp_dir.exists() {
+        fs::remove_dir_all(&tmp_dir)
+            .map_err(|io_err| Error::Destination(tmp_dir.clone(), io_err))?;
+    }
+    let result = import_entries(&mut reader, &tmp_dir, max_db_size).and_then(|state_root| {
+        fs::rename(tmp_dir.join(TRIE_STORE_FILE_NAME), &dest_file)
+            .map_err(|io_err| Error::Destination(dest_file.clone(), io_err))?;
+        Ok(state_root)
+    });
+    if let Err(io_err) = fs::remove_dir_all(&tmp_dir) {
+        warn!("Couldn't remove {}: {io_err}", tmp_dir.display());
+    }
+    result
+}
+
+/// Builds the trie of the entries read by `reader` in a new trie store in
+/// `dest_path`.
+fn import_entries(
+    reader: &mut StateFileReader,
+    dest_path: &Path,
+    max_db_size: usize,
+) -> Result<Digest, Error> {
+    let header = reader.header();
+    let (engine_state, _env) = trie_compact::create_execution_engine(dest_path, max_db_size, true)
+        .map_err(Error::CreateTrieStore)?;
+    let global_state = engine_state.get_state();
+    let mut maybe_progress_tracker = ProgressTracker::new(
+        header.entry_count as usize,
+        Box::new(|completion| info!("State import {}% complete...", completion)),
+    )
+    .ok();
+
+    // Each batch is committed on top of the state root of the previous one.
+    // Tries only reachable from those intermediate state roots stay in the
+    // store until it is compacted.
+    let empty_root = global_state.empty_root();
+    let mut state_root = empty_root;
+    loop {
+        let state_view = global_state
+            .checkout(state_root)
+            .map_err(|ee_err| Error::Commit(ee_err.into()))?
+            .ok_or_else(|| Error::Commit(anyhow::anyhow!("state root {state_root} is missing")))?;
+        let mut batch = HashMap::with_capacity(IMPORT_BATCH_SIZE);
+        while batch.len() < IMPORT_BATCH_SIZE {
+            let (key, value) = match reader.next_entry()? {
+                Some(entry) => entry,
+                None => break,
+            };
+            // Entries with the same key would silently overwrite each other,
+            // both within a batch and across batches.
+            let imported = state_root != empty_root
+                && state_view
+                    .read(CorrelationId::new(), &key)
+                    .map_err(|ee_err| Error::Commit(ee_err.into()))?
+                    .is_some();
+            match batch.entry(key) {
+                Entry::Vacant(entry) if !imported => {
+                    entry.insert(value);
+                }
+                _ => return Err(Error::DuplicateKey(key)),
+            }
+        }
+        if batch.is_empty() {
+            break;
+        }
+        let batch_len = batch.len();
+        state_root = global_state
+            .put_stored_values(CorrelationId::new(), state_root, batch)
+            .map_err(|ee_err| Error::Commit(ee_err.into()))?;
+        engine_state.flush_environment()?;
+        if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
+            progress_tracker.advance_by(batch_len);
+        }
+    }
+
+    if state_root != header.state_root {
+        return Err(Error::StateRootMismatch {
+            expected: header.state_root,
+            actual: state_root,
+        });
+    }
+    info!(
+        "Imported {} entries under state root {state_root}.",
+        header.entry_count
+    );
+    Ok(state_root)
+}
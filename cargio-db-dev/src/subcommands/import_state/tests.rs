@@ -0,0 +1,126 @@
+use std::{collections::HashMap, fs};
+
+use tempfile::tempdir;
+
+use cargio_execution_engine::shared::newtypes::CorrelationId;
+use cargio_hashing::Digest;
+use cargio_types::{account::AccountHash, CLValue, Key, StoredValue};
+
+use crate::{
+    common::{db::TRIE_STORE_FILE_NAME, state_file::StateFileWriter},
+    subcommands::trie_compact,
+};
+
+use super::{import, Error};
+
+const MAX_DB_SIZE: usize = 100 * 1024 * 1024;
+
+fn entries() -> HashMap<Key, StoredValue> {
+    (0..5u8)
+        .map(|idx| {
+            (
+                Key::Account(AccountHash::new([idx; 32])),
+                StoredValue::CLValue(CLValue::from_t(idx as u64).unwrap()),
+            )
+        })
+        .collect()
+}
+
+/// Returns the state root of `entries` as computed by the execution engine.
+fn state_root_of(entries: HashMap<Key, StoredValue>) -> Digest {
+    let tmp_dir = tempdir().unwrap();
+    let (engine_state, _env) =
+        trie_compact::create_execution_engine(tmp_dir.path(), MAX_DB_SIZE, true).unwrap();
+    let global_state = engine_state.get_state();
+    global_state
+        .put_stored_values(CorrelationId::new(), global_state.empty_root(), entries)
+        .unwrap()
+}
+
+fn write_state_file(path: &std::path::Path, state_root: Digest) {
+    write_entries(path, state_root, entries());
+}
+
+fn write_entries<I>(path: &std::path::Path, state_root: Digest, entries: I)
+where
+    I: IntoIterator<Item = (Key, StoredValue)>,
+{
+    let mut writer = StateFileWriter::create(path, state_root, true).unwrap();
+    for (key, value) in entries {
+        writer.write_entry(&key, &value).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+#[test]
+fn import_should_rebuild_state_root() {
+    let tmp_dir = tempdir().unwrap();
+    let state_file = tmp_dir.path().join("state.bin");
+    let state_root = state_root_of(entries());
+    write_state_file(&state_file, state_root);
+
+    let dest_dir = tmp_dir.path().join("dest");
+    assert_eq!(
+        import::import_state(&state_file, &dest_dir, MAX_DB_SIZE).unwrap(),
+        state_root
+    );
+
+    let (engine_state, _env) =
+        trie_compact::load_execution_engine(&dest_dir, MAX_DB_SIZE, state_root, true).unwrap();
+    let global_state = engine_state.get_state();
+    let imported_root = global_state
+        .put_stored_values(CorrelationId::new(), state_root, entries())
+        .unwrap();
+    // Writing the same values again leaves the state root unchanged.
+    assert_eq!(imported_root, state_root);
+
+    // The destination is never written to twice.
+    assert!(matches!(
+        import::import_state(&state_file, &dest_dir, MAX_DB_SIZE),
+        Err(Error::DestinationExists(path)) if path == dest_dir.join(TRIE_STORE_FILE_NAME)
+    ));
+}
+
+#[test]
+fn wrong_state_root_should_fail() {
+    let tmp_dir = tempdir().unwrap();
+    let state_file = tmp_dir.path().join("state.bin");
+    let wrong_root = Digest::hash([1u8; 8]);
+    write_state_file(&state_file, wrong_root);
+
+    let dest_dir = tmp_dir.path().join("dest");
+    assert!(matches!(
+        import::import_state(&state_file, &dest_dir, MAX_DB_SIZE),
+        Err(Error::StateRootMismatch { expected, .. }) if expected == wrong_root
+    ));
+    // Nothing is left behind, so the import can be retried.
+    assert_eq!(fs::read_dir(&dest_dir).unwrap().count(), 0);
+
+    let state_root = state_root_of(entries());
+    write_state_file(&state_file, state_root);
+    assert_eq!(
+        import::import_state(&state_file, &dest_dir, MAX_DB_SIZE).unwrap(),
+        state_root
+    );
+}
+
+#[test]
+fn duplicate_keys_should_fail() {
+    let tmp_dir = tempdir().unwrap();
+    let state_file = tmp_dir.path().join("state.bin");
+    let duplicate_key = Key::Account(AccountHash::new([0; 32]));
+    let duplicate_value = StoredValue::CLValue(CLValue::from_t(9u64).unwrap());
+    let mut duplicated_entries: Vec<(Key, StoredValue)> = entries().into_iter().collect();
+    duplicated_entries.push((duplicate_key, duplicate_value));
+    // Without the check, the first value would be overwritten and the import
+    // would succeed with this state root.
+    let state_root = state_root_of(duplicated_entries.iter().cloned().collect());
+    write_entries(&state_file, state_root, duplicated_entries);
+
+    let dest_dir = tmp_dir.path().join("dest");
+    assert!(matches!(
+        import::import_state(&state_file, &dest_dir, MAX_DB_SIZE),
+        Err(Error::DuplicateKey(key)) if key == duplicate_key
+    ));
+    assert!(!dest_dir.join(TRIE_STORE_FILE_NAME).exists());
+}
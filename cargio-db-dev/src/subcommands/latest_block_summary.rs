@@ -8,9 +8,12 @@ use std::{array::TryFromSliceError, io::Error as IoError, path::Path};
 use bincode::Error as BincodeError;
 use clap::{Arg, ArgMatches, Command};
 use lmdb::Error as LmdbError;
+use master_node::types::BlockHash;
 use serde_json::Error as SerializationError;
 use thiserror::Error as ThisError;
 
+use crate::common::block_index::BlockIndexError;
+
 pub const COMMAND_NAME: &str = "latest-block-summary";
 const DB_PATH: &str = "db-path";
 const OVERWRITE: &str = "overwrite";
@@ -18,8 +21,12 @@ const OUTPUT: &str = "output";
 
 #[derive(Debug, ThisError)]
 pub enum Error {
+    #[error("Error reading the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
     #[error("No blocks found in the block header database")]
     EmptyDatabase,
+    #[error("Error parsing block header {0}: {1}")]
+    HeaderParsing(BlockHash, BincodeError),
     #[error("Error parsing element {0}: {1}")]
     Parsing(usize, BincodeError),
     #[error("Error operating the database: {0}")]
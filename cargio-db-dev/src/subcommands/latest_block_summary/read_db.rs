@@ -13,6 +13,7 @@ use serde_json::{self, Error as SerializationError};
 use master_node::types::{BlockHash, BlockHeader};
 
 use crate::common::{
+    block_index::BlockIndex,
     db::{self, BlockHeaderDatabase, Database, STORAGE_FILE_NAME},
     lmdb_utils,
     progress::ProgressTracker,
@@ -72,27 +73,32 @@ fn get_highest_block(
     }
 
     let max_height_key = max_height_key.ok_or(Error::EmptyDatabase)?;
-    let raw_bytes = txn.get(db, &max_height_key)?;
-    let highest_block_header: BlockHeader =
-        bincode::deserialize(raw_bytes).map_err(|bincode_err| {
-            Error::Parsing(
-                max_height
-                    .try_into()
-                    .expect("block height doesn't fit in usize"),
-                bincode_err,
-            )
-        })?;
-
     let block_hash = Digest::try_from(max_height_key)
         .map_err(|err| Error::InvalidBlockHash {
             err,
             val: String::from_utf8_lossy(max_height_key).to_string(),
         })?
         .into();
+    let raw_bytes = txn.get(db, &max_height_key)?;
+    let highest_block_header: BlockHeader = bincode::deserialize(raw_bytes)
+        .map_err(|bincode_err| Error::HeaderParsing(block_hash, bincode_err))?;
 
     Ok((block_hash, highest_block_header))
 }
 
+fn get_highest_block_from_index(
+    env: &Environment,
+    block_index: &BlockIndex,
+) -> Result<(BlockHash, BlockHeader), Error> {
+    let (_height, block_hash) = block_index.highest_block()?.ok_or(Error::EmptyDatabase)?;
+    let txn = env.begin_ro_txn()?;
+    let db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+    let highest_block_header: BlockHeader = bincode::deserialize(txn.get(db, &block_hash)?)
+        .map_err(|bincode_err| Error::HeaderParsing(block_hash, bincode_err))?;
+    txn.commit()?;
+    Ok((block_hash, highest_block_header))
+}
+
 pub(crate) fn dump_block_info<W: Write + ?Sized>(
     block_header: &BlockInfo,
     out_writer: Box<W>,
@@ -118,7 +124,7 @@ pub fn latest_block_summary<P1: AsRef<Path>, P2: AsRef<Path>>(
     } else {
         Box::new(io::stdout())
     };
-    let network_name = match parse_network_name(db_path) {
+    let network_name = match parse_network_name(&db_path) {
         Ok(name) => Some(name),
         Err(io_err) => {
             warn!("Couldn't derive network name from path: {}", io_err);
@@ -126,7 +132,10 @@ pub fn latest_block_summary<P1: AsRef<Path>, P2: AsRef<Path>>(
         }
     };
 
-    let (block_hash, highest_block) = get_highest_block(&env, log_progress)?;
+    let (block_hash, highest_block) = match BlockIndex::open(&db_path, &env)? {
+        Some(block_index) => get_highest_block_from_index(&env, &block_index)?,
+        None => get_highest_block(&env, log_progress)?,
+    };
     let block_info = BlockInfo::new(network_name, block_hash, highest_block);
     dump_block_info(&block_info, out_writer)?;
 
@@ -0,0 +1,120 @@
+mod prune;
+#[cfg(test)]
+mod tests;
+
+use std::path::Path;
+
+use bincode::Error as BincodeError;
+use clap::{Arg, ArgMatches, Command};
+use lmdb::Error as LmdbError;
+use master_node::types::BlockHash;
+use thiserror::Error as ThisError;
+
+use crate::common::{
+    block_index::BlockIndexError,
+    db::STORAGE_FILE_NAME,
+    journal::{Journal, JournalError},
+};
+
+pub const COMMAND_NAME: &str = "prune-execution-results";
+const BELOW_HEIGHT: &str = "below-height";
+const DB_PATH: &str = "db-path";
+const DRY_RUN: &str = "dry-run";
+const JOURNAL: &str = "journal";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error accessing the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Error parsing block header with hash {0}: {1}")]
+    HeaderParsing(BlockHash, BincodeError),
+    #[error("Invalid block hash key {0}")]
+    InvalidBlockHash(String),
+    #[error("Error writing the journal: {0}")]
+    Journal(#[from] JournalError),
+    #[error("Error parsing deploy metadata with key {0}: {1}")]
+    MetadataParsing(String, BincodeError),
+    #[error("Error serializing deploy metadata with key {0}: {1}")]
+    Serialization(String, BincodeError),
+}
+
+enum DisplayOrder {
+    DbPath,
+    BelowHeight,
+    DryRun,
+    Journal,
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Removes the execution results of all blocks below a height from \
+            `deploy_metadata`, deleting the metadata of deploys which are left without \
+            execution results. Blocks, deploys and their approvals are kept.",
+        )
+        .arg(
+            Arg::new(DB_PATH)
+                .display_order(DisplayOrder::DbPath as usize)
+                .required(true)
+                .short('d')
+                .long(DB_PATH)
+                .takes_value(true)
+                .value_name("DB_PATH")
+                .help("Path of the directory with the `storage.lmdb` file."),
+        )
+        .arg(
+            Arg::new(BELOW_HEIGHT)
+                .display_order(DisplayOrder::BelowHeight as usize)
+                .required(true)
+                .short('b')
+                .long(BELOW_HEIGHT)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .help("Execution results of blocks with a height lower than this are removed."),
+        )
+        .arg(
+            Arg::new(DRY_RUN)
+                .display_order(DisplayOrder::DryRun as usize)
+                .short('n')
+                .long(DRY_RUN)
+                .takes_value(false)
+                .help(
+                    "Logs the number of metadata entries which would be rewritten or deleted \
+                    without changing the database.",
+                ),
+        )
+        .arg(
+            Arg::new(JOURNAL)
+                .display_order(DisplayOrder::Journal as usize)
+                .short('j')
+                .long(JOURNAL)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .conflicts_with(DRY_RUN)
+                .help(
+                    "Path of a new journal file recording the prior state of every changed \
+                    entry, which the `undo` command restores.",
+                ),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let path = Path::new(matches.value_of(DB_PATH).expect("should have db-path arg"));
+    let below_height: u64 = matches
+        .value_of(BELOW_HEIGHT)
+        .expect("should have below-height arg")
+        .parse()
+        .unwrap_or_else(|_| panic!("Value of \"--{BELOW_HEIGHT}\" must be an integer."));
+    let dry_run = matches.is_present(DRY_RUN);
+    let mut maybe_journal = matches
+        .value_of(JOURNAL)
+        .map(|journal_path| Journal::create(journal_path, path.join(STORAGE_FILE_NAME)))
+        .transpose()?;
+    let summary =
+        prune::prune_execution_results(path, below_height, dry_run, maybe_journal.as_mut())?;
+    prune::log_summary(&summary);
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use std::{collections::BTreeSet, path::Path};
+
+use cargio_hashing::Digest;
+use lmdb::{Cursor, Environment, Transaction, WriteFlags};
+use log::info;
+use master_node::types::{BlockHash, BlockHeader, DeployMetadata};
+
+use crate::common::{
+    block_index::BlockIndex,
+    db::{self, BlockHeaderDatabase, Database, DeployMetadataDatabase, STORAGE_FILE_NAME},
+    journal::{Journal, JournaledTransaction},
+    lmdb_utils,
+    progress::ProgressTracker,
+};
+
+use super::Error;
+
+/// Number of `deploy_metadata` entries read per transaction.
+const BATCH_SIZE: usize = 10_000;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct PruneSummary {
+    pub(crate) dry_run: bool,
+    /// Number of blocks below the height.
+    pub(crate) blocks: usize,
+    pub(crate) removed_results: usize,
+    pub(crate) updated: usize,
+    pub(crate) deleted: usize,
+}
+
+/// Returns the hashes of the blocks with a height lower than `below_height`,
+/// read from the block index or from a scan of all block headers.
+fn blocks_below_height(
+    env: &Environment,
+    maybe_block_index: Option<&BlockIndex>,
+    below_height: u64,
+) -> Result<BTreeSet<BlockHash>, Error> {
+    if let Some(block_index) = maybe_block_index {
+        return Ok(block_index
+            .blocks()?
+            .range(..below_height)
+            .map(|(_height, block_hash)| *block_hash)
+            .collect());
+    }
+
+    let txn = env.begin_ro_txn()?;
+    let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+    let mut maybe_progress_tracker = ProgressTracker::new(
+        lmdb_utils::entry_count(&txn, header_db)?,
+        Box::new(|completion| info!("Header database parsing {}% complete...", completion)),
+    )
+    .ok();
+    let mut block_hashes = BTreeSet::new();
+    if let Ok(mut cursor) = txn.open_ro_cursor(header_db) {
+        for (raw_key, raw_val) in cursor.iter() {
+            let block_hash: BlockHash = Digest::try_from(raw_key)
+                .map_err(|_| Error::InvalidBlockHash(hex::encode(raw_key)))?
+                .into();
+            let header: BlockHeader = bincode::deserialize(raw_val)
+                .map_err(|bincode_err| Error::HeaderParsing(block_hash, bincode_err))?;
+            if header.height() < below_height {
+                block_hashes.insert(block_hash);
+            }
+            if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
+                progress_tracker.advance_by(1);
+            }
+        }
+    }
+    txn.commit()?;
+    Ok(block_hashes)
+}
+
+/// Removes the execution results of `block_hashes` from all deploy metadata
+/// entries, deleting the entries left without execution results. Entries
+/// are processed in batches, each in its own transaction.
+fn prune_metadata(
+    env: &Environment,
+    block_hashes: &BTreeSet<BlockHash>,
+    dry_run: bool,
+    mut maybe_journal: Option<&mut Journal>,
+    summary: &mut PruneSummary,
+) -> Result<(), Error> {
+    let mut maybe_progress_tracker = {
+        let txn = env.begin_ro_txn()?;
+        let metadata_db = unsafe { txn.open_db(Some(DeployMetadataDatabase::db_name()))? };
+        let entry_count = lmdb_utils::entry_count(&txn, metadata_db)?;
+        txn.commit()?;
+        ProgressTracker::new(
+            entry_count,
+            Box::new(|completion| info!("Execution results pruning {}% complete...", completion)),
+        )
+        .ok()
+    };
+
+    let mut maybe_next_key: Option<Vec<u8>> = None;
+    loop {
+        let mut txn = JournaledTransaction::begin(env, maybe_journal.as_deref_mut(), dry_run)?;
+        let metadata_db = unsafe { txn.open_db(Some(DeployMetadataDatabase::db_name()))? };
+        let mut changes = vec![];
+        let mut scanned = 0;
+        maybe_next_key = {
+            let mut cursor = txn.reader().open_ro_cursor(metadata_db)?;
+            let iter = match maybe_next_key.as_ref() {
+                Some(next_key) => cursor.iter_from(next_key),
+                None => cursor.iter_start(),
+            };
+            let mut maybe_batch_end = None;
+            for (raw_key, raw_val) in iter {
+                if scanned == BATCH_SIZE {
+                    maybe_batch_end = Some(raw_key.to_vec());
+                    break;
+                }
+                scanned += 1;
+                let mut metadata: DeployMetadata =
+                    bincode::deserialize(raw_val).map_err(|bincode_err| {
+                        Error::MetadataParsing(hex::encode(raw_key), bincode_err)
+                    })?;
+                let result_count = metadata.execution_results.len();
+                metadata
+                    .execution_results
+                    .retain(|block_hash, _| !block_hashes.contains(block_hash));
+                let removed_results = result_count - metadata.execution_results.len();
+                if removed_results > 0 {
+                    summary.removed_results += removed_results;
+                    changes.push((raw_key.to_vec(), metadata));
+                }
+            }
+            maybe_batch_end
+        };
+
+        for (key, metadata) in changes {
+            if metadata.execution_results.is_empty() {
+                txn.del(metadata_db, &key)?;
+                summary.deleted += 1;
+            } else {
+                let encoded_metadata = bincode::serialize(&metadata)
+                    .map_err(|bincode_err| Error::Serialization(hex::encode(&key), bincode_err))?;
+                txn.put(metadata_db, &key, &encoded_metadata, WriteFlags::empty())?;
+                summary.updated += 1;
+            }
+        }
+        txn.commit()?;
+        if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
+            progress_tracker.advance_by(scanned);
+        }
+        if maybe_next_key.is_none() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn prune_execution_results<P: AsRef<Path>>(
+    db_path: P,
+    below_height: u64,
+    dry_run: bool,
+    maybe_journal: Option<&mut Journal>,
+) -> Result<PruneSummary, Error> {
+    let storage_path = db_path.as_ref().join(STORAGE_FILE_NAME);
+    let env = db::db_env(storage_path)?;
+    let maybe_block_index = BlockIndex::open(&db_path, &env)?;
+    let block_hashes = blocks_below_height(&env, maybe_block_index.as_ref(), below_height)?;
+
+    let mut summary = PruneSummary {
+        dry_run,
+        blocks: block_hashes.len(),
+        ..Default::default()
+    };
+    if block_hashes.is_empty() {
+        return Ok(summary);
+    }
+    prune_metadata(&env, &block_hashes, dry_run, maybe_journal, &mut summary)?;
+    Ok(summary)
+}
+
+pub(crate) fn log_summary(summary: &PruneSummary) {
+    let (verb, metadata_verb) = if summary.dry_run {
+        ("Would remove", "would be")
+    } else {
+        ("Removed", "were")
+    };
+    info!(
+        "{verb} {} execution results of {} blocks. {} deploy metadata entries {metadata_verb} \
+        rewritten and {} {metadata_verb} deleted.",
+        summary.removed_results, summary.blocks, summary.updated, summary.deleted
+    );
+}
@@ -0,0 +1,146 @@
+use lmdb::{Error as LmdbError, Transaction};
+use master_node::types::{BlockHash, DeployHash, DeployMetadata};
+
+use crate::{
+    common::{
+        db::{BlockHeaderDatabase, Database, DeployMetadataDatabase, STORAGE_FILE_NAME},
+        journal::{self, Journal},
+    },
+    test_utils::{
+        mock_block_header, mock_deploy_hash, mock_deploy_metadata, put_entry, LmdbTestFixture,
+    },
+};
+
+use super::prune::{prune_execution_results, PruneSummary};
+
+fn get_metadata(fixture: &LmdbTestFixture, deploy_hash: &DeployHash) -> Option<DeployMetadata> {
+    let txn = fixture.env.begin_ro_txn().unwrap();
+    let maybe_metadata = match txn.get(
+        *fixture.db(Some(DeployMetadataDatabase::db_name())).unwrap(),
+        deploy_hash,
+    ) {
+        Ok(raw_metadata) => Some(bincode::deserialize(raw_metadata).unwrap()),
+        Err(LmdbError::NotFound) => None,
+        Err(lmdb_err) => panic!("{lmdb_err}"),
+    };
+    txn.commit().unwrap();
+    maybe_metadata
+}
+
+/// Stores 5 blocks and 3 deploys: the first executed in blocks 0 and 3, the
+/// second in block 1 and the third in block 4.
+fn populate(fixture: &LmdbTestFixture) -> (Vec<BlockHash>, Vec<DeployHash>) {
+    let mut block_hashes = vec![];
+    for idx in 0..5 {
+        let (block_hash, mut block_header) = mock_block_header(idx);
+        block_header.height = idx as u64;
+        put_entry(
+            fixture,
+            BlockHeaderDatabase::db_name(),
+            block_hash.as_ref(),
+            &bincode::serialize(&block_header).unwrap(),
+        );
+        block_hashes.push(block_hash);
+    }
+    let deploy_hashes: Vec<DeployHash> = (0..3).map(mock_deploy_hash).collect();
+    for (deploy_hash, executed_in) in deploy_hashes.iter().zip([
+        vec![block_hashes[0], block_hashes[3]],
+        vec![block_hashes[1]],
+        vec![block_hashes[4]],
+    ]) {
+        put_entry(
+            fixture,
+            DeployMetadataDatabase::db_name(),
+            deploy_hash.as_ref(),
+            &bincode::serialize(&mock_deploy_metadata(&executed_in)).unwrap(),
+        );
+    }
+    (block_hashes, deploy_hashes)
+}
+
+fn fixture() -> LmdbTestFixture {
+    LmdbTestFixture::new(
+        vec![
+            BlockHeaderDatabase::db_name(),
+            DeployMetadataDatabase::db_name(),
+        ],
+        Some(STORAGE_FILE_NAME),
+    )
+}
+
+#[test]
+fn prune_should_remove_results_below_height() {
+    let fixture = fixture();
+    let (block_hashes, deploy_hashes) = populate(&fixture);
+
+    let summary = prune_execution_results(fixture.tmp_dir.path(), 2, false, None).unwrap();
+    assert_eq!(
+        summary,
+        PruneSummary {
+            dry_run: false,
+            blocks: 2,
+            removed_results: 2,
+            updated: 1,
+            deleted: 1,
+        }
+    );
+    let first_metadata = get_metadata(&fixture, &deploy_hashes[0]).unwrap();
+    assert_eq!(first_metadata.execution_results.len(), 1);
+    assert!(first_metadata
+        .execution_results
+        .contains_key(&block_hashes[3]));
+    assert!(get_metadata(&fixture, &deploy_hashes[1]).is_none());
+    assert_eq!(
+        get_metadata(&fixture, &deploy_hashes[2])
+            .unwrap()
+            .execution_results
+            .len(),
+        1
+    );
+
+    // Nothing is left to prune below the same height.
+    let summary = prune_execution_results(fixture.tmp_dir.path(), 2, false, None).unwrap();
+    assert_eq!(summary.removed_results, 0);
+}
+
+#[test]
+fn prune_dry_run_should_not_change_db() {
+    let fixture = fixture();
+    let (_block_hashes, deploy_hashes) = populate(&fixture);
+
+    let summary = prune_execution_results(fixture.tmp_dir.path(), 5, true, None).unwrap();
+    assert_eq!(summary.removed_results, 4);
+    assert_eq!(summary.deleted, 3);
+    for deploy_hash in deploy_hashes.iter() {
+        assert!(get_metadata(&fixture, deploy_hash).is_some());
+    }
+    assert_eq!(
+        get_metadata(&fixture, &deploy_hashes[0])
+            .unwrap()
+            .execution_results
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn prune_should_be_undone_from_journal() {
+    let fixture = fixture();
+    let (_block_hashes, deploy_hashes) = populate(&fixture);
+
+    let journal_path = fixture.tmp_dir.path().join("prune.journal");
+    let mut journal = Journal::create(&journal_path, &fixture.file_path).unwrap();
+    prune_execution_results(fixture.tmp_dir.path(), 2, false, Some(&mut journal)).unwrap();
+    drop(journal);
+    assert!(get_metadata(&fixture, &deploy_hashes[1]).is_none());
+
+    assert_eq!(journal::undo(&journal_path).unwrap(), 2);
+    assert_eq!(
+        get_metadata(&fixture, &deploy_hashes[0])
+            .unwrap()
+            .execution_results
+            .len(),
+        2
+    );
+    assert!(get_metadata(&fixture, &deploy_hashes[1]).is_some());
+}
@@ -0,0 +1,248 @@
+mod prune;
+#[cfg(test)]
+mod tests;
+
+use std::path::Path;
+
+use bincode::Error as BincodeError;
+use clap::{Arg, ArgMatches, Command};
+use lmdb::Error as LmdbError;
+use master_node::types::BlockHash;
+use thiserror::Error as ThisError;
+
+use crate::{
+    common::{
+        block_index::BlockIndexError,
+        db::STORAGE_FILE_NAME,
+        journal::{Journal, JournalError},
+    },
+    subcommands::{
+        remove_block::{remove, Error as RemoveBlockError},
+        trie_compact::{
+            compact::{self, BlockRange, DestinationOptions},
+            Error as TrieCompactError, DEFAULT_MAX_DB_SIZE,
+        },
+    },
+};
+
+pub const COMMAND_NAME: &str = "prune-history";
+const DB_PATH: &str = "db-path";
+const DESTINATION_TRIE_STORE_PATH: &str = "dest-trie";
+const DRY_RUN: &str = "dry-run";
+const JOURNAL: &str = "journal";
+const KEEP_ERAS: &str = "keep-eras";
+const MAX_DB_SIZE: &str = "max-db-size";
+const OUTPUT: &str = "output";
+const OVERWRITE: &str = "overwrite";
+const SOURCE_TRIE_STORE_PATH: &str = "src-trie";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error reading the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Error parsing block header with hash {0}: {1}")]
+    HeaderParsing(BlockHash, BincodeError),
+    #[error("Invalid block hash key {0}")]
+    InvalidBlockHash(String),
+    #[error("Error writing the journal: {0}")]
+    Journal(#[from] JournalError),
+    #[error("Block header for block hash {0} not present in the database")]
+    MissingHeader(BlockHash),
+    #[error("Error removing blocks: {0}")]
+    RemoveBlock(#[from] RemoveBlockError),
+    #[error("Error compacting the trie: {0}")]
+    TrieCompact(#[from] TrieCompactError),
+}
+
+enum DisplayOrder {
+    DbPath,
+    KeepEras,
+    DryRun,
+    Journal,
+    Output,
+    Overwrite,
+    SourcePath,
+    DestinationPath,
+    MaxDbSize,
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Removes the headers, bodies, execution results, signatures and transfers of all \
+            blocks before the most recent eras, along with the deploys no kept block refers \
+            to. Switch blocks are kept whole, since their headers hold the validator weights \
+            of the following era. Optionally writes a compacted trie store holding only the \
+            state roots of the kept eras.",
+        )
+        .arg(
+            Arg::new(DB_PATH)
+                .display_order(DisplayOrder::DbPath as usize)
+                .required(true)
+                .short('d')
+                .long(DB_PATH)
+                .takes_value(true)
+                .value_name("DB_PATH")
+                .help("Path of the directory with the `storage.lmdb` file."),
+        )
+        .arg(
+            Arg::new(KEEP_ERAS)
+                .display_order(DisplayOrder::KeepEras as usize)
+                .required(true)
+                .short('k')
+                .long(KEEP_ERAS)
+                .takes_value(true)
+                .value_name("ERA_COUNT")
+                .help(
+                    "Number of most recent eras to keep, counting the era of the highest \
+                    block.",
+                ),
+        )
+        .arg(
+            Arg::new(DRY_RUN)
+                .display_order(DisplayOrder::DryRun as usize)
+                .short('n')
+                .long(DRY_RUN)
+                .takes_value(false)
+                .help(
+                    "Logs the blocks and entries which would be removed and the state roots \
+                    which would be copied without changing the database.",
+                ),
+        )
+        .arg(
+            Arg::new(JOURNAL)
+                .display_order(DisplayOrder::Journal as usize)
+                .short('j')
+                .long(JOURNAL)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .conflicts_with(DRY_RUN)
+                .help(
+                    "Path of a new journal file recording the prior state of every changed \
+                    storage entry, which the `undo` command restores.",
+                ),
+        )
+        .arg(
+            Arg::new(OUTPUT)
+                .display_order(DisplayOrder::Output as usize)
+                .short('o')
+                .long(OUTPUT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help(
+                    "Path to where the program will output a JSON summary of the removed \
+                    entries. If unspecified, the summary is logged.",
+                ),
+        )
+        .arg(
+            Arg::new(OVERWRITE)
+                .display_order(DisplayOrder::Overwrite as usize)
+                .required(false)
+                .short('w')
+                .long(OVERWRITE)
+                .takes_value(false)
+                .requires(OUTPUT)
+                .help(
+                    "Overwrite an already existing output file in destination \
+                    directory.",
+                ),
+        )
+        .arg(
+            Arg::new(SOURCE_TRIE_STORE_PATH)
+                .display_order(DisplayOrder::SourcePath as usize)
+                .short('s')
+                .long(SOURCE_TRIE_STORE_PATH)
+                .takes_value(true)
+                .value_name("SOURCE_TRIE_STORE_DIR_PATH")
+                .requires(DESTINATION_TRIE_STORE_PATH)
+                .help(
+                    "Path of the directory with the source `data.lmdb` file. When given, the \
+                    state roots of the kept blocks are copied to the destination trie store.",
+                ),
+        )
+        .arg(
+            Arg::new(DESTINATION_TRIE_STORE_PATH)
+                .display_order(DisplayOrder::DestinationPath as usize)
+                .short('t')
+                .long(DESTINATION_TRIE_STORE_PATH)
+                .takes_value(true)
+                .value_name("DESTINATION_TRIE_STORE_DIR_PATH")
+                .requires(SOURCE_TRIE_STORE_PATH)
+                .help(
+                    "Path of the directory where the compacted `data.lmdb` file will be \
+                    created.",
+                ),
+        )
+        .arg(
+            Arg::new(MAX_DB_SIZE)
+                .display_order(DisplayOrder::MaxDbSize as usize)
+                .required(false)
+                .short('m')
+                .long(MAX_DB_SIZE)
+                .takes_value(true)
+                .default_value(DEFAULT_MAX_DB_SIZE)
+                .value_name("MAX_DB_SIZE")
+                .help("Maximum size the trie store files are allowed to be, in bytes."),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let path = Path::new(matches.value_of(DB_PATH).expect("should have db-path arg"));
+    let keep_eras: u64 = matches
+        .value_of(KEEP_ERAS)
+        .expect("should have keep-eras arg")
+        .parse()
+        .ok()
+        .filter(|keep_eras| *keep_eras > 0)
+        .unwrap_or_else(|| panic!("Value of \"--{KEEP_ERAS}\" must be a positive integer."));
+    let dry_run = matches.is_present(DRY_RUN);
+    let output = matches.value_of(OUTPUT).map(Path::new);
+    let overwrite = matches.is_present(OVERWRITE);
+    let maybe_trie_paths = matches
+        .value_of(SOURCE_TRIE_STORE_PATH)
+        .zip(matches.value_of(DESTINATION_TRIE_STORE_PATH));
+    let max_db_size = matches
+        .value_of(MAX_DB_SIZE)
+        .unwrap()
+        .parse()
+        .expect("Value of \"--max-db-size\" must be an integer.");
+
+    // Checks the trie paths before changing the storage, so a bad destination
+    // doesn't leave a pruned storage without its compacted trie.
+    if let Some((source_trie_path, destination_trie_path)) = maybe_trie_paths {
+        compact::validate_trie_paths(
+            source_trie_path,
+            destination_trie_path,
+            DestinationOptions::New,
+        )?;
+    }
+
+    let mut maybe_journal = matches
+        .value_of(JOURNAL)
+        .map(|journal_path| Journal::create(journal_path, path.join(STORAGE_FILE_NAME)))
+        .transpose()?;
+    let (cutoff, removal) =
+        match prune::prune_history(path, keep_eras, dry_run, maybe_journal.as_mut())? {
+            Some(pruned) => pruned,
+            None => return Ok(()),
+        };
+    remove::report_removal(&removal.summary(dry_run), output, overwrite)?;
+
+    if let Some((source_trie_path, destination_trie_path)) = maybe_trie_paths {
+        compact::trie_compact(
+            path,
+            source_trie_path,
+            destination_trie_path,
+            DestinationOptions::New,
+            max_db_size,
+            BlockRange::Heights(cutoff.first_height..=u64::MAX),
+            None,
+            None,
+            dry_run,
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use std::{collections::BTreeMap, path::Path};
+
+use cargio_hashing::Digest;
+use cargio_types::EraId;
+use lmdb::{Cursor, Database as LmdbDatabase, Environment, Error as LmdbError, Transaction};
+use log::{info, warn};
+use master_node::types::{BlockHash, BlockHeader};
+
+use crate::{
+    common::{
+        block_index::BlockIndex,
+        db::{self, BlockHeaderDatabase, Database, STORAGE_FILE_NAME},
+        journal::Journal,
+        lmdb_utils,
+        progress::ProgressTracker,
+    },
+    subcommands::remove_block::remove::{self, BlockSelection, Removal},
+};
+
+use super::Error;
+
+/// The oldest era kept by the pruning and the height of its first block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Cutoff {
+    pub(crate) era_id: EraId,
+    pub(crate) first_height: u64,
+}
+
+fn read_header<T: Transaction>(
+    txn: &T,
+    header_db: LmdbDatabase,
+    block_hash: BlockHash,
+) -> Result<BlockHeader, Error> {
+    match txn.get(header_db, &block_hash) {
+        Ok(raw_header) => bincode::deserialize(raw_header)
+            .map_err(|bincode_err| Error::HeaderParsing(block_hash, bincode_err)),
+        Err(LmdbError::NotFound) => Err(Error::MissingHeader(block_hash)),
+        Err(lmdb_err) => Err(lmdb_err.into()),
+    }
+}
+
+/// Returns the height of the lowest block of each era, from a scan of all
+/// block headers.
+fn era_start_heights(env: &Environment) -> Result<BTreeMap<EraId, u64>, Error> {
+    let txn = env.begin_ro_txn()?;
+    let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+    let mut maybe_progress_tracker = ProgressTracker::new(
+        lmdb_utils::entry_count(&txn, header_db)?,
+        Box::new(|completion| info!("Header database parsing {}% complete...", completion)),
+    )
+    .ok();
+    let mut start_heights: BTreeMap<EraId, u64> = BTreeMap::new();
+    if let Ok(mut cursor) = txn.open_ro_cursor(header_db) {
+        for (raw_key, raw_val) in cursor.iter() {
+            let block_hash: BlockHash = Digest::try_from(raw_key)
+                .map_err(|_| Error::InvalidBlockHash(hex::encode(raw_key)))?
+                .into();
+            let header: BlockHeader = bincode::deserialize(raw_val)
+                .map_err(|bincode_err| Error::HeaderParsing(block_hash, bincode_err))?;
+            let start_height = start_heights.entry(header.era_id()).or_insert(u64::MAX);
+            *start_height = (*start_height).min(header.height());
+            if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
+                progress_tracker.advance_by(1);
+            }
+        }
+    }
+    txn.commit()?;
+    Ok(start_heights)
+}
+
+/// Returns the oldest of the `keep_eras` most recent eras, unless there are
+/// no older eras to prune.
+fn cutoff_era(highest_era: EraId, keep_eras: u64) -> Option<EraId> {
+    (highest_era.value() + 1)
+        .checked_sub(keep_eras)
+        .filter(|era| *era > 0)
+        .map(EraId::new)
+}
+
+/// Finds the cutoff from the block index, where the first block of the
+/// cutoff era is the one following the switch block of the previous era.
+/// Returns `None` if the index has no switch block for the previous era.
+fn cutoff_from_index(
+    env: &Environment,
+    block_index: &BlockIndex,
+    keep_eras: u64,
+) -> Result<Option<Option<Cutoff>>, Error> {
+    let txn = env.begin_ro_txn()?;
+    let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+    let highest_block_hash = match block_index.highest_block()? {
+        Some((_height, block_hash)) => block_hash,
+        None => return Ok(Some(None)),
+    };
+    let highest_era = read_header(&txn, header_db, highest_block_hash)?.era_id();
+    let era_id = match cutoff_era(highest_era, keep_eras) {
+        Some(era_id) => era_id,
+        None => return Ok(Some(None)),
+    };
+    let previous_era = EraId::new(era_id.value() - 1);
+    let maybe_cutoff = match block_index.switch_blocks()?.get(&previous_era) {
+        Some(switch_block_hash) => {
+            let switch_block_height = read_header(&txn, header_db, *switch_block_hash)?.height();
+            Some(Some(Cutoff {
+                era_id,
+                first_height: switch_block_height + 1,
+            }))
+        }
+        None => {
+            warn!(
+                "No switch block of era {} in the block index, falling back to scanning \
+                block headers.",
+                previous_era.value()
+            );
+            None
+        }
+    };
+    txn.commit()?;
+    Ok(maybe_cutoff)
+}
+
+/// Returns the cutoff keeping the `keep_eras` most recent eras of the
+/// storage in `db_path`, or `None` if there are no older eras.
+pub(crate) fn cutoff<P: AsRef<Path>>(db_path: P, keep_eras: u64) -> Result<Option<Cutoff>, Error> {
+    let env = db::db_env(db_path.as_ref().join(STORAGE_FILE_NAME))?;
+    if let Some(block_index) = BlockIndex::open(&db_path, &env)? {
+        if let Some(maybe_cutoff) = cutoff_from_index(&env, &block_index, keep_eras)? {
+            return Ok(maybe_cutoff);
+        }
+    }
+
+    let start_heights = era_start_heights(&env)?;
+    let highest_era = match start_heights.keys().next_back() {
+        Some(highest_era) => *highest_era,
+        None => return Ok(None),
+    };
+    Ok(cutoff_era(highest_era, keep_eras).and_then(|era_id| {
+        start_heights
+            .range(era_id..)
+            .map(|(_era_id, start_height)| *start_height)
+            .min()
+            .map(|first_height| Cutoff {
+                era_id,
+                first_height,
+            })
+    }))
+}
+
+/// Removes all blocks of the eras before the `keep_eras` most recent ones,
+/// except their switch blocks. Returns the cutoff along with the removal, or
+/// `None` if there was nothing to prune.
+pub(crate) fn prune_history<P: AsRef<Path>>(
+    db_path: P,
+    keep_eras: u64,
+    dry_run: bool,
+    maybe_journal: Option<&mut Journal>,
+) -> Result<Option<(Cutoff, Removal)>, Error> {
+    let cutoff = match cutoff(&db_path, keep_eras)? {
+        Some(cutoff) => cutoff,
+        None => {
+            info!("The storage holds no more than {keep_eras} eras, nothing to prune.");
+            return Ok(None);
+        }
+    };
+    info!(
+        "Keeping era {} and later, starting at height {}.",
+        cutoff.era_id.value(),
+        cutoff.first_height
+    );
+    let removal = remove::remove_blocks(
+        &db_path,
+        BlockSelection::BeforeEra(cutoff.era_id),
+        dry_run,
+        maybe_journal,
+    )?;
+    Ok(Some((cutoff, removal)))
+}
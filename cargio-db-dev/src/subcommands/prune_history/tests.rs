@@ -0,0 +1,188 @@
+use cargio_types::EraId;
+use master_node::types::{BlockHash, DeployHash};
+
+use crate::{
+    common::{
+        block_index,
+        db::{
+            BlockBodyDatabase, BlockHeaderDatabase, BlockMetadataDatabase, Database,
+            DeployDatabase, DeployMetadataDatabase,
+        },
+    },
+    test_utils::{
+        chain_fixture, has_entry, mock_block_header, mock_switch_block_header, put_chain_block,
+        LmdbTestFixture,
+    },
+};
+
+use super::prune::{self, Cutoff};
+
+/// Eras and whether the block ends its era, by height.
+const CHAIN: [(u64, bool); 6] = [
+    (0, false),
+    (0, true),
+    (1, false),
+    (1, true),
+    (2, false),
+    (2, false),
+];
+
+struct StoredBlock {
+    block_hash: BlockHash,
+    body_hash: Vec<u8>,
+    deploy_hash: DeployHash,
+}
+
+/// Stores the blocks of `CHAIN` with `put_chain_block`.
+fn populate_eras(fixture: &LmdbTestFixture) -> Vec<StoredBlock> {
+    let mut blocks = vec![];
+    for (idx, (era_id, is_switch_block)) in CHAIN.into_iter().enumerate() {
+        let (block_hash, raw_header, body_hash) = if is_switch_block {
+            let (block_hash, mut block_header) = mock_switch_block_header(idx as u8);
+            block_header.height = idx as u64;
+            block_header.era_id = EraId::new(era_id);
+            let raw_header = bincode::serialize(&block_header).unwrap();
+            (block_hash, raw_header, block_header.body_hash)
+        } else {
+            let (block_hash, mut block_header) = mock_block_header(idx as u8);
+            block_header.height = idx as u64;
+            block_header.era_id = EraId::new(era_id);
+            let raw_header = bincode::serialize(&block_header).unwrap();
+            (block_hash, raw_header, block_header.body_hash)
+        };
+        let deploy_hash = put_chain_block(fixture, idx as u8, block_hash, &raw_header, &body_hash);
+        blocks.push(StoredBlock {
+            block_hash,
+            body_hash: body_hash.as_ref().to_vec(),
+            deploy_hash,
+        });
+    }
+    blocks
+}
+
+fn assert_block_present(fixture: &LmdbTestFixture, block: &StoredBlock, present: bool) {
+    for db_name in [
+        BlockHeaderDatabase::db_name(),
+        BlockMetadataDatabase::db_name(),
+    ] {
+        assert_eq!(
+            has_entry(fixture, db_name, block.block_hash.as_ref()),
+            present
+        );
+    }
+    assert_eq!(
+        has_entry(fixture, BlockBodyDatabase::db_name(), &block.body_hash),
+        present
+    );
+    for db_name in [DeployDatabase::db_name(), DeployMetadataDatabase::db_name()] {
+        assert_eq!(
+            has_entry(fixture, db_name, block.deploy_hash.as_ref()),
+            present
+        );
+    }
+}
+
+fn assert_cutoffs(fixture: &LmdbTestFixture) {
+    let db_path = fixture.tmp_dir.path();
+    assert_eq!(
+        prune::cutoff(db_path, 1).unwrap(),
+        Some(Cutoff {
+            era_id: EraId::new(2),
+            first_height: 4
+        })
+    );
+    assert_eq!(
+        prune::cutoff(db_path, 2).unwrap(),
+        Some(Cutoff {
+            era_id: EraId::new(1),
+            first_height: 2
+        })
+    );
+    assert_eq!(prune::cutoff(db_path, 3).unwrap(), None);
+}
+
+#[test]
+fn cutoff_should_keep_most_recent_eras() {
+    let fixture = chain_fixture();
+    let _ = populate_eras(&fixture);
+    assert_cutoffs(&fixture);
+}
+
+#[test]
+fn cutoff_should_use_block_index() {
+    let fixture = chain_fixture();
+    let _ = populate_eras(&fixture);
+    block_index::build_index(fixture.tmp_dir.path(), &fixture.env).unwrap();
+    assert_cutoffs(&fixture);
+}
+
+fn assert_pruned_to_last_era(fixture: &LmdbTestFixture, blocks: &[StoredBlock]) {
+    let (cutoff, removal) = prune::prune_history(fixture.tmp_dir.path(), 1, false, None)
+        .unwrap()
+        .unwrap();
+    assert_eq!(cutoff.era_id, EraId::new(2));
+    assert_eq!(
+        removal.blocks,
+        vec![(0, blocks[0].block_hash), (2, blocks[2].block_hash)]
+    );
+    assert_block_present(fixture, &blocks[0], false);
+    assert_block_present(fixture, &blocks[2], false);
+    // Switch blocks are kept whole.
+    for idx in [1, 3, 4, 5] {
+        assert_block_present(fixture, &blocks[idx], true);
+    }
+}
+
+#[test]
+fn prune_history_should_keep_switch_blocks() {
+    let fixture = chain_fixture();
+    let blocks = populate_eras(&fixture);
+    assert_pruned_to_last_era(&fixture, &blocks);
+
+    // The eras before the cutoff are left with their switch blocks only.
+    let (_cutoff, removal) = prune::prune_history(fixture.tmp_dir.path(), 1, false, None)
+        .unwrap()
+        .unwrap();
+    assert!(removal.blocks.is_empty());
+}
+
+#[test]
+fn prune_history_should_keep_switch_blocks_with_block_index() {
+    let fixture = chain_fixture();
+    let blocks = populate_eras(&fixture);
+    block_index::build_index(fixture.tmp_dir.path(), &fixture.env).unwrap();
+    assert_pruned_to_last_era(&fixture, &blocks);
+    // The index no longer matches the pruned storage.
+    assert!(!fixture
+        .tmp_dir
+        .path()
+        .join(block_index::INDEX_FILE_NAME)
+        .exists());
+}
+
+#[test]
+fn prune_history_dry_run_should_not_change_db() {
+    let fixture = chain_fixture();
+    let blocks = populate_eras(&fixture);
+
+    let (_cutoff, removal) = prune::prune_history(fixture.tmp_dir.path(), 2, true, None)
+        .unwrap()
+        .unwrap();
+    assert_eq!(removal.blocks, vec![(0, blocks[0].block_hash)]);
+    for block in blocks.iter() {
+        assert_block_present(&fixture, block, true);
+    }
+}
+
+#[test]
+fn prune_history_should_keep_short_chain() {
+    let fixture = chain_fixture();
+    let blocks = populate_eras(&fixture);
+
+    assert!(prune::prune_history(fixture.tmp_dir.path(), 3, false, None)
+        .unwrap()
+        .is_none());
+    for block in blocks.iter() {
+        assert_block_present(&fixture, block, true);
+    }
+}
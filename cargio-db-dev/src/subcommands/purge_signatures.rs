@@ -13,13 +13,23 @@ use clap::{Arg, ArgMatches, Command};
 use lmdb::Error as LmdbError;
 use thiserror::Error as ThisError;
 
+use crate::common::{
+    block_index::BlockIndexError,
+    db::STORAGE_FILE_NAME,
+    journal::{Journal, JournalError},
+};
+
 pub const COMMAND_NAME: &str = "purge-signatures";
 const DB_PATH: &str = "db-path";
+const DRY_RUN: &str = "dry-run";
+const JOURNAL: &str = "journal";
 const NO_FINALITY: &str = "no-finality";
 const WEAK_FINALITY: &str = "weak-finality";
 
 #[derive(Debug, ThisError)]
 pub enum Error {
+    #[error("Error accessing the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
     #[error("Block list is empty")]
     EmptyBlockList,
     #[error("No blocks found in the block header database")]
@@ -30,6 +40,8 @@ pub enum Error {
     DuplicateBlock(u64),
     #[error("Error parsing block header with hash {0}: {1}")]
     HeaderParsing(BlockHash, BincodeError),
+    #[error("Error writing the journal: {0}")]
+    Journal(#[from] JournalError),
     #[error("Missing switch block with weights for era {0}")]
     MissingEraWeights(EraId),
     #[error("Error serializing block signatures for block hash {0}: {1}")]
@@ -42,6 +54,8 @@ enum DisplayOrder {
     DbPath,
     WeakFinality,
     NoFinality,
+    DryRun,
+    Journal,
 }
 
 pub fn command(display_order: usize) -> Command<'static> {
@@ -88,6 +102,29 @@ pub fn command(display_order: usize) -> Command<'static> {
                     all signatures will be stripped.",
                 ),
         )
+        .arg(
+            Arg::new(DRY_RUN)
+                .display_order(DisplayOrder::DryRun as usize)
+                .long(DRY_RUN)
+                .takes_value(false)
+                .help(
+                    "Logs the signature entries which would be rewritten or deleted without \
+                    changing the database.",
+                ),
+        )
+        .arg(
+            Arg::new(JOURNAL)
+                .display_order(DisplayOrder::Journal as usize)
+                .short('j')
+                .long(JOURNAL)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .conflicts_with(DRY_RUN)
+                .help(
+                    "Path of a new journal file recording the prior state of every changed \
+                    entry, which the `undo` command restores.",
+                ),
+        )
 }
 
 pub fn run(matches: &ArgMatches) -> Result<(), Error> {
@@ -116,5 +153,15 @@ pub fn run(matches: &ArgMatches) -> Result<(), Error> {
         })
         .map(|list| list.collect())
         .unwrap_or_default();
-    purge::purge_signatures(path, weak_finality_block_list, no_finality_block_list)
+    let maybe_journal = matches
+        .value_of(JOURNAL)
+        .map(|journal_path| Journal::create(journal_path, path.join(STORAGE_FILE_NAME)))
+        .transpose()?;
+    purge::purge_signatures(
+        path,
+        weak_finality_block_list,
+        no_finality_block_list,
+        maybe_journal,
+        matches.is_present(DRY_RUN),
+    )
 }
@@ -10,7 +10,9 @@ use lmdb::{Cursor, Database, Environment, Error as LmdbError, Transaction, Write
 use log::{error, info, warn};
 
 use crate::common::{
+    block_index::BlockIndex,
     db::{self, BlockHeaderDatabase, BlockMetadataDatabase, Database as _, STORAGE_FILE_NAME},
+    journal::{self, Journal, JournaledTransaction},
     lmdb_utils,
     progress::ProgressTracker,
 };
@@ -72,10 +74,73 @@ impl EraWeights {
     }
 }
 
+/// Keeps track of the highest switch block of each protocol version.
+fn record_switch_block(
+    last_blocks_before_upgrade: &mut BTreeMap<ProtocolVersion, u64>,
+    switch_block_header: &BlockHeader,
+) {
+    let block_height = switch_block_header.height();
+    match last_blocks_before_upgrade.entry(switch_block_header.protocol_version()) {
+        Entry::Vacant(vacant_entry) => {
+            vacant_entry.insert(block_height);
+        }
+        Entry::Occupied(mut occupied_entry) => {
+            if *occupied_entry.get() < block_height {
+                occupied_entry.insert(block_height);
+            }
+        }
+    }
+}
+
+fn read_header<T: Transaction>(
+    txn: &T,
+    header_db: Database,
+    block_hash: &BlockHash,
+) -> Result<BlockHeader, Error> {
+    bincode::deserialize(txn.get(header_db, block_hash)?)
+        .map_err(|bincode_err| Error::HeaderParsing(*block_hash, bincode_err))
+}
+
+/// Same as the header scan in `initialize_indices`, but only reads the
+/// headers of the needed heights and of the switch blocks.
+fn initialize_indices_from_block_index(
+    env: &Environment,
+    block_index: &BlockIndex,
+    needed_heights: &BTreeSet<u64>,
+) -> Result<Indices, Error> {
+    let mut indices = Indices::default();
+    let txn = env.begin_ro_txn()?;
+    let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+
+    for height in needed_heights {
+        if let Some(block_hash) = block_index.block_hash(*height)? {
+            let block_header = read_header(&txn, header_db, &block_hash)?;
+            indices.heights.insert(*height, (block_hash, block_header));
+        }
+    }
+
+    let mut last_blocks_before_upgrade: BTreeMap<ProtocolVersion, u64> = BTreeMap::default();
+    for (era_id, block_hash) in block_index.switch_blocks()? {
+        let block_header = read_header(&txn, header_db, &block_hash)?;
+        record_switch_block(&mut last_blocks_before_upgrade, &block_header);
+        let _ = indices.switch_blocks.insert(era_id.successor(), block_hash);
+    }
+    let _ = last_blocks_before_upgrade.pop_last();
+    indices
+        .switch_blocks_before_upgrade
+        .extend(last_blocks_before_upgrade.into_values());
+    txn.commit()?;
+    Ok(indices)
+}
+
 pub(crate) fn initialize_indices(
     env: &Environment,
+    maybe_block_index: Option<&BlockIndex>,
     needed_heights: &BTreeSet<u64>,
 ) -> Result<Indices, Error> {
+    if let Some(block_index) = maybe_block_index {
+        return initialize_indices_from_block_index(env, block_index, needed_heights);
+    }
     let mut indices = Indices::default();
     let txn = env.begin_ro_txn()?;
     let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
@@ -115,16 +180,7 @@ pub(crate) fn initialize_indices(
                 let _ = indices
                     .switch_blocks
                     .insert(block_header.era_id().successor(), block_hash);
-                match last_blocks_before_upgrade.entry(block_header.protocol_version()) {
-                    Entry::Vacant(vacant_entry) => {
-                        vacant_entry.insert(block_height);
-                    }
-                    Entry::Occupied(mut occupied_entry) => {
-                        if *occupied_entry.get() < block_height {
-                            occupied_entry.insert(block_height);
-                        }
-                    }
-                }
+                record_switch_block(&mut last_blocks_before_upgrade, &block_header);
             }
             if needed_heights.contains(&block_height)
                 && indices
@@ -149,8 +205,10 @@ pub(crate) fn purge_signatures_for_blocks(
     indices: &Indices,
     heights_to_visit: BTreeSet<u64>,
     full_purge: bool,
+    maybe_journal: Option<&mut Journal>,
+    dry_run: bool,
 ) -> Result<(), Error> {
-    let mut txn = env.begin_rw_txn()?;
+    let mut txn = JournaledTransaction::begin(env, maybe_journal, dry_run)?;
     let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
     let signatures_db = unsafe { txn.open_db(Some(BlockMetadataDatabase::db_name()))? };
 
@@ -195,7 +253,7 @@ pub(crate) fn purge_signatures_for_blocks(
         let block_height = block_header.height();
         let era_id = block_header.era_id();
         let era_after_upgrade =
-            era_weights.refresh_weights_for_era(&txn, header_db, indices, era_id)?;
+            era_weights.refresh_weights_for_era(txn.reader(), header_db, indices, era_id)?;
 
         let mut block_signatures: BlockSignatures = match txn.get(signatures_db, &block_hash) {
             Ok(raw_signatures) => bincode::deserialize(raw_signatures)
@@ -212,7 +270,7 @@ pub(crate) fn purge_signatures_for_blocks(
         };
 
         if full_purge {
-            txn.del(signatures_db, &block_hash, None)?;
+            txn.del(signatures_db, &block_hash)?;
         } else if strip_signatures(&mut block_signatures, &era_weights.weights) {
             if era_after_upgrade {
                 warn!(
@@ -233,7 +291,10 @@ pub(crate) fn purge_signatures_for_blocks(
         }
         progress_tracker.advance_by(1);
     }
-    txn.commit()?;
+    let planned_changes = txn.commit()?;
+    if dry_run {
+        journal::log_planned_changes(&planned_changes);
+    }
     Ok(())
 }
 
@@ -241,6 +302,8 @@ pub fn purge_signatures<P: AsRef<Path>>(
     db_path: P,
     weak_finality_block_list: BTreeSet<u64>,
     no_finality_block_list: BTreeSet<u64>,
+    mut maybe_journal: Option<Journal>,
+    dry_run: bool,
 ) -> Result<(), Error> {
     let storage_path = db_path.as_ref().join(STORAGE_FILE_NAME);
     let env = db::db_env(storage_path)?;
@@ -248,12 +311,27 @@ pub fn purge_signatures<P: AsRef<Path>>(
         .union(&no_finality_block_list)
         .copied()
         .collect();
-    let indices = initialize_indices(&env, &heights_to_visit)?;
+    let maybe_block_index = BlockIndex::open(&db_path, &env)?;
+    let indices = initialize_indices(&env, maybe_block_index.as_ref(), &heights_to_visit)?;
     if !weak_finality_block_list.is_empty() {
-        purge_signatures_for_blocks(&env, &indices, weak_finality_block_list, false)?;
+        purge_signatures_for_blocks(
+            &env,
+            &indices,
+            weak_finality_block_list,
+            false,
+            maybe_journal.as_mut(),
+            dry_run,
+        )?;
     }
     if !no_finality_block_list.is_empty() {
-        purge_signatures_for_blocks(&env, &indices, no_finality_block_list, true)?;
+        purge_signatures_for_blocks(
+            &env,
+            &indices,
+            no_finality_block_list,
+            true,
+            maybe_journal.as_mut(),
+            dry_run,
+        )?;
     }
     Ok(())
 }
@@ -5,6 +5,7 @@ use cargio_types::{ProtocolVersion, Signature, U512};
 use lmdb::{Error as LmdbError, Transaction, WriteFlags};
 
 use crate::{
+    common::journal::{self, Journal},
     subcommands::purge_signatures::{
         block_signatures::BlockSignatures,
         purge::{initialize_indices, purge_signatures_for_blocks, EraWeights},
@@ -75,7 +76,7 @@ fn indices_initialization() {
         txn.commit().unwrap();
     };
 
-    let indices = initialize_indices(env, &BTreeSet::from([100, 200, 300])).unwrap();
+    let indices = initialize_indices(env, None, &BTreeSet::from([100, 200, 300])).unwrap();
     assert_eq!(
         indices.heights.get(&block_headers[0].1.height).unwrap().0,
         block_headers[0].0
@@ -117,7 +118,7 @@ fn indices_initialization() {
         txn.commit().unwrap();
     };
 
-    match initialize_indices(env, &BTreeSet::from([100, 200, 300])) {
+    match initialize_indices(env, None, &BTreeSet::from([100, 200, 300])) {
         Err(Error::DuplicateBlock(height)) => assert_eq!(height, block_headers[0].1.height),
         _ => panic!("Unexpected error"),
     }
@@ -187,7 +188,7 @@ fn indices_initialization_with_upgrade() {
         txn.commit().unwrap();
     };
 
-    let indices = initialize_indices(env, &BTreeSet::from([100, 200, 300])).unwrap();
+    let indices = initialize_indices(env, None, &BTreeSet::from([100, 200, 300])).unwrap();
     assert!(!indices
         .switch_blocks_before_upgrade
         .contains(&switch_block_headers[0].1.height));
@@ -244,7 +245,7 @@ fn era_weights() {
         }
         txn.commit().unwrap();
     };
-    let indices = initialize_indices(env, &BTreeSet::from([80])).unwrap();
+    let indices = initialize_indices(env, None, &BTreeSet::from([80])).unwrap();
     let mut era_weights = EraWeights::default();
     if let Ok(txn) = env.begin_ro_txn() {
         let db = env.open_db(Some("block_header")).unwrap();
@@ -378,7 +379,7 @@ fn era_weights_with_upgrade() {
         }
         txn.commit().unwrap();
     };
-    let indices = initialize_indices(env, &BTreeSet::from([80, 280])).unwrap();
+    let indices = initialize_indices(env, None, &BTreeSet::from([80, 280])).unwrap();
     let mut era_weights = EraWeights::default();
     if let Ok(txn) = env.begin_ro_txn() {
         let db = env.open_db(Some("block_header")).unwrap();
@@ -525,11 +526,17 @@ fn purge_signatures_should_work() {
         txn.commit().unwrap();
     };
 
-    let indices = initialize_indices(env, &BTreeSet::from([100, 200, 300, 400])).unwrap();
-
-    assert!(
-        purge_signatures_for_blocks(env, &indices, BTreeSet::from([100, 200, 300]), false).is_ok()
-    );
+    let indices = initialize_indices(env, None, &BTreeSet::from([100, 200, 300, 400])).unwrap();
+
+    assert!(purge_signatures_for_blocks(
+        env,
+        &indices,
+        BTreeSet::from([100, 200, 300]),
+        false,
+        None,
+        false
+    )
+    .is_ok());
     if let Ok(txn) = env.begin_ro_txn() {
         let block_1_sigs = get_sigs_from_db(&txn, &fixture, &block_headers[0].0);
         assert!(
@@ -555,7 +562,15 @@ fn purge_signatures_should_work() {
         txn.commit().unwrap();
     };
 
-    assert!(purge_signatures_for_blocks(env, &indices, BTreeSet::from([100, 400]), true).is_ok());
+    assert!(purge_signatures_for_blocks(
+        env,
+        &indices,
+        BTreeSet::from([100, 400]),
+        true,
+        None,
+        false
+    )
+    .is_ok());
     if let Ok(txn) = env.begin_ro_txn() {
         match txn.get(
             *fixture.db(Some("block_metadata")).unwrap(),
@@ -583,6 +598,51 @@ fn purge_signatures_should_work() {
         }
         txn.commit().unwrap();
     };
+
+    let block_2_raw_sigs = {
+        let txn = env.begin_ro_txn().unwrap();
+        let raw_sigs = txn
+            .get(
+                *fixture.db(Some("block_metadata")).unwrap(),
+                &block_headers[1].0,
+            )
+            .unwrap()
+            .to_vec();
+        txn.commit().unwrap();
+        raw_sigs
+    };
+    assert!(
+        purge_signatures_for_blocks(env, &indices, BTreeSet::from([200]), true, None, true).is_ok()
+    );
+    if let Ok(txn) = env.begin_ro_txn() {
+        let _ = get_sigs_from_db(&txn, &fixture, &block_headers[1].0);
+        txn.commit().unwrap();
+    };
+
+    let journal_path = fixture.tmp_dir.as_ref().join("purge.journal");
+    let mut journal = Journal::create(&journal_path, &fixture.file_path).unwrap();
+    assert!(purge_signatures_for_blocks(
+        env,
+        &indices,
+        BTreeSet::from([200]),
+        true,
+        Some(&mut journal),
+        false
+    )
+    .is_ok());
+    drop(journal);
+    assert_eq!(journal::undo(&journal_path).unwrap(), 1);
+    if let Ok(txn) = env.begin_ro_txn() {
+        assert_eq!(
+            txn.get(
+                *fixture.db(Some("block_metadata")).unwrap(),
+                &block_headers[1].0,
+            )
+            .unwrap(),
+            block_2_raw_sigs.as_slice()
+        );
+        txn.commit().unwrap();
+    };
 }
 
 #[test]
@@ -668,8 +728,16 @@ fn purge_signatures_bad_input() {
         txn.commit().unwrap();
     };
 
-    let indices = initialize_indices(env, &BTreeSet::from([100])).unwrap();
-    assert!(purge_signatures_for_blocks(env, &indices, BTreeSet::from([100, 200]), false).is_ok());
+    let indices = initialize_indices(env, None, &BTreeSet::from([100])).unwrap();
+    assert!(purge_signatures_for_blocks(
+        env,
+        &indices,
+        BTreeSet::from([100, 200]),
+        false,
+        None,
+        false
+    )
+    .is_ok());
     if let Ok(txn) = env.begin_ro_txn() {
         let block_1_sigs = get_sigs_from_db(&txn, &fixture, &block_headers[0].0);
         assert!(block_1_sigs.proofs.contains_key(&KEYS[0]));
@@ -692,8 +760,15 @@ fn purge_signatures_bad_input() {
         txn.commit().unwrap();
     };
 
-    let indices = initialize_indices(env, &BTreeSet::from([100, 200])).unwrap();
-    match purge_signatures_for_blocks(env, &indices, BTreeSet::from([100, 200]), false) {
+    let indices = initialize_indices(env, None, &BTreeSet::from([100, 200])).unwrap();
+    match purge_signatures_for_blocks(
+        env,
+        &indices,
+        BTreeSet::from([100, 200]),
+        false,
+        None,
+        false,
+    ) {
         Err(Error::SignaturesParsing(block_hash, _)) if block_hash == block_headers[1].0 => {}
         other => panic!("Unexpected result: {other:?}"),
     };
@@ -756,9 +831,17 @@ fn purge_signatures_missing_from_db() {
         txn.commit().unwrap();
     };
 
-    let indices = initialize_indices(env, &BTreeSet::from([100, 200])).unwrap();
-
-    assert!(purge_signatures_for_blocks(env, &indices, BTreeSet::from([100, 200]), false).is_ok());
+    let indices = initialize_indices(env, None, &BTreeSet::from([100, 200])).unwrap();
+
+    assert!(purge_signatures_for_blocks(
+        env,
+        &indices,
+        BTreeSet::from([100, 200]),
+        false,
+        None,
+        false
+    )
+    .is_ok());
     if let Ok(txn) = env.begin_ro_txn() {
         let block_1_sigs = get_sigs_from_db(&txn, &fixture, &block_headers[0].0);
         assert!(block_1_sigs.proofs.contains_key(&KEYS[0]));
@@ -774,7 +857,15 @@ fn purge_signatures_missing_from_db() {
         txn.commit().unwrap();
     };
 
-    assert!(purge_signatures_for_blocks(env, &indices, BTreeSet::from([100, 200]), true).is_ok());
+    assert!(purge_signatures_for_blocks(
+        env,
+        &indices,
+        BTreeSet::from([100, 200]),
+        true,
+        None,
+        false
+    )
+    .is_ok());
     if let Ok(txn) = env.begin_ro_txn() {
         match txn.get(
             *fixture.db(Some("block_metadata")).unwrap(),
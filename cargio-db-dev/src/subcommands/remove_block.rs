@@ -1,22 +1,41 @@
-mod remove;
+pub(crate) mod remove;
 #[cfg(test)]
 mod tests;
 
-use std::path::Path;
+use std::{io::Error as IoError, path::Path};
 
 use bincode::Error as BincodeError;
 use cargio_hashing::Digest;
 use master_node::types::{BlockHash, DeployHash};
-use clap::{Arg, ArgMatches, Command};
+use clap::{Arg, ArgGroup, ArgMatches, Command};
 use lmdb::Error as LmdbError;
+use serde_json::Error as SerializationError;
 use thiserror::Error as ThisError;
 
+use crate::common::{
+    block_index::BlockIndexError,
+    db::{DeserializationError, STORAGE_FILE_NAME},
+    journal::{Journal, JournalError},
+};
+
+use self::remove::BlockSelection;
+
 pub const COMMAND_NAME: &str = "remove-block";
 const BLOCK_HASH: &str = "block-hash";
+const BLOCKS: &str = "blocks";
 const DB_PATH: &str = "db-path";
+const DRY_RUN: &str = "dry-run";
+const FROM_HEIGHT: &str = "from-height";
+const JOURNAL: &str = "journal";
+const OUTPUT: &str = "output";
+const OVERWRITE: &str = "overwrite";
+const TO_HEIGHT: &str = "to-height";
+const TRUNCATE_ABOVE: &str = "truncate-above";
 
 #[derive(Debug, ThisError)]
 pub enum Error {
+    #[error("Error accessing the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
     #[error("Error parsing block body for block with hash {0}: {1}")]
     BodyParsing(BlockHash, BincodeError),
     #[error("Error operating the database: {0}")]
@@ -25,17 +44,32 @@ pub enum Error {
     ExecutionResultsParsing(BlockHash, DeployHash, BincodeError),
     #[error("Error parsing block header with hash {0}: {1}")]
     HeaderParsing(BlockHash, BincodeError),
+    #[error("Error writing the journal: {0}")]
+    Journal(#[from] JournalError),
     #[error("Deploy with hash {0} not present in the database")]
     MissingDeploy(DeployHash),
     #[error("Block header for block hash {0} not present in the database")]
     MissingHeader(BlockHash),
+    #[error("Error parsing block body merkle entry with key {0}: {1}")]
+    MerkleParsing(String, DeserializationError),
+    #[error("Error writing output: {0}")]
+    Output(#[from] IoError),
     #[error("Error serializing execution results for deploy {0}: {1}")]
     Serialization(DeployHash, BincodeError),
+    #[error("Error serializing output: {0}")]
+    Serialize(#[from] SerializationError),
 }
 
 enum DisplayOrder {
     DbPath,
     BlockHash,
+    FromHeight,
+    ToHeight,
+    TruncateAbove,
+    DryRun,
+    Journal,
+    Output,
+    Overwrite,
 }
 
 pub fn command(display_order: usize) -> Command<'static> {
@@ -43,7 +77,10 @@ pub fn command(display_order: usize) -> Command<'static> {
         .display_order(display_order)
         .about(
             "Removes the block header, body and execution results for a given \
-            block hash from a storage database.",
+            block hash, a range of heights or all blocks above a height from a storage \
+            database, along with their signatures, transfers, block body merkle entries and \
+            the deploys no other block refers to. All blocks are removed in a single \
+            transaction, and the number of entries removed from each database is reported.",
         )
         .arg(
             Arg::new(DB_PATH)
@@ -64,17 +101,120 @@ pub fn command(display_order: usize) -> Command<'static> {
                 .value_name("BLOCK_HASH")
                 .help("Hash of the block to be removed."),
         )
+        .arg(
+            Arg::new(FROM_HEIGHT)
+                .display_order(DisplayOrder::FromHeight as usize)
+                .short('f')
+                .long(FROM_HEIGHT)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .requires(TO_HEIGHT)
+                .help("Height of the first block to be removed."),
+        )
+        .arg(
+            Arg::new(TO_HEIGHT)
+                .display_order(DisplayOrder::ToHeight as usize)
+                .short('t')
+                .long(TO_HEIGHT)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .requires(FROM_HEIGHT)
+                .help("Height of the last block to be removed."),
+        )
+        .arg(
+            Arg::new(TRUNCATE_ABOVE)
+                .display_order(DisplayOrder::TruncateAbove as usize)
+                .short('a')
+                .long(TRUNCATE_ABOVE)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .help("Removes all blocks higher than this height."),
+        )
+        .arg(
+            Arg::new(DRY_RUN)
+                .display_order(DisplayOrder::DryRun as usize)
+                .short('n')
+                .long(DRY_RUN)
+                .takes_value(false)
+                .help(
+                    "Logs the blocks and entries which would be removed without changing \
+                    the database.",
+                ),
+        )
+        .arg(
+            Arg::new(JOURNAL)
+                .display_order(DisplayOrder::Journal as usize)
+                .short('j')
+                .long(JOURNAL)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .conflicts_with(DRY_RUN)
+                .help(
+                    "Path of a new journal file recording the prior state of every changed \
+                    entry, which the `undo` command restores.",
+                ),
+        )
+        .arg(
+            Arg::new(OUTPUT)
+                .display_order(DisplayOrder::Output as usize)
+                .short('o')
+                .long(OUTPUT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help(
+                    "Path to where the program will output a JSON summary of the removed \
+                    entries. If unspecified, the summary is logged.",
+                ),
+        )
+        .arg(
+            Arg::new(OVERWRITE)
+                .display_order(DisplayOrder::Overwrite as usize)
+                .required(false)
+                .short('w')
+                .long(OVERWRITE)
+                .takes_value(false)
+                .requires(OUTPUT)
+                .help(
+                    "Overwrite an already existing output file in destination \
+                    directory.",
+                ),
+        )
+        .group(ArgGroup::new(BLOCKS).required(true).args(&[
+            BLOCK_HASH,
+            FROM_HEIGHT,
+            TRUNCATE_ABOVE,
+        ]))
+}
+
+fn parse_height(matches: &ArgMatches, arg: &str) -> Option<u64> {
+    matches.value_of(arg).map(|height| {
+        height
+            .parse()
+            .unwrap_or_else(|_| panic!("Value of \"--{arg}\" must be an integer."))
+    })
 }
 
 pub fn run(matches: &ArgMatches) -> Result<(), Error> {
     let path = Path::new(matches.value_of(DB_PATH).expect("should have db-path arg"));
-    let block_hash: BlockHash = matches
-        .value_of(BLOCK_HASH)
-        .map(|block_hash_str| {
-            Digest::from_hex(block_hash_str)
-                .expect("should parse block hash to hex format")
-                .into()
-        })
-        .expect("should have block-hash arg");
-    remove::remove_block(path, block_hash)
+    let selection = if let Some(block_hash_str) = matches.value_of(BLOCK_HASH) {
+        let block_hash: BlockHash = Digest::from_hex(block_hash_str)
+            .expect("should parse block hash to hex format")
+            .into();
+        BlockSelection::Hash(block_hash)
+    } else if let Some(height) = parse_height(matches, TRUNCATE_ABOVE) {
+        BlockSelection::HeightRange(height.saturating_add(1)..=u64::MAX)
+    } else {
+        let from_height = parse_height(matches, FROM_HEIGHT).expect("should have from-height arg");
+        let to_height = parse_height(matches, TO_HEIGHT).expect("should have to-height arg");
+        BlockSelection::HeightRange(from_height..=to_height)
+    };
+    let dry_run = matches.is_present(DRY_RUN);
+    let output = matches.value_of(OUTPUT).map(Path::new);
+    let overwrite = matches.is_present(OVERWRITE);
+    let mut maybe_journal = matches
+        .value_of(JOURNAL)
+        .map(|journal_path| Journal::create(journal_path, path.join(STORAGE_FILE_NAME)))
+        .transpose()?;
+    let removal = remove::remove_blocks(path, selection, dry_run, maybe_journal.as_mut())?;
+    remove::report_removal(&removal.summary(dry_run), output, overwrite)
 }
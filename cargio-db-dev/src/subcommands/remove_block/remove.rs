@@ -1,40 +1,474 @@
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::OpenOptions,
+    ops::RangeInclusive,
+    path::Path,
+};
 
+use cargio_hashing::Digest;
+use cargio_types::EraId;
+use lmdb::{Cursor, Database as LmdbDatabase, Error as LmdbError, Transaction, WriteFlags};
+use log::{error, info, warn};
 use master_node::types::{BlockHash, BlockHeader, DeployMetadata};
-use lmdb::{Error as LmdbError, Transaction, WriteFlags};
-use log::warn;
+use serde::Serialize;
 
 use crate::{
-    common::db::{
-        self, BlockBodyDatabase, BlockHeaderDatabase, Database, DeployMetadataDatabase,
-        STORAGE_FILE_NAME,
+    common::{
+        block_index::{self, BlockIndex},
+        db::{
+            self, BlockBodyDatabase, BlockBodyMerkleDatabase, BlockHeaderDatabase,
+            BlockMetadataDatabase, Database, DeployDatabase, DeployHashesDatabase,
+            DeployMetadataDatabase, FinalizedApprovalsDatabase, ProposerDatabase, TransferDatabase,
+            TransferHashesDatabase, STORAGE_FILE_NAME,
+        },
+        journal::{Journal, JournalError, JournaledTransaction},
+        lmdb_utils,
+        progress::ProgressTracker,
     },
     subcommands::execution_results_summary::block_body::BlockBody,
 };
 
 use super::Error;
 
-pub(crate) fn remove_block<P: AsRef<Path>>(db_path: P, block_hash: BlockHash) -> Result<(), Error> {
-    let storage_path = db_path.as_ref().join(STORAGE_FILE_NAME);
-    let env = db::db_env(storage_path)?;
+/// The blocks to remove from the storage.
+pub(crate) enum BlockSelection {
+    Hash(BlockHash),
+    /// All blocks with a height in the range, inclusive.
+    HeightRange(RangeInclusive<u64>),
+    /// All blocks of the eras before the given one, except the switch blocks
+    /// ending them.
+    BeforeEra(EraId),
+}
+
+/// The blocks which were removed and the database entries which were
+/// deleted or rewritten to remove them.
+#[derive(Debug, Default)]
+pub(crate) struct Removal {
+    pub(crate) blocks: Vec<(u64, BlockHash)>,
+    pub(crate) deleted: Vec<(&'static str, Vec<u8>)>,
+    pub(crate) updated: Vec<(&'static str, Vec<u8>)>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct RemovedBlock {
+    height: u64,
+    block_hash: BlockHash,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct DatabaseRemovalSummary {
+    pub(crate) deleted: usize,
+    pub(crate) updated: usize,
+}
+
+/// Summary of a removal, with the number of changed entries per database.
+#[derive(Debug, Serialize)]
+pub(crate) struct RemovalSummary {
+    dry_run: bool,
+    blocks: Vec<RemovedBlock>,
+    pub(crate) databases: BTreeMap<&'static str, DatabaseRemovalSummary>,
+}
+
+impl Removal {
+    pub(crate) fn summary(&self, dry_run: bool) -> RemovalSummary {
+        let mut databases: BTreeMap<&'static str, DatabaseRemovalSummary> = BTreeMap::new();
+        for (db_name, _key) in self.deleted.iter() {
+            databases.entry(*db_name).or_default().deleted += 1;
+        }
+        for (db_name, _key) in self.updated.iter() {
+            databases.entry(*db_name).or_default().updated += 1;
+        }
+        RemovalSummary {
+            dry_run,
+            blocks: self
+                .blocks
+                .iter()
+                .map(|(height, block_hash)| RemovedBlock {
+                    height: *height,
+                    block_hash: *block_hash,
+                })
+                .collect(),
+            databases,
+        }
+    }
+}
+
+/// Handles of the databases a block has entries in. Only the header, body
+/// and deploy metadata databases are required to exist.
+struct BlockDatabases {
+    header_db: LmdbDatabase,
+    body_db: LmdbDatabase,
+    deploy_metadata_db: LmdbDatabase,
+    maybe_block_metadata_db: Option<LmdbDatabase>,
+    maybe_body_merkle_db: Option<LmdbDatabase>,
+    maybe_deploy_db: Option<LmdbDatabase>,
+    maybe_finalized_approvals_db: Option<LmdbDatabase>,
+    maybe_transfer_db: Option<LmdbDatabase>,
+    /// The databases holding the parts of block bodies the merkle links
+    /// point to.
+    merkle_leaf_dbs: Vec<(&'static str, LmdbDatabase)>,
+}
 
-    let mut txn = env.begin_rw_txn()?;
-    let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
-    let body_db = unsafe { txn.open_db(Some(BlockBodyDatabase::db_name()))? };
-    let deploy_metadata_db = unsafe { txn.open_db(Some(DeployMetadataDatabase::db_name()))? };
+impl BlockDatabases {
+    fn open(txn: &mut JournaledTransaction) -> Result<Self, Error> {
+        Ok(Self {
+            header_db: unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? },
+            body_db: unsafe { txn.open_db(Some(BlockBodyDatabase::db_name()))? },
+            deploy_metadata_db: unsafe { txn.open_db(Some(DeployMetadataDatabase::db_name()))? },
+            maybe_block_metadata_db: open_optional_db(txn, BlockMetadataDatabase::db_name())?,
+            maybe_body_merkle_db: open_optional_db(txn, BlockBodyMerkleDatabase::db_name())?,
+            maybe_deploy_db: open_optional_db(txn, DeployDatabase::db_name())?,
+            maybe_finalized_approvals_db: open_optional_db(
+                txn,
+                FinalizedApprovalsDatabase::db_name(),
+            )?,
+            maybe_transfer_db: open_optional_db(txn, TransferDatabase::db_name())?,
+            merkle_leaf_dbs: [
+                DeployHashesDatabase::db_name(),
+                TransferHashesDatabase::db_name(),
+                ProposerDatabase::db_name(),
+            ]
+            .into_iter()
+            .filter_map(|db_name| {
+                open_optional_db(&mut *txn, db_name)
+                    .map(|maybe_db| maybe_db.map(|db| (db_name, db)))
+                    .transpose()
+            })
+            .collect::<Result<_, _>>()?,
+        })
+    }
+}
 
-    let header: BlockHeader = match txn.get(header_db, &block_hash) {
+fn open_optional_db(
+    txn: &mut JournaledTransaction,
+    db_name: &str,
+) -> Result<Option<LmdbDatabase>, Error> {
+    match unsafe { txn.open_db(Some(db_name)) } {
+        Ok(db) => Ok(Some(db)),
+        Err(LmdbError::NotFound) => Ok(None),
+        Err(lmdb_err) => Err(lmdb_err.into()),
+    }
+}
+
+fn delete_entry(
+    txn: &mut JournaledTransaction,
+    db: LmdbDatabase,
+    db_name: &'static str,
+    key: &[u8],
+    removal: &mut Removal,
+) -> Result<(), Error> {
+    match txn.del(db, &key) {
+        Ok(()) => {
+            removal.deleted.push((db_name, key.to_vec()));
+            Ok(())
+        }
+        Err(JournalError::Database(LmdbError::NotFound)) => Ok(()),
+        Err(lmdb_err) => Err(lmdb_err.into()),
+    }
+}
+
+fn read_header<T: Transaction>(
+    txn: &T,
+    header_db: LmdbDatabase,
+    block_hash: BlockHash,
+) -> Result<BlockHeader, Error> {
+    match txn.get(header_db, &block_hash) {
         Ok(raw_header) => bincode::deserialize(raw_header)
-            .map_err(|bincode_err| Error::HeaderParsing(block_hash, bincode_err))?,
-        Err(LmdbError::NotFound) => {
-            return Err(Error::MissingHeader(block_hash));
+            .map_err(|bincode_err| Error::HeaderParsing(block_hash, bincode_err)),
+        Err(LmdbError::NotFound) => Err(Error::MissingHeader(block_hash)),
+        Err(lmdb_err) => Err(lmdb_err.into()),
+    }
+}
+
+/// Calls `f` with every block header in the storage.
+fn for_each_header<T: Transaction, F: FnMut(BlockHash, BlockHeader) -> Result<(), Error>>(
+    txn: &T,
+    header_db: LmdbDatabase,
+    mut f: F,
+) -> Result<(), Error> {
+    let mut maybe_progress_tracker = ProgressTracker::new(
+        lmdb_utils::entry_count(txn, header_db)?,
+        Box::new(|completion| info!("Header database parsing {}% complete...", completion)),
+    )
+    .ok();
+    if let Ok(mut cursor) = txn.open_ro_cursor(header_db) {
+        for (raw_key, raw_value) in cursor.iter() {
+            if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
+                progress_tracker.advance_by(1);
+            }
+            let block_hash: BlockHash = match Digest::try_from(raw_key) {
+                Ok(digest) => digest.into(),
+                Err(digest_parsing_err) => {
+                    error!("Skipping block header because of invalid hash {raw_key:?}: {digest_parsing_err}");
+                    continue;
+                }
+            };
+            let block_header: BlockHeader = bincode::deserialize(raw_value)
+                .map_err(|bincode_err| Error::HeaderParsing(block_hash, bincode_err))?;
+            f(block_hash, block_header)?;
         }
-        Err(lmdb_err) => {
-            return Err(lmdb_err.into());
+    }
+    Ok(())
+}
+
+fn select_blocks<T: Transaction>(
+    txn: &T,
+    header_db: LmdbDatabase,
+    maybe_block_index: Option<&BlockIndex>,
+    selection: &BlockSelection,
+) -> Result<Vec<(BlockHash, BlockHeader)>, Error> {
+    let mut selected = vec![];
+    match (selection, maybe_block_index) {
+        (BlockSelection::Hash(block_hash), _) => {
+            selected.push((*block_hash, read_header(txn, header_db, *block_hash)?));
+        }
+        (BlockSelection::HeightRange(heights), Some(block_index)) => {
+            for (_height, block_hash) in block_index.blocks()?.range(heights.clone()) {
+                selected.push((*block_hash, read_header(txn, header_db, *block_hash)?));
+            }
+        }
+        (BlockSelection::HeightRange(heights), None) => {
+            for_each_header(txn, header_db, |block_hash, block_header| {
+                if heights.contains(&block_header.height()) {
+                    selected.push((block_hash, block_header));
+                }
+                Ok(())
+            })?;
+        }
+        (BlockSelection::BeforeEra(era_id), maybe_block_index) => {
+            // The switch block ending the previous era is the highest block
+            // which can be selected.
+            let maybe_last_switch_block = match (maybe_block_index, era_id.value().checked_sub(1)) {
+                (Some(block_index), Some(previous_era)) => block_index
+                    .switch_blocks()?
+                    .get(&EraId::new(previous_era))
+                    .map(|block_hash| (block_index, *block_hash)),
+                _ => None,
+            };
+            match maybe_last_switch_block {
+                Some((block_index, switch_block_hash)) => {
+                    let switch_block_height =
+                        read_header(txn, header_db, switch_block_hash)?.height();
+                    for (_height, block_hash) in block_index.blocks()?.range(..switch_block_height)
+                    {
+                        let block_header = read_header(txn, header_db, *block_hash)?;
+                        if !block_header.is_switch_block() {
+                            selected.push((*block_hash, block_header));
+                        }
+                    }
+                }
+                None => {
+                    for_each_header(txn, header_db, |block_hash, block_header| {
+                        if block_header.era_id() < *era_id && !block_header.is_switch_block() {
+                            selected.push((block_hash, block_header));
+                        }
+                        Ok(())
+                    })?;
+                }
+            }
+        }
+    }
+    selected.sort_by_key(|(_, block_header)| block_header.height());
+    Ok(selected)
+}
+
+/// Returns the keys of the `block_body_merkle` entries linked from
+/// `body_hash`, starting with `body_hash` itself, along with the leaves
+/// they point to.
+fn merkle_chain<T: Transaction>(
+    txn: &T,
+    body_merkle_db: LmdbDatabase,
+    body_hash: Digest,
+) -> Result<Vec<(Digest, Digest)>, Error> {
+    let mut chain = vec![];
+    let mut key = body_hash;
+    loop {
+        let raw_link = match txn.get(body_merkle_db, &key) {
+            Ok(raw_link) => raw_link,
+            Err(LmdbError::NotFound) => break,
+            Err(lmdb_err) => return Err(lmdb_err.into()),
+        };
+        let (left, right) = BlockBodyMerkleDatabase::parse_element(raw_link)
+            .map_err(|parsing_err| Error::MerkleParsing(hex::encode(key), parsing_err))?;
+        chain.push((key, left));
+        // Guards against looping forever over a corrupted database.
+        if chain.iter().any(|(link_key, _)| *link_key == right) {
+            break;
+        }
+        key = right;
+    }
+    Ok(chain)
+}
+
+/// Content addressed entries of removed blocks which no other block refers
+/// to.
+#[derive(Default)]
+struct UnsharedBodyEntries {
+    bodies: BTreeSet<Digest>,
+    merkle_links: BTreeSet<Digest>,
+    merkle_leaves: BTreeSet<Digest>,
+}
+
+/// Bodies, merkle links and the leaves they point to are content addressed,
+/// so blocks which aren't removed can share them with removed blocks.
+fn unshared_body_entries<T: Transaction>(
+    txn: &T,
+    dbs: &BlockDatabases,
+    selected: &[(BlockHash, BlockHeader)],
+) -> Result<UnsharedBodyEntries, Error> {
+    let selected_hashes: BTreeSet<BlockHash> =
+        selected.iter().map(|(block_hash, _)| *block_hash).collect();
+    let mut bodies: BTreeSet<Digest> = selected
+        .iter()
+        .map(|(_, block_header)| *block_header.body_hash())
+        .collect();
+    let mut merkle_links: BTreeMap<Digest, Digest> = BTreeMap::new();
+    if let Some(body_merkle_db) = dbs.maybe_body_merkle_db {
+        for body_hash in bodies.iter() {
+            merkle_links.extend(merkle_chain(txn, body_merkle_db, *body_hash)?);
+        }
+    }
+
+    let mut shared_bodies = BTreeSet::new();
+    let mut shared_merkle_roots = BTreeSet::new();
+    for_each_header(txn, dbs.header_db, |block_hash, block_header| {
+        if !selected_hashes.contains(&block_hash) {
+            let body_hash = *block_header.body_hash();
+            if bodies.contains(&body_hash) {
+                shared_bodies.insert(body_hash);
+            }
+            if merkle_links.contains_key(&body_hash) {
+                shared_merkle_roots.insert(body_hash);
+            }
+        }
+        Ok(())
+    })?;
+    bodies.retain(|body_hash| !shared_bodies.contains(body_hash));
+
+    let mut merkle_leaves: BTreeSet<Digest> = merkle_links.values().copied().collect();
+    let body_merkle_db = match dbs.maybe_body_merkle_db {
+        Some(body_merkle_db) if !merkle_links.is_empty() => body_merkle_db,
+        _ => {
+            return Ok(UnsharedBodyEntries {
+                bodies,
+                ..Default::default()
+            })
         }
     };
+    let mut shared_leaves = BTreeSet::new();
+    if let Ok(mut cursor) = txn.open_ro_cursor(body_merkle_db) {
+        for (raw_key, raw_link) in cursor.iter() {
+            if Digest::try_from(raw_key)
+                .map(|key| merkle_links.contains_key(&key))
+                .unwrap_or(false)
+            {
+                continue;
+            }
+            let (left, right) = BlockBodyMerkleDatabase::parse_element(raw_link)
+                .map_err(|parsing_err| Error::MerkleParsing(hex::encode(raw_key), parsing_err))?;
+            if merkle_leaves.contains(&left) {
+                shared_leaves.insert(left);
+            }
+            for child in [left, right] {
+                if merkle_links.contains_key(&child) {
+                    shared_merkle_roots.insert(child);
+                }
+            }
+        }
+    }
+    for root in shared_merkle_roots {
+        for (key, left) in merkle_chain(txn, body_merkle_db, root)? {
+            merkle_links.remove(&key);
+            shared_leaves.insert(left);
+        }
+    }
+    merkle_leaves.retain(|leaf| !shared_leaves.contains(leaf));
+    Ok(UnsharedBodyEntries {
+        bodies,
+        merkle_links: merkle_links.into_keys().collect(),
+        merkle_leaves,
+    })
+}
 
-    let maybe_body: Option<BlockBody> = match txn.get(body_db, header.body_hash()) {
+/// Removes the execution results of the block from `deploy_metadata`, and
+/// the deploys which were only executed in that block. Deploys without
+/// metadata fail the removal unless `skip_missing_metadata` is set.
+fn remove_execution_results(
+    txn: &mut JournaledTransaction,
+    dbs: &BlockDatabases,
+    block_hash: BlockHash,
+    body: &BlockBody,
+    skip_missing_metadata: bool,
+    removal: &mut Removal,
+) -> Result<(), Error> {
+    for deploy_hash in body.deploy_hashes.iter().chain(body.transfer_hashes.iter()) {
+        let mut metadata: DeployMetadata = match txn.get(dbs.deploy_metadata_db, deploy_hash) {
+            Ok(raw_metadata) => bincode::deserialize(raw_metadata).map_err(|bincode_err| {
+                Error::ExecutionResultsParsing(block_hash, *deploy_hash, bincode_err)
+            })?,
+            Err(LmdbError::NotFound) if skip_missing_metadata => {
+                warn!("No deploy metadata found for deploy {deploy_hash} of block {block_hash}");
+                continue;
+            }
+            Err(LmdbError::NotFound) => return Err(Error::MissingDeploy(*deploy_hash)),
+            Err(lmdb_error) => return Err(lmdb_error.into()),
+        };
+        if let Some(_execution_result) = metadata.execution_results.remove(&block_hash) {
+            if metadata.execution_results.is_empty() {
+                delete_entry(
+                    txn,
+                    dbs.deploy_metadata_db,
+                    DeployMetadataDatabase::db_name(),
+                    deploy_hash.as_ref(),
+                    removal,
+                )?;
+                if let Some(deploy_db) = dbs.maybe_deploy_db {
+                    delete_entry(
+                        txn,
+                        deploy_db,
+                        DeployDatabase::db_name(),
+                        deploy_hash.as_ref(),
+                        removal,
+                    )?;
+                }
+                if let Some(finalized_approvals_db) = dbs.maybe_finalized_approvals_db {
+                    delete_entry(
+                        txn,
+                        finalized_approvals_db,
+                        FinalizedApprovalsDatabase::db_name(),
+                        deploy_hash.as_ref(),
+                        removal,
+                    )?;
+                }
+            } else {
+                let encoded_metadata = bincode::serialize(&metadata)
+                    .map_err(|bincode_err| Error::Serialization(*deploy_hash, bincode_err))?;
+                txn.put(
+                    dbs.deploy_metadata_db,
+                    deploy_hash,
+                    &encoded_metadata,
+                    WriteFlags::default(),
+                )?;
+                removal.updated.push((
+                    DeployMetadataDatabase::db_name(),
+                    deploy_hash.as_ref().to_vec(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn remove_block_entries(
+    txn: &mut JournaledTransaction,
+    dbs: &BlockDatabases,
+    block_hash: BlockHash,
+    header: &BlockHeader,
+    unshared_bodies: &BTreeSet<Digest>,
+    skip_missing_metadata: bool,
+    removal: &mut Removal,
+) -> Result<(), Error> {
+    let maybe_body: Option<BlockBody> = match txn.get(dbs.body_db, header.body_hash()) {
         Ok(raw_body) => Some(
             bincode::deserialize(raw_body)
                 .map_err(|bincode_err| Error::BodyParsing(block_hash, bincode_err))?,
@@ -52,34 +486,158 @@ pub(crate) fn remove_block<P: AsRef<Path>>(db_path: P, block_hash: BlockHash) ->
     };
 
     if let Some(body) = maybe_body {
-        for deploy_hash in body.deploy_hashes() {
-            let mut metadata: DeployMetadata = match txn.get(deploy_metadata_db, deploy_hash) {
-                Ok(raw_metadata) => bincode::deserialize(raw_metadata).map_err(|bincode_err| {
-                    Error::ExecutionResultsParsing(block_hash, *deploy_hash, bincode_err)
-                })?,
-                Err(LmdbError::NotFound) => return Err(Error::MissingDeploy(*deploy_hash)),
-                Err(lmdb_error) => return Err(lmdb_error.into()),
-            };
-            if let Some(_execution_result) = metadata.execution_results.remove(&block_hash) {
-                if metadata.execution_results.is_empty() {
-                    txn.del(deploy_metadata_db, deploy_hash, None)?;
-                } else {
-                    let encoded_metadata = bincode::serialize(&metadata)
-                        .map_err(|bincode_err| Error::Serialization(*deploy_hash, bincode_err))?;
-                    txn.put(
-                        deploy_metadata_db,
-                        deploy_hash,
-                        &encoded_metadata,
-                        WriteFlags::default(),
-                    )?;
-                }
-            }
+        remove_execution_results(txn, dbs, block_hash, &body, skip_missing_metadata, removal)?;
+        if unshared_bodies.contains(header.body_hash()) {
+            delete_entry(
+                txn,
+                dbs.body_db,
+                BlockBodyDatabase::db_name(),
+                header.body_hash().as_ref(),
+                removal,
+            )?;
         }
+    }
 
-        txn.del(body_db, header.body_hash(), None)?;
+    if let Some(transfer_db) = dbs.maybe_transfer_db {
+        delete_entry(
+            txn,
+            transfer_db,
+            TransferDatabase::db_name(),
+            block_hash.as_ref(),
+            removal,
+        )?;
+    }
+    if let Some(block_metadata_db) = dbs.maybe_block_metadata_db {
+        delete_entry(
+            txn,
+            block_metadata_db,
+            BlockMetadataDatabase::db_name(),
+            block_hash.as_ref(),
+            removal,
+        )?;
     }
+    delete_entry(
+        txn,
+        dbs.header_db,
+        BlockHeaderDatabase::db_name(),
+        block_hash.as_ref(),
+        removal,
+    )?;
+    removal.blocks.push((header.height(), block_hash));
+    Ok(())
+}
 
-    txn.del(header_db, &block_hash, None)?;
+/// Removes the selected blocks and everything stored for them in a single
+/// transaction, recording the prior state of the changed entries in
+/// `maybe_journal`. In a dry run nothing is written and the entries which
+/// would have been changed are logged.
+pub(crate) fn remove_blocks<P: AsRef<Path>>(
+    db_path: P,
+    selection: BlockSelection,
+    dry_run: bool,
+    maybe_journal: Option<&mut Journal>,
+) -> Result<Removal, Error> {
+    let storage_path = db_path.as_ref().join(STORAGE_FILE_NAME);
+    let env = db::db_env(storage_path)?;
+    let maybe_block_index = match selection {
+        BlockSelection::Hash(_) => None,
+        BlockSelection::HeightRange(_) | BlockSelection::BeforeEra(_) => {
+            BlockIndex::open(&db_path, &env)?
+        }
+    };
+
+    let mut txn = JournaledTransaction::begin(&env, maybe_journal, dry_run)?;
+    let dbs = BlockDatabases::open(&mut txn)?;
+    let selected = select_blocks(
+        txn.reader(),
+        dbs.header_db,
+        maybe_block_index.as_ref(),
+        &selection,
+    )?;
+    let mut removal = Removal::default();
+    if selected.is_empty() {
+        warn!("No blocks to remove.");
+        return Ok(removal);
+    }
+
+    let unshared = unshared_body_entries(txn.reader(), &dbs, &selected)?;
+    // A single block is expected to be whole, while a range of blocks is
+    // removed even if some of its deploys were already pruned.
+    let skip_missing_metadata = !matches!(selection, BlockSelection::Hash(_));
+    for (block_hash, header) in selected.iter() {
+        remove_block_entries(
+            &mut txn,
+            &dbs,
+            *block_hash,
+            header,
+            &unshared.bodies,
+            skip_missing_metadata,
+            &mut removal,
+        )?;
+    }
+    if let Some(body_merkle_db) = dbs.maybe_body_merkle_db {
+        for key in unshared.merkle_links {
+            delete_entry(
+                &mut txn,
+                body_merkle_db,
+                BlockBodyMerkleDatabase::db_name(),
+                key.as_ref(),
+                &mut removal,
+            )?;
+        }
+    }
+    for leaf in unshared.merkle_leaves {
+        for &(db_name, db) in dbs.merkle_leaf_dbs.iter() {
+            delete_entry(&mut txn, db, db_name, leaf.as_ref(), &mut removal)?;
+        }
+    }
+
+    if !dry_run {
+        block_index::remove_index(&db_path)?;
+    }
     txn.commit()?;
+    if dry_run {
+        for (height, block_hash) in removal.blocks.iter() {
+            info!("Would remove block {block_hash} at height {height}.");
+        }
+        for (db_name, key) in removal.deleted.iter() {
+            info!("Would delete entry {} from {db_name}.", hex::encode(key));
+        }
+        for (db_name, key) in removal.updated.iter() {
+            info!("Would update entry {} in {db_name}.", hex::encode(key));
+        }
+    }
+    Ok(removal)
+}
+
+/// Writes the summary of the removal as JSON to `output` if given, or logs
+/// it otherwise.
+pub(crate) fn report_removal<P: AsRef<Path>>(
+    summary: &RemovalSummary,
+    output: Option<P>,
+    overwrite: bool,
+) -> Result<(), Error> {
+    if let Some(out_path) = output {
+        let file = OpenOptions::new()
+            .create_new(!overwrite)
+            .create(overwrite)
+            .truncate(overwrite)
+            .write(true)
+            .open(out_path)?;
+        serde_json::to_writer_pretty(file, summary)?;
+        return Ok(());
+    }
+    let verb = if summary.dry_run {
+        "Would remove"
+    } else {
+        "Removed"
+    };
+    info!("{verb} {} blocks.", summary.blocks.len());
+    for (db_name, db_summary) in summary.databases.iter() {
+        info!(
+            "{db_name}: {} entries deleted, {} entries updated.",
+            db_summary.deleted, db_summary.updated
+        );
+    }
     Ok(())
 }
@@ -1,18 +1,29 @@
 use std::slice;
 
-use master_node::types::{BlockHash, DeployHash, DeployMetadata};
+use cargio_hashing::Digest;
+use cargio_types::bytesrepr::ToBytes;
 use lmdb::{Error as LmdbError, Transaction, WriteFlags};
+use master_node::types::{BlockHash, DeployHash, DeployMetadata};
 
 use crate::{
-    common::db::{
-        BlockBodyDatabase, BlockHeaderDatabase, Database, DeployMetadataDatabase, STORAGE_FILE_NAME,
+    common::{
+        db::{
+            BlockBodyDatabase, BlockBodyMerkleDatabase, BlockHeaderDatabase, BlockMetadataDatabase,
+            Database, DeployDatabase, DeployHashesDatabase, DeployMetadataDatabase,
+            ProposerDatabase, TransferDatabase, TransferHashesDatabase, STORAGE_FILE_NAME,
+        },
+        journal::{self, Journal},
     },
     subcommands::{
         execution_results_summary::block_body::BlockBody,
-        remove_block::{remove::remove_block, Error},
+        remove_block::{
+            remove::{remove_blocks, BlockSelection, DatabaseRemovalSummary},
+            Error,
+        },
     },
     test_utils::{
-        mock_block_header, mock_deploy_hash, mock_deploy_metadata, LmdbTestFixture, MockBlockHeader,
+        chain_fixture, has_entry, mock_block_header, mock_deploy_hash, mock_deploy_metadata,
+        populate_chain, put_entry, LmdbTestFixture, MockBlockHeader,
     },
 };
 
@@ -81,7 +92,13 @@ fn remove_block_should_work() {
         txn.commit().unwrap();
     };
 
-    assert!(remove_block(test_fixture.tmp_dir.path(), block_headers[0].0).is_ok());
+    assert!(remove_blocks(
+        test_fixture.tmp_dir.path(),
+        BlockSelection::Hash(block_headers[0].0),
+        false,
+        None
+    )
+    .is_ok());
 
     {
         let txn = test_fixture.env.begin_ro_txn().unwrap();
@@ -232,7 +249,13 @@ fn remove_block_no_deploys() {
         txn.commit().unwrap();
     };
 
-    assert!(remove_block(test_fixture.tmp_dir.path(), block_headers[0].0).is_ok());
+    assert!(remove_blocks(
+        test_fixture.tmp_dir.path(),
+        BlockSelection::Hash(block_headers[0].0),
+        false,
+        None
+    )
+    .is_ok());
 
     {
         let txn = test_fixture.env.begin_ro_txn().unwrap();
@@ -331,7 +354,7 @@ fn remove_block_missing_header() {
 
     let (block_hash, _block_header) = mock_block_header(0);
     assert!(
-        matches!(remove_block(test_fixture.tmp_dir.path(), block_hash).unwrap_err(), Error::MissingHeader(actual_block_hash) if block_hash == actual_block_hash)
+        matches!(remove_blocks(test_fixture.tmp_dir.path(), BlockSelection::Hash(block_hash), false, None).unwrap_err(), Error::MissingHeader(actual_block_hash) if block_hash == actual_block_hash)
     );
 }
 
@@ -393,7 +416,13 @@ fn remove_block_missing_body() {
         txn.commit().unwrap();
     };
 
-    assert!(remove_block(test_fixture.tmp_dir.path(), block_headers[0].0).is_ok());
+    assert!(remove_blocks(
+        test_fixture.tmp_dir.path(),
+        BlockSelection::Hash(block_headers[0].0),
+        false,
+        None
+    )
+    .is_ok());
 
     {
         let txn = test_fixture.env.begin_ro_txn().unwrap();
@@ -475,8 +504,19 @@ fn remove_block_missing_deploys() {
     };
 
     assert!(
-        matches!(remove_block(test_fixture.tmp_dir.path(), block_hash).unwrap_err(), Error::MissingDeploy(actual_deploy_hash) if deploy_hash == actual_deploy_hash)
+        matches!(remove_blocks(test_fixture.tmp_dir.path(), BlockSelection::Hash(block_hash), false, None).unwrap_err(), Error::MissingDeploy(actual_deploy_hash) if deploy_hash == actual_deploy_hash)
     );
+
+    // Height ranges skip the deploys without metadata.
+    let height = block_header.height;
+    let removal = remove_blocks(
+        test_fixture.tmp_dir.path(),
+        BlockSelection::HeightRange(height..=height),
+        false,
+        None,
+    )
+    .unwrap();
+    assert_eq!(removal.blocks, vec![(height, block_hash)]);
 }
 
 #[test]
@@ -509,7 +549,7 @@ fn remove_block_invalid_header() {
     };
 
     assert!(
-        matches!(remove_block(test_fixture.tmp_dir.path(), block_hash).unwrap_err(), Error::HeaderParsing(actual_block_hash, _) if block_hash == actual_block_hash)
+        matches!(remove_blocks(test_fixture.tmp_dir.path(), BlockSelection::Hash(block_hash), false, None).unwrap_err(), Error::HeaderParsing(actual_block_hash, _) if block_hash == actual_block_hash)
     );
 }
 
@@ -550,9 +590,459 @@ fn remove_block_invalid_body() {
     };
 
     assert!(
-        matches!(remove_block(test_fixture.tmp_dir.path(), block_hash).unwrap_err(), Error::BodyParsing(actual_block_hash, _) if block_hash == actual_block_hash)
+        matches!(remove_blocks(test_fixture.tmp_dir.path(), BlockSelection::Hash(block_hash), false, None).unwrap_err(), Error::BodyParsing(actual_block_hash, _) if block_hash == actual_block_hash)
+    );
+}
+
+fn assert_block_present(
+    fixture: &LmdbTestFixture,
+    (block_hash, block_header, deploy_hash): &(BlockHash, MockBlockHeader, DeployHash),
+    present: bool,
+) {
+    assert_eq!(
+        has_entry(fixture, BlockHeaderDatabase::db_name(), block_hash.as_ref()),
+        present
+    );
+    assert_eq!(
+        has_entry(
+            fixture,
+            BlockBodyDatabase::db_name(),
+            block_header.body_hash.as_ref()
+        ),
+        present
     );
+    for db_name in [
+        TransferDatabase::db_name(),
+        BlockMetadataDatabase::db_name(),
+    ] {
+        assert_eq!(has_entry(fixture, db_name, block_hash.as_ref()), present);
+    }
+    for db_name in [DeployDatabase::db_name(), DeployMetadataDatabase::db_name()] {
+        assert_eq!(has_entry(fixture, db_name, deploy_hash.as_ref()), present);
+    }
+}
+
+#[test]
+fn remove_blocks_by_height_range() {
+    let fixture = chain_fixture();
+    let blocks = populate_chain(&fixture, 4);
+
+    let removal = remove_blocks(
+        fixture.tmp_dir.path(),
+        BlockSelection::HeightRange(1..=2),
+        false,
+        None,
+    )
+    .unwrap();
+    assert_eq!(removal.blocks, vec![(1, blocks[1].0), (2, blocks[2].0)]);
+    assert!(removal.updated.is_empty());
+    assert_block_present(&fixture, &blocks[0], true);
+    assert_block_present(&fixture, &blocks[1], false);
+    assert_block_present(&fixture, &blocks[2], false);
+    assert_block_present(&fixture, &blocks[3], true);
+}
+
+#[test]
+fn remove_blocks_should_truncate_chain() {
+    let fixture = chain_fixture();
+    let blocks = populate_chain(&fixture, 4);
+
+    let removal = remove_blocks(
+        fixture.tmp_dir.path(),
+        BlockSelection::HeightRange(2..=u64::MAX),
+        false,
+        None,
+    )
+    .unwrap();
+    assert_eq!(removal.blocks.len(), 2);
+    assert_block_present(&fixture, &blocks[0], true);
+    assert_block_present(&fixture, &blocks[1], true);
+    assert_block_present(&fixture, &blocks[2], false);
+    assert_block_present(&fixture, &blocks[3], false);
+
+    // Nothing is left above the new tip.
+    let removal = remove_blocks(
+        fixture.tmp_dir.path(),
+        BlockSelection::HeightRange(2..=u64::MAX),
+        false,
+        None,
+    )
+    .unwrap();
+    assert!(removal.blocks.is_empty());
 }
 
+#[test]
+fn remove_blocks_dry_run_should_not_change_db() {
+    let fixture = chain_fixture();
+    let blocks = populate_chain(&fixture, 3);
+
+    let removal = remove_blocks(
+        fixture.tmp_dir.path(),
+        BlockSelection::HeightRange(1..=u64::MAX),
+        true,
+        None,
+    )
+    .unwrap();
+    assert_eq!(removal.blocks, vec![(1, blocks[1].0), (2, blocks[2].0)]);
+    // Header, body, transfers, signatures, deploy and deploy metadata of each
+    // block.
+    assert_eq!(removal.deleted.len(), 12);
+    for block in blocks.iter() {
+        assert_block_present(&fixture, block, true);
+    }
+}
+
+#[test]
+fn remove_block_should_keep_shared_body_entries() {
+    let fixture = chain_fixture();
+    let mut blocks = populate_chain(&fixture, 2);
+
+    // Both blocks link to the same tail of the merkle tree, and the second
+    // block also shares its body with a third block.
+    let shared_link = Digest::hash_pair(Digest::hash([1u8]), Digest::hash([2u8]));
+    let removed_link = Digest::hash_pair(Digest::hash([3u8]), shared_link);
+    let removed_root = Digest::hash_pair(Digest::hash([4u8]), removed_link);
+    let kept_root = Digest::hash_pair(Digest::hash([5u8]), shared_link);
+    let links = [
+        (shared_link, (Digest::hash([1u8]), Digest::hash([2u8]))),
+        (removed_link, (Digest::hash([3u8]), shared_link)),
+        (removed_root, (Digest::hash([4u8]), removed_link)),
+        (kept_root, (Digest::hash([5u8]), shared_link)),
+    ];
+    for (key, link) in links {
+        put_entry(
+            &fixture,
+            BlockBodyMerkleDatabase::db_name(),
+            key.as_ref(),
+            &link.to_bytes().unwrap(),
+        );
+    }
+    let shared_leaf = Digest::hash([1u8]);
+    let removed_leaf = Digest::hash([3u8]);
+    let removed_proposer = Digest::hash([4u8]);
+    put_entry(
+        &fixture,
+        DeployHashesDatabase::db_name(),
+        shared_leaf.as_ref(),
+        &[1u8],
+    );
+    put_entry(
+        &fixture,
+        TransferHashesDatabase::db_name(),
+        removed_leaf.as_ref(),
+        &[3u8],
+    );
+    put_entry(
+        &fixture,
+        ProposerDatabase::db_name(),
+        removed_proposer.as_ref(),
+        &[4u8],
+    );
+    for (block, body_hash) in blocks.iter_mut().zip([removed_root, kept_root]) {
+        block.1.body_hash = body_hash;
+        put_entry(
+            &fixture,
+            BlockHeaderDatabase::db_name(),
+            block.0.as_ref(),
+            &bincode::serialize(&block.1).unwrap(),
+        );
+        put_entry(
+            &fixture,
+            BlockBodyDatabase::db_name(),
+            body_hash.as_ref(),
+            &bincode::serialize(&BlockBody::new(vec![block.2])).unwrap(),
+        );
+    }
+    let (sharing_block_hash, mut sharing_block_header) = mock_block_header(2);
+    sharing_block_header.height = 2;
+    sharing_block_header.body_hash = kept_root;
+    put_entry(
+        &fixture,
+        BlockHeaderDatabase::db_name(),
+        sharing_block_hash.as_ref(),
+        &bincode::serialize(&sharing_block_header).unwrap(),
+    );
 
-      
+    remove_blocks(
+        fixture.tmp_dir.path(),
+        BlockSelection::HeightRange(0..=0),
+        false,
+        None,
+    )
+    .unwrap();
+    assert!(!has_entry(
+        &fixture,
+        BlockBodyMerkleDatabase::db_name(),
+        removed_root.as_ref()
+    ));
+    assert!(!has_entry(
+        &fixture,
+        BlockBodyMerkleDatabase::db_name(),
+        removed_link.as_ref()
+    ));
+    assert!(has_entry(
+        &fixture,
+        BlockBodyMerkleDatabase::db_name(),
+        shared_link.as_ref()
+    ));
+    assert!(has_entry(
+        &fixture,
+        BlockBodyMerkleDatabase::db_name(),
+        kept_root.as_ref()
+    ));
+    assert!(has_entry(
+        &fixture,
+        DeployHashesDatabase::db_name(),
+        shared_leaf.as_ref()
+    ));
+    assert!(!has_entry(
+        &fixture,
+        TransferHashesDatabase::db_name(),
+        removed_leaf.as_ref()
+    ));
+    assert!(!has_entry(
+        &fixture,
+        ProposerDatabase::db_name(),
+        removed_proposer.as_ref()
+    ));
+
+    remove_blocks(
+        fixture.tmp_dir.path(),
+        BlockSelection::HeightRange(1..=1),
+        false,
+        None,
+    )
+    .unwrap();
+    assert!(has_entry(
+        &fixture,
+        BlockBodyDatabase::db_name(),
+        kept_root.as_ref()
+    ));
+    assert!(has_entry(
+        &fixture,
+        BlockBodyMerkleDatabase::db_name(),
+        kept_root.as_ref()
+    ));
+    assert!(has_entry(
+        &fixture,
+        BlockBodyMerkleDatabase::db_name(),
+        shared_link.as_ref()
+    ));
+}
+
+#[test]
+fn remove_block_by_hash_should_remove_unshared_merkle_chain() {
+    let fixture = chain_fixture();
+    let mut blocks = populate_chain(&fixture, 2);
+
+    let leaf = Digest::hash([1u8]);
+    let tail_leaf = Digest::hash([2u8]);
+    let tail_link = Digest::hash_pair(tail_leaf, Digest::hash([3u8]));
+    let root = Digest::hash_pair(leaf, tail_link);
+    for (key, link) in [
+        (tail_link, (tail_leaf, Digest::hash([3u8]))),
+        (root, (leaf, tail_link)),
+    ] {
+        put_entry(
+            &fixture,
+            BlockBodyMerkleDatabase::db_name(),
+            key.as_ref(),
+            &link.to_bytes().unwrap(),
+        );
+    }
+    put_entry(
+        &fixture,
+        DeployHashesDatabase::db_name(),
+        leaf.as_ref(),
+        &[1u8],
+    );
+    put_entry(
+        &fixture,
+        TransferHashesDatabase::db_name(),
+        tail_leaf.as_ref(),
+        &[2u8],
+    );
+    blocks[0].1.body_hash = root;
+    put_entry(
+        &fixture,
+        BlockHeaderDatabase::db_name(),
+        blocks[0].0.as_ref(),
+        &bincode::serialize(&blocks[0].1).unwrap(),
+    );
+    put_entry(
+        &fixture,
+        BlockBodyDatabase::db_name(),
+        root.as_ref(),
+        &bincode::serialize(&BlockBody::new(vec![blocks[0].2])).unwrap(),
+    );
+
+    remove_blocks(
+        fixture.tmp_dir.path(),
+        BlockSelection::Hash(blocks[0].0),
+        false,
+        None,
+    )
+    .unwrap();
+    assert!(!has_entry(
+        &fixture,
+        BlockBodyDatabase::db_name(),
+        root.as_ref()
+    ));
+    for key in [root, tail_link] {
+        assert!(!has_entry(
+            &fixture,
+            BlockBodyMerkleDatabase::db_name(),
+            key.as_ref()
+        ));
+    }
+    assert!(!has_entry(
+        &fixture,
+        DeployHashesDatabase::db_name(),
+        leaf.as_ref()
+    ));
+    assert!(!has_entry(
+        &fixture,
+        TransferHashesDatabase::db_name(),
+        tail_leaf.as_ref()
+    ));
+    assert_block_present(&fixture, &blocks[1], true);
+}
+
+#[test]
+fn remove_block_by_hash_should_keep_body_shared_with_other_block() {
+    let fixture = chain_fixture();
+    let mut blocks = populate_chain(&fixture, 2);
+
+    // Both blocks have an identical body, e.g. empty blocks of the same
+    // proposer.
+    let leaf = Digest::hash([1u8]);
+    let body_hash = blocks[0].1.body_hash;
+    put_entry(
+        &fixture,
+        BlockBodyMerkleDatabase::db_name(),
+        body_hash.as_ref(),
+        &(leaf, Digest::hash([2u8])).to_bytes().unwrap(),
+    );
+    put_entry(&fixture, ProposerDatabase::db_name(), leaf.as_ref(), &[1u8]);
+    blocks[1].1.body_hash = body_hash;
+    put_entry(
+        &fixture,
+        BlockHeaderDatabase::db_name(),
+        blocks[1].0.as_ref(),
+        &bincode::serialize(&blocks[1].1).unwrap(),
+    );
+
+    remove_blocks(
+        fixture.tmp_dir.path(),
+        BlockSelection::Hash(blocks[0].0),
+        false,
+        None,
+    )
+    .unwrap();
+    assert!(!has_entry(
+        &fixture,
+        BlockHeaderDatabase::db_name(),
+        blocks[0].0.as_ref()
+    ));
+    assert!(has_entry(
+        &fixture,
+        BlockHeaderDatabase::db_name(),
+        blocks[1].0.as_ref()
+    ));
+    assert!(has_entry(
+        &fixture,
+        BlockBodyDatabase::db_name(),
+        body_hash.as_ref()
+    ));
+    assert!(has_entry(
+        &fixture,
+        BlockBodyMerkleDatabase::db_name(),
+        body_hash.as_ref()
+    ));
+    assert!(has_entry(
+        &fixture,
+        ProposerDatabase::db_name(),
+        leaf.as_ref()
+    ));
+}
+
+#[test]
+fn remove_block_should_summarize_removal_per_db() {
+    let fixture = chain_fixture();
+    let blocks = populate_chain(&fixture, 2);
+    let (block_hash, block_header) = mock_block_header(2);
+    let shared_deploy_metadata = mock_deploy_metadata(&[blocks[1].0, block_hash]);
+    put_entry(
+        &fixture,
+        DeployMetadataDatabase::db_name(),
+        blocks[1].2.as_ref(),
+        &bincode::serialize(&shared_deploy_metadata).unwrap(),
+    );
+    put_entry(
+        &fixture,
+        BlockHeaderDatabase::db_name(),
+        block_hash.as_ref(),
+        &bincode::serialize(&block_header).unwrap(),
+    );
+
+    let removal = remove_blocks(
+        fixture.tmp_dir.path(),
+        BlockSelection::Hash(blocks[1].0),
+        false,
+        None,
+    )
+    .unwrap();
+    let summary = removal.summary(false);
+    let deleted_once = DatabaseRemovalSummary {
+        deleted: 1,
+        updated: 0,
+    };
+    assert_eq!(
+        summary.databases.into_iter().collect::<Vec<_>>(),
+        vec![
+            (BlockBodyDatabase::db_name(), deleted_once.clone()),
+            (BlockHeaderDatabase::db_name(), deleted_once.clone()),
+            (BlockMetadataDatabase::db_name(), deleted_once.clone()),
+            (
+                DeployMetadataDatabase::db_name(),
+                DatabaseRemovalSummary {
+                    deleted: 0,
+                    updated: 1
+                }
+            ),
+            (TransferDatabase::db_name(), deleted_once),
+        ]
+    );
+    // The deploy is still executed in the remaining block.
+    assert!(has_entry(
+        &fixture,
+        DeployDatabase::db_name(),
+        blocks[1].2.as_ref()
+    ));
+}
+
+#[test]
+fn remove_blocks_should_be_undone_from_journal() {
+    let fixture = chain_fixture();
+    let blocks = populate_chain(&fixture, 3);
+
+    let journal_path = fixture.tmp_dir.path().join("remove.journal");
+    let mut journal = Journal::create(&journal_path, &fixture.file_path).unwrap();
+    let removal = remove_blocks(
+        fixture.tmp_dir.path(),
+        BlockSelection::HeightRange(1..=u64::MAX),
+        false,
+        Some(&mut journal),
+    )
+    .unwrap();
+    drop(journal);
+    assert_eq!(removal.blocks.len(), 2);
+    for block in blocks.iter().skip(1) {
+        assert_block_present(&fixture, block, false);
+    }
+
+    assert_eq!(journal::undo(&journal_path).unwrap(), removal.deleted.len());
+    for block in blocks.iter() {
+        assert_block_present(&fixture, block, true);
+    }
+}
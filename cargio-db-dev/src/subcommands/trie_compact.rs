@@ -1,10 +1,14 @@
-mod compact;
+mod checkpoint;
+pub(crate) mod compact;
 mod helpers;
 #[cfg(test)]
 pub(crate) mod tests;
 mod utils;
 
-use std::{io::Error as IoError, path::PathBuf};
+use std::{
+    io::Error as IoError,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Error as AnyError;
 use clap::{Arg, ArgMatches, Command};
@@ -12,23 +16,40 @@ use lmdb::Error as LmdbError;
 use thiserror::Error as ThisError;
 
 use cargio_hashing::Digest;
-use master_node::storage::Error as StorageError;
 
-use compact::DestinationOptions;
+use crate::{
+    common::{block_index::BlockIndexError, journal::JournalError},
+    subcommands::execution_results_summary,
+};
+
+use checkpoint::CheckpointError;
+use compact::{BlockRange, DestinationOptions};
 pub use helpers::copy_state_root;
+pub(crate) use helpers::trie_pointers;
 pub use utils::{create_execution_engine, load_execution_engine};
 
 pub const COMMAND_NAME: &str = "compact-trie";
 const APPEND: &str = "append";
+const CHECKPOINT: &str = "checkpoint";
 const DESTINATION_TRIE_STORE_PATH: &str = "dest-trie";
+const DRY_RUN: &str = "dry-run";
+const FROM_HEIGHT: &str = "from-height";
+const JOURNAL: &str = "journal";
+const LAST_N_BLOCKS: &str = "last-n-blocks";
 const OVERWRITE: &str = "overwrite";
+const RESUME: &str = "resume";
 const MAX_DB_SIZE: &str = "max-db-size";
 pub const DEFAULT_MAX_DB_SIZE: &str = "483183820800"; // 450 gb
 const SOURCE_TRIE_STORE_PATH: &str = "src-trie";
 const STORAGE_PATH: &str = "storage-path";
+const TO_HEIGHT: &str = "to-height";
 
 #[derive(Debug, ThisError)]
 pub enum Error {
+    #[error("Error reading the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
+    #[error("Error operating the checkpoint: {0}")]
+    Checkpoint(#[from] CheckpointError),
     #[error("Error copying state root {0}: {1}")]
     CopyStateRoot(Digest, AnyError),
     #[error("Error loading the execution engine: {0}")]
@@ -37,16 +58,16 @@ pub enum Error {
     InvalidDest(String),
     #[error("Path {0} cannot be created/resolved: {1}")]
     InvalidPath(PathBuf, IoError),
+    #[error("Error writing the journal: {0}")]
+    Journal(#[from] JournalError),
     #[error("Error while operating on LMDB: {0}")]
     LmdbOperation(LmdbError),
     #[error("Storage database is missing block {0}")]
     MissingBlock(u64),
     #[error("Error creating the execution engine: {0}")]
     OpenSourceTrie(AnyError),
-    #[error("Error opening the block/deploy storage: {0}")]
-    OpenStorage(AnyError),
-    #[error("Storage error while trying to retrieve block {0}: {1}")]
-    Storage(u64, StorageError),
+    #[error("Error reading block headers: {0}")]
+    Traversal(#[from] execution_results_summary::Error),
 }
 
 enum DisplayOrder {
@@ -55,7 +76,14 @@ enum DisplayOrder {
     StoragePath,
     Append,
     Overwrite,
+    Checkpoint,
+    Resume,
     MaxDbSize,
+    FromHeight,
+    ToHeight,
+    LastNBlocks,
+    DryRun,
+    Journal,
 }
 
 pub fn command(display_order: usize) -> Command<'static> {
@@ -63,7 +91,8 @@ pub fn command(display_order: usize) -> Command<'static> {
         .display_order(display_order)
         .about(
             "Writes a compacted version of the block entries in the source trie store to the \
-            destination.",
+            destination. By default the state roots of all blocks are copied, which can be \
+            limited to a range of heights or to the most recent blocks.",
         )
         .arg(
             Arg::new(SOURCE_TRIE_STORE_PATH)
@@ -124,6 +153,34 @@ pub fn command(display_order: usize) -> Command<'static> {
                     directory.",
                 ),
         )
+        .arg(
+            Arg::new(CHECKPOINT)
+                .display_order(DisplayOrder::Checkpoint as usize)
+                .short('k')
+                .long(CHECKPOINT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help(
+                    "Path of a file where the height and state root of every copied block \
+                    are recorded while copying.",
+                ),
+        )
+        .arg(
+            Arg::new(RESUME)
+                .display_order(DisplayOrder::Resume as usize)
+                .required(false)
+                .short('r')
+                .long(RESUME)
+                .takes_value(false)
+                .requires(CHECKPOINT)
+                .conflicts_with_all(&[APPEND, OVERWRITE, FROM_HEIGHT, TO_HEIGHT, LAST_N_BLOCKS])
+                .help(
+                    "Resume an interrupted run from the \"--checkpoint\" file, copying the \
+                    state roots of the blocks of its range below the last recorded one. A run \
+                    interrupted while clearing the destination for \"--overwrite\" has to be \
+                    started again.",
+                ),
+        )
         .arg(
             Arg::new(MAX_DB_SIZE)
                 .display_order(DisplayOrder::MaxDbSize as usize)
@@ -135,6 +192,76 @@ pub fn command(display_order: usize) -> Command<'static> {
                 .value_name("MAX_DB_SIZE")
                 .help("Maximum size the DB files are allowed to be, in bytes."),
         )
+        .arg(
+            Arg::new(FROM_HEIGHT)
+                .display_order(DisplayOrder::FromHeight as usize)
+                .short('f')
+                .long(FROM_HEIGHT)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .conflicts_with(LAST_N_BLOCKS)
+                .help("Height of the lowest block whose state root is copied. Defaults to 0."),
+        )
+        .arg(
+            Arg::new(TO_HEIGHT)
+                .display_order(DisplayOrder::ToHeight as usize)
+                .short('t')
+                .long(TO_HEIGHT)
+                .takes_value(true)
+                .value_name("BLOCK_HEIGHT")
+                .conflicts_with(LAST_N_BLOCKS)
+                .help(
+                    "Height of the highest block whose state root is copied. Defaults to the \
+                    highest block in the storage.",
+                ),
+        )
+        .arg(
+            Arg::new(LAST_N_BLOCKS)
+                .display_order(DisplayOrder::LastNBlocks as usize)
+                .short('l')
+                .long(LAST_N_BLOCKS)
+                .takes_value(true)
+                .value_name("BLOCK_COUNT")
+                .help(
+                    "Only copies the state roots of this many blocks, up to and including \
+                    the highest block in the storage.",
+                ),
+        )
+        .arg(
+            Arg::new(DRY_RUN)
+                .display_order(DisplayOrder::DryRun as usize)
+                .short('n')
+                .long(DRY_RUN)
+                .takes_value(false)
+                .help(
+                    "Logs the state roots which would be copied with the number of tries \
+                    each would write and, with `--overwrite`, the number of destination \
+                    entries which would be deleted, without changing the destination.",
+                ),
+        )
+        .arg(
+            Arg::new(JOURNAL)
+                .display_order(DisplayOrder::Journal as usize)
+                .short('j')
+                .long(JOURNAL)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .conflicts_with(DRY_RUN)
+                .help(
+                    "Path of a new journal file recording the prior state of every changed \
+                    destination entry, which the `undo` command restores. With \
+                    `--overwrite`, the destination entries are deleted and journaled instead \
+                    of truncating the file.",
+                ),
+        )
+}
+
+fn parse_height(matches: &ArgMatches, arg: &str) -> Option<u64> {
+    matches.value_of(arg).map(|height| {
+        height
+            .parse()
+            .unwrap_or_else(|_| panic!("Value of \"--{arg}\" must be an integer."))
+    })
 }
 
 pub fn run(matches: &ArgMatches) -> Result<(), Error> {
@@ -144,6 +271,7 @@ pub fn run(matches: &ArgMatches) -> Result<(), Error> {
     let dest_opt = match matches {
         _ if matches.is_present(APPEND) => DestinationOptions::Append,
         _ if matches.is_present(OVERWRITE) => DestinationOptions::Overwrite,
+        _ if matches.is_present(RESUME) => DestinationOptions::Resume,
         _ => DestinationOptions::New,
     };
     let max_db_size = matches
@@ -151,6 +279,14 @@ pub fn run(matches: &ArgMatches) -> Result<(), Error> {
         .unwrap()
         .parse()
         .expect("Value of \"--max-db-size\" must be an integer.");
+    let block_range = match parse_height(matches, LAST_N_BLOCKS) {
+        Some(0) => panic!("Value of \"--{LAST_N_BLOCKS}\" must be a positive integer."),
+        Some(count) => BlockRange::Last(count),
+        None => BlockRange::Heights(
+            parse_height(matches, FROM_HEIGHT).unwrap_or(0)
+                ..=parse_height(matches, TO_HEIGHT).unwrap_or(u64::MAX),
+        ),
+    };
 
     compact::trie_compact(
         storage_path,
@@ -158,5 +294,9 @@ pub fn run(matches: &ArgMatches) -> Result<(), Error> {
         destination_trie_path,
         dest_opt,
         max_db_size,
+        block_range,
+        matches.value_of(CHECKPOINT).map(Path::new),
+        matches.value_of(JOURNAL).map(Path::new),
+        matches.is_present(DRY_RUN),
     )
 }
@@ -0,0 +1,182 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{Error as IoError, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Error as JsonError;
+use thiserror::Error;
+
+use cargio_hashing::Digest;
+
+const CHECKPOINT_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    #[error("Error accessing checkpoint file {0}: {1}")]
+    Io(PathBuf, IoError),
+    #[error("Error (de)serializing checkpoint file {0}: {1}")]
+    Json(PathBuf, JsonError),
+    #[error("Checkpoint file {0} has no header")]
+    MissingHeader(PathBuf),
+    #[error("Checkpoint file {0} has unsupported version {1}")]
+    UnsupportedVersion(PathBuf, u32),
+}
+
+/// First line of a checkpoint, describing the run.
+#[derive(Debug, Serialize, Deserialize)]
+struct CheckpointHeader {
+    version: u32,
+    lowest_height: u64,
+    top_height: u64,
+}
+
+/// A line of the checkpoint for each state root which was copied and
+/// flushed to the destination.
+#[derive(Debug, Serialize, Deserialize)]
+struct CompletedStateRoot {
+    height: u64,
+    state_root: Digest,
+}
+
+/// What an interrupted run had copied when it stopped.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Progress {
+    pub(crate) lowest_height: u64,
+    pub(crate) top_height: u64,
+    /// Height of the lowest block whose state root was copied. The state
+    /// roots of all blocks from there up to `top_height` are in the
+    /// destination.
+    pub(crate) maybe_last_height: Option<u64>,
+    pub(crate) completed: HashSet<Digest>,
+    /// Length of the checkpoint without a last line cut short by an
+    /// interruption.
+    valid_len: u64,
+}
+
+impl Progress {
+    /// Returns the height to continue copying from, or `None` if every
+    /// block was copied.
+    pub(crate) fn resume_height(&self) -> Option<u64> {
+        match self.maybe_last_height {
+            None => Some(self.top_height),
+            Some(last_height) if last_height > self.lowest_height => Some(last_height - 1),
+            Some(_) => None,
+        }
+    }
+}
+
+/// An append only record of the state roots copied by a `compact-trie` run,
+/// from which an interrupted run is resumed. Unlike the checkpoint of
+/// `check`, it is never rewritten, since a run records millions of state
+/// roots.
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    file: File,
+}
+
+impl Checkpoint {
+    /// Starts a new checkpoint at `path`, replacing any previous one.
+    pub(crate) fn create<P: AsRef<Path>>(
+        path: P,
+        lowest_height: u64,
+        top_height: u64,
+    ) -> Result<Self, CheckpointError> {
+        let path = path.as_ref().to_path_buf();
+        let file =
+            File::create(&path).map_err(|io_err| CheckpointError::Io(path.clone(), io_err))?;
+        let mut checkpoint = Self { path, file };
+        checkpoint.write_line(&CheckpointHeader {
+            version: CHECKPOINT_VERSION,
+            lowest_height,
+            top_height,
+        })?;
+        Ok(checkpoint)
+    }
+
+    /// Opens the checkpoint at `path` read by `read_progress` to record
+    /// further progress, discarding a last line cut short by an interruption.
+    pub(crate) fn resume<P: AsRef<Path>>(
+        path: P,
+        progress: &Progress,
+    ) -> Result<Self, CheckpointError> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .and_then(|file| file.set_len(progress.valid_len).map(|_| file))
+            .map_err(|io_err| CheckpointError::Io(path.clone(), io_err))?;
+        Ok(Self { path, file })
+    }
+
+    /// Records that the state root of the block at `height` was copied. The
+    /// destination must be flushed before, so the checkpoint never gets
+    /// ahead of it.
+    pub(crate) fn record(
+        &mut self,
+        height: u64,
+        state_root: Digest,
+    ) -> Result<(), CheckpointError> {
+        self.write_line(&CompletedStateRoot { height, state_root })
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<(), CheckpointError> {
+        let mut line = serde_json::to_vec(value)
+            .map_err(|serde_err| CheckpointError::Json(self.path.clone(), serde_err))?;
+        line.push(b'\n');
+        self.file
+            .write_all(&line)
+            .and_then(|_| self.file.sync_data())
+            .map_err(|io_err| CheckpointError::Io(self.path.clone(), io_err))
+    }
+}
+
+/// Removes the checkpoint at `path`, if there is one.
+pub(crate) fn remove<P: AsRef<Path>>(path: P) -> Result<(), CheckpointError> {
+    match fs::remove_file(&path) {
+        Err(io_err) if io_err.kind() != ErrorKind::NotFound => {
+            Err(CheckpointError::Io(path.as_ref().to_path_buf(), io_err))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads the progress recorded in the checkpoint at `path`.
+pub(crate) fn read_progress<P: AsRef<Path>>(path: P) -> Result<Progress, CheckpointError> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .map_err(|io_err| CheckpointError::Io(path.to_path_buf(), io_err))?;
+    let valid_len = contents.rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+    let mut lines = contents[..valid_len].lines();
+
+    let header: CheckpointHeader = match lines.next() {
+        Some(line) => serde_json::from_str(line)
+            .map_err(|serde_err| CheckpointError::Json(path.to_path_buf(), serde_err))?,
+        None => return Err(CheckpointError::MissingHeader(path.to_path_buf())),
+    };
+    if header.version != CHECKPOINT_VERSION {
+        return Err(CheckpointError::UnsupportedVersion(
+            path.to_path_buf(),
+            header.version,
+        ));
+    }
+    let mut progress = Progress {
+        lowest_height: header.lowest_height,
+        top_height: header.top_height,
+        valid_len: valid_len as u64,
+        ..Default::default()
+    };
+    for line in lines {
+        let completed: CompletedStateRoot = serde_json::from_str(line)
+            .map_err(|serde_err| CheckpointError::Json(path.to_path_buf(), serde_err))?;
+        progress.completed.insert(completed.state_root);
+        progress.maybe_last_height = Some(
+            progress
+                .maybe_last_height
+                .map_or(completed.height, |height| height.min(completed.height)),
+        );
+    }
+    Ok(progress)
+}
@@ -1,28 +1,70 @@
 use std::{
-    collections::HashSet,
-    fs::{self, File, OpenOptions},
+    collections::{BTreeMap, HashSet},
+    fs::{File, OpenOptions},
+    ops::RangeInclusive,
     path::Path,
 };
 
+use lmdb::{Cursor, Transaction};
 use log::info;
 
 use cargio_hashing::Digest;
 
-use crate::common::db::TRIE_STORE_FILE_NAME;
+use crate::{
+    common::{
+        block_index::BlockIndex,
+        db::{self, BlockHeaderDatabase, Database, STORAGE_FILE_NAME, TRIE_STORE_FILE_NAME},
+        journal::{Journal, JournaledTransaction},
+        lmdb_utils,
+    },
+    subcommands::execution_results_summary::read_db,
+};
 
 use super::{
-    utils::{create_execution_engine, create_storage, load_execution_engine},
+    checkpoint::{self, Checkpoint},
+    utils::{create_execution_engine, load_execution_engine},
     Error,
 };
 
+/// Number of destination entries deleted per transaction when clearing a
+/// journaled destination.
+const CLEAR_BATCH_SIZE: usize = 10_000;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DestinationOptions {
     Append,
     Overwrite,
     New,
+    /// Continues an interrupted run from its checkpoint.
+    Resume,
+}
+
+/// The blocks whose state roots are copied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlockRange {
+    /// Blocks with a height in the range, inclusive.
+    Heights(RangeInclusive<u64>),
+    /// The given number of blocks up to and including the highest one.
+    Last(u64),
+}
+
+impl BlockRange {
+    /// Returns the lowest and highest heights in the range, given the height
+    /// of the highest block in the storage. The range is empty if the lowest
+    /// height is greater than the highest one.
+    pub(crate) fn bounds(&self, highest_height: u64) -> (u64, u64) {
+        match self {
+            BlockRange::Heights(heights) => {
+                (*heights.start(), (*heights.end()).min(highest_height))
+            }
+            BlockRange::Last(count) => {
+                ((highest_height + 1).saturating_sub(*count), highest_height)
+            }
+        }
+    }
 }
 
-fn validate_trie_paths<P1: AsRef<Path>, P2: AsRef<Path>>(
+pub(crate) fn validate_trie_paths<P1: AsRef<Path>, P2: AsRef<Path>>(
     source_trie_path: P1,
     destination_trie_path: P2,
     dest_opt: DestinationOptions,
@@ -31,11 +73,8 @@ fn validate_trie_paths<P1: AsRef<Path>, P2: AsRef<Path>>(
 
     if !dest_path_exists {
         match dest_opt {
-            DestinationOptions::New => {
-                fs::create_dir_all(destination_trie_path.as_ref()).map_err(|err| {
-                    Error::InvalidPath(destination_trie_path.as_ref().to_owned(), err)
-                })?;
-            }
+            // The destination directory is created along with the trie store.
+            DestinationOptions::New => return Ok(()),
             DestinationOptions::Append => {
                 return Err(Error::InvalidDest(
                     "No destination trie to append. Consider not using \"--append\".".to_string(),
@@ -47,6 +86,11 @@ fn validate_trie_paths<P1: AsRef<Path>, P2: AsRef<Path>>(
                         .to_string(),
                 ));
             }
+            DestinationOptions::Resume => {
+                return Err(Error::InvalidDest(
+                    "No destination trie to resume. Consider not using \"--resume\".".to_string(),
+                ));
+            }
         }
     } else {
         let dest_data_exists = destination_trie_path
@@ -80,17 +124,7 @@ fn validate_trie_paths<P1: AsRef<Path>, P2: AsRef<Path>>(
                 }
             }
             DestinationOptions::Overwrite => {
-                if dest_data_exists {
-                    let _f: File = OpenOptions::new()
-                        .truncate(true)
-                        .write(true)
-                        .open(destination_trie_path.as_ref().join(TRIE_STORE_FILE_NAME))
-                        .map_err(|io_err| {
-                            Error::InvalidDest(format!(
-                                "Couldn't overwrite destination file: {io_err}"
-                            ))
-                        })?;
-                } else {
+                if !dest_data_exists {
                     return Err(Error::InvalidDest(format!(
                         "Nothing to overwrite, output file \"data.lmdb\" doesn't exist at \
                         destination \"{}\". Run the program without `--overwrite`",
@@ -101,6 +135,18 @@ fn validate_trie_paths<P1: AsRef<Path>, P2: AsRef<Path>>(
                     )));
                 }
             }
+            DestinationOptions::Resume => {
+                if !dest_data_exists {
+                    return Err(Error::InvalidDest(format!(
+                        "Nothing to resume, output file \"data.lmdb\" doesn't exist at \
+                        destination \"{}\". Run the program without `--resume`",
+                        destination_trie_path
+                            .as_ref()
+                            .join(TRIE_STORE_FILE_NAME)
+                            .to_string_lossy()
+                    )));
+                }
+            }
         }
     }
 
@@ -121,61 +167,275 @@ fn validate_trie_paths<P1: AsRef<Path>, P2: AsRef<Path>>(
     Ok(())
 }
 
+/// Returns the state root hash of every block in the storage at
+/// `storage_path`, by height, using the block index if it is up to date.
+fn stored_state_roots(storage_path: &Path) -> Result<BTreeMap<u64, Digest>, Error> {
+    let env = db::db_env(storage_path.join(STORAGE_FILE_NAME)).map_err(Error::LmdbOperation)?;
+    let maybe_block_index = BlockIndex::open(storage_path, &env)?;
+    let txn = env.begin_ro_txn().map_err(Error::LmdbOperation)?;
+    let block_header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name())) }
+        .map_err(Error::LmdbOperation)?;
+    let mut state_roots = BTreeMap::new();
+    read_db::visit_blocks(
+        &txn,
+        block_header_db,
+        maybe_block_index.as_ref(),
+        0..=u64::MAX,
+        |_block_hash, header| {
+            state_roots.insert(header.height(), *header.state_root_hash());
+            Ok::<_, Error>(())
+        },
+    )?;
+    txn.commit().map_err(Error::LmdbOperation)?;
+    Ok(state_roots)
+}
+
+/// Returns the lowest and highest heights of the blocks in `block_range`, or
+/// `None` if the storage has no such blocks.
+fn height_bounds(
+    stored_state_roots: &BTreeMap<u64, Digest>,
+    block_range: &BlockRange,
+) -> Option<(u64, u64)> {
+    let highest_height = *stored_state_roots.keys().next_back()?;
+    let (lowest_height, top_height) = block_range.bounds(highest_height);
+    (lowest_height <= top_height).then_some((lowest_height, top_height))
+}
+
+/// Returns the state root hashes of the blocks from `top_height` down to
+/// `lowest_height` which aren't in `visited_roots`, without duplicates. Each
+/// state root comes with the height of the highest block it belongs to.
+fn state_roots(
+    stored_state_roots: &BTreeMap<u64, Digest>,
+    lowest_height: u64,
+    top_height: u64,
+    mut visited_roots: HashSet<Digest>,
+) -> Result<Vec<(u64, Digest)>, Error> {
+    let mut state_roots = vec![];
+    for block_height in (lowest_height..=top_height).rev() {
+        let state_root = *stored_state_roots
+            .get(&block_height)
+            .ok_or(Error::MissingBlock(block_height))?;
+        if visited_roots.insert(state_root) {
+            state_roots.push((block_height, state_root));
+        }
+    }
+    Ok(state_roots)
+}
+
+fn destination_entry_count(dest_data_path: &Path) -> Result<usize, Error> {
+    let env = db::db_env(dest_data_path).map_err(Error::LmdbOperation)?;
+    let txn = env.begin_ro_txn().map_err(Error::LmdbOperation)?;
+    let db = unsafe { txn.open_db(None) }.map_err(Error::LmdbOperation)?;
+    let entry_count = lmdb_utils::entry_count(&txn, db).map_err(Error::LmdbOperation)?;
+    txn.commit().map_err(Error::LmdbOperation)?;
+    Ok(entry_count)
+}
+
+/// Deletes all entries of the destination trie store in batches, recording
+/// them in the journal so the overwritten destination can be restored.
+fn clear_destination(dest_data_path: &Path, journal: &mut Journal) -> Result<usize, Error> {
+    let env = db::db_env(dest_data_path).map_err(Error::LmdbOperation)?;
+    let mut deleted = 0;
+    loop {
+        let mut txn = JournaledTransaction::begin(&env, Some(&mut *journal), false)?;
+        let db = unsafe { txn.open_db(None) }.map_err(Error::LmdbOperation)?;
+        let keys: Vec<Vec<u8>> = {
+            let mut cursor = txn
+                .reader()
+                .open_ro_cursor(db)
+                .map_err(Error::LmdbOperation)?;
+            cursor
+                .iter_start()
+                .take(CLEAR_BATCH_SIZE)
+                .map(|(raw_key, _raw_val)| raw_key.to_vec())
+                .collect()
+        };
+        if keys.is_empty() {
+            break;
+        }
+        for key in keys.iter() {
+            txn.del(db, key)?;
+        }
+        txn.commit()?;
+        deleted += keys.len();
+    }
+    Ok(deleted)
+}
+
+/// Copies the tries under the state roots of the blocks in `block_range` to
+/// the destination, recording every copied state root in the checkpoint at
+/// `maybe_checkpoint_path`. With `DestinationOptions::Resume`, the range is
+/// read from the checkpoint and the copy continues below the last recorded
+/// block.
+#[allow(clippy::too_many_arguments)]
 pub fn trie_compact<P1: AsRef<Path>, P2: AsRef<Path>, P3: AsRef<Path>>(
     storage_path: P1,
     source_trie_path: P2,
     destination_trie_path: P3,
     dest_opt: DestinationOptions,
     max_db_size: usize,
+    block_range: BlockRange,
+    maybe_checkpoint_path: Option<&Path>,
+    maybe_journal_path: Option<&Path>,
+    dry_run: bool,
 ) -> Result<(), Error> {
     validate_trie_paths(&source_trie_path, &destination_trie_path, dest_opt)?;
+    let dest_data_path = destination_trie_path.as_ref().join(TRIE_STORE_FILE_NAME);
+
+    let stored_state_roots = stored_state_roots(storage_path.as_ref())?;
+    let maybe_progress = match (dest_opt, maybe_checkpoint_path) {
+        (DestinationOptions::Resume, Some(checkpoint_path)) => {
+            Some(checkpoint::read_progress(checkpoint_path)?)
+        }
+        (DestinationOptions::Resume, None) => {
+            return Err(Error::InvalidDest(
+                "Can't resume without a checkpoint.".to_string(),
+            ))
+        }
+        _ => None,
+    };
+    let (lowest_height, top_height, visited_roots) = match maybe_progress.as_ref() {
+        Some(progress) => match progress.resume_height() {
+            Some(top_height) => {
+                info!(
+                    "Resuming from height {top_height} with {} state roots already copied.",
+                    progress.completed.len()
+                );
+                (
+                    progress.lowest_height,
+                    top_height,
+                    progress.completed.clone(),
+                )
+            }
+            None => {
+                info!("All blocks recorded in the checkpoint were copied, exiting.");
+                return Ok(());
+            }
+        },
+        None => match height_bounds(&stored_state_roots, &block_range) {
+            Some((lowest_height, top_height)) => (lowest_height, top_height, HashSet::new()),
+            None => {
+                info!("No blocks found in storage in the selected range, exiting.");
+                return Ok(());
+            }
+        },
+    };
+    let state_roots = state_roots(
+        &stored_state_roots,
+        lowest_height,
+        top_height,
+        visited_roots,
+    )?;
+    if state_roots.is_empty() {
+        info!("No state roots left to copy, exiting.");
+        return Ok(());
+    }
 
     let (source_state, _env) =
         load_execution_engine(source_trie_path, max_db_size, Digest::default(), true)
             .map_err(Error::OpenSourceTrie)?;
 
-    let (destination_state, _env) =
-        create_execution_engine(destination_trie_path, max_db_size, true)
-            .map_err(Error::CreateDestTrie)?;
-
-    let storage = create_storage(&storage_path).map_err(Error::OpenStorage)?;
+    if dry_run {
+        if dest_opt == DestinationOptions::Overwrite {
+            info!(
+                "Would delete {} entries from {}.",
+                destination_entry_count(&dest_data_path)?,
+                dest_data_path.display()
+            );
+        }
+        // A new or overwritten destination starts out empty.
+        let maybe_destination_state = match dest_opt {
+            DestinationOptions::Append | DestinationOptions::Resume => Some(
+                load_execution_engine(&destination_trie_path, max_db_size, Digest::default(), true)
+                    .map_err(Error::CreateDestTrie)?
+                    .0,
+            ),
+            DestinationOptions::New | DestinationOptions::Overwrite => None,
+        };
+        let mut total_tries = 0;
+        for (height, state_root) in state_roots.iter() {
+            let tries = super::helpers::count_missing_tries(
+                *state_root,
+                &source_state,
+                maybe_destination_state.as_deref(),
+            )
+            .map_err(|err| Error::CopyStateRoot(*state_root, err))?;
+            info!("Would copy state root {state_root} of block {height}, writing {tries} tries.");
+            total_tries += tries;
+        }
+        // Nothing is written, so the subtries shared by several state roots
+        // are counted for each of them.
+        info!(
+            "Would copy {} state roots to {}, writing at most {total_tries} tries.",
+            state_roots.len(),
+            dest_data_path.display()
+        );
+        return Ok(());
+    }
 
-    let mut block = match storage
-        .read_highest_block()
-        .map_err(|err| Error::Storage(0, err))?
+    // The checkpoint of a previous run is removed before the destination is
+    // cleared, and the new one only started once it is empty. A partly
+    // cleared destination can't be resumed, as it may hold tries whose
+    // subtries were deleted.
+    if let (DestinationOptions::Overwrite, Some(checkpoint_path)) =
+        (dest_opt, maybe_checkpoint_path)
     {
-        Some(block) => block,
-        None => {
-            info!("No blocks found in storage, exiting.");
-            return Ok(());
+        checkpoint::remove(checkpoint_path)?;
+    }
+    let mut maybe_journal = maybe_journal_path
+        .map(|journal_path| Journal::create(journal_path, &dest_data_path))
+        .transpose()?;
+    if dest_opt == DestinationOptions::Overwrite {
+        match maybe_journal.as_mut() {
+            Some(journal) => {
+                let deleted = clear_destination(&dest_data_path, journal)?;
+                info!(
+                    "Deleted {deleted} entries from {}.",
+                    dest_data_path.display()
+                );
+            }
+            None => {
+                let _f: File = OpenOptions::new()
+                    .truncate(true)
+                    .write(true)
+                    .open(&dest_data_path)
+                    .map_err(|io_err| {
+                        Error::InvalidDest(format!("Couldn't overwrite destination file: {io_err}"))
+                    })?;
+            }
         }
-    };
-    let mut visited_roots = HashSet::new();
-    let mut block_height;
+    }
+
+    let mut maybe_checkpoint = maybe_checkpoint_path
+        .map(|checkpoint_path| match maybe_progress.as_ref() {
+            Some(progress) => Checkpoint::resume(checkpoint_path, progress),
+            None => Checkpoint::create(checkpoint_path, lowest_height, top_height),
+        })
+        .transpose()?;
+
+    let (destination_state, _env) =
+        create_execution_engine(destination_trie_path, max_db_size, true)
+            .map_err(Error::CreateDestTrie)?;
 
     info!("Copying state roots from source to destination.");
-    loop {
-        block_height = block.height();
-        let state_root = *block.take_header().state_root_hash();
-        if !visited_roots.contains(&state_root) {
-            super::helpers::copy_state_root(state_root, &source_state, &destination_state)
-                .map_err(|err| Error::CopyStateRoot(state_root, err))?;
-            destination_state
-                .flush_environment()
-                .map_err(Error::LmdbOperation)?;
-            visited_roots.insert(state_root);
-        }
-        if block_height == 0 {
-            break;
+    for (height, state_root) in state_roots.iter() {
+        super::helpers::copy_state_root(
+            *state_root,
+            &source_state,
+            &destination_state,
+            maybe_journal.as_mut(),
+        )
+        .map_err(|err| Error::CopyStateRoot(*state_root, err))?;
+        destination_state
+            .flush_environment()
+            .map_err(Error::LmdbOperation)?;
+        if let Some(checkpoint) = maybe_checkpoint.as_mut() {
+            checkpoint.record(*height, *state_root)?;
         }
-        block = storage
-            .read_block_by_height(block_height - 1)
-            .map_err(|storage_err| Error::Storage(block_height - 1, storage_err))?
-            .ok_or(Error::MissingBlock(block_height - 1))?;
     }
     info!(
         "Finished copying {} state roots to new database.",
-        visited_roots.len()
+        state_roots.len()
     );
 
     Ok(())
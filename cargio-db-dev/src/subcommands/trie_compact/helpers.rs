@@ -1,7 +1,17 @@
-use std::time::{Duration, Instant};
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+        Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
-use lmdb::{RwTransaction, Transaction};
-use log::{info, warn};
+use lmdb::Transaction;
+use log::info;
 
 use cargio_execution_engine::{
     core::engine_state::EngineState,
@@ -9,7 +19,6 @@ use cargio_execution_engine::{
         global_state::lmdb::LmdbGlobalState,
         transaction_source::{Readable, TransactionSource, Writable},
         trie::{Pointer, Trie},
-        trie_store::lmdb::LmdbTrieStore,
     },
 };
 use cargio_hashing::Digest;
@@ -18,130 +27,433 @@ use cargio_types::{
     Key, StoredValue,
 };
 
-fn memoized_find_missing_descendants(
+use crate::common::journal::Journal;
+
+/// Upper bound on the number of threads reading tries from the source.
+const MAX_READER_COUNT: usize = 16;
+/// Number of trie keys a reader takes from the queue at once and reads under
+/// the same transactions.
+const READ_BATCH_SIZE: usize = 1_000;
+/// Number of tries written to the destination per transaction.
+const WRITE_BATCH_SIZE: usize = 10_000;
+/// Number of read tries which can wait for the writer before the readers
+/// block.
+const WRITE_QUEUE_SIZE: usize = 4 * WRITE_BATCH_SIZE;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A trie read from the source which is sent to the writer once all of its
+/// children were.
+struct IncompleteTrie {
+    key_bytes: Vec<u8>,
     value_bytes: Bytes,
-    trie_store: &LmdbTrieStore,
-    txn: &RwTransaction<'_>,
-    missing_trie_keys: &mut Vec<Digest>,
-    time_in_missing_trie_keys: &mut Duration,
-) -> Result<(), anyhow::Error> {
-    if let Some(0u8) = value_bytes.first() {
-        return Ok(());
-    }
-    let start_trie_keys = Instant::now();
-    let trie: Trie<Key, StoredValue> = bytesrepr::deserialize(value_bytes.into())
-        .map_err(|err| anyhow::anyhow!("couldn't deserialize trie: {:?}", err))?;
-    match trie {
-        Trie::Leaf { .. } => {
-            warn!("did not expect to see a trie leaf in `find_missing_descendents` after shortcut");
+    remaining_children: usize,
+    maybe_parent: Option<Digest>,
+}
+
+/// Trie keys waiting to be read, each with the key of the trie pointing to
+/// it.
+#[derive(Default)]
+struct ReadQueue {
+    pending: Vec<(Digest, Option<Digest>)>,
+    /// Number of keys taken by readers whose children aren't queued yet.
+    in_flight: usize,
+    /// Tries with children which weren't sent to the writer yet.
+    incomplete: HashMap<Digest, IncompleteTrie>,
+    stopped: bool,
+}
+
+/// State shared by the readers and the writer of a copy.
+struct SharedState {
+    queue: Mutex<ReadQueue>,
+    queue_changed: Condvar,
+    tries_read: AtomicU64,
+    bytes_read: AtomicU64,
+}
+
+impl SharedState {
+    fn new(state_root: Digest) -> Self {
+        Self {
+            queue: Mutex::new(ReadQueue {
+                pending: vec![(state_root, None)],
+                ..Default::default()
+            }),
+            queue_changed: Condvar::new(),
+            tries_read: AtomicU64::new(0),
+            bytes_read: AtomicU64::new(0),
         }
-        Trie::Node { pointer_block } => {
-            for (_index, ptr) in pointer_block.as_indexed_pointers() {
-                find_missing_trie_keys(ptr, missing_trie_keys, trie_store, txn)?;
+    }
+
+    /// Takes the next keys to read, waiting while other readers may still
+    /// queue more. Returns an empty batch once every key was read or the copy
+    /// was stopped.
+    fn take_batch(&self) -> Vec<(Digest, Option<Digest>)> {
+        let mut queue = self.queue.lock().expect("read queue lock poisoned");
+        loop {
+            if queue.stopped {
+                return vec![];
             }
+            if !queue.pending.is_empty() {
+                let split_at = queue.pending.len().saturating_sub(READ_BATCH_SIZE);
+                let batch = queue.pending.split_off(split_at);
+                queue.in_flight += batch.len();
+                return batch;
+            }
+            if queue.in_flight == 0 {
+                return vec![];
+            }
+            queue = self
+                .queue_changed
+                .wait(queue)
+                .expect("read queue lock poisoned");
         }
-        Trie::Extension { affix: _, pointer } => {
-            find_missing_trie_keys(pointer, missing_trie_keys, trie_store, txn)?;
+    }
+
+    /// Queues the children of the tries in `incomplete`, which are sent to the
+    /// writer once all of their children were.
+    fn finish_batch(
+        &self,
+        batch_len: usize,
+        children: Vec<(Digest, Option<Digest>)>,
+        incomplete: Vec<(Digest, IncompleteTrie)>,
+    ) {
+        let mut queue = self.queue.lock().expect("read queue lock poisoned");
+        queue.in_flight -= batch_len;
+        queue.pending.extend(children);
+        queue.incomplete.extend(incomplete);
+        drop(queue);
+        self.queue_changed.notify_all();
+    }
+
+    /// Records that a child of `parent` was sent to the writer. Returns
+    /// `parent` once all of its children were sent.
+    fn child_sent(&self, parent: Digest) -> Option<IncompleteTrie> {
+        let mut queue = self.queue.lock().expect("read queue lock poisoned");
+        let incomplete_trie = queue
+            .incomplete
+            .get_mut(&parent)
+            .expect("parent of a read trie should be incomplete");
+        incomplete_trie.remaining_children -= 1;
+        if incomplete_trie.remaining_children > 0 {
+            return None;
         }
+        queue.incomplete.remove(&parent)
+    }
+
+    fn stop(&self) {
+        self.queue.lock().expect("read queue lock poisoned").stopped = true;
+        self.queue_changed.notify_all();
     }
-    *time_in_missing_trie_keys += start_trie_keys.elapsed();
-    Ok(())
 }
 
-fn find_missing_trie_keys(
-    ptr: Pointer,
-    missing_trie_keys: &mut Vec<Digest>,
-    handle: &LmdbTrieStore,
-    txn: &RwTransaction<'_>,
-) -> Result<(), anyhow::Error> {
-    let ptr = match ptr {
-        Pointer::LeafPointer(pointer) | Pointer::NodePointer(pointer) => pointer,
+/// Returns the keys of the tries `trie` points to.
+pub(crate) fn trie_pointers(trie: &Trie<Key, StoredValue>) -> Vec<Digest> {
+    let pointers = match trie {
+        Trie::Leaf { .. } => vec![],
+        Trie::Node { pointer_block } => pointer_block
+            .as_indexed_pointers()
+            .map(|(_index, ptr)| ptr)
+            .collect(),
+        Trie::Extension { affix: _, pointer } => vec![*pointer],
     };
-    let existing = txn.read(
-        handle.get_db(),
-        &ptr.to_bytes()
-            .map_err(|err| anyhow::anyhow!("couldn't serialize trie pointer: {:?}", err))?,
-    )?;
-    if existing.is_none() {
-        missing_trie_keys.push(ptr);
+    pointers
+        .into_iter()
+        .map(|ptr| match ptr {
+            Pointer::LeafPointer(pointer) | Pointer::NodePointer(pointer) => pointer,
+        })
+        .collect()
+}
+
+/// Returns the keys of the tries a serialized trie points to.
+fn trie_children(value_bytes: &Bytes) -> Result<Vec<Digest>, anyhow::Error> {
+    // Leaves have no children, so they aren't deserialized.
+    if let Some(0u8) = value_bytes.first() {
+        return Ok(vec![]);
     }
-    Ok(())
+    let trie: Trie<Key, StoredValue> = bytesrepr::deserialize(value_bytes.clone().into())
+        .map_err(|err| anyhow::anyhow!("couldn't deserialize trie: {:?}", err))?;
+    Ok(trie_pointers(&trie))
 }
 
-pub fn copy_state_root(
-    state_root: Digest,
+/// Sends a trie whose children were all sent to the writer, followed by the
+/// ancestors it completes. Returns `false` if the writer stopped.
+fn send_complete_trie(
+    shared: &SharedState,
+    sender: &SyncSender<(Vec<u8>, Bytes)>,
+    mut trie: (Vec<u8>, Bytes),
+    mut maybe_parent: Option<Digest>,
+) -> bool {
+    loop {
+        if sender.send(trie).is_err() {
+            return false;
+        }
+        match maybe_parent.and_then(|parent| shared.child_sent(parent)) {
+            Some(parent) => {
+                trie = (parent.key_bytes, parent.value_bytes);
+                maybe_parent = parent.maybe_parent;
+            }
+            None => return true,
+        }
+    }
+}
+
+/// Reads batches of tries from the source and sends them to the writer after
+/// their children, queueing the children which aren't in `maybe_destination`
+/// yet.
+fn read_tries(
+    shared: &SharedState,
     source: &EngineState<LmdbGlobalState>,
-    destination: &EngineState<LmdbGlobalState>,
+    maybe_destination: Option<&EngineState<LmdbGlobalState>>,
+    sender: SyncSender<(Vec<u8>, Bytes)>,
 ) -> Result<(), anyhow::Error> {
-    let mut missing_trie_keys = vec![state_root];
-    let start_time = Instant::now();
-    let mut heartbeat_interval = Instant::now();
+    let source_store = source.get_state().trie_store();
+    loop {
+        let batch = shared.take_batch();
+        if batch.is_empty() {
+            return Ok(());
+        }
+        // Leaves hold their full keys, so no subtrie appears twice in a trie
+        // and only the tries already in the destination have to be skipped.
+        // Tries are written after their children, so those have their whole
+        // subtrie in the destination, even after an interrupted copy.
+        let source_txn = source.get_state().environment().create_read_txn()?;
+        let maybe_destination_txn = maybe_destination
+            .map(|destination| {
+                destination
+                    .get_state()
+                    .environment()
+                    .create_read_txn()
+                    .map(|txn| (destination.get_state().trie_store(), txn))
+            })
+            .transpose()?;
+        let mut children = vec![];
+        let mut incomplete = vec![];
+        for (trie_key, maybe_parent) in batch.iter() {
+            let key_bytes = trie_key
+                .to_bytes()
+                .map_err(|err| anyhow::anyhow!("couldn't serialize trie key: {:?}", err))?;
+            let value_bytes = source_txn
+                .read(source_store.get_db(), &key_bytes)?
+                .ok_or_else(|| anyhow::anyhow!("trie {} is missing from the source", trie_key))?;
+            shared.tries_read.fetch_add(1, Ordering::Relaxed);
+            shared.bytes_read.fetch_add(
+                (key_bytes.len() + value_bytes.len()) as u64,
+                Ordering::Relaxed,
+            );
 
-    let mut total_tries: u64 = 0;
-    let mut total_bytes: u64 = 0;
+            let mut missing_children = 0;
+            for child in trie_children(&value_bytes)? {
+                if let Some((destination_store, destination_txn)) = maybe_destination_txn.as_ref() {
+                    let child_bytes = child
+                        .to_bytes()
+                        .map_err(|err| anyhow::anyhow!("couldn't serialize trie key: {:?}", err))?;
+                    if destination_txn
+                        .read(destination_store.get_db(), &child_bytes)?
+                        .is_some()
+                    {
+                        continue;
+                    }
+                }
+                children.push((child, Some(*trie_key)));
+                missing_children += 1;
+            }
+            if missing_children > 0 {
+                incomplete.push((
+                    *trie_key,
+                    IncompleteTrie {
+                        key_bytes,
+                        value_bytes,
+                        remaining_children: missing_children,
+                        maybe_parent: *maybe_parent,
+                    },
+                ));
+            } else if !send_complete_trie(shared, &sender, (key_bytes, value_bytes), *maybe_parent)
+            {
+                // The writer failed and reports its own error.
+                return Ok(());
+            }
+        }
+        source_txn.commit()?;
+        if let Some((_, destination_txn)) = maybe_destination_txn {
+            destination_txn.commit()?;
+        }
+        shared.finish_batch(batch.len(), children, incomplete);
+    }
+}
 
-    let mut time_searching_for_trie_keys = Duration::from_secs(0);
+fn write_batch(
+    destination: &EngineState<LmdbGlobalState>,
+    mut maybe_journal: Option<&mut Journal>,
+    batch: &mut Vec<(Vec<u8>, Bytes)>,
+) -> Result<u64, anyhow::Error> {
+    let destination_store = destination.get_state().trie_store();
+    let mut write_txn = destination
+        .get_state()
+        .environment()
+        .create_read_write_txn()?;
+    for (key_bytes, value_bytes) in batch.iter() {
+        if let Some(journal) = maybe_journal.as_mut() {
+            let prior_value = write_txn.read(destination_store.get_db(), key_bytes)?;
+            journal.record(None, key_bytes, prior_value.as_deref())?;
+        }
+        write_txn.write(destination_store.get_db(), key_bytes, value_bytes)?;
+    }
+    // LMDB may write the pages of a commit to disk before the environment is
+    // flushed, so the journal has to be on disk first.
+    if let Some(journal) = maybe_journal {
+        journal.sync()?;
+    }
+    write_txn.commit()?;
+    let written = batch.len() as u64;
+    batch.clear();
+    Ok(written)
+}
 
-    while let Some(next_trie_key) = missing_trie_keys.pop() {
-        if heartbeat_interval.elapsed().as_secs() > 10 {
+/// Passes the tries sent by the readers in batches to `write_batch` until
+/// all readers are done, logging the progress of the copy. Returns the
+/// number of written tries.
+fn write_tries<W>(
+    shared: &SharedState,
+    receiver: Receiver<(Vec<u8>, Bytes)>,
+    mut write_batch: W,
+) -> Result<u64, anyhow::Error>
+where
+    W: FnMut(&mut Vec<(Vec<u8>, Bytes)>) -> Result<u64, anyhow::Error>,
+{
+    let mut batch = Vec::with_capacity(WRITE_BATCH_SIZE);
+    let mut tries_written = 0;
+    let mut heartbeat_interval = Instant::now();
+    loop {
+        let readers_done = match receiver.recv_timeout(HEARTBEAT_INTERVAL) {
+            Ok(trie) => {
+                batch.push(trie);
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        if batch.len() >= WRITE_BATCH_SIZE || (readers_done && !batch.is_empty()) {
+            tries_written += write_batch(&mut batch)?;
+        }
+        if heartbeat_interval.elapsed() >= HEARTBEAT_INTERVAL {
             info!(
-                "trie migration progress: bytes copied {}, tries copied {}",
-                total_bytes, total_tries,
+                "trie migration progress: bytes copied {}, tries copied {}, tries written {}",
+                shared.bytes_read.load(Ordering::Relaxed),
+                shared.tries_read.load(Ordering::Relaxed),
+                tries_written,
             );
             heartbeat_interval = Instant::now();
         }
-
-        let source_store = source.get_state().trie_store();
-        let destination_store = destination.get_state().trie_store();
-        let trie_key_bytes = next_trie_key
-            .to_bytes()
-            .map_err(|err| anyhow::anyhow!("couldn't serialize trie key: {:?}", err))?;
-
-        let read_txn = source.get_state().environment().create_read_txn()?;
-        let mut write_txn = destination
-            .get_state()
-            .environment()
-            .create_read_write_txn()?;
-
-        match read_txn.read(source_store.get_db(), &trie_key_bytes)? {
-            Some(value_bytes) => {
-                let key_bytes = next_trie_key
-                    .to_bytes()
-                    .map_err(|err| anyhow::anyhow!("couldn't serialize trie key: {:?}", err))?;
-                let read_bytes = key_bytes.len() as u64 + value_bytes.len() as u64;
-                total_bytes += read_bytes;
-                total_tries += 1;
-
-                write_txn.write(destination_store.get_db(), &key_bytes, &value_bytes)?;
-
-                memoized_find_missing_descendants(
-                    value_bytes,
-                    destination_store,
-                    &write_txn,
-                    &mut missing_trie_keys,
-                    &mut time_searching_for_trie_keys,
-                )?;
-            }
-            None => {
-                return Err(anyhow::anyhow!(
-                    "error migrating state root {} {}, ",
-                    state_root,
-                    next_trie_key
-                ));
-            }
+        if readers_done {
+            return Ok(tries_written);
         }
-        read_txn.commit()?;
-        write_txn.commit()?;
     }
+}
+
+fn reader_count() -> usize {
+    thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+        .min(MAX_READER_COUNT)
+}
+
+/// Walks the trie under the state root of `shared` with `reader_count`
+/// reader threads, skipping the subtries which are already in
+/// `maybe_destination`, while the calling thread passes the read tries to
+/// `write_batch`. Returns the number of written tries.
+fn walk_state_root<W>(
+    shared: &SharedState,
+    reader_count: usize,
+    source: &EngineState<LmdbGlobalState>,
+    maybe_destination: Option<&EngineState<LmdbGlobalState>>,
+    write_batch: W,
+) -> Result<u64, anyhow::Error>
+where
+    W: FnMut(&mut Vec<(Vec<u8>, Bytes)>) -> Result<u64, anyhow::Error>,
+{
+    let (sender, receiver) = mpsc::sync_channel(WRITE_QUEUE_SIZE);
+    thread::scope(|scope| {
+        let readers: Vec<_> = (0..reader_count)
+            .map(|_| {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let result = read_tries(shared, source, maybe_destination, sender);
+                    if result.is_err() {
+                        shared.stop();
+                    }
+                    result
+                })
+            })
+            .collect();
+        // The writer is done once every reader dropped its sender.
+        drop(sender);
+
+        let write_result = write_tries(shared, receiver, write_batch);
+        if write_result.is_err() {
+            shared.stop();
+        }
+        readers
+            .into_iter()
+            .try_fold(write_result?, |tries_written, reader| {
+                reader
+                    .join()
+                    .map_err(|_| anyhow::anyhow!("trie reader thread panicked"))??;
+                Ok::<_, anyhow::Error>(tries_written)
+            })
+    })
+}
+
+/// Copies the trie under `state_root` from `source` to `destination`,
+/// recording the prior state of every written entry in `maybe_journal`.
+///
+/// Tries are read and decoded by several reader threads, while the calling
+/// thread writes them to the destination in large transactions. Every trie is
+/// written after all of its children, so a trie in the destination always has
+/// its whole subtrie there. Subtries which are already in the destination
+/// aren't copied again.
+pub fn copy_state_root(
+    state_root: Digest,
+    source: &EngineState<LmdbGlobalState>,
+    destination: &EngineState<LmdbGlobalState>,
+    mut maybe_journal: Option<&mut Journal>,
+) -> Result<(), anyhow::Error> {
+    let start_time = Instant::now();
+    let shared = SharedState::new(state_root);
+    let reader_count = reader_count();
+    let tries_written =
+        walk_state_root(&shared, reader_count, source, Some(destination), |batch| {
+            write_batch(destination, maybe_journal.as_deref_mut(), batch)
+        })?;
 
     info!(
         "Trie migration complete\nTotal bytes: {}\n\
             Total tries: {}\nMigration duration (us): {}\n\
-            Trie key search duration (us):{}",
-        total_bytes,
-        total_tries,
+            Reader threads: {}",
+        shared.bytes_read.load(Ordering::Relaxed),
+        tries_written,
         start_time.elapsed().as_micros(),
-        time_searching_for_trie_keys.as_micros(),
+        reader_count,
     );
     Ok(())
 }
+
+/// Returns the number of tries `copy_state_root` would write to copy the trie
+/// under `state_root` to `maybe_destination`, or to an empty destination if
+/// `None`, without writing anything.
+pub(crate) fn count_missing_tries(
+    state_root: Digest,
+    source: &EngineState<LmdbGlobalState>,
+    maybe_destination: Option<&EngineState<LmdbGlobalState>>,
+) -> Result<u64, anyhow::Error> {
+    let shared = SharedState::new(state_root);
+    walk_state_root(
+        &shared,
+        reader_count(),
+        source,
+        maybe_destination,
+        |batch| {
+            let tries_written = batch.len() as u64;
+            batch.clear();
+            Ok(tries_written)
+        },
+    )
+}
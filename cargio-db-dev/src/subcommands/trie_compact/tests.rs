@@ -1,4 +1,11 @@
-use std::fs::{self, File};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
 
 use lmdb::DatabaseFlags;
 use once_cell::sync::Lazy;
@@ -11,16 +18,24 @@ use cargio_execution_engine::storage::{
     trie_store::lmdb::LmdbTrieStore,
 };
 use cargio_hashing::Digest;
-use master_node::storage::Storage;
 use cargio_types::bytesrepr::{Bytes, ToBytes};
 
 static DEFAULT_MAX_DB_SIZE: Lazy<usize> = Lazy::new(|| super::DEFAULT_MAX_DB_SIZE.parse().unwrap());
-
-use crate::common::db::TRIE_STORE_FILE_NAME;
+/// Time after which a copy is considered to hang.
+const COPY_TIMEOUT: Duration = Duration::from_secs(120);
+
+use crate::{
+    common::{
+        db::{BlockHeaderDatabase, Database, STORAGE_FILE_NAME, TRIE_STORE_FILE_NAME},
+        journal::{self, Journal},
+    },
+    test_utils::{self, mock_block_header, LmdbTestFixture},
+};
 
 use super::{
-    compact::{self, DestinationOptions},
-    utils::{create_execution_engine, create_storage, load_execution_engine},
+    checkpoint::{self, Checkpoint},
+    compact::{self, BlockRange, DestinationOptions},
+    utils::{create_execution_engine, load_execution_engine},
     Error,
 };
 
@@ -108,10 +123,137 @@ fn create_test_trie_store() -> (TempDir, Vec<TestData<Bytes, Bytes>>) {
     (tmp_dir, data)
 }
 
-fn create_empty_test_storage() -> (TempDir, Storage) {
+/// Creates a trie store with a root node over 256 nodes of 256 leaves each,
+/// more tries than a copy reads, writes or queues at once. `maybe_missing_leaf`
+/// selects a leaf which is referenced but not stored. Returns the keys of the
+/// stored tries, the root last.
+fn create_large_test_trie_store(maybe_missing_leaf: Option<(u8, u8)>) -> (TempDir, Vec<Digest>) {
     let tmp_dir = tempdir().unwrap();
-    let storage = create_storage(tmp_dir.as_ref()).unwrap();
-    (tmp_dir, storage)
+    let env = LmdbEnvironment::new(tmp_dir.path(), *DEFAULT_MAX_DB_SIZE, 512, true).unwrap();
+    let store = LmdbTrieStore::new(&env, None, DatabaseFlags::empty()).unwrap();
+    let mut trie_keys = vec![];
+    let mut root_pointer_block = PointerBlock::new();
+    for node_idx in 0..=u8::MAX {
+        let mut tries = vec![];
+        let mut pointer_block = PointerBlock::new();
+        for leaf_idx in 0..=u8::MAX {
+            let leaf: Trie<Bytes, Bytes> = Trie::Leaf {
+                key: Bytes::from(vec![node_idx, leaf_idx]),
+                value: Bytes::from(vec![leaf_idx, node_idx]),
+            };
+            let leaf_hash = Digest::hash(leaf.to_bytes().unwrap());
+            pointer_block[leaf_idx as usize] = Some(Pointer::LeafPointer(leaf_hash));
+            if maybe_missing_leaf != Some((node_idx, leaf_idx)) {
+                tries.push(TestData(leaf_hash, leaf));
+            }
+        }
+        let node = Trie::Node {
+            pointer_block: Box::new(pointer_block),
+        };
+        let node_hash = Digest::hash(node.to_bytes().unwrap());
+        root_pointer_block[node_idx as usize] = Some(Pointer::NodePointer(node_hash));
+        tries.push(TestData(node_hash, node));
+
+        let mut txn = env.create_read_write_txn().unwrap();
+        store
+            .put_many(&mut txn, tries.iter().map(Into::into))
+            .unwrap();
+        txn.commit().unwrap();
+        trie_keys.extend(tries.iter().map(|test_data| test_data.0));
+    }
+    let root: Trie<Bytes, Bytes> = Trie::Node {
+        pointer_block: Box::new(root_pointer_block),
+    };
+    let root = TestData(Digest::hash(root.to_bytes().unwrap()), root);
+    let mut txn = env.create_read_write_txn().unwrap();
+    store
+        .put_many(&mut txn, [&root].into_iter().map(Into::into))
+        .unwrap();
+    txn.commit().unwrap();
+    trie_keys.push(root.0);
+    (tmp_dir, trie_keys)
+}
+
+/// Copies `state_root` to a new trie store of `max_db_size` bytes at
+/// `dst_path` on another thread, panicking if the copy hangs.
+fn copy_with_timeout(
+    state_root: Digest,
+    src_path: PathBuf,
+    dst_path: PathBuf,
+    max_db_size: usize,
+) -> Result<(), anyhow::Error> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let (source_state, _env) =
+            load_execution_engine(src_path, *DEFAULT_MAX_DB_SIZE, Digest::default(), true).unwrap();
+        let (destination_state, _env) =
+            create_execution_engine(dst_path, max_db_size, true).unwrap();
+        let result =
+            super::helpers::copy_state_root(state_root, &source_state, &destination_state, None);
+        sender.send(result).unwrap();
+    });
+    receiver
+        .recv_timeout(COPY_TIMEOUT)
+        .expect("copy should finish")
+}
+
+/// Creates a storage with a block at every height up to the number of
+/// `state_roots`, each with the state root at the index of its height.
+fn create_test_storage(state_roots: &[Digest]) -> LmdbTestFixture {
+    let fixture = LmdbTestFixture::new(
+        vec![BlockHeaderDatabase::db_name()],
+        Some(STORAGE_FILE_NAME),
+    );
+    for (idx, state_root) in state_roots.iter().enumerate() {
+        let (block_hash, mut header) = mock_block_header(idx as u8);
+        header.height = idx as u64;
+        header.state_root_hash = *state_root;
+        test_utils::put_entry(
+            &fixture,
+            BlockHeaderDatabase::db_name(),
+            block_hash.as_ref(),
+            &bincode::serialize(&header).unwrap(),
+        );
+    }
+    fixture
+}
+
+/// Returns whether each of the tries with the given keys is in the trie store
+/// at `path`.
+fn stored_tries(path: &Path, trie_keys: &[Digest]) -> Vec<bool> {
+    let (_state, env) =
+        load_execution_engine(path, *DEFAULT_MAX_DB_SIZE, Digest::default(), true).unwrap();
+    let store = LmdbTrieStore::new(&env, None, DatabaseFlags::empty()).unwrap();
+    let txn = env.create_read_txn().unwrap();
+    let entries: Vec<Option<Trie<Bytes, Bytes>>> = store.get_many(&txn, trie_keys.iter()).unwrap();
+    txn.commit().unwrap();
+    entries.iter().map(Option::is_some).collect()
+}
+
+/// Copies the state roots of the blocks in `block_range` of a storage whose
+/// blocks have the subtries of node 2, leaf 1, leaf 2 and leaf 3 as state
+/// roots, in that order, to a new trie store. Returns which tries of the
+/// test data were copied.
+fn compact_block_range(block_range: BlockRange) -> Vec<bool> {
+    let (src_tmp_dir, data) = create_test_trie_store();
+    let storage = create_test_storage(&[data[4].0, data[0].0, data[1].0, data[2].0]);
+    let dst_tmp_dir = tempdir().unwrap();
+    let dst_path = dst_tmp_dir.path().join("dest");
+
+    compact::trie_compact(
+        storage.tmp_dir.path(),
+        src_tmp_dir.path(),
+        &dst_path,
+        DestinationOptions::New,
+        *DEFAULT_MAX_DB_SIZE,
+        block_range,
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+    let trie_keys: Vec<_> = data.iter().map(|test_data| test_data.0).collect();
+    stored_tries(&dst_path, &trie_keys)
 }
 
 #[test]
@@ -141,7 +283,7 @@ fn copy_state_root_roundtrip() {
     let (destination_state, dst_env) =
         create_execution_engine(dst_tmp_dir.path(), *DEFAULT_MAX_DB_SIZE, true).unwrap();
 
-    super::helpers::copy_state_root(data[3].0, &source_state, &destination_state).unwrap();
+    super::helpers::copy_state_root(data[3].0, &source_state, &destination_state, None).unwrap();
 
     let dst_store = LmdbTrieStore::new(&dst_env, None, DatabaseFlags::empty()).unwrap();
     {
@@ -196,7 +338,7 @@ fn check_no_extra_tries() {
     let (destination_state, dst_env) =
         create_execution_engine(dst_tmp_dir.path(), *DEFAULT_MAX_DB_SIZE, true).unwrap();
 
-    super::helpers::copy_state_root(data[4].0, &source_state, &destination_state).unwrap();
+    super::helpers::copy_state_root(data[4].0, &source_state, &destination_state, None).unwrap();
 
     let dst_store = LmdbTrieStore::new(&dst_env, None, DatabaseFlags::empty()).unwrap();
     {
@@ -232,3 +374,270 @@ fn check_no_extra_tries() {
     src_tmp_dir.close().unwrap();
     dst_tmp_dir.close().unwrap();
 }
+
+#[test]
+fn copy_should_cover_trie_larger_than_batches() {
+    let (src_tmp_dir, trie_keys) = create_large_test_trie_store(None);
+    let dst_tmp_dir = tempdir().unwrap();
+
+    copy_with_timeout(
+        *trie_keys.last().unwrap(),
+        src_tmp_dir.path().to_path_buf(),
+        dst_tmp_dir.path().to_path_buf(),
+        *DEFAULT_MAX_DB_SIZE,
+    )
+    .unwrap();
+    assert!(stored_tries(dst_tmp_dir.path(), &trie_keys)
+        .into_iter()
+        .all(|stored| stored));
+}
+
+#[test]
+fn copy_should_fail_on_missing_source_trie() {
+    let (src_tmp_dir, trie_keys) = create_large_test_trie_store(Some((100, 200)));
+    let dst_tmp_dir = tempdir().unwrap();
+
+    let error = copy_with_timeout(
+        *trie_keys.last().unwrap(),
+        src_tmp_dir.path().to_path_buf(),
+        dst_tmp_dir.path().to_path_buf(),
+        *DEFAULT_MAX_DB_SIZE,
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("missing from the source"));
+}
+
+#[test]
+fn copy_should_stop_readers_on_write_error() {
+    let (src_tmp_dir, trie_keys) = create_large_test_trie_store(None);
+    let dst_tmp_dir = tempdir().unwrap();
+
+    // The first batch doesn't fit in the destination, while the readers fill
+    // the queue to the writer and block.
+    let error = copy_with_timeout(
+        *trie_keys.last().unwrap(),
+        src_tmp_dir.path().to_path_buf(),
+        dst_tmp_dir.path().to_path_buf(),
+        256 * 1024,
+    )
+    .unwrap_err();
+    assert!(!error.to_string().contains("missing from the source"));
+}
+
+#[test]
+fn resumed_compact_should_complete_interrupted_tries() {
+    let (src_tmp_dir, trie_keys) = create_large_test_trie_store(None);
+    let state_root = *trie_keys.last().unwrap();
+    let storage = create_test_storage(&[state_root]);
+    let dst_tmp_dir = tempdir().unwrap();
+    let dst_path = dst_tmp_dir.path().join("dest");
+    let checkpoint_path = dst_tmp_dir.path().join("checkpoint.jsonl");
+    let compact = |dest_opt, max_db_size| {
+        compact::trie_compact(
+            storage.tmp_dir.path(),
+            src_tmp_dir.path(),
+            &dst_path,
+            dest_opt,
+            max_db_size,
+            BlockRange::Last(1),
+            Some(checkpoint_path.as_path()),
+            None,
+            false,
+        )
+    };
+
+    // The destination fills up after the first batch was committed, leaving
+    // it as if the run was killed.
+    assert!(matches!(
+        compact(DestinationOptions::New, 2 * 1024 * 1024),
+        Err(Error::CopyStateRoot(root, _)) if root == state_root
+    ));
+    let copied = stored_tries(&dst_path, &trie_keys)
+        .into_iter()
+        .filter(|stored| *stored)
+        .count();
+    assert!(copied > 0 && copied < trie_keys.len());
+
+    // The tries written before the interruption are skipped, but none of
+    // their subtries may be missing.
+    compact(DestinationOptions::Resume, *DEFAULT_MAX_DB_SIZE).unwrap();
+    assert!(stored_tries(&dst_path, &trie_keys)
+        .into_iter()
+        .all(|stored| stored));
+    assert_eq!(
+        checkpoint::read_progress(&checkpoint_path)
+            .unwrap()
+            .resume_height(),
+        None
+    );
+}
+
+#[test]
+fn copied_state_root_should_be_undone_from_journal() {
+    let (src_tmp_dir, data) = create_test_trie_store();
+    let dst_tmp_dir = tempdir().unwrap();
+
+    let (source_state, _env) = load_execution_engine(
+        src_tmp_dir.path(),
+        *DEFAULT_MAX_DB_SIZE,
+        Digest::default(),
+        true,
+    )
+    .unwrap();
+    let (destination_state, dst_env) =
+        create_execution_engine(dst_tmp_dir.path(), *DEFAULT_MAX_DB_SIZE, true).unwrap();
+
+    let journal_path = dst_tmp_dir.path().join("compact.journal");
+    let mut journal =
+        Journal::create(&journal_path, dst_tmp_dir.path().join(TRIE_STORE_FILE_NAME)).unwrap();
+    super::helpers::copy_state_root(
+        data[4].0,
+        &source_state,
+        &destination_state,
+        Some(&mut journal),
+    )
+    .unwrap();
+    journal.sync().unwrap();
+    destination_state.flush_environment().unwrap();
+    drop(journal);
+
+    // The root and the two nodes under it were copied.
+    assert_eq!(journal::undo(&journal_path).unwrap(), 3);
+    let dst_store = LmdbTrieStore::new(&dst_env, None, DatabaseFlags::empty()).unwrap();
+    let txn = dst_env.create_read_txn().unwrap();
+    let keys: Vec<_> = data.iter().map(|test_data| test_data.0).collect();
+    let entries: Vec<Option<Trie<Bytes, Bytes>>> = dst_store.get_many(&txn, keys.iter()).unwrap();
+    assert!(entries.iter().all(Option::is_none));
+    txn.commit().unwrap();
+}
+
+#[test]
+fn missing_tries_should_be_counted_without_writing() {
+    let (src_tmp_dir, data) = create_test_trie_store();
+    let dst_tmp_dir = tempdir().unwrap();
+
+    let (source_state, _env) = load_execution_engine(
+        src_tmp_dir.path(),
+        *DEFAULT_MAX_DB_SIZE,
+        Digest::default(),
+        true,
+    )
+    .unwrap();
+    let (destination_state, dst_env) =
+        create_execution_engine(dst_tmp_dir.path(), *DEFAULT_MAX_DB_SIZE, true).unwrap();
+
+    assert_eq!(
+        super::helpers::count_missing_tries(data[3].0, &source_state, None).unwrap(),
+        data.len() as u64
+    );
+    super::helpers::copy_state_root(data[4].0, &source_state, &destination_state, None).unwrap();
+    // Only the root, the leaf and the extension above the copied node are
+    // missing.
+    assert_eq!(
+        super::helpers::count_missing_tries(data[3].0, &source_state, Some(&destination_state))
+            .unwrap(),
+        3
+    );
+    let dst_store = LmdbTrieStore::new(&dst_env, None, DatabaseFlags::empty()).unwrap();
+    let txn = dst_env.create_read_txn().unwrap();
+    let entries: Vec<Option<Trie<Bytes, Bytes>>> = dst_store
+        .get_many(&txn, [data[3].0, data[5].0].iter())
+        .unwrap();
+    assert!(entries.iter().all(Option::is_none));
+    txn.commit().unwrap();
+}
+
+#[test]
+fn block_range_bounds() {
+    assert_eq!(BlockRange::Heights(2..=u64::MAX).bounds(10), (2, 10));
+    assert_eq!(BlockRange::Heights(2..=5).bounds(10), (2, 5));
+    assert_eq!(BlockRange::Last(3).bounds(10), (8, 10));
+    assert_eq!(BlockRange::Last(20).bounds(10), (0, 10));
+    // Blocks above the highest one select nothing.
+    let (lowest_height, highest_height) = BlockRange::Heights(12..=20).bounds(10);
+    assert!(lowest_height > highest_height);
+}
+
+#[test]
+fn compact_should_copy_state_roots_of_height_range() {
+    // Leaf 1 and leaf 2 are the state roots at heights 1 and 2.
+    assert_eq!(
+        compact_block_range(BlockRange::Heights(1..=2)),
+        vec![true, true, false, false, false, false]
+    );
+    // Node 2 and both leaves under it.
+    assert_eq!(
+        compact_block_range(BlockRange::Heights(0..=0)),
+        vec![false, true, true, false, true, false]
+    );
+}
+
+#[test]
+fn compact_should_copy_state_roots_of_last_blocks() {
+    // Leaf 2 and leaf 3 are the state roots of the two highest blocks.
+    assert_eq!(
+        compact_block_range(BlockRange::Last(2)),
+        vec![false, true, true, false, false, false]
+    );
+    assert_eq!(
+        compact_block_range(BlockRange::Last(10)),
+        vec![true, true, true, false, true, false]
+    );
+}
+
+#[test]
+fn checkpoint_should_record_progress() {
+    let tmp_dir = tempdir().unwrap();
+    let checkpoint_path = tmp_dir.path().join("checkpoint.jsonl");
+    let state_roots: Vec<Digest> = (0..3u8).map(|idx| Digest::hash([idx])).collect();
+
+    let mut checkpoint = Checkpoint::create(&checkpoint_path, 5, 10).unwrap();
+    let progress = checkpoint::read_progress(&checkpoint_path).unwrap();
+    assert_eq!(progress.maybe_last_height, None);
+    assert_eq!(progress.resume_height(), Some(10));
+
+    checkpoint.record(10, state_roots[0]).unwrap();
+    checkpoint.record(7, state_roots[1]).unwrap();
+    drop(checkpoint);
+    let progress = checkpoint::read_progress(&checkpoint_path).unwrap();
+    assert_eq!((progress.lowest_height, progress.top_height), (5, 10));
+    assert_eq!(progress.maybe_last_height, Some(7));
+    assert_eq!(
+        progress.completed,
+        state_roots[..2].iter().copied().collect::<HashSet<_>>()
+    );
+    assert_eq!(progress.resume_height(), Some(6));
+
+    let mut checkpoint = Checkpoint::resume(&checkpoint_path, &progress).unwrap();
+    checkpoint.record(5, state_roots[2]).unwrap();
+    let progress = checkpoint::read_progress(&checkpoint_path).unwrap();
+    assert_eq!(progress.completed.len(), 3);
+    assert_eq!(progress.resume_height(), None);
+}
+
+#[test]
+fn checkpoint_should_discard_interrupted_line() {
+    let tmp_dir = tempdir().unwrap();
+    let checkpoint_path = tmp_dir.path().join("checkpoint.jsonl");
+    let state_root = Digest::hash([0u8]);
+
+    let mut checkpoint = Checkpoint::create(&checkpoint_path, 0, 10).unwrap();
+    checkpoint.record(10, state_root).unwrap();
+    drop(checkpoint);
+    let mut contents = fs::read(&checkpoint_path).unwrap();
+    let complete_len = contents.len();
+    contents.extend_from_slice(b"{\"height\":9,\"sta");
+    fs::write(&checkpoint_path, &contents).unwrap();
+
+    let progress = checkpoint::read_progress(&checkpoint_path).unwrap();
+    assert_eq!(progress.maybe_last_height, Some(10));
+    let mut checkpoint = Checkpoint::resume(&checkpoint_path, &progress).unwrap();
+    assert_eq!(
+        fs::metadata(&checkpoint_path).unwrap().len(),
+        complete_len as u64
+    );
+    checkpoint.record(9, Digest::hash([1u8])).unwrap();
+    let progress = checkpoint::read_progress(&checkpoint_path).unwrap();
+    assert_eq!(progress.maybe_last_height, Some(9));
+    assert_eq!(progress.completed.len(), 2);
+}
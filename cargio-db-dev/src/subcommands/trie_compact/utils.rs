@@ -1,8 +1,4 @@
-use std::{
-    env, fs,
-    path::{Path, PathBuf},
-    sync::Arc,
-};
+use std::{fs, path::Path, sync::Arc};
 
 use log::info;
 
@@ -14,8 +10,6 @@ use cargio_execution_engine::{
     },
 };
 use cargio_hashing::Digest;
-use master_node::{storage::Storage, StorageConfig, WithDir};
-use cargio_types::ProtocolVersion;
 use lmdb::DatabaseFlags;
 
 use crate::common::db::TRIE_STORE_FILE_NAME;
@@ -92,29 +86,3 @@ pub fn create_execution_engine(
         lmdb_environment,
     ))
 }
-
-pub fn create_storage(chain_download_path: impl AsRef<Path>) -> Result<Storage, anyhow::Error> {
-    let chain_download_path = normalize_path(chain_download_path)?;
-    let mut storage_config = StorageConfig::default();
-    storage_config.path = chain_download_path.clone();
-    Ok(Storage::new(
-        &WithDir::new(chain_download_path, storage_config),
-        None,
-        ProtocolVersion::from_parts(0, 0, 0),
-        false,
-        #[cfg(not(test))]
-        ".",
-        #[cfg(test)]
-        "cargio",
-    )?)
-}
-
-pub fn normalize_path<P: AsRef<Path>>(path: P) -> Result<PathBuf, anyhow::Error> {
-    let path = path.as_ref();
-    let path = if path.is_absolute() {
-        path.into()
-    } else {
-        env::current_dir()?.join(path)
-    };
-    Ok(fs::canonicalize(path)?)
-}
@@ -0,0 +1,131 @@
+mod stats;
+#[cfg(test)]
+mod tests;
+
+use std::{io::Error as IoError, path::Path};
+
+use clap::{Arg, ArgMatches, Command};
+use lmdb::Error as LmdbError;
+use serde_json::Error as SerializationError;
+use thiserror::Error as ThisError;
+
+use cargio_hashing::Digest;
+
+pub const COMMAND_NAME: &str = "trie-stats";
+const DEFAULT_TOP_COUNT: &str = "10";
+const OUTPUT: &str = "output";
+const OVERWRITE: &str = "overwrite";
+const STATE_ROOT: &str = "state-root";
+const TOP_COUNT: &str = "top";
+const TRIE_PATH: &str = "trie-path";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Trie with key {0} not present in the trie store")]
+    MissingTrie(Digest),
+    #[error("Error writing output: {0}")]
+    Output(#[from] IoError),
+    #[error("Error serializing output: {0}")]
+    Serialize(#[from] SerializationError),
+    #[error("Error parsing trie with key {0}: {1}")]
+    TrieParsing(Digest, String),
+}
+
+enum DisplayOrder {
+    TriePath,
+    StateRoot,
+    TopCount,
+    Output,
+    Overwrite,
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Walks the trie under a state root and outputs a JSON summary of its nodes: \
+            counts of each node type, their depths, the number and size of leaves for \
+            each key type and the largest stored values.",
+        )
+        .arg(
+            Arg::new(TRIE_PATH)
+                .display_order(DisplayOrder::TriePath as usize)
+                .required(true)
+                .short('t')
+                .long(TRIE_PATH)
+                .takes_value(true)
+                .value_name("TRIE_STORE_DIR_PATH")
+                .help("Path of the directory with the `data.lmdb` file."),
+        )
+        .arg(
+            Arg::new(STATE_ROOT)
+                .display_order(DisplayOrder::StateRoot as usize)
+                .required(true)
+                .short('r')
+                .long(STATE_ROOT)
+                .takes_value(true)
+                .value_name("STATE_ROOT_HASH")
+                .help("Hash of the state root to walk."),
+        )
+        .arg(
+            Arg::new(TOP_COUNT)
+                .display_order(DisplayOrder::TopCount as usize)
+                .short('n')
+                .long(TOP_COUNT)
+                .takes_value(true)
+                .value_name("COUNT")
+                .default_value(DEFAULT_TOP_COUNT)
+                .help("Number of the largest stored values to list."),
+        )
+        .arg(
+            Arg::new(OUTPUT)
+                .display_order(DisplayOrder::Output as usize)
+                .short('o')
+                .long(OUTPUT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help(
+                    "Path to where the program will output the summary. \
+                    If unspecified, defaults to standard output.",
+                ),
+        )
+        .arg(
+            Arg::new(OVERWRITE)
+                .display_order(DisplayOrder::Overwrite as usize)
+                .required(false)
+                .short('w')
+                .long(OVERWRITE)
+                .takes_value(false)
+                .requires(OUTPUT)
+                .help(
+                    "Overwrite an already existing output file in destination \
+                    directory.",
+                ),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let trie_path = Path::new(
+        matches
+            .value_of(TRIE_PATH)
+            .expect("should have trie-path arg"),
+    );
+    let state_root = Digest::from_hex(
+        matches
+            .value_of(STATE_ROOT)
+            .expect("should have state-root arg"),
+    )
+    .expect("should parse state root hash to hex format");
+    let top_count: usize = matches
+        .value_of(TOP_COUNT)
+        .expect("should have top arg")
+        .parse()
+        .unwrap_or_else(|_| panic!("Value of \"--{TOP_COUNT}\" must be an integer."));
+    let output = matches.value_of(OUTPUT).map(Path::new);
+    let overwrite = matches.is_present(OVERWRITE);
+
+    let trie_stats = stats::trie_stats(trie_path, state_root, top_count)?;
+    stats::write_trie_stats(&trie_stats, output, overwrite)
+}
@@ -0,0 +1,201 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+};
+
+use lmdb::{Error as LmdbError, Transaction};
+use log::info;
+use serde::Serialize;
+
+use cargio_execution_engine::storage::trie::Trie;
+use cargio_hashing::Digest;
+use cargio_types::{
+    bytesrepr::{self, ToBytes},
+    Key, StoredValue,
+};
+
+use crate::{
+    common::db::{self, TRIE_STORE_FILE_NAME},
+    subcommands::trie_compact,
+};
+
+use super::Error;
+
+const HEARTBEAT_INTERVAL: u64 = 1_000_000;
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct NodeCounts {
+    pub(crate) leaves: u64,
+    pub(crate) nodes: u64,
+    pub(crate) extensions: u64,
+}
+
+impl NodeCounts {
+    fn count(&mut self, trie: &Trie<Key, StoredValue>) {
+        match trie {
+            Trie::Leaf { .. } => self.leaves += 1,
+            Trie::Node { .. } => self.nodes += 1,
+            Trie::Extension { .. } => self.extensions += 1,
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.leaves + self.nodes + self.extensions
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct KeyTypeStats {
+    pub(crate) leaves: u64,
+    /// Total size of the serialized leaves, keys and values included.
+    pub(crate) bytes: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub(crate) struct LargeValue {
+    /// Size of the serialized `StoredValue`.
+    pub(crate) size: usize,
+    pub(crate) key: String,
+    pub(crate) value_type: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TrieStats {
+    pub(crate) state_root: Digest,
+    /// Total size of the serialized tries.
+    pub(crate) total_bytes: u64,
+    pub(crate) node_counts: NodeCounts,
+    /// Counts of each node type at each depth, the state root being at depth 0.
+    pub(crate) depths: BTreeMap<usize, NodeCounts>,
+    pub(crate) key_types: BTreeMap<String, KeyTypeStats>,
+    /// The largest values in the trie, from the largest down.
+    pub(crate) largest_values: Vec<LargeValue>,
+}
+
+/// Keeps the `capacity` largest values fed to it.
+struct LargestValues {
+    capacity: usize,
+    heap: BinaryHeap<Reverse<LargeValue>>,
+}
+
+impl LargestValues {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            heap: BinaryHeap::with_capacity(capacity + 1),
+        }
+    }
+
+    fn feed(&mut self, key: &Key, value: &StoredValue) {
+        let size = value.serialized_length();
+        if self.capacity == 0
+            || (self.heap.len() == self.capacity
+                && self
+                    .heap
+                    .peek()
+                    .map_or(false, |Reverse(smallest)| smallest.size >= size))
+        {
+            return;
+        }
+        self.heap.push(Reverse(LargeValue {
+            size,
+            key: key.to_formatted_string(),
+            value_type: value.type_name(),
+        }));
+        if self.heap.len() > self.capacity {
+            self.heap.pop();
+        }
+    }
+
+    fn into_sorted_vec(self) -> Vec<LargeValue> {
+        // Sorting by `Reverse` puts the largest values first.
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(large_value)| large_value)
+            .collect()
+    }
+}
+
+/// Walks the trie under `state_root` in the trie store in `trie_path` and
+/// gathers statistics on its nodes and values, keeping the `top_count`
+/// largest values.
+pub(crate) fn trie_stats<P: AsRef<Path>>(
+    trie_path: P,
+    state_root: Digest,
+    top_count: usize,
+) -> Result<TrieStats, Error> {
+    let env = db::db_env(trie_path.as_ref().join(TRIE_STORE_FILE_NAME))?;
+    let txn = env.begin_ro_txn()?;
+    let trie_db = unsafe { txn.open_db(None)? };
+
+    let mut total_bytes = 0;
+    let mut node_counts = NodeCounts::default();
+    let mut depths: BTreeMap<usize, NodeCounts> = BTreeMap::new();
+    let mut key_types: BTreeMap<String, KeyTypeStats> = BTreeMap::new();
+    let mut largest_values = LargestValues::new(top_count);
+    // Leaves hold their full keys, so no subtrie appears twice in a trie and
+    // no visited set is needed.
+    let mut pending = vec![(state_root, 0usize)];
+    while let Some((trie_key, depth)) = pending.pop() {
+        let raw_trie = match txn.get(trie_db, &trie_key) {
+            Ok(raw_trie) => raw_trie,
+            Err(LmdbError::NotFound) => return Err(Error::MissingTrie(trie_key)),
+            Err(lmdb_err) => return Err(lmdb_err.into()),
+        };
+        let trie: Trie<Key, StoredValue> = bytesrepr::deserialize(raw_trie.to_vec())
+            .map_err(|bytesrepr_err| Error::TrieParsing(trie_key, bytesrepr_err.to_string()))?;
+        total_bytes += raw_trie.len() as u64;
+        node_counts.count(&trie);
+        depths.entry(depth).or_default().count(&trie);
+        if let Trie::Leaf { key, value } = &trie {
+            let key_type_stats = key_types.entry(key.type_string()).or_default();
+            key_type_stats.leaves += 1;
+            key_type_stats.bytes += raw_trie.len() as u64;
+            largest_values.feed(key, value);
+        }
+        pending.extend(
+            trie_compact::trie_pointers(&trie)
+                .into_iter()
+                .map(|child| (child, depth + 1)),
+        );
+        if node_counts.total() % HEARTBEAT_INTERVAL == 0 {
+            info!("Walked {} tries...", node_counts.total());
+        }
+    }
+    txn.commit()?;
+
+    Ok(TrieStats {
+        state_root,
+        total_bytes,
+        node_counts,
+        depths,
+        key_types,
+        largest_values: largest_values.into_sorted_vec(),
+    })
+}
+
+/// Writes the statistics as JSON to `output` if given, or to standard output
+/// otherwise.
+pub(crate) fn write_trie_stats<P: AsRef<Path>>(
+    stats: &TrieStats,
+    output: Option<P>,
+    overwrite: bool,
+) -> Result<(), Error> {
+    let out_writer: Box<dyn Write> = if let Some(out_path) = output {
+        let file = OpenOptions::new()
+            .create_new(!overwrite)
+            .create(overwrite)
+            .truncate(overwrite)
+            .write(true)
+            .open(out_path)?;
+        Box::new(file)
+    } else {
+        Box::new(io::stdout())
+    };
+    serde_json::to_writer_pretty(out_writer, stats)?;
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use cargio_execution_engine::storage::trie::{Pointer, PointerBlock, Trie};
+use cargio_hashing::Digest;
+use cargio_types::{
+    account::AccountHash,
+    bytesrepr::{Bytes, ToBytes},
+    AccessRights, CLValue, Key, StoredValue, URef,
+};
+
+use crate::{
+    common::db::TRIE_STORE_FILE_NAME,
+    test_utils::{put_trie, LmdbTestFixture},
+};
+
+use super::{
+    stats::{self, KeyTypeStats, NodeCounts},
+    Error,
+};
+
+fn put_sized_trie(fixture: &LmdbTestFixture, trie: &Trie<Key, StoredValue>) -> (Digest, usize) {
+    (put_trie(fixture, trie), trie.to_bytes().unwrap().len())
+}
+
+fn value_of_size(size: usize) -> StoredValue {
+    StoredValue::CLValue(CLValue::from_t(Bytes::from(vec![0u8; size])).unwrap())
+}
+
+#[test]
+fn trie_stats_should_count_nodes_and_values() {
+    let fixture = LmdbTestFixture::new(vec![], Some(TRIE_STORE_FILE_NAME));
+    let account_keys = [
+        Key::Account(AccountHash::new([1u8; 32])),
+        Key::Account(AccountHash::new([2u8; 32])),
+    ];
+    let uref_key = Key::URef(URef::new([3u8; 32], AccessRights::READ));
+    let leaves = [
+        (account_keys[0], value_of_size(10)),
+        (account_keys[1], value_of_size(30)),
+        (uref_key, value_of_size(20)),
+    ];
+    let leaf_entries: Vec<(Digest, usize)> = leaves
+        .iter()
+        .map(|(key, value)| {
+            put_sized_trie(
+                &fixture,
+                &Trie::Leaf {
+                    key: *key,
+                    value: value.clone(),
+                },
+            )
+        })
+        .collect();
+
+    // The account leaves hang from a node below an extension, the URef leaf
+    // from the root node.
+    let mut inner_block = PointerBlock::new();
+    inner_block[1] = Some(Pointer::LeafPointer(leaf_entries[0].0));
+    inner_block[2] = Some(Pointer::LeafPointer(leaf_entries[1].0));
+    let (inner_hash, inner_len) = put_sized_trie(
+        &fixture,
+        &Trie::Node {
+            pointer_block: Box::new(inner_block),
+        },
+    );
+    let (extension_hash, extension_len) = put_sized_trie(
+        &fixture,
+        &Trie::Extension {
+            affix: vec![0u8].into(),
+            pointer: Pointer::NodePointer(inner_hash),
+        },
+    );
+    let mut root_block = PointerBlock::new();
+    root_block[0] = Some(Pointer::NodePointer(extension_hash));
+    root_block[3] = Some(Pointer::LeafPointer(leaf_entries[2].0));
+    let (root_hash, root_len) = put_sized_trie(
+        &fixture,
+        &Trie::Node {
+            pointer_block: Box::new(root_block),
+        },
+    );
+
+    let trie_stats = stats::trie_stats(fixture.tmp_dir.path(), root_hash, 2).unwrap();
+    assert_eq!(trie_stats.state_root, root_hash);
+    let leaves_len: usize = leaf_entries.iter().map(|(_, len)| len).sum();
+    assert_eq!(
+        trie_stats.total_bytes,
+        (leaves_len + inner_len + extension_len + root_len) as u64
+    );
+    assert_eq!(
+        trie_stats.node_counts,
+        NodeCounts {
+            leaves: 3,
+            nodes: 2,
+            extensions: 1
+        }
+    );
+
+    let depths: Vec<(usize, NodeCounts)> = trie_stats.depths.into_iter().collect();
+    assert_eq!(
+        depths,
+        vec![
+            (
+                0,
+                NodeCounts {
+                    nodes: 1,
+                    ..Default::default()
+                }
+            ),
+            (
+                1,
+                NodeCounts {
+                    leaves: 1,
+                    extensions: 1,
+                    ..Default::default()
+                }
+            ),
+            (
+                2,
+                NodeCounts {
+                    nodes: 1,
+                    ..Default::default()
+                }
+            ),
+            (
+                3,
+                NodeCounts {
+                    leaves: 2,
+                    ..Default::default()
+                }
+            ),
+        ]
+    );
+
+    assert_eq!(trie_stats.key_types.len(), 2);
+    assert_eq!(
+        trie_stats.key_types[&account_keys[0].type_string()],
+        KeyTypeStats {
+            leaves: 2,
+            bytes: (leaf_entries[0].1 + leaf_entries[1].1) as u64
+        }
+    );
+    assert_eq!(
+        trie_stats.key_types[&uref_key.type_string()],
+        KeyTypeStats {
+            leaves: 1,
+            bytes: leaf_entries[2].1 as u64
+        }
+    );
+
+    let largest_keys: Vec<String> = trie_stats
+        .largest_values
+        .iter()
+        .map(|large_value| large_value.key.clone())
+        .collect();
+    assert_eq!(
+        largest_keys,
+        vec![
+            account_keys[1].to_formatted_string(),
+            uref_key.to_formatted_string()
+        ]
+    );
+    assert_eq!(
+        trie_stats.largest_values[0].size,
+        leaves[1].1.serialized_length()
+    );
+}
+
+#[test]
+fn missing_trie_should_fail() {
+    let fixture = LmdbTestFixture::new(vec![], Some(TRIE_STORE_FILE_NAME));
+    let missing_root = Digest::hash([1u8; 8]);
+    assert!(matches!(
+        stats::trie_stats(fixture.tmp_dir.path(), missing_root, 10),
+        Err(Error::MissingTrie(trie_key)) if trie_key == missing_root
+    ));
+}
@@ -0,0 +1,54 @@
+use std::{ffi::OsStr, path::Path};
+
+use clap::{Arg, ArgMatches, Command};
+use thiserror::Error as ThisError;
+
+use crate::common::{
+    block_index::{self, BlockIndexError},
+    db::STORAGE_FILE_NAME,
+    journal::{self, JournalError},
+};
+
+pub const COMMAND_NAME: &str = "undo";
+const JOURNAL_PATH: &str = "journal-path";
+
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error("Error removing the block index: {0}")]
+    BlockIndex(#[from] BlockIndexError),
+    #[error("Error restoring from the journal: {0}")]
+    Journal(#[from] JournalError),
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Restores the entries changed by a run of `remove-block`, `purge-signatures`, \
+            `compact-trie`, `gc-deploys`, `prune-execution-results` or `prune-history` to their \
+            state before that run, using the journal it wrote with `--journal`. Journals must \
+            be undone in the reverse order they were written.",
+        )
+        .arg(
+            Arg::new(JOURNAL_PATH)
+                .required(true)
+                .value_name("JOURNAL_PATH")
+                .help("Path of the journal file."),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let path = Path::new(
+        matches
+            .value_of(JOURNAL_PATH)
+            .expect("should have journal-path arg"),
+    );
+    let db_file = journal::journaled_db_file(path)?;
+    if db_file.file_name() == Some(OsStr::new(STORAGE_FILE_NAME)) {
+        if let Some(db_path) = db_file.parent() {
+            block_index::remove_index(db_path)?;
+        }
+    }
+    journal::undo(path)?;
+    Ok(())
+}
@@ -9,8 +9,11 @@ use lmdb::{Environment, EnvironmentFlags, Error as LmdbError};
 use log::{error, info};
 use thiserror::Error as ThisError;
 
+use crate::common::lmdb_utils;
+
 pub const COMMAND_NAME: &str = "unsparse";
 const DB_PATH: &str = "file-path";
+const DRY_RUN: &str = "dry-run";
 
 #[derive(ThisError, Debug)]
 pub enum Error {
@@ -36,6 +39,17 @@ pub fn command(display_order: usize) -> Command<'static> {
                 .required(true)
                 .help("Path to the storage.lmdb or data.lmdb file."),
         )
+        .arg(
+            Arg::new(DRY_RUN)
+                .display_order(1)
+                .short('n')
+                .long(DRY_RUN)
+                .takes_value(false)
+                .help(
+                    "Logs the size the file would be reduced to, opening it read-only \
+                    instead of resizing it.",
+                ),
+        )
 }
 
 pub fn run(matches: &ArgMatches) -> Result<(), Error> {
@@ -44,28 +58,38 @@ pub fn run(matches: &ArgMatches) -> Result<(), Error> {
             .value_of(DB_PATH)
             .expect("should have file-path arg"),
     );
-    unsparse(path)
+    unsparse(path, matches.is_present(DRY_RUN))
 }
 
-fn unsparse(path: &Path) -> Result<(), Error> {
+fn unsparse(path: &Path, dry_run: bool) -> Result<(), Error> {
     let size_before = fs::metadata(path)
         .map(|metadata| metadata.len())
         .map_err(|io_err| Error::Metadata(path.to_path_buf(), io_err))?;
 
-    let _env = Environment::new()
-        .set_flags(EnvironmentFlags::WRITE_MAP | EnvironmentFlags::NO_SUB_DIR)
-        .set_max_dbs(100)
-        .set_map_size(1)
-        .open(path)
-        .map_err(|lmdb_err| Error::Lmdb(path.to_path_buf(), lmdb_err))?;
+    let size_after = if dry_run {
+        let env = Environment::new()
+            .set_flags(EnvironmentFlags::READ_ONLY | EnvironmentFlags::NO_SUB_DIR)
+            .set_max_dbs(100)
+            .open(path)
+            .map_err(|lmdb_err| Error::Lmdb(path.to_path_buf(), lmdb_err))?;
+        lmdb_utils::used_size(&env).map_err(|lmdb_err| Error::Lmdb(path.to_path_buf(), lmdb_err))?
+    } else {
+        let _env = Environment::new()
+            .set_flags(EnvironmentFlags::WRITE_MAP | EnvironmentFlags::NO_SUB_DIR)
+            .set_max_dbs(100)
+            .set_map_size(1)
+            .open(path)
+            .map_err(|lmdb_err| Error::Lmdb(path.to_path_buf(), lmdb_err))?;
 
-    let size_after = fs::metadata(path)
-        .map(|metadata| metadata.len())
-        .map_err(|io_err| Error::Metadata(path.to_path_buf(), io_err))?;
+        fs::metadata(path)
+            .map(|metadata| metadata.len())
+            .map_err(|io_err| Error::Metadata(path.to_path_buf(), io_err))?
+    };
 
     if size_before > size_after {
+        let verb = if dry_run { "Would reduce" } else { "Reduced" };
         info!(
-            "Reduced size of {} from {} to {} bytes.",
+            "{verb} size of {} from {} to {} bytes.",
             path.display(),
             size_before,
             size_after
@@ -98,11 +122,25 @@ mod tests {
                 .len()
         };
         let size_before = db_size();
-        unsparse(db_path).expect("unsparse should succeed");
+        unsparse(db_path, true).expect("unsparse dry run should succeed");
+        assert_eq!(
+            db_size(),
+            size_before,
+            "dry run should not change file size"
+        );
+
+        unsparse(db_path, false).expect("unsparse should succeed");
         let size_after = db_size();
         assert!(size_after < size_before, "unsparse should reduce file size");
 
-        assert!(unsparse(db_path).is_err(), "repeat unsparse should fail");
+        assert!(
+            unsparse(db_path, true).is_err(),
+            "repeat dry run should fail"
+        );
+        assert!(
+            unsparse(db_path, false).is_err(),
+            "repeat unsparse should fail"
+        );
         assert_eq!(db_size(), size_after, "file size should be unchanged");
     }
 }
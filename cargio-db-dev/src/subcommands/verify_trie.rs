@@ -0,0 +1,147 @@
+#[cfg(test)]
+mod tests;
+mod verify;
+
+use std::{collections::BTreeSet, io::Error as IoError, path::Path};
+
+use bincode::Error as BincodeError;
+use clap::{Arg, ArgGroup, ArgMatches, Command};
+use lmdb::Error as LmdbError;
+use serde_json::Error as SerializationError;
+use thiserror::Error as ThisError;
+
+use cargio_hashing::Digest;
+
+pub const COMMAND_NAME: &str = "verify-trie";
+const OUTPUT: &str = "output";
+const OVERWRITE: &str = "overwrite";
+const ROOTS: &str = "roots";
+const STATE_ROOT: &str = "state-root";
+const STORAGE_PATH: &str = "storage-path";
+const TRIE_PATH: &str = "trie-path";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error operating the database: {0}")]
+    Database(#[from] LmdbError),
+    #[error("Error parsing block header with key {0}: {1}")]
+    HeaderParsing(String, BincodeError),
+    #[error("Found {0} missing or invalid nodes in the trie")]
+    IncompleteTrie(usize),
+    #[error("Error writing output: {0}")]
+    Output(#[from] IoError),
+    #[error("Error serializing output: {0}")]
+    Serialize(#[from] SerializationError),
+}
+
+enum DisplayOrder {
+    TriePath,
+    StateRoot,
+    StoragePath,
+    Output,
+    Overwrite,
+}
+
+pub fn command(display_order: usize) -> Command<'static> {
+    Command::new(COMMAND_NAME)
+        .display_order(display_order)
+        .about(
+            "Walks the tries under the given state roots, or under the state roots of all \
+            blocks in storage, without changing the trie store. Reports every node which \
+            is missing, can't be decoded or doesn't match its hash, and fails if any is \
+            found.",
+        )
+        .arg(
+            Arg::new(TRIE_PATH)
+                .display_order(DisplayOrder::TriePath as usize)
+                .required(true)
+                .short('t')
+                .long(TRIE_PATH)
+                .takes_value(true)
+                .value_name("TRIE_STORE_DIR_PATH")
+                .help("Path of the directory with the `data.lmdb` file."),
+        )
+        .arg(
+            Arg::new(STATE_ROOT)
+                .display_order(DisplayOrder::StateRoot as usize)
+                .short('r')
+                .long(STATE_ROOT)
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("STATE_ROOT_HASH")
+                .help("Hash of a state root to verify. Can be given several times."),
+        )
+        .arg(
+            Arg::new(STORAGE_PATH)
+                .display_order(DisplayOrder::StoragePath as usize)
+                .short('b')
+                .long(STORAGE_PATH)
+                .takes_value(true)
+                .value_name("STORAGE_DIR_PATH")
+                .help(
+                    "Path of the directory with the `storage.lmdb` file. The state roots of \
+                    all blocks in it are verified.",
+                ),
+        )
+        .arg(
+            Arg::new(OUTPUT)
+                .display_order(DisplayOrder::Output as usize)
+                .short('o')
+                .long(OUTPUT)
+                .takes_value(true)
+                .value_name("FILE_PATH")
+                .help(
+                    "Path to where the program will output a JSON report. If unspecified, \
+                    the report is logged.",
+                ),
+        )
+        .arg(
+            Arg::new(OVERWRITE)
+                .display_order(DisplayOrder::Overwrite as usize)
+                .required(false)
+                .short('w')
+                .long(OVERWRITE)
+                .takes_value(false)
+                .requires(OUTPUT)
+                .help(
+                    "Overwrite an already existing output file in destination \
+                    directory.",
+                ),
+        )
+        .group(
+            ArgGroup::new(ROOTS)
+                .required(true)
+                .multiple(true)
+                .args(&[STATE_ROOT, STORAGE_PATH]),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let trie_path = Path::new(
+        matches
+            .value_of(TRIE_PATH)
+            .expect("should have trie-path arg"),
+    );
+    let mut state_roots: BTreeSet<Digest> = matches
+        .values_of(STATE_ROOT)
+        .into_iter()
+        .flatten()
+        .map(|state_root_hash_str| {
+            Digest::from_hex(state_root_hash_str)
+                .expect("should parse state root hash to hex format")
+        })
+        .collect();
+    if let Some(storage_path) = matches.value_of(STORAGE_PATH) {
+        state_roots.extend(verify::storage_state_roots(storage_path)?);
+    }
+    let output = matches.value_of(OUTPUT).map(Path::new);
+    let overwrite = matches.is_present(OVERWRITE);
+
+    let report = verify::verify_trie(trie_path, state_roots)?;
+    verify::write_report(&report, output, overwrite)?;
+    if report.is_complete() {
+        Ok(())
+    } else {
+        Err(Error::IncompleteTrie(report.problems.len()))
+    }
+}
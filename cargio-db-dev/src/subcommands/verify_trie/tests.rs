@@ -0,0 +1,180 @@
+use std::collections::BTreeSet;
+
+use lmdb::{Transaction, WriteFlags};
+
+use cargio_execution_engine::storage::trie::{Pointer, PointerBlock, Trie};
+use cargio_hashing::Digest;
+use cargio_types::{account::AccountHash, CLValue, Key, StoredValue};
+
+use crate::{
+    common::db::{BlockHeaderDatabase, Database, STORAGE_FILE_NAME, TRIE_STORE_FILE_NAME},
+    test_utils::{mock_block_header, put_trie, LmdbTestFixture},
+};
+
+use super::verify::{self, ProblemKind};
+
+struct TestTrie {
+    root: Digest,
+    leaves: Vec<Digest>,
+}
+
+fn leaf(idx: u8) -> Trie<Key, StoredValue> {
+    Trie::Leaf {
+        key: Key::Account(AccountHash::new([idx; 32])),
+        value: StoredValue::CLValue(CLValue::from_t(idx as u64).unwrap()),
+    }
+}
+
+/// Stores a node pointing to two leaves.
+fn put_test_trie(fixture: &LmdbTestFixture) -> TestTrie {
+    let leaves: Vec<Digest> = (1..=2).map(|idx| put_trie(fixture, &leaf(idx))).collect();
+    let mut pointer_block = PointerBlock::new();
+    for (idx, leaf_hash) in leaves.iter().enumerate() {
+        pointer_block[idx + 1] = Some(Pointer::LeafPointer(*leaf_hash));
+    }
+    let root = put_trie(
+        fixture,
+        &Trie::Node {
+            pointer_block: Box::new(pointer_block),
+        },
+    );
+    TestTrie { root, leaves }
+}
+
+fn trie_fixture() -> LmdbTestFixture {
+    LmdbTestFixture::new(vec![], Some(TRIE_STORE_FILE_NAME))
+}
+
+#[test]
+fn complete_trie_should_pass() {
+    let fixture = trie_fixture();
+    let test_trie = put_test_trie(&fixture);
+
+    let report =
+        verify::verify_trie(fixture.tmp_dir.path(), BTreeSet::from([test_trie.root])).unwrap();
+    assert!(report.is_complete());
+    assert_eq!(report.state_roots, 1);
+    assert_eq!(report.nodes, 3);
+    assert_eq!(report.leaves, 2);
+}
+
+#[test]
+fn shared_nodes_should_be_walked_once() {
+    let fixture = trie_fixture();
+    let test_trie = put_test_trie(&fixture);
+    let extension = put_trie(
+        &fixture,
+        &Trie::Extension {
+            affix: vec![0u8].into(),
+            pointer: Pointer::NodePointer(test_trie.root),
+        },
+    );
+
+    let report = verify::verify_trie(
+        fixture.tmp_dir.path(),
+        BTreeSet::from([test_trie.root, extension]),
+    )
+    .unwrap();
+    assert!(report.is_complete());
+    assert_eq!(report.state_roots, 2);
+    assert_eq!(report.nodes, 4);
+    assert_eq!(report.leaves, 2);
+}
+
+#[test]
+fn missing_node_should_be_reported() {
+    let fixture = trie_fixture();
+    let test_trie = put_test_trie(&fixture);
+    let mut txn = fixture.env.begin_rw_txn().unwrap();
+    txn.del(*fixture.db(None).unwrap(), &test_trie.leaves[1], None)
+        .unwrap();
+    txn.commit().unwrap();
+
+    let report =
+        verify::verify_trie(fixture.tmp_dir.path(), BTreeSet::from([test_trie.root])).unwrap();
+    assert!(!report.is_complete());
+    assert_eq!(report.problems.len(), 1);
+    let problem = &report.problems[0];
+    assert_eq!(problem.state_root, test_trie.root);
+    assert_eq!(problem.node, test_trie.leaves[1]);
+    assert_eq!(problem.depth, 1);
+    assert_eq!(problem.parent, Some(test_trie.root));
+    assert_eq!(problem.kind, ProblemKind::Missing);
+    assert_eq!(report.nodes, 2);
+}
+
+#[test]
+fn missing_state_root_should_be_reported() {
+    let fixture = trie_fixture();
+    let missing_root = Digest::hash([1u8; 8]);
+
+    let report =
+        verify::verify_trie(fixture.tmp_dir.path(), BTreeSet::from([missing_root])).unwrap();
+    assert_eq!(report.problems.len(), 1);
+    let problem = &report.problems[0];
+    assert_eq!(problem.node, missing_root);
+    assert_eq!(problem.depth, 0);
+    assert_eq!(problem.parent, None);
+    assert_eq!(problem.kind, ProblemKind::Missing);
+}
+
+#[test]
+fn corrupt_node_should_be_reported() {
+    let fixture = trie_fixture();
+    let test_trie = put_test_trie(&fixture);
+    let corrupt_value = vec![0u8, 255, 255];
+    let mut txn = fixture.env.begin_rw_txn().unwrap();
+    txn.put(
+        *fixture.db(None).unwrap(),
+        &test_trie.leaves[0],
+        &corrupt_value,
+        WriteFlags::empty(),
+    )
+    .unwrap();
+    txn.commit().unwrap();
+
+    let report =
+        verify::verify_trie(fixture.tmp_dir.path(), BTreeSet::from([test_trie.root])).unwrap();
+    assert_eq!(report.problems.len(), 2);
+    for problem in report.problems.iter() {
+        assert_eq!(problem.node, test_trie.leaves[0]);
+        assert_eq!(problem.depth, 1);
+        assert_eq!(problem.parent, Some(test_trie.root));
+    }
+    assert_eq!(
+        report.problems[0].kind,
+        ProblemKind::HashMismatch {
+            actual_hash: Digest::hash(&corrupt_value)
+        }
+    );
+    assert!(matches!(
+        report.problems[1].kind,
+        ProblemKind::Undecodable { .. }
+    ));
+    assert_eq!(report.leaves, 1);
+}
+
+#[test]
+fn storage_state_roots_should_be_unique() {
+    let fixture = LmdbTestFixture::new(
+        vec![BlockHeaderDatabase::db_name()],
+        Some(STORAGE_FILE_NAME),
+    );
+    let state_roots: [Digest; 3] = [[1u8; 32].into(), [2u8; 32].into(), [1u8; 32].into()];
+    let mut txn = fixture.env.begin_rw_txn().unwrap();
+    for (idx, state_root) in state_roots.iter().enumerate() {
+        let (block_hash, mut header) = mock_block_header(idx as u8);
+        header.state_root_hash = *state_root;
+        txn.put(
+            *fixture.db(Some(BlockHeaderDatabase::db_name())).unwrap(),
+            &block_hash,
+            &bincode::serialize(&header).unwrap(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+    }
+    txn.commit().unwrap();
+
+    let storage_roots = verify::storage_state_roots(fixture.tmp_dir.path()).unwrap();
+    assert_eq!(storage_roots, BTreeSet::from(state_roots));
+}
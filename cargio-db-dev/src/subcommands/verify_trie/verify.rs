@@ -0,0 +1,229 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs::OpenOptions,
+    path::Path,
+};
+
+use lmdb::{Cursor, Database as LmdbDatabase, Error as LmdbError, RoTransaction, Transaction};
+use log::{error, info};
+use serde::Serialize;
+
+use cargio_execution_engine::storage::trie::Trie;
+use cargio_hashing::Digest;
+use cargio_types::{bytesrepr, Key, StoredValue};
+use master_node::types::BlockHeader;
+
+use crate::{
+    common::{
+        db::{self, BlockHeaderDatabase, Database, STORAGE_FILE_NAME, TRIE_STORE_FILE_NAME},
+        lmdb_utils,
+        progress::ProgressTracker,
+    },
+    subcommands::trie_compact,
+};
+
+use super::Error;
+
+/// Upper bound on the number of nodes remembered as checked across state
+/// roots, about 200 MB of digests.
+const MAX_VISITED_NODES: usize = 4_000_000;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum ProblemKind {
+    Missing,
+    Undecodable { error: String },
+    HashMismatch { actual_hash: Digest },
+}
+
+/// A node of the trie under `state_root` which is missing or invalid.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct TrieProblem {
+    pub(crate) state_root: Digest,
+    pub(crate) node: Digest,
+    /// Distance from the state root, which is at depth 0.
+    pub(crate) depth: usize,
+    /// The node pointing to this one, if it isn't the state root.
+    pub(crate) parent: Option<Digest>,
+    #[serde(flatten)]
+    pub(crate) kind: ProblemKind,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct VerifyReport {
+    pub(crate) state_roots: usize,
+    pub(crate) nodes: u64,
+    pub(crate) leaves: u64,
+    pub(crate) problems: Vec<TrieProblem>,
+}
+
+impl VerifyReport {
+    pub(crate) fn is_complete(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Returns the unique state root hashes of all blocks in the storage in
+/// `db_path`.
+pub(crate) fn storage_state_roots<P: AsRef<Path>>(db_path: P) -> Result<BTreeSet<Digest>, Error> {
+    let env = db::db_env(db_path.as_ref().join(STORAGE_FILE_NAME))?;
+    let txn = env.begin_ro_txn()?;
+    let header_db = unsafe { txn.open_db(Some(BlockHeaderDatabase::db_name()))? };
+    let mut maybe_progress_tracker = ProgressTracker::new(
+        lmdb_utils::entry_count(&txn, header_db)?,
+        Box::new(|completion| info!("Header database parsing {}% complete...", completion)),
+    )
+    .ok();
+    let mut state_roots = BTreeSet::new();
+    if let Ok(mut cursor) = txn.open_ro_cursor(header_db) {
+        for (raw_key, raw_val) in cursor.iter() {
+            let header: BlockHeader = bincode::deserialize(raw_val)
+                .map_err(|bincode_err| Error::HeaderParsing(hex::encode(raw_key), bincode_err))?;
+            state_roots.insert(*header.state_root_hash());
+            if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
+                progress_tracker.advance_by(1);
+            }
+        }
+    }
+    txn.commit()?;
+    Ok(state_roots)
+}
+
+/// Walks the trie under `state_root`, skipping the nodes in `visited` which
+/// were checked under another state root. No node appears twice under the
+/// same state root, so `visited` is cleared once it holds
+/// `MAX_VISITED_NODES`, at the cost of checking the nodes shared with later
+/// state roots again.
+fn verify_state_root(
+    txn: &RoTransaction,
+    trie_db: LmdbDatabase,
+    state_root: Digest,
+    visited: &mut HashSet<Digest>,
+    report: &mut VerifyReport,
+) -> Result<(), Error> {
+    let mut pending = vec![(state_root, 0usize, None)];
+    while let Some((node, depth, parent)) = pending.pop() {
+        if visited.len() >= MAX_VISITED_NODES {
+            visited.clear();
+        }
+        if !visited.insert(node) {
+            continue;
+        }
+        let problem = |kind| TrieProblem {
+            state_root,
+            node,
+            depth,
+            parent,
+            kind,
+        };
+        let raw_trie = match txn.get(trie_db, &node) {
+            Ok(raw_trie) => raw_trie,
+            Err(LmdbError::NotFound) => {
+                report.problems.push(problem(ProblemKind::Missing));
+                continue;
+            }
+            Err(lmdb_err) => return Err(lmdb_err.into()),
+        };
+        report.nodes += 1;
+        let actual_hash = Digest::hash(raw_trie);
+        if actual_hash != node {
+            report
+                .problems
+                .push(problem(ProblemKind::HashMismatch { actual_hash }));
+        }
+        let trie: Trie<Key, StoredValue> = match bytesrepr::deserialize(raw_trie.to_vec()) {
+            Ok(trie) => trie,
+            Err(bytesrepr_err) => {
+                report.problems.push(problem(ProblemKind::Undecodable {
+                    error: bytesrepr_err.to_string(),
+                }));
+                continue;
+            }
+        };
+        if let Trie::Leaf { .. } = trie {
+            report.leaves += 1;
+        }
+        pending.extend(
+            trie_compact::trie_pointers(&trie)
+                .into_iter()
+                .map(|child| (child, depth + 1, Some(node))),
+        );
+    }
+    Ok(())
+}
+
+/// Walks the tries under `state_roots` in the trie store in `trie_path`
+/// without writing anything, recording every missing, undecodable or
+/// mismatched node. Nodes shared by several state roots are counted and
+/// reported once, unless the walk had to forget them in between.
+pub(crate) fn verify_trie<P: AsRef<Path>>(
+    trie_path: P,
+    state_roots: BTreeSet<Digest>,
+) -> Result<VerifyReport, Error> {
+    let env = db::db_env(trie_path.as_ref().join(TRIE_STORE_FILE_NAME))?;
+    let txn = env.begin_ro_txn()?;
+    let trie_db = unsafe { txn.open_db(None)? };
+    let mut maybe_progress_tracker = ProgressTracker::new(
+        state_roots.len(),
+        Box::new(|completion| info!("Trie verification {}% complete...", completion)),
+    )
+    .ok();
+    let mut report = VerifyReport {
+        state_roots: state_roots.len(),
+        ..Default::default()
+    };
+    let mut visited = HashSet::new();
+    for state_root in state_roots {
+        verify_state_root(&txn, trie_db, state_root, &mut visited, &mut report)?;
+        if let Some(progress_tracker) = maybe_progress_tracker.as_mut() {
+            progress_tracker.advance_by(1);
+        }
+    }
+    txn.commit()?;
+    Ok(report)
+}
+
+/// Writes the report as JSON to `output` if given, or logs it otherwise.
+pub(crate) fn write_report<P: AsRef<Path>>(
+    report: &VerifyReport,
+    output: Option<P>,
+    overwrite: bool,
+) -> Result<(), Error> {
+    if let Some(out_path) = output {
+        let file = OpenOptions::new()
+            .create_new(!overwrite)
+            .create(overwrite)
+            .truncate(overwrite)
+            .write(true)
+            .open(out_path)?;
+        serde_json::to_writer_pretty(file, report)?;
+        return Ok(());
+    }
+    info!(
+        "Walked {} state roots: {} nodes, {} leaves.",
+        report.state_roots, report.nodes, report.leaves
+    );
+    for problem in report.problems.iter() {
+        let parent = problem
+            .parent
+            .map(|parent| parent.to_string())
+            .unwrap_or_else(|| "none".to_string());
+        match &problem.kind {
+            ProblemKind::Missing => error!(
+                "Node {} under state root {} is missing, at depth {} below parent {parent}.",
+                problem.node, problem.state_root, problem.depth
+            ),
+            ProblemKind::Undecodable { error } => error!(
+                "Node {} under state root {} can't be decoded, at depth {} below parent \
+                {parent}: {error}",
+                problem.node, problem.state_root, problem.depth
+            ),
+            ProblemKind::HashMismatch { actual_hash } => error!(
+                "Node {} under state root {} hashes to {actual_hash}, at depth {} below parent \
+                {parent}.",
+                problem.node, problem.state_root, problem.depth
+            ),
+        }
+    }
+    Ok(())
+}
@@ -4,18 +4,32 @@ use std::{
     collections::{BTreeMap, HashMap},
     fs::OpenOptions,
     path::PathBuf,
+    slice,
 };
 
-use lmdb::{Database as LmdbDatabase, DatabaseFlags, Environment, EnvironmentFlags};
+use lmdb::{
+    Database as LmdbDatabase, DatabaseFlags, Environment, EnvironmentFlags, Error as LmdbError,
+    Transaction, WriteFlags,
+};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use tempfile::{NamedTempFile, TempDir};
 
+use cargio_execution_engine::storage::trie::Trie;
 use cargio_hashing::Digest;
 use cargio_node::types::{BlockHash, DeployHash, DeployMetadata};
 use cargio_types::{
-    EraId, ExecutionEffect, ExecutionResult, ProtocolVersion, PublicKey, SecretKey, Timestamp,
-    U256, U512,
+    bytesrepr::ToBytes, EraId, ExecutionEffect, ExecutionResult, Key, ProtocolVersion, PublicKey,
+    SecretKey, StoredValue, Timestamp, U256, U512,
+};
+
+use crate::{
+    common::db::{
+        BlockBodyDatabase, BlockBodyMerkleDatabase, BlockHeaderDatabase, BlockMetadataDatabase,
+        Database, DeployDatabase, DeployHashesDatabase, DeployMetadataDatabase, ProposerDatabase,
+        TransferDatabase, TransferHashesDatabase, STORAGE_FILE_NAME,
+    },
+    subcommands::execution_results_summary::block_body::BlockBody,
 };
 
 pub(crate) static KEYS: Lazy<Vec<PublicKey>> = Lazy::new(|| {
@@ -105,6 +119,137 @@ impl LmdbTestFixture {
     }
 }
 
+pub(crate) fn put_entry(fixture: &LmdbTestFixture, db_name: &str, key: &[u8], value: &[u8]) {
+    let mut txn = fixture.env.begin_rw_txn().unwrap();
+    txn.put(
+        *fixture.db(Some(db_name)).unwrap(),
+        &key,
+        &value,
+        WriteFlags::empty(),
+    )
+    .unwrap();
+    txn.commit().unwrap();
+}
+
+pub(crate) fn has_entry(fixture: &LmdbTestFixture, db_name: &str, key: &[u8]) -> bool {
+    let txn = fixture.env.begin_ro_txn().unwrap();
+    let found = match txn.get(*fixture.db(Some(db_name)).unwrap(), &key) {
+        Ok(_) => true,
+        Err(LmdbError::NotFound) => false,
+        Err(lmdb_err) => panic!("{lmdb_err}"),
+    };
+    txn.commit().unwrap();
+    found
+}
+
+/// Stores `trie` under its hash in the default database of a trie store
+/// fixture. Returns the hash.
+pub(crate) fn put_trie(fixture: &LmdbTestFixture, trie: &Trie<Key, StoredValue>) -> Digest {
+    let trie_bytes = trie.to_bytes().unwrap();
+    let trie_hash = Digest::hash(&trie_bytes);
+    let mut txn = fixture.env.begin_rw_txn().unwrap();
+    txn.put(
+        *fixture.db(None).unwrap(),
+        &trie_hash,
+        &trie_bytes,
+        WriteFlags::empty(),
+    )
+    .unwrap();
+    txn.commit().unwrap();
+    trie_hash
+}
+
+/// A storage fixture with every database a block has entries in.
+pub(crate) fn chain_fixture() -> LmdbTestFixture {
+    LmdbTestFixture::new(
+        vec![
+            BlockHeaderDatabase::db_name(),
+            BlockBodyDatabase::db_name(),
+            BlockBodyMerkleDatabase::db_name(),
+            BlockMetadataDatabase::db_name(),
+            DeployDatabase::db_name(),
+            DeployHashesDatabase::db_name(),
+            DeployMetadataDatabase::db_name(),
+            ProposerDatabase::db_name(),
+            TransferDatabase::db_name(),
+            TransferHashesDatabase::db_name(),
+        ],
+        Some(STORAGE_FILE_NAME),
+    )
+}
+
+/// Stores the block with the given hash, serialized header and body hash,
+/// along with its signatures, transfers and deploy `idx`, which is executed
+/// only in that block. Returns the hash of the deploy.
+pub(crate) fn put_chain_block(
+    fixture: &LmdbTestFixture,
+    idx: u8,
+    block_hash: BlockHash,
+    raw_header: &[u8],
+    body_hash: &Digest,
+) -> DeployHash {
+    let deploy_hash = mock_deploy_hash(idx);
+    put_entry(
+        fixture,
+        BlockHeaderDatabase::db_name(),
+        block_hash.as_ref(),
+        raw_header,
+    );
+    put_entry(
+        fixture,
+        BlockBodyDatabase::db_name(),
+        body_hash.as_ref(),
+        &bincode::serialize(&BlockBody::new(vec![deploy_hash])).unwrap(),
+    );
+    put_entry(
+        fixture,
+        DeployMetadataDatabase::db_name(),
+        deploy_hash.as_ref(),
+        &bincode::serialize(&mock_deploy_metadata(slice::from_ref(&block_hash))).unwrap(),
+    );
+    put_entry(
+        fixture,
+        DeployDatabase::db_name(),
+        deploy_hash.as_ref(),
+        &[idx],
+    );
+    put_entry(
+        fixture,
+        TransferDatabase::db_name(),
+        block_hash.as_ref(),
+        &[idx],
+    );
+    put_entry(
+        fixture,
+        BlockMetadataDatabase::db_name(),
+        block_hash.as_ref(),
+        &[idx],
+    );
+    deploy_hash
+}
+
+/// Stores a block at each height below `block_count` with
+/// `put_chain_block`.
+pub(crate) fn populate_chain(
+    fixture: &LmdbTestFixture,
+    block_count: u8,
+) -> Vec<(BlockHash, MockBlockHeader, DeployHash)> {
+    let mut blocks = vec![];
+    for idx in 0..block_count {
+        let (block_hash, mut block_header) = mock_block_header(idx);
+        block_header.height = idx as u64;
+        let deploy_hash = put_chain_block(
+            fixture,
+            idx,
+            block_hash,
+            &bincode::serialize(&block_header).unwrap(),
+            &block_header.body_hash,
+        );
+        blocks.push((block_hash, block_header, deploy_hash));
+    }
+    blocks
+}
+
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize, Debug)]
 pub struct MockBlockHeader {
     pub parent_hash: BlockHash,